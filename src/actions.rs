@@ -1,15 +1,71 @@
+use super::analysis::{Duplicate, TermCount};
+use super::document::{OpenMode, OpenOptions};
+use super::error::Error;
+use super::export::ExportFormat;
+use super::position::Position;
+use super::workspace::{FileMatches, Query};
+use crate::gio;
+
 pub enum Action {
-    OpenFile(Option<std::path::PathBuf>),
-    SaveFile(std::path::PathBuf),
-    DocumentChanged(String),
+    // Files are gio locations, so they may be remote.
+    OpenFile(Option<gio::File>),
+    OpenFileAs(gio::File, OpenMode),
+    OpenFileWith(gio::File, OpenOptions),
+    NewFromTemplate(std::path::PathBuf),
+    SaveFile(gio::File),
+    // Writes the text elsewhere, leaving the document as it is.
+    SaveCopy(gio::File),
+    CopySaved(Result<gio::File, Error>),
+    // Saves the note, unless it was edited again since this was sent.
+    SaveNote(usize),
+    ReopenClosed,
+    // An untitled document closed without saving, with its text unsaved.
+    RestoreDiscarded(String),
+    // Standard input, read to its end, as a new untitled document.
+    StandardInputRead(Result<String, Error>),
+    // The files of a batch that could not be opened, reported together.
+    OpenFailures(Vec<(gio::File, Error)>),
+    // Where the active window's cursor and view are.
+    ViewMoved(Position),
+    // Offsets count characters.
+    TextInserted { offset: usize, text: String },
+    TextDeleted { start: usize, end: usize },
+    // The edits between these undo as one step, as GTK groups them.
+    UserActionStarted,
+    UserActionEnded,
+    Undo,
+    Redo,
+    // The next step of the background work, unless the text changed since.
+    IdleStep(usize),
+    FileOpenProgress(f64),
     FileOpenFinished(IOResult),
+    OpenLargeFile(bool),
+    CancelOpen,
+    FileSaveProgress(f64),
     FileSaveFinished(IOResult),
+    CancelSave,
+    FileChangedOnDisk(IOResult),
+    ReloadFile,
+    KeepFile,
+    MergeFile,
+    CompareFile,
+    SetAutoReload(bool),
+    // One of the document label colors, or empty for none.
+    SetDocumentLabel(String),
+    PreferencesChanged,
+    AnalyzeWordFrequency,
+    WordFrequencyFinished(Vec<TermCount>),
+    FindDuplicates,
+    DuplicatesFinished(Vec<Duplicate>),
+    FindInFiles(Query),
+    // Results are tagged with the search they belong to.
+    FindInFilesFound(usize, FileMatches),
+    FindInFilesFinished(usize),
+    CancelFindInFiles,
+    ReplaceInFile(std::path::PathBuf, String),
+    ReplaceInFileFinished(Result<(std::path::PathBuf, usize), Error>),
+    Export(std::path::PathBuf, ExportFormat),
+    ExportFinished(Result<std::path::PathBuf, Error>),
 }
 
-#[derive(Debug, Clone)]
-pub enum Err {
-    IOError(),
-    UnknownError(),
-}
-
-pub type IOResult = Result<(std::path::PathBuf, String), Err>;
+pub type IOResult = Result<(gio::File, String), Error>;
@@ -0,0 +1,435 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+
+const SHINGLE_SIZE: usize = 2;
+const MIN_SENTENCE_WORDS: usize = 5;
+
+const STOP_WORDS_EN: &[&str] = &[
+    "a", "about", "after", "all", "also", "an", "and", "any", "are", "as", "at", "be", "been",
+    "but", "by", "can", "could", "did", "do", "does", "for", "from", "had", "has", "have", "he",
+    "her", "him", "his", "how", "i", "if", "in", "into", "is", "it", "its", "just", "me", "my",
+    "no", "not", "of", "on", "or", "our", "out", "she", "so", "than", "that", "the", "their",
+    "them", "then", "there", "these", "they", "this", "to", "up", "us", "was", "we", "were",
+    "what", "when", "which", "who", "will", "with", "would", "you", "your",
+];
+const STOP_WORDS_DE: &[&str] = &[
+    "aber", "als", "am", "an", "auch", "auf", "aus", "bei", "bin", "bis", "das", "dass", "dem",
+    "den", "der", "des", "die", "du", "ein", "eine", "einem", "einen", "einer", "er", "es", "für",
+    "hat", "ich", "ihr", "im", "in", "ist", "mit", "nach", "nicht", "noch", "nur", "oder", "sich",
+    "sie", "sind", "so", "um", "und", "uns", "von", "vor", "war", "was", "wie", "wir", "zu", "zum",
+    "zur",
+];
+const STOP_WORDS_ES: &[&str] = &[
+    "a", "al", "como", "con", "de", "del", "el", "en", "es", "esta", "este", "ha", "la", "las",
+    "le", "les", "lo", "los", "más", "me", "mi", "no", "o", "para", "pero", "por", "que", "se",
+    "si", "sin", "su", "sus", "te", "tu", "un", "una", "y", "ya", "yo",
+];
+const STOP_WORDS_FR: &[&str] = &[
+    "au", "aux", "avec", "ce", "ces", "dans", "de", "des", "du", "elle", "en", "est", "et", "il",
+    "ils", "je", "la", "le", "les", "leur", "lui", "ma", "mais", "me", "mes", "ne", "nous", "on",
+    "ou", "par", "pas", "pour", "qu", "que", "qui", "sa", "se", "ses", "son", "sur", "ta", "te",
+    "tu", "un", "une", "vous",
+];
+const STOP_WORDS_IT: &[&str] = &[
+    "a", "al", "alla", "che", "con", "da", "del", "della", "di", "e", "è", "gli", "ha", "i", "il",
+    "in", "la", "le", "lo", "ma", "mi", "ne", "non", "per", "più", "si", "su", "ti", "un", "una",
+    "uno",
+];
+const STOP_WORDS_PT: &[&str] = &[
+    "a", "ao", "as", "com", "como", "da", "das", "de", "do", "dos", "e", "é", "em", "mais", "mas",
+    "na", "no", "não", "nos", "o", "os", "ou", "para", "por", "que", "se", "sem", "seu", "sua",
+    "um", "uma",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TermCount {
+    pub term: String,
+    pub count: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Passage {
+    pub text: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Duplicate {
+    pub first: Passage,
+    pub second: Passage,
+    pub similarity: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Word {
+    text: String,
+    start: usize,
+    end: usize,
+}
+
+// Accepts locale names such as "de_DE.UTF-8" and falls back to English.
+pub fn stop_words(language: &str) -> &'static [&'static str] {
+    let code = language
+        .split(&['_', '-', '.', '@'][..])
+        .next()
+        .unwrap_or("")
+        .to_lowercase();
+    match code.as_str() {
+        "de" => STOP_WORDS_DE,
+        "es" => STOP_WORDS_ES,
+        "fr" => STOP_WORDS_FR,
+        "it" => STOP_WORDS_IT,
+        "pt" => STOP_WORDS_PT,
+        _ => STOP_WORDS_EN,
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '\'' || c == '’'
+}
+
+// Offsets are in characters, matching gtk::TextIter offsets.
+fn words(text: &str) -> Vec<Word> {
+    let mut result = Vec::new();
+    let mut current = String::new();
+    let mut start = 0;
+    let mut count = 0;
+    for (i, c) in text.chars().enumerate() {
+        if is_word_char(c) {
+            if current.is_empty() {
+                start = i;
+            }
+            current.push(c);
+        } else if !current.is_empty() {
+            result.push(Word::new(&current, start, i));
+            current.clear();
+        }
+        count = i + 1;
+    }
+    if !current.is_empty() {
+        result.push(Word::new(&current, start, count));
+    }
+    result
+}
+
+impl Word {
+    // Quotes are word characters for contractions but are not kept at the
+    // edges of a word.
+    fn new(raw: &str, start: usize, end: usize) -> Self {
+        let is_quote = |c| c == '\'' || c == '’';
+        let body = raw.trim_matches(is_quote);
+        let leading = raw.chars().take_while(|c| is_quote(*c)).count();
+        Self {
+            text: body.to_lowercase(),
+            start: start + leading,
+            end: (start + leading + body.chars().count()).min(end),
+        }
+    }
+}
+
+// Counts single words and two-word phrases, most frequent first. Phrases
+// are only reported when they occur more than once.
+pub fn term_frequencies(text: &str, language: &str, limit: usize) -> Vec<TermCount> {
+    let stop = stop_words(language);
+    let words: Vec<Word> = words(text)
+        .into_iter()
+        .filter(|w| w.text.chars().any(char::is_alphabetic))
+        .collect();
+    let is_stop = |w: &Word| stop.contains(&w.text.as_str());
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for w in words.iter().filter(|w| !is_stop(w)) {
+        *counts.entry(w.text.clone()).or_insert(0) += 1;
+    }
+    let mut phrases: HashMap<String, usize> = HashMap::new();
+    for pair in words.windows(2) {
+        if !is_stop(&pair[0]) && !is_stop(&pair[1]) {
+            let phrase = format!("{} {}", pair[0].text, pair[1].text);
+            *phrases.entry(phrase).or_insert(0) += 1;
+        }
+    }
+    counts.extend(phrases.into_iter().filter(|(_, count)| *count > 1));
+    let mut result: Vec<TermCount> = counts
+        .into_iter()
+        .map(|(term, count)| TermCount { term, count })
+        .collect();
+    result.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.term.cmp(&b.term)));
+    result.truncate(limit);
+    result
+}
+
+// Returns the character ranges of every whole-word, case-insensitive match
+// of the given word or phrase.
+pub fn find_occurrences(text: &str, term: &str) -> Vec<(usize, usize)> {
+    let needle: Vec<String> = words(term).into_iter().map(|w| w.text).collect();
+    if needle.is_empty() {
+        return Vec::new();
+    }
+    words(text)
+        .windows(needle.len())
+        .filter(|window| window.iter().zip(needle.iter()).all(|(w, n)| &w.text == n))
+        .map(|window| (window[0].start, window[window.len() - 1].end))
+        .collect()
+}
+
+fn passage(chars: &[char], start: usize, end: usize) -> Option<Passage> {
+    let is_space = |i: &usize| chars[*i].is_whitespace();
+    let start = (start..end).find(|i| !is_space(i))?;
+    let end = (start..end).rev().find(|i| !is_space(i))? + 1;
+    Some(Passage {
+        text: chars[start..end].iter().collect(),
+        start,
+        end,
+    })
+}
+
+fn is_blank_line_at(chars: &[char], i: usize) -> bool {
+    chars[i] == '\n'
+        && chars[i + 1..]
+            .iter()
+            .take_while(|c| **c != '\n')
+            .all(|c| c.is_whitespace())
+        && chars[i + 1..].contains(&'\n')
+}
+
+fn paragraphs(chars: &[char]) -> Vec<Passage> {
+    let mut result = Vec::new();
+    let mut start = 0;
+    for i in 0..chars.len() {
+        if is_blank_line_at(chars, i) {
+            result.extend(passage(chars, start, i));
+            start = i + 1;
+        }
+    }
+    result.extend(passage(chars, start, chars.len()));
+    result
+}
+
+fn sentences(chars: &[char]) -> Vec<Passage> {
+    let mut result = Vec::new();
+    let mut start = 0;
+    for i in 0..chars.len() {
+        let terminal = matches!(chars[i], '.' | '!' | '?' | '…')
+            && !matches!(chars.get(i + 1), Some(c) if !c.is_whitespace());
+        if terminal {
+            result.extend(passage(chars, start, i + 1));
+            start = i + 1;
+        } else if is_blank_line_at(chars, i) {
+            result.extend(passage(chars, start, i));
+            start = i + 1;
+        }
+    }
+    result.extend(passage(chars, start, chars.len()));
+    result
+}
+
+fn shingles(passage: &Passage) -> HashSet<u64> {
+    let words: Vec<String> = words(&passage.text)
+        .into_iter()
+        .map(|w| w.text)
+        .filter(|w| !w.is_empty())
+        .collect();
+    words
+        .windows(SHINGLE_SIZE.min(words.len()).max(1))
+        .map(|window| {
+            let mut hasher = DefaultHasher::new();
+            window.hash(&mut hasher);
+            hasher.finish()
+        })
+        .collect()
+}
+
+// Pairs up passages whose word shingles overlap by at least `threshold`
+// (Jaccard similarity between 0 and 1).
+fn similar_pairs(passages: &[Passage], threshold: f64) -> Vec<(usize, usize, f64)> {
+    let sets: Vec<HashSet<u64>> = passages.iter().map(shingles).collect();
+    let mut index: HashMap<u64, Vec<usize>> = HashMap::new();
+    for (i, set) in sets.iter().enumerate() {
+        for shingle in set {
+            index.entry(*shingle).or_default().push(i);
+        }
+    }
+    let mut shared: HashMap<(usize, usize), usize> = HashMap::new();
+    for ids in index.values() {
+        for (n, a) in ids.iter().enumerate() {
+            for b in ids[n + 1..].iter() {
+                *shared.entry((*a, *b)).or_insert(0) += 1;
+            }
+        }
+    }
+    let mut pairs: Vec<(usize, usize, f64)> = shared
+        .into_iter()
+        .map(|((a, b), common)| {
+            let union = sets[a].len() + sets[b].len() - common;
+            (a, b, common as f64 / union as f64)
+        })
+        .filter(|(_, _, similarity)| *similarity >= threshold)
+        .collect();
+    pairs.sort_by_key(|(a, b, _)| (*a, *b));
+    pairs
+}
+
+// Finds repeated or nearly repeated paragraphs and sentences. Sentences
+// are left out when they belong to an already reported paragraph pair.
+pub fn find_duplicates(text: &str, threshold: f64) -> Vec<Duplicate> {
+    let chars: Vec<char> = text.chars().collect();
+    let paragraphs: Vec<Passage> = paragraphs(&chars)
+        .into_iter()
+        .filter(|p| sentences(&chars[p.start..p.end]).len() > 1)
+        .collect();
+    let sentences: Vec<Passage> = sentences(&chars)
+        .into_iter()
+        .filter(|s| words(&s.text).len() >= MIN_SENTENCE_WORDS)
+        .collect();
+    let mut result: Vec<Duplicate> = similar_pairs(&paragraphs, threshold)
+        .into_iter()
+        .map(|(a, b, similarity)| Duplicate {
+            first: paragraphs[a].clone(),
+            second: paragraphs[b].clone(),
+            similarity,
+        })
+        .collect();
+    let within = |s: &Passage, p: &Passage| p.start <= s.start && s.end <= p.end;
+    for (a, b, similarity) in similar_pairs(&sentences, threshold) {
+        let (first, second) = (&sentences[a], &sentences[b]);
+        let covered = result
+            .iter()
+            .any(|d| within(first, &d.first) && within(second, &d.second));
+        if !covered {
+            result.push(Duplicate {
+                first: first.clone(),
+                second: second.clone(),
+                similarity,
+            });
+        }
+    }
+    result.sort_by_key(|d| (d.first.start, d.second.start));
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn count_of(counts: &[TermCount], term: &str) -> Option<usize> {
+        counts.iter().find(|c| c.term == term).map(|c| c.count)
+    }
+
+    #[test]
+    fn test_stop_words_by_locale() {
+        assert!(stop_words("de_DE.UTF-8").contains(&"und"));
+        assert!(stop_words("fr").contains(&"avec"));
+        assert!(stop_words("C").contains(&"the"), "Falls back to English");
+    }
+
+    #[test]
+    fn test_word_counts() {
+        let counts = term_frequencies("The cat and the hat. A cat!", "en_US", 10);
+        assert_eq!(Some(2), count_of(&counts, "cat"));
+        assert_eq!(Some(1), count_of(&counts, "hat"));
+        assert_eq!(None, count_of(&counts, "the"), "Stop words are filtered");
+        assert_eq!("cat", counts[0].term, "Most frequent first");
+    }
+
+    #[test]
+    fn test_phrases() {
+        let text = "New York is big. I love New York. York is old.";
+        let counts = term_frequencies(text, "en", 10);
+        assert_eq!(Some(2), count_of(&counts, "new york"));
+        assert_eq!(Some(3), count_of(&counts, "york"));
+        assert_eq!(
+            None,
+            count_of(&counts, "york old"),
+            "Single phrases are dropped"
+        );
+    }
+
+    #[test]
+    fn test_unicode_and_limit() {
+        let counts = term_frequencies("Über über Straße straße straße", "de", 1);
+        assert_eq!(
+            vec![TermCount {
+                term: "straße".into(),
+                count: 3
+            }],
+            counts
+        );
+    }
+
+    #[test]
+    fn test_apostrophes() {
+        let counts = term_frequencies("'quoted' don't don't", "en", 10);
+        assert_eq!(Some(1), count_of(&counts, "quoted"));
+        assert_eq!(Some(2), count_of(&counts, "don't"));
+    }
+
+    #[test]
+    fn test_find_occurrences() {
+        let text = "Cat cats cat, ünï cat";
+        assert_eq!(
+            vec![(0, 3), (9, 12), (18, 21)],
+            find_occurrences(text, "cat")
+        );
+        assert_eq!(vec![(9, 17)], find_occurrences(text, "cat ünï"));
+        assert_eq!(Vec::<(usize, usize)>::new(), find_occurrences(text, "  "));
+    }
+
+    #[test]
+    fn test_sentences_and_paragraphs() {
+        let chars: Vec<char> = "One. Two!\n\n  Three?\nStill three".chars().collect();
+        let texts = |passages: Vec<Passage>| -> Vec<String> {
+            passages.into_iter().map(|p| p.text).collect()
+        };
+        assert_eq!(
+            vec!["One.", "Two!", "Three?", "Still three"],
+            texts(sentences(&chars))
+        );
+        assert_eq!(
+            vec!["One. Two!", "Three?\nStill three"],
+            texts(paragraphs(&chars))
+        );
+        let third = &paragraphs(&chars)[1];
+        assert_eq!(13, third.start, "Leading whitespace is skipped");
+        assert_eq!(chars.len(), third.end);
+    }
+
+    #[test]
+    fn test_duplicate_sentences() {
+        let text = "The quick brown fox jumps over the lazy dog. Something else entirely here. \
+                    The quick brown fox jumped over the lazy dog.";
+        let duplicates = find_duplicates(text, 0.5);
+        assert_eq!(1, duplicates.len());
+        let d = &duplicates[0];
+        assert_eq!("The quick brown fox jumps over the lazy dog.", d.first.text);
+        assert_eq!(
+            "The quick brown fox jumped over the lazy dog.",
+            d.second.text
+        );
+        assert!(d.similarity < 1.0, "Near duplicates are not identical");
+        let chars: Vec<char> = text.chars().collect();
+        let second: String = chars[d.second.start..d.second.end].iter().collect();
+        assert_eq!(d.second.text, second, "Offsets match the passage");
+    }
+
+    #[test]
+    fn test_short_sentences_ignored() {
+        assert!(find_duplicates("Yes it is. Yes it is.", 0.8).is_empty());
+    }
+
+    #[test]
+    fn test_duplicate_paragraphs() {
+        let paragraph = "Mary had a little lamb whose fleece was white. \
+                         Everywhere that Mary went the lamb was sure to go.";
+        let text = format!("{}\n\nA different paragraph.\n\n{}\n", paragraph, paragraph);
+        let duplicates = find_duplicates(&text, 0.8);
+        assert_eq!(
+            1,
+            duplicates.len(),
+            "Sentences inside duplicate paragraphs are merged"
+        );
+        assert_eq!(paragraph, duplicates[0].first.text);
+        assert_eq!(paragraph, duplicates[0].second.text);
+        assert!((duplicates[0].similarity - 1.0).abs() < f64::EPSILON);
+    }
+}
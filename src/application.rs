@@ -1,43 +1,137 @@
 use gettextrs::gettext;
-use log::{debug, info};
+use log::{debug, info, warn};
 
 use glib::{clone, Continue, MainContext, PRIORITY_DEFAULT};
 use gtk::prelude::*;
 use gtk::subclass::prelude::*;
-use gtk::{gdk, gio, glib};
+use gtk::{cairo, gdk, gio, glib};
 
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
+use std::thread;
+use std::time::{Duration, Instant};
 
 use super::actions::Action;
 use super::actions::Action::*;
-use super::application_model::{ApplicationModel, Changes};
+use super::application_model::{
+    config_dir, recent_documents, templates_dir, ApplicationModel, Changes, OpenFiles,
+    LAST_DOCUMENT,
+};
+use super::cli;
 use super::config::{APP_ID, PKGDATADIR, PROFILE, VERSION};
+use super::document::{OpenMode, OpenOptions};
+use super::error::{Error, IoKind};
+use super::export::{self, ExportFormat};
+use super::file_system::FileSystem;
+use super::import_editor::{self, EditorSettings};
+use super::jobs::Jobs;
+use super::preferences::PreferencesWindow;
+use super::sandbox;
+use super::settings::Settings;
+use super::settings_archive;
+use super::startup::Timing;
+use super::template;
+use super::transforms::Registry;
+use super::tray::{self, Tray};
 use super::window::ApplicationWindow;
-use crate::glib::Sender;
+use super::workspace::Query;
+
+const DESKTOP_SCHEMA: &str = "org.gnome.desktop.interface";
+const LAST_DIRECTORY: &str = "last-directory";
+const LAST_PROJECT_DIRECTORY: &str = "last-project-directory";
+// How many lines an untitled document's suggested name may come from.
+const NAME_LINES: usize = 50;
+const LAST_FILE_FILTER: &str = "last-file-filter";
+// Points, as cairo measures a printed page.
+const PDF_FONT_SIZE: f64 = 10.0;
+// The open chooser's choice of how to open the file.
+const NEW_WINDOW_OPTION: &str = "new-window";
+// How many discarded documents are kept for Undo Close.
+const DISCARDED_LIMIT: usize = 10;
+const READ_ONLY_OPTION: &str = "read-only";
+const LINE_OPTION: &str = "line";
+const COLUMN_OPTION: &str = "column";
+const ENCODING_OPTION: &str = "encoding";
+// How long quitting waits for files still being written, and how often it
+// looks.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(30);
+const WRITE_POLL: Duration = Duration::from_millis(100);
+const TRAY_ICON: &str = "tray-icon";
+// Filters are stored by these identifiers, as their names are translated.
+const FILE_FILTERS: [&str; 4] = ["text", "markdown", "source", "all"];
+// What a window is opened with, such as from the command line.
+#[derive(Debug, Clone)]
+enum Opening {
+    File(gio::File),
+    Stream(gio::InputStream),
+}
+
+const SOURCE_PATTERNS: [&str; 20] = [
+    "*.rs", "*.c", "*.h", "*.cpp", "*.hpp", "*.cs", "*.py", "*.js", "*.ts", "*.go", "*.java",
+    "*.rb", "*.sh", "*.toml", "*.json", "*.yaml", "*.yml", "*.xml", "*.html", "*.css",
+];
 
 mod imp {
     use super::*;
     use glib::WeakRef;
-    use once_cell::sync::OnceCell;
 
+    // A window and the model of its document.
     #[derive(Debug)]
-    pub struct Application {
-        pub window: OnceCell<WeakRef<ApplicationWindow>>,
+    pub struct Instance {
+        pub window: WeakRef<ApplicationWindow>,
         pub model: Rc<RefCell<ApplicationModel>>,
-        pub undo_action: gio::SimpleAction,
-        pub redo_action: gio::SimpleAction,
+        // Set once its unsaved changes may be lost, so it closes without
+        // asking again.
+        pub discard: Cell<bool>,
+    }
+
+    #[derive(Debug)]
+    pub struct Application {
+        pub windows: RefCell<Vec<Instance>>,
+        // Untitled documents closed without saving this session, most
+        // recent last.
+        pub discarded: RefCell<Vec<String>>,
+        pub open_files: Rc<RefCell<OpenFiles>>,
+        pub jobs: Jobs,
+        pub auto_reload_file_action: gio::SimpleAction,
+        pub document_label_action: gio::SimpleAction,
+        pub settings: gio::Settings,
+        pub desktop_settings: Option<gio::Settings>,
+        pub dark_provider: gtk::CssProvider,
+        pub dark_loaded: Cell<bool>,
+        pub file_chooser: RefCell<Option<gtk::FileChooserNative>>,
+        // Set once the first window has started the application.
+        pub started: Cell<bool>,
+        pub tray: RefCell<Option<Tray>>,
+        // Until the first window is ready.
+        pub timing: RefCell<Option<Timing>>,
     }
 
     impl Default for Application {
         fn default() -> Self {
-            let undo_action = gio::SimpleAction::new("undo", None);
-            let redo_action = gio::SimpleAction::new("redo", None);
+            let auto_reload_file_action =
+                gio::SimpleAction::new_stateful("auto-reload-file", None, &false.to_variant());
+            let document_label_action = gio::SimpleAction::new_stateful(
+                "document-label",
+                Some(&String::static_variant_type()),
+                &"".to_variant(),
+            );
             Self {
-                window: OnceCell::default(),
-                model: Rc::default(),
-                undo_action,
-                redo_action,
+                windows: RefCell::default(),
+                discarded: RefCell::default(),
+                open_files: Rc::default(),
+                jobs: Jobs::default(),
+                auto_reload_file_action,
+                document_label_action,
+                settings: gio::Settings::new(APP_ID),
+                desktop_settings: super::Application::desktop_settings(),
+                dark_provider: gtk::CssProvider::new(),
+                dark_loaded: Cell::new(false),
+                file_chooser: RefCell::default(),
+                started: Cell::new(false),
+                tray: RefCell::default(),
+                timing: RefCell::default(),
             }
         }
     }
@@ -55,51 +149,104 @@ mod imp {
         fn activate(&self, app: &Self::Type) {
             debug!("GtkApplication<Application>::activate");
 
-            if let Some(window) = self.window.get() {
-                let window = window.upgrade().unwrap();
+            if let Some(window) = app.main_window() {
                 window.show();
                 window.present();
                 return;
             }
 
-            let window = ApplicationWindow::new(app);
-            self.window
-                .set(window.downgrade())
-                .expect("Window already set.");
+            app.start();
+            app.restore_session();
+        }
 
-            let (tx, rx) = MainContext::channel(PRIORITY_DEFAULT);
+        fn open(&self, app: &Self::Type, files: &[gio::File], _hint: &str) {
+            debug!("GtkApplication<Application>::open");
+            let files: Vec<(Opening, OpenOptions)> = files
+                .iter()
+                .map(|file| (Opening::File(file.clone()), OpenOptions::default()))
+                .collect();
+            app.open_files(&files, false);
+        }
 
-            let model_rc = app.model();
-            {
-                let local_m = model_rc.clone();
-                let mut model = local_m.borrow_mut();
-                model.transmit(tx.clone());
+        // Runs in the primary instance, for its own command line and for
+        // those of later launches, which exit once it returns.
+        fn command_line(
+            &self,
+            app: &Self::Type,
+            command_line: &gio::ApplicationCommandLine,
+        ) -> i32 {
+            debug!("GtkApplication<Application>::command_line");
+            let options = command_line.options_dict();
+            let number = |key: &str| {
+                let number = options.lookup_value(key, None)?.get::<i32>()?;
+                Some(number).filter(|n| *n > 0).map(|n| n as usize)
+            };
+            let opened = OpenOptions {
+                mode: if options.contains(READ_ONLY_OPTION) {
+                    OpenMode::ReadOnly
+                } else {
+                    OpenMode::Normal
+                },
+                line: number(LINE_OPTION),
+                column: number(COLUMN_OPTION),
+                encoding: options
+                    .lookup_value(ENCODING_OPTION, None)
+                    .and_then(|v| v.get::<String>()),
+            };
+            // The first argument is the program.
+            let arguments = command_line.arguments();
+            let arguments = arguments.get(1..).unwrap_or(&[]);
+            let files: Vec<(Opening, OpenOptions)> = cli::file_args(arguments)
+                .into_iter()
+                .filter_map(|(arg, line)| {
+                    let options = OpenOptions {
+                        line: line.or(opened.line),
+                        ..opened.clone()
+                    };
+                    // Later launches pass their standard input along.
+                    let opening = if arg == cli::STDIN_ARG {
+                        Opening::Stream(command_line.stdin()?)
+                    } else {
+                        Opening::File(command_line.create_file_for_arg(&arg))
+                    };
+                    Some((opening, options))
+                })
+                .collect();
+            let new_window = options.contains(NEW_WINDOW_OPTION);
+            if files.is_empty() && !new_window {
+                app.activate();
+            } else {
+                app.open_files(&files, new_window);
             }
-            app.transmit(tx);
-
-            app.main_window().present();
-            let local_app = app.clone();
-
-            rx.attach(None, move |action| {
-                let changes = {
-                    let mut model = model_rc.borrow_mut();
-                    model.update(action)
-                };
-                local_app.update(&changes);
-                Continue(true)
-            });
+            0
         }
 
         fn startup(&self, app: &Self::Type) {
             debug!("GtkApplication<Application>::startup");
             self.parent_startup(app);
 
+            if let Err(e) = Settings::upgrade(&config_dir()) {
+                warn!("Failed to upgrade the settings, {}", e);
+            }
+
             // Set icons for shell
             gtk::Window::set_default_icon_name(APP_ID);
 
             app.setup_css();
             app.setup_gactions();
             app.setup_accels();
+            app.mark_startup("startup");
+        }
+
+        fn shutdown(&self, app: &Self::Type) {
+            debug!("GtkApplication<Application>::shutdown");
+            for instance in self.windows.borrow().iter() {
+                let model = instance.model.borrow();
+                model.save_position();
+                model.flush_note();
+            }
+            app.drain_writes();
+            self.parent_shutdown(app);
         }
     }
 
@@ -120,47 +267,701 @@ impl Default for Application {
 
 impl Application {
     pub fn new() -> Self {
-        glib::Object::new(&[
+        let app: Self = glib::Object::new(&[
             ("application-id", &Some(APP_ID)),
-            ("flags", &gio::ApplicationFlags::empty()),
+            (
+                "flags",
+                &(gio::ApplicationFlags::HANDLES_OPEN
+                    | gio::ApplicationFlags::HANDLES_COMMAND_LINE),
+            ),
             ("resource-base-path", &Some("/com/bernardigiri/TextEdit2/")),
         ])
-        .expect("Application initialization failed...")
+        .expect("Application initialization failed...");
+        app.add_main_option(
+            NEW_WINDOW_OPTION,
+            glib::Char::from(b'n'),
+            glib::OptionFlags::NONE,
+            glib::OptionArg::None,
+            &gettext("Open the files in a new window"),
+            None,
+        );
+        app.add_main_option(
+            READ_ONLY_OPTION,
+            glib::Char::from(b'r'),
+            glib::OptionFlags::NONE,
+            glib::OptionArg::None,
+            &gettext("Open the files without editing them"),
+            None,
+        );
+        app.add_main_option(
+            LINE_OPTION,
+            glib::Char::from(b'\0'),
+            glib::OptionFlags::NONE,
+            glib::OptionArg::Int,
+            &gettext("Put the cursor on this line, also given as +N before a file"),
+            Some("N"),
+        );
+        app.add_main_option(
+            COLUMN_OPTION,
+            glib::Char::from(b'\0'),
+            glib::OptionFlags::NONE,
+            glib::OptionArg::Int,
+            &gettext("Put the cursor at this column of the line"),
+            Some("N"),
+        );
+        app.add_main_option(
+            ENCODING_OPTION,
+            glib::Char::from(b'\0'),
+            glib::OptionFlags::NONE,
+            glib::OptionArg::String,
+            &gettext("Read the files in this character set, they are saved as UTF-8"),
+            Some("CHARSET"),
+        );
+        app
+    }
+
+    pub fn time_startup(&self, launched: Instant) {
+        let imp = imp::Application::from_instance(self);
+        imp.timing.replace(Some(Timing::new(launched)));
+    }
+
+    fn mark_startup(&self, stage: &'static str) {
+        let imp = imp::Application::from_instance(self);
+        if let Some(timing) = imp.timing.borrow_mut().as_mut() {
+            timing.mark(stage);
+        }
+    }
+
+    // The first window, and what is watched for as long as the application
+    // runs.
+    // Windows may all be closed while the tray icon keeps the application
+    // running, so only the first one sets it up.
+    fn start(&self) -> ApplicationWindow {
+        let imp = imp::Application::from_instance(self);
+        let first = !imp.started.replace(true);
+        if first {
+            self.watch_preferences();
+            self.watch_active_window();
+        }
+        let window = self.add_window();
+        if first {
+            self.mark_startup("window");
+            // Idle sources run after the window is drawn, so what is only
+            // needed later waits until typing is possible.
+            glib::idle_add_local(
+                clone!(@weak self as app => @default-return Continue(false), move || {
+                    app.finish_startup();
+                    Continue(false)
+                }),
+            );
+        }
+        window
+    }
+
+    fn finish_startup(&self) {
+        let imp = imp::Application::from_instance(self);
+        if let Some(timing) = imp.timing.borrow_mut().take() {
+            timing.ready();
+        }
+        self.offer_settings_import();
+        self.watch_tray();
+    }
+
+    // The tray icon holds the application, so it keeps running with its
+    // windows closed and notes stay a click away.
+    fn watch_tray(&self) {
+        let imp = imp::Application::from_instance(self);
+        self.update_tray();
+        imp.settings.connect_changed(
+            Some(TRAY_ICON),
+            clone!(@weak self as app => move |_, _| {
+                app.update_tray();
+            }),
+        );
+    }
+
+    fn update_tray(&self) {
+        let imp = imp::Application::from_instance(self);
+        let shown = imp.tray.borrow().is_some();
+        if !imp.settings.boolean(TRAY_ICON) {
+            self.hide_tray();
+            return;
+        }
+        if shown {
+            return;
+        }
+        let connection = match self.dbus_connection() {
+            Some(connection) => connection,
+            None => return info!("No session bus to show the tray icon on"),
+        };
+        let command = clone!(@weak self as app => move |command: tray::Command| {
+            app.run_tray_command(command);
+        });
+        let failed = clone!(@weak self as app => move || {
+            app.hide_tray();
+        });
+        match Tray::start(&connection, APP_ID, recent_documents, command, failed) {
+            Ok(tray) => {
+                imp.tray.replace(Some(tray));
+                self.hold();
+            }
+            Err(e) => warn!("Failed to show the tray icon, {}", e),
+        }
+    }
+
+    fn hide_tray(&self) {
+        let imp = imp::Application::from_instance(self);
+        let tray = imp.tray.borrow_mut().take();
+        if tray.is_some() {
+            drop(tray);
+            self.release();
+        }
+    }
+
+    fn run_tray_command(&self, command: tray::Command) {
+        match command {
+            tray::Command::ShowWindow => self.activate(),
+            tray::Command::NewNote => {
+                let imp = imp::Application::from_instance(self);
+                let window = if imp.windows.borrow().is_empty() {
+                    self.start()
+                } else {
+                    self.add_window()
+                };
+                window.present();
+            }
+            tray::Command::OpenRecent(path) => {
+                let file = gio::File::for_path(path);
+                self.open_files(&[(Opening::File(file), OpenOptions::default())], false);
+            }
+            tray::Command::Quit => self.activate_action("quit", None),
+        }
+    }
+
+    // Files from the command line or the file manager. A file that is open
+    // already has its window shown. Others take the place of an empty
+    // untitled document, or get a window of their own.
+    fn open_files(&self, files: &[(Opening, OpenOptions)], new_window: bool) {
+        let imp = imp::Application::from_instance(self);
+        let mut empty = if imp.windows.borrow().is_empty() {
+            Some(self.start())
+        } else if new_window {
+            Some(self.add_window())
+        } else {
+            self.main_window()
+                .filter(|window| self.is_empty_window(window))
+        };
+        for (opening, options) in files {
+            if let Opening::File(file) = opening {
+                if let Some(window) = self.window_with(file) {
+                    window.present();
+                    continue;
+                }
+            }
+            let window = match empty.take() {
+                Some(window) => window,
+                None => self.add_window(),
+            };
+            let model = match self.window_model(&window) {
+                Some(model) => model,
+                None => continue,
+            };
+            match opening {
+                Opening::File(file) => {
+                    let sent = model
+                        .borrow()
+                        .send(OpenFileWith(file.clone(), options.clone()));
+                    if let Err(e) = sent {
+                        warn!("{}", e);
+                        window.show_error(&e);
+                    }
+                }
+                Opening::Stream(stream) => {
+                    let model = Rc::downgrade(&model);
+                    FileSystem::read_stream(
+                        stream.clone(),
+                        options.encoding.clone(),
+                        &gio::Cancellable::new(),
+                        move |r| {
+                            if let Some(model) = model.upgrade() {
+                                if let Err(e) = model.borrow().send(StandardInputRead(r)) {
+                                    warn!("{}", e);
+                                }
+                            }
+                        },
+                    );
+                }
+            }
+            window.present();
+        }
+        if let Some(window) = self.main_window().filter(|_| files.is_empty()) {
+            window.present();
+        }
+    }
+
+    // Several files at once, chosen together or dropped on a window. Each
+    // gets a window of its own. Those that cannot be opened are reported
+    // together in the focused window, rather than each in a window.
+    pub fn open_batch(&self, files: Vec<gio::File>, mode: OpenMode) {
+        let app = self.downgrade();
+        MainContext::default().spawn_local(async move {
+            let mut openable = Vec::new();
+            let mut failures = Vec::new();
+            for file in files {
+                let info = file
+                    .query_info_async_future(
+                        "standard::type",
+                        gio::FileQueryInfoFlags::NONE,
+                        PRIORITY_DEFAULT,
+                    )
+                    .await;
+                match info {
+                    Ok(info) if info.file_type() == gio::FileType::Directory => {
+                        let e = Error::Folder(file.uri().to_string());
+                        failures.push((file, e));
+                    }
+                    Ok(_) => {
+                        let options = OpenOptions {
+                            mode,
+                            ..OpenOptions::default()
+                        };
+                        openable.push((Opening::File(file), options));
+                    }
+                    Err(e) => failures.push((file, e.into())),
+                }
+            }
+            let app = match app.upgrade() {
+                Some(app) => app,
+                None => return,
+            };
+            app.open_files(&openable, false);
+            if !failures.is_empty() {
+                for (file, e) in &failures {
+                    warn!("Failed to open {}, {}", file.uri(), e);
+                }
+                app.send(OpenFailures(failures));
+            }
+        });
+    }
+
+    fn is_empty_window(&self, window: &ApplicationWindow) -> bool {
+        self.window_model(window).is_some_and(|model| {
+            let model = model.borrow();
+            model.document().file().is_none() && !model.document().modified()
+        })
+    }
+
+    fn window_with(&self, file: &gio::File) -> Option<ApplicationWindow> {
+        let imp = imp::Application::from_instance(self);
+        let windows = imp.windows.borrow();
+        windows
+            .iter()
+            .find(|instance| matches!(instance.model.try_borrow(), Ok(m) if m.document().is_file(file)))
+            .and_then(|instance| instance.window.upgrade())
     }
 
-    fn transmit(&self, tx: Sender<Action>) {
-        let window = self.main_window();
+    // Each window has its own document and model, which talk over their
+    // own channel.
+    fn add_window(&self) -> ApplicationWindow {
+        let imp = imp::Application::from_instance(self);
+        let window = ApplicationWindow::new(self);
+        let model: Rc<RefCell<ApplicationModel>> = Rc::default();
+        let (tx, rx) = MainContext::channel(PRIORITY_DEFAULT);
+        model.borrow_mut().transmit(tx.clone());
+        model
+            .borrow_mut()
+            .join(imp.open_files.clone(), imp.jobs.clone());
         window.transmit(tx);
+        imp.windows.borrow_mut().push(imp::Instance {
+            window: window.downgrade(),
+            model: model.clone(),
+            discard: Cell::new(false),
+        });
+        window.connect_destroy(clone!(@weak self as app => move |window| {
+            app.remove_window(window);
+        }));
+        // The channel closes once the window and its model are gone.
+        let weak_model = Rc::downgrade(&model);
+        rx.attach(
+            None,
+            clone!(@weak self as app, @weak window => @default-return Continue(false), move |action| {
+                let model = match weak_model.upgrade() {
+                    Some(model) => model,
+                    None => return Continue(false),
+                };
+                let changes = model.borrow_mut().update(action);
+                app.update(&window, &changes);
+                Continue(true)
+            }),
+        );
+        glib::idle_add_local(
+            clone!(@weak self as app, @weak window => @default-return Continue(false), move || {
+                app.load_templates(&window);
+                Continue(false)
+            }),
+        );
+        self.update(
+            &window,
+            &Changes {
+                preferences: true,
+                ..Changes::default()
+            },
+        );
+        window.present();
+        window
+    }
+
+    fn remove_window(&self, window: &ApplicationWindow) {
+        let imp = imp::Application::from_instance(self);
+        let mut windows = imp.windows.borrow_mut();
+        let open =
+            |instance: &imp::Instance| matches!(instance.window.upgrade(), Some(w) if w != *window);
+        let mut discarded = imp.discarded.borrow_mut();
+        let count = discarded.len();
+        for instance in windows.iter().filter(|instance| !open(instance)) {
+            let model = instance.model.borrow();
+            model.save_position();
+            model.flush_note();
+            model.leave();
+            if instance.discard.get() {
+                discarded.extend(model.discardable_text());
+            }
+        }
+        windows.retain(open);
+        let offer = discarded.len() > count && !windows.is_empty();
+        if discarded.len() > DISCARDED_LIMIT {
+            discarded.remove(0);
+        }
+        drop(discarded);
+        drop(windows);
+        if let Some(window) = self.main_window().filter(|_| offer) {
+            window.offer_undo_close();
+        }
+    }
+
+    // The most recently discarded document comes back in a window of its own.
+    fn undo_close(&self) {
+        let imp = imp::Application::from_instance(self);
+        let text = match imp.discarded.borrow_mut().pop() {
+            Some(text) => text,
+            None => return,
+        };
+        let window = self.add_window();
+        if let Some(model) = self.window_model(&window) {
+            if let Err(e) = model.borrow().send(RestoreDiscarded(text)) {
+                warn!("{}", e);
+            }
+        }
+    }
+
+    fn watch_preferences(&self) {
+        let imp = imp::Application::from_instance(self);
+        imp.settings.connect_changed(
+            None,
+            clone!(@weak self as app => move |_, key| {
+                debug!("GtkApplication<Application>::watch_preferences {}", key);
+                let imp = imp::Application::from_instance(&app);
+                for instance in imp.windows.borrow().iter() {
+                    // The model itself may be writing the setting.
+                    if let Ok(model) = instance.model.try_borrow() {
+                        if let Err(e) = model.send(PreferencesChanged) {
+                            warn!("{}", e);
+                        }
+                    }
+                }
+            }),
+        );
+    }
+
+    // The application's actions follow the focused window's document.
+    fn watch_active_window(&self) {
+        self.connect_notify_local(Some("active-window"), |app, _| {
+            let window = match app.active_window() {
+                Some(window) => window,
+                None => return,
+            };
+            let window = match window.downcast::<ApplicationWindow>() {
+                Ok(window) => window,
+                Err(_) => return,
+            };
+            if let Some(model) = app.window_model(&window) {
+                if let Ok(model) = model.try_borrow() {
+                    app.update_actions(&model);
+                }
+            }
+        });
+    }
+
+    // Reopens the last document when the preferences ask for it. Files
+    // chosen through the document portal keep their path, so these work
+    // inside the sandbox too.
+    fn restore_session(&self) {
+        if !Settings::new().restore_session() {
+            return;
+        }
+        let imp = imp::Application::from_instance(self);
+        let path = imp.settings.string(LAST_DOCUMENT);
+        if !path.is_empty() && Path::new(path.as_str()).is_file() {
+            self.send(OpenFile(Some(gio::File::for_path(path.as_str()))));
+        }
     }
 
-    fn update(&self, changes: &Changes) {
+    fn update(&self, window: &ApplicationWindow, changes: &Changes) {
         debug!("GtkApplication<Application>::update");
-        let model_ref = self.model();
+        let model_ref = match self.window_model(window) {
+            Some(model) => model,
+            None => return,
+        };
         let model = model_ref.borrow();
-        let window = self.main_window();
-        let imp = imp::Application::from_instance(self);
-        imp.undo_action.set_enabled(window.can_undo());
-        imp.redo_action.set_enabled(window.can_redo());
+        if self.main_window().as_ref() == Some(window) {
+            self.update_actions(&model);
+        }
         window.update(&model, changes);
+        if changes.open_elsewhere {
+            self.present_model(model.open_elsewhere());
+        }
+    }
+
+    fn present_model(&self, id: Option<usize>) {
+        let imp = imp::Application::from_instance(self);
+        let window = imp
+            .windows
+            .borrow()
+            .iter()
+            .find(|instance| matches!(instance.model.try_borrow(), Ok(m) if Some(m.id()) == id))
+            .and_then(|instance| instance.window.upgrade());
+        if let Some(window) = window {
+            window.present();
+        }
+    }
+
+    fn update_actions(&self, model: &ApplicationModel) {
+        let imp = imp::Application::from_instance(self);
+        for (name, enabled) in model.capabilities().iter() {
+            let action = self
+                .lookup_action(name)
+                .and_then(|a| a.downcast::<gio::SimpleAction>().ok());
+            if let Some(action) = action {
+                action.set_enabled(*enabled);
+            }
+        }
+        let auto_reload = model.auto_reload_file();
+        imp.auto_reload_file_action
+            .set_state(&auto_reload.unwrap_or(false).to_variant());
+        let label = model.document_label();
+        imp.document_label_action
+            .set_state(&label.unwrap_or_default().to_variant());
+    }
+
+    // The focused window's model.
+    fn model(&self) -> Option<Rc<RefCell<ApplicationModel>>> {
+        self.window_model(&self.main_window()?)
+    }
+
+    fn window_model(&self, window: &ApplicationWindow) -> Option<Rc<RefCell<ApplicationModel>>> {
+        let imp = imp::Application::from_instance(self);
+        let windows = imp.windows.borrow();
+        windows
+            .iter()
+            .find(|instance| instance.window.upgrade().as_ref() == Some(window))
+            .map(|instance| instance.model.clone())
+    }
+
+    // Failures are shown in the window rather than aborting.
+    fn send(&self, action: Action) {
+        let model = match self.model() {
+            Some(model) => model,
+            None => return,
+        };
+        let sent = model.borrow().send(action);
+        if let Err(e) = sent {
+            warn!("{}", e);
+            self.show_error(&e);
+        }
+    }
+
+    // In the focused window, or only logged once every window is closed.
+    fn show_error(&self, e: &Error) {
+        if let Some(window) = self.main_window() {
+            window.show_error(e);
+        }
+    }
+
+    // The focused document window, or the one focused last. Windows are
+    // listed most recently focused first. There is none once every window
+    // is closed and the tray icon keeps the application running.
+    fn main_window(&self) -> Option<ApplicationWindow> {
+        self.windows()
+            .into_iter()
+            .find_map(|window| window.downcast::<ApplicationWindow>().ok())
+    }
+
+    // Closing a window with unsaved changes asks first, and closes it again
+    // once they may be lost.
+    pub fn may_close(&self, window: &ApplicationWindow) -> bool {
+        let unsaved = self.unsaved_windows(Some(window));
+        if unsaved.is_empty() {
+            return true;
+        }
+        let window = window.downgrade();
+        self.confirm_discard(
+            unsaved,
+            Box::new(move |_: &Self| {
+                if let Some(window) = window.upgrade() {
+                    window.close();
+                }
+            }),
+        );
+        false
+    }
+
+    fn unsaved_windows(&self, only: Option<&ApplicationWindow>) -> Vec<ApplicationWindow> {
+        let imp = imp::Application::from_instance(self);
+        let windows = imp.windows.borrow();
+        windows
+            .iter()
+            .filter(|instance| !instance.discard.get() && instance.model.borrow().unsaved())
+            .filter_map(|instance| instance.window.upgrade())
+            .filter(|window| only.is_none_or(|only| only == window))
+            .collect()
+    }
+
+    // Asks about each window's changes in turn, then calls `done` once all
+    // of them may be lost. Cancelling keeps every window as it was.
+    fn confirm_discard(&self, mut unsaved: Vec<ApplicationWindow>, done: Box<dyn FnOnce(&Self)>) {
+        let window = match unsaved.pop() {
+            Some(window) => window,
+            None => return done(self),
+        };
+        let name = self
+            .window_model(&window)
+            .and_then(|model| model.borrow().document().filename())
+            .unwrap_or_else(|| gettext("Untitled"));
+        window.present();
+        let dialog = gtk::MessageDialog::new(
+            Some(&window),
+            gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+            gtk::MessageType::Question,
+            gtk::ButtonsType::None,
+            &format!("{}: \"{}\"", gettext("Discard unsaved changes"), name),
+        );
+        dialog.set_secondary_text(Some(&gettext(
+            "The changes to this document will be lost if it is closed without saving.",
+        )));
+        dialog.add_button(&gettext("Cancel"), gtk::ResponseType::Cancel);
+        dialog.add_button(&gettext("Discard"), gtk::ResponseType::Accept);
+        if let Some(button) = dialog.widget_for_response(gtk::ResponseType::Accept) {
+            button.add_css_class("destructive-action");
+        }
+        let done = RefCell::new(Some(done));
+        dialog.connect_response(
+            clone!(@weak self as app, @weak window => move |d, response| {
+                d.close();
+                let imp = imp::Application::from_instance(&app);
+                let discard = response == gtk::ResponseType::Accept;
+                for instance in imp.windows.borrow().iter() {
+                    if !discard || instance.window.upgrade().as_ref() == Some(&window) {
+                        instance.discard.set(discard);
+                    }
+                }
+                if let (true, Some(done)) = (discard, done.borrow_mut().take()) {
+                    app.confirm_discard(unsaved.clone(), done);
+                }
+            }),
+        );
+        dialog.show();
     }
 
-    fn model(&self) -> Rc<RefCell<ApplicationModel>> {
+    // Quitting waits for the files still being written, with the windows
+    // still open to show how many are left. It gives up after a while, or
+    // when asked to.
+    fn finish_writes(&self, done: Box<dyn FnOnce(&Self)>) {
         let imp = imp::Application::from_instance(self);
-        imp.model.clone()
+        let count = imp.jobs.count();
+        if count == 0 {
+            return done(self);
+        }
+        let dialog = gtk::MessageDialog::new(
+            self.main_window().as_ref(),
+            gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+            gtk::MessageType::Info,
+            gtk::ButtonsType::None,
+            &gettext("Finishing saving files"),
+        );
+        let left = |count: usize| format!("{}: {}", gettext("Files left to write"), count);
+        dialog.set_secondary_text(Some(&left(count)));
+        let progress = gtk::ProgressBar::new();
+        if let Ok(area) = dialog.message_area().downcast::<gtk::Box>() {
+            area.append(&progress);
+        }
+        dialog.add_button(&gettext("Quit Now"), gtk::ResponseType::Close);
+        let done = Rc::new(RefCell::new(Some(done)));
+        dialog.connect_response(clone!(@weak self as app, @strong done => move |d, _| {
+            d.close();
+            let done = done.borrow_mut().take();
+            if let Some(done) = done {
+                done(&app);
+            }
+        }));
+        let started = Instant::now();
+        glib::timeout_add_local(
+            WRITE_POLL,
+            clone!(@weak self as app, @weak dialog => @default-return Continue(false), move || {
+                if done.borrow().is_none() {
+                    return Continue(false);
+                }
+                let imp = imp::Application::from_instance(&app);
+                let count = imp.jobs.count();
+                if count > 0 && started.elapsed() < WRITE_TIMEOUT {
+                    dialog.set_secondary_text(Some(&left(count)));
+                    progress.pulse();
+                    return Continue(true);
+                }
+                if count > 0 {
+                    warn!("Quitting with {} files still being written", count);
+                }
+                dialog.close();
+                let done = done.borrow_mut().take();
+                if let Some(done) = done {
+                    done(&app);
+                }
+                Continue(false)
+            }),
+        );
+        dialog.show();
     }
 
-    fn main_window(&self) -> ApplicationWindow {
+    // Writes may still be in flight once the last window has closed, with
+    // nothing left to show them in, so the main context runs here until
+    // they finish.
+    fn drain_writes(&self) {
         let imp = imp::Application::from_instance(self);
-        imp.window.get().unwrap().upgrade().unwrap()
+        let context = glib::MainContext::default();
+        let started = Instant::now();
+        while imp.jobs.count() > 0 && started.elapsed() < WRITE_TIMEOUT {
+            if !context.iteration(false) {
+                thread::sleep(WRITE_POLL / 10);
+            }
+        }
+        if imp.jobs.count() > 0 {
+            warn!("Quit with {} files still being written", imp.jobs.count());
+        }
     }
 
     fn setup_gactions(&self) {
         // Quit
         let action = gio::SimpleAction::new("quit", None);
         action.connect_activate(clone!(@weak self as app => move |_, _| {
-            // This is needed to trigger the delete event and saving the window state
-            app.main_window().close();
-            app.quit();
+            let unsaved = app.unsaved_windows(None);
+            app.confirm_discard(unsaved, Box::new(|app: &Application| {
+                app.finish_writes(Box::new(|app: &Application| {
+                    // This is needed to trigger the delete event and saving the window state
+                    for window in app.windows() {
+                        window.close();
+                    }
+                    app.quit();
+                }));
+            }));
         }));
         self.add_action(&action);
 
@@ -171,6 +972,41 @@ impl Application {
         }));
         self.add_action(&action);
 
+        // Preferences
+        let action = gio::SimpleAction::new("preferences", None);
+        action.connect_activate(clone!(@weak self as app => move |_, _| {
+            app.show_preferences();
+        }));
+        self.add_action(&action);
+
+        // Cancel Open
+        let action = gio::SimpleAction::new("cancel-open", None);
+        action.connect_activate(clone!(@weak self as app => move |_, _| {
+            app.cancel_open();
+        }));
+        self.add_action(&action);
+
+        // Cancel Save
+        let action = gio::SimpleAction::new("cancel-save", None);
+        action.connect_activate(clone!(@weak self as app => move |_, _| {
+            app.cancel_save();
+        }));
+        self.add_action(&action);
+
+        // Export Settings
+        let action = gio::SimpleAction::new("export-settings", None);
+        action.connect_activate(clone!(@weak self as app => move |_, _| {
+            app.export_settings();
+        }));
+        self.add_action(&action);
+
+        // Import Settings
+        let action = gio::SimpleAction::new("import-settings", None);
+        action.connect_activate(clone!(@weak self as app => move |_, _| {
+            app.import_settings();
+        }));
+        self.add_action(&action);
+
         // Save
         let action = gio::SimpleAction::new("save", None);
         action.connect_activate(clone!(@weak self as app => move |_, _| {
@@ -185,10 +1021,69 @@ impl Application {
         }));
         self.add_action(&action);
 
+        // Save a Copy
+        let action = gio::SimpleAction::new("save-copy", None);
+        action.connect_activate(clone!(@weak self as app => move |_, _| {
+            app.save_copy();
+        }));
+        self.add_action(&action);
+
+        // Export
+        let action = gio::SimpleAction::new("export-html", None);
+        action.connect_activate(clone!(@weak self as app => move |_, _| {
+            app.export_file(ExportFormat::Html);
+        }));
+        self.add_action(&action);
+        let action = gio::SimpleAction::new("export-annotated", None);
+        action.connect_activate(clone!(@weak self as app => move |_, _| {
+            app.export_file(ExportFormat::Annotated);
+        }));
+        self.add_action(&action);
+        let action = gio::SimpleAction::new("export-side-by-side", None);
+        action.connect_activate(clone!(@weak self as app => move |_, _| {
+            app.export_file(ExportFormat::SideBySide);
+        }));
+        self.add_action(&action);
+        let action = gio::SimpleAction::new("export-patch", None);
+        action.connect_activate(clone!(@weak self as app => move |_, _| {
+            app.export_file(ExportFormat::Patch);
+        }));
+        self.add_action(&action);
+        let action = gio::SimpleAction::new("export-pdf", None);
+        action.connect_activate(clone!(@weak self as app => move |_, _| {
+            app.export_file(ExportFormat::Pdf);
+        }));
+        self.add_action(&action);
+
         // Open
         let action = gio::SimpleAction::new("open", None);
         action.connect_activate(clone!(@weak self as app => move |_, _| {
-            app.open_file();
+            app.open_file(OpenMode::Normal);
+        }));
+        self.add_action(&action);
+        let action = gio::SimpleAction::new("open-read-only", None);
+        action.connect_activate(clone!(@weak self as app => move |_, _| {
+            app.open_file(OpenMode::ReadOnly);
+        }));
+        self.add_action(&action);
+        // pkexec cannot be reached from inside the sandbox, so the menu
+        // hides this there.
+        let action = gio::SimpleAction::new("open-elevated", None);
+        action.connect_activate(clone!(@weak self as app => move |_, _| {
+            app.open_file(OpenMode::Elevated);
+        }));
+        action.set_enabled(!sandbox::is_sandboxed());
+        self.add_action(&action);
+
+        let action = gio::SimpleAction::new("undo-close", None);
+        action.connect_activate(clone!(@weak self as app => move |_, _| {
+            app.undo_close();
+        }));
+        self.add_action(&action);
+
+        let action = gio::SimpleAction::new("reopen-closed", None);
+        action.connect_activate(clone!(@weak self as app => move |_, _| {
+            app.send(ReopenClosed);
         }));
         self.add_action(&action);
 
@@ -199,33 +1094,214 @@ impl Application {
         }));
         self.add_action(&action);
 
-        // Toggle actions
-        {
-            let imp = imp::Application::from_instance(self);
-            // Undo
-            let action = &imp.undo_action;
-            action.connect_activate(clone!(@weak self as app => move |_, _| {
-                app.undo();
-            }));
-            self.add_action(action);
+        // New Window
+        let action = gio::SimpleAction::new("new-window", None);
+        action.connect_activate(clone!(@weak self as app => move |_, _| {
+            debug!("GtkApplication<Application>::new_window");
+            app.add_window();
+        }));
+        self.add_action(&action);
 
-            // Redo
-            let action = &imp.redo_action;
-            action.connect_activate(clone!(@weak self as app => move |_, _| {
-                app.redo();
-            }));
-            self.add_action(action);
-        }
-    }
+        // New from Template
+        let action =
+            gio::SimpleAction::new("new-from-template", Some(&String::static_variant_type()));
+        action.connect_activate(clone!(@weak self as app => move |_, parameter| {
+            if let Some(path) = parameter.and_then(|p| p.get::<String>()) {
+                app.new_from_template(PathBuf::from(path));
+            }
+        }));
+        self.add_action(&action);
+        let action = gio::SimpleAction::new("open-templates-folder", None);
+        action.connect_activate(clone!(@weak self as app => move |_, _| {
+            app.open_templates_folder();
+        }));
+        self.add_action(&action);
 
-    // Sets up keyboard shortcuts
-    fn setup_accels(&self) {
-        self.set_accels_for_action("app.new", &["<primary>n"]);
-        self.set_accels_for_action("app.open", &["<primary>o"]);
-        self.set_accels_for_action("app.quit", &["<primary>q"]);
-        self.set_accels_for_action("app.redo", &["<primary><shift>z"]);
-        self.set_accels_for_action("app.save", &["<primary>s"]);
-        self.set_accels_for_action("app.undo", &["<primary>z"]);
+        // Word Frequency
+        let action = gio::SimpleAction::new("word-frequency", None);
+        action.connect_activate(clone!(@weak self as app => move |_, _| {
+            app.word_frequency();
+        }));
+        self.add_action(&action);
+
+        // Find in Files
+        let action = gio::SimpleAction::new("find-in-files", None);
+        action.connect_activate(clone!(@weak self as app => move |_, _| {
+            let query = app.model().map(|model| model.borrow().search_query().clone());
+            app.find_in_files(&query.unwrap_or_default());
+        }));
+        self.add_action(&action);
+
+        // Find Duplicates
+        let action = gio::SimpleAction::new("find-duplicates", None);
+        action.connect_activate(clone!(@weak self as app => move |_, _| {
+            app.find_duplicates();
+        }));
+        self.add_action(&action);
+
+        // Edit actions. The window keeps them enabled only when they apply.
+        // They have no accelerators, as the text view and entries already
+        // handle the usual keys themselves.
+        let action = gio::SimpleAction::new("cut", None);
+        action.connect_activate(clone!(@weak self as app => move |_, _| {
+            if let Some(window) = app.main_window() {
+                window.cut();
+            }
+        }));
+        self.add_action(&action);
+
+        let action = gio::SimpleAction::new("copy", None);
+        action.connect_activate(clone!(@weak self as app => move |_, _| {
+            if let Some(window) = app.main_window() {
+                window.copy();
+            }
+        }));
+        self.add_action(&action);
+
+        let action = gio::SimpleAction::new("paste", None);
+        action.connect_activate(clone!(@weak self as app => move |_, _| {
+            if let Some(window) = app.main_window() {
+                window.paste();
+            }
+        }));
+        self.add_action(&action);
+
+        let action = gio::SimpleAction::new("delete", None);
+        action.connect_activate(clone!(@weak self as app => move |_, _| {
+            if let Some(window) = app.main_window() {
+                window.delete_selection();
+            }
+        }));
+        self.add_action(&action);
+
+        let action = gio::SimpleAction::new("select-all", None);
+        action.connect_activate(clone!(@weak self as app => move |_, _| {
+            if let Some(window) = app.main_window() {
+                window.select_all();
+            }
+        }));
+        self.add_action(&action);
+
+        // Insert Date and Time
+        let action = gio::SimpleAction::new("insert-datetime", None);
+        action.connect_activate(clone!(@weak self as app => move |_, _| {
+            if let Some(window) = app.main_window() {
+                window.insert_datetime();
+            }
+        }));
+        self.add_action(&action);
+
+        // Date and Time Format
+        let action = gio::SimpleAction::new("datetime-format", None);
+        action.connect_activate(clone!(@weak self as app => move |_, _| {
+            if let Some(window) = app.main_window() {
+                window.show_datetime_format_dialog();
+            }
+        }));
+        self.add_action(&action);
+
+        let imp = imp::Application::from_instance(self);
+        let settings = &imp.settings;
+
+        // Style
+        self.add_action(&settings.create_action("color-scheme"));
+
+        // Create Backup Files
+        self.add_action(&settings.create_action("create-backup"));
+
+        // Auto-Reload Unmodified Files
+        self.add_action(&settings.create_action("auto-reload"));
+
+        // Undo
+        let action = gio::SimpleAction::new("undo", None);
+        action.connect_activate(clone!(@weak self as app => move |_, _| {
+            app.undo();
+        }));
+        self.add_action(&action);
+
+        // Redo
+        let action = gio::SimpleAction::new("redo", None);
+        action.connect_activate(clone!(@weak self as app => move |_, _| {
+            app.redo();
+        }));
+        self.add_action(&action);
+
+        // Revert
+        let action = gio::SimpleAction::new("revert", None);
+        action.connect_activate(clone!(@weak self as app => move |_, _| {
+            app.revert();
+        }));
+        self.add_action(&action);
+
+        // Open Containing Folder
+        let action = gio::SimpleAction::new("open-containing-folder", None);
+        action.connect_activate(clone!(@weak self as app => move |_, _| {
+            app.open_containing_folder();
+        }));
+        self.add_action(&action);
+
+        // Copy File Path
+        let action = gio::SimpleAction::new("copy-file-path", None);
+        action.connect_activate(clone!(@weak self as app => move |_, _| {
+            app.copy_file_path();
+        }));
+        self.add_action(&action);
+
+        // Toggle actions
+        {
+            // Auto-Reload This File
+            let action = &imp.auto_reload_file_action;
+            action.connect_activate(clone!(@weak self as app => move |action, _| {
+                let enabled = action.state().and_then(|s| s.get::<bool>()).unwrap_or(false);
+                app.set_auto_reload(!enabled);
+            }));
+            self.add_action(action);
+
+            // Color Label
+            let action = &imp.document_label_action;
+            action.connect_activate(clone!(@weak self as app => move |_, parameter| {
+                let label = parameter.and_then(|p| p.get::<String>()).unwrap_or_default();
+                debug!("GtkApplication<Application>::set_document_label {}", label);
+                app.send(SetDocumentLabel(label));
+            }));
+            self.add_action(action);
+        }
+    }
+
+    // Sets up keyboard shortcuts
+    fn setup_accels(&self) {
+        self.set_accels_for_action("app.find-in-files", &["<primary><shift>f"]);
+        self.set_accels_for_action("app.insert-datetime", &["F5"]);
+        self.set_accels_for_action("app.new", &["<primary>n"]);
+        self.set_accels_for_action("app.new-window", &["<primary><shift>n"]);
+        self.set_accels_for_action("app.open", &["<primary>o"]);
+        self.set_accels_for_action("app.reopen-closed", &["<primary><shift>t"]);
+        self.set_accels_for_action("app.preferences", &["<primary>comma"]);
+        self.set_accels_for_action("app.quit", &["<primary>q"]);
+        self.set_accels_for_action("app.redo", &["<primary><shift>z"]);
+        self.set_accels_for_action("app.save", &["<primary>s"]);
+        self.set_accels_for_action("app.undo", &["<primary>z"]);
+        self.set_accels_for_action("win.duplicate", &["<primary><shift>d"]);
+        self.set_accels_for_action("win.fullscreen", &["F11"]);
+        self.set_accels_for_action("win.join-lines", &["<primary>j"]);
+        // Alt+Up and Alt+Down expand and shrink the selection, as in other
+        // editors, so moving lines takes Shift as well.
+        self.set_accels_for_action("win.move-lines-down", &["<alt><shift>Down"]);
+        self.set_accels_for_action("win.move-lines-up", &["<alt><shift>Up"]);
+        self.set_accels_for_action("win.expand-selection", &["<alt>Up"]);
+        self.set_accels_for_action("win.shrink-selection", &["<alt>Down"]);
+        self.set_accels_for_action("win.reading-mode", &["F9"]);
+        self.set_accels_for_action("win.toggle-comment", &["<primary>slash"]);
+        self.set_accels_for_action("win.record-macro", &["<primary><shift>m"]);
+        self.set_accels_for_action("win.play-macro", &["<primary>m"]);
+        self.set_accels_for_action("win.show-menu", &["F10"]);
+        self.set_accels_for_action("win.next-match", &["F8"]);
+        self.set_accels_for_action("win.previous-match", &["<shift>F8"]);
+        for transform in Registry::builtin().iter() {
+            if let Some(accel) = transform.accel() {
+                self.set_accels_for_action(&format!("win.{}", transform.id()), &[accel]);
+            }
+        }
     }
 
     fn setup_css(&self) {
@@ -238,6 +1314,70 @@ impl Application {
                 gtk::STYLE_PROVIDER_PRIORITY_APPLICATION,
             );
         }
+
+        let imp = imp::Application::from_instance(self);
+        self.apply_color_scheme();
+        imp.settings.connect_changed(
+            Some("color-scheme"),
+            clone!(@weak self as app => move |_, _| {
+                app.apply_color_scheme();
+            }),
+        );
+        if let Some(desktop_settings) = &imp.desktop_settings {
+            desktop_settings.connect_changed(
+                Some("color-scheme"),
+                clone!(@weak self as app => move |_, _| {
+                    app.apply_color_scheme();
+                }),
+            );
+        }
+    }
+
+    // The desktop color scheme is only available on GNOME 42 and later.
+    fn desktop_settings() -> Option<gio::Settings> {
+        let schema = gio::SettingsSchemaSource::default()?.lookup(DESKTOP_SCHEMA, true)?;
+        if schema.has_key("color-scheme") {
+            Some(gio::Settings::new(DESKTOP_SCHEMA))
+        } else {
+            None
+        }
+    }
+
+    fn apply_color_scheme(&self) {
+        let imp = imp::Application::from_instance(self);
+        let dark = match imp.settings.string("color-scheme").as_str() {
+            "dark" => true,
+            "light" => false,
+            _ => match &imp.desktop_settings {
+                Some(desktop_settings) => {
+                    desktop_settings.string("color-scheme").as_str() == "prefer-dark"
+                }
+                None => false,
+            },
+        };
+        debug!(
+            "GtkApplication<Application>::apply_color_scheme dark {}",
+            dark
+        );
+        if let Some(settings) = gtk::Settings::default() {
+            settings.set_gtk_application_prefer_dark_theme(dark);
+        }
+        // Most never use the dark style, so it is only read when needed.
+        if dark && !imp.dark_loaded.replace(true) {
+            imp.dark_provider
+                .load_from_resource("/com/bernardigiri/TextEdit2/style-dark.css");
+        }
+        if let Some(display) = gdk::Display::default() {
+            if dark {
+                gtk::StyleContext::add_provider_for_display(
+                    &display,
+                    &imp.dark_provider,
+                    gtk::STYLE_PROVIDER_PRIORITY_APPLICATION,
+                );
+            } else {
+                gtk::StyleContext::remove_provider_for_display(&display, &imp.dark_provider);
+            }
+        }
     }
 
     fn show_about_dialog(&self) {
@@ -250,117 +1390,931 @@ impl Application {
             .license_type(gtk::License::MitX11)
             .website("https://github.com/BernardIgiri/TextEdit2")
             .version(VERSION)
-            .transient_for(&self.main_window())
             .translator_credits(&gettext("translator-credits"))
             .modal(true)
             .authors(vec!["Bernard Igiri".into()])
             .artists(vec!["Bernard Igiri".into()])
             .build();
+        dialog.set_transient_for(self.main_window().as_ref());
 
         dialog.show();
     }
 
+    // Asks once whether to take over the preferences of another editor.
+    fn offer_settings_import(&self) {
+        let imp = imp::Application::from_instance(self);
+        if imp.settings.boolean("settings-imported") {
+            return;
+        }
+        let found = import_editor::detect();
+        let imported = match found.into_iter().next() {
+            Some(imported) => imported,
+            None => {
+                imp.settings.set_boolean("settings-imported", true).ok();
+                return;
+            }
+        };
+        let dialog = gtk::MessageDialog::new(
+            self.main_window().as_ref(),
+            gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+            gtk::MessageType::Question,
+            gtk::ButtonsType::None,
+            &format!("{} {}?", gettext("Import settings from"), imported.editor),
+        );
+        dialog.set_secondary_text(Some(&Self::import_summary(&imported)));
+        dialog.add_button(&gettext("Don't Import"), gtk::ResponseType::Reject);
+        dialog.add_button(&gettext("Import"), gtk::ResponseType::Accept);
+        dialog.set_default_response(gtk::ResponseType::Accept);
+        dialog.connect_response(clone!(@weak self as app => move |d, response| {
+            let imp = imp::Application::from_instance(&app);
+            if response == gtk::ResponseType::Accept {
+                if let Err(e) = imported.apply(&imp.settings) {
+                    log::warn!("Failed to import settings, {}", e);
+                }
+            }
+            if response != gtk::ResponseType::DeleteEvent {
+                imp.settings.set_boolean("settings-imported", true).ok();
+            }
+            d.close();
+        }));
+        dialog.show();
+    }
+
+    fn import_summary(imported: &EditorSettings) -> String {
+        let mut lines = Vec::new();
+        if let Some(font) = &imported.font {
+            lines.push(format!("{}: {}", gettext("Font"), font));
+        }
+        if let Some(wrap_text) = imported.wrap_text {
+            let value = if wrap_text {
+                gettext("On")
+            } else {
+                gettext("Off")
+            };
+            lines.push(format!("{}: {}", gettext("Wrap text"), value));
+        }
+        if let Some(tab_width) = imported.tab_width {
+            lines.push(format!("{}: {}", gettext("Tab width"), tab_width));
+        }
+        if let Some(color_scheme) = imported.color_scheme {
+            let value = match color_scheme {
+                "light" => gettext("Light"),
+                "dark" => gettext("Dark"),
+                _ => gettext("Follow System"),
+            };
+            lines.push(format!("{}: {}", gettext("Style"), value));
+        }
+        lines.join("\n")
+    }
+
+    fn settings_file_chooser(
+        &self,
+        title: &str,
+        action: gtk::FileChooserAction,
+    ) -> gtk::FileChooserNative {
+        let accept = match action {
+            gtk::FileChooserAction::Save => gettext("Export"),
+            _ => gettext("Import"),
+        };
+        let file_chooser = self.file_chooser(title, action, &accept);
+        let filter = gtk::FileFilter::new();
+        filter.add_pattern("*.toml");
+        filter.set_name(Some(&gettext("Settings Files")));
+        file_chooser.add_filter(&filter);
+        file_chooser
+    }
+
+    fn show_message(&self, message_type: gtk::MessageType, text: &str, secondary: &str) {
+        let dialog = gtk::MessageDialog::new(
+            self.main_window().as_ref(),
+            gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+            message_type,
+            gtk::ButtonsType::Close,
+            text,
+        );
+        dialog.set_secondary_text(Some(secondary));
+        dialog.connect_response(|d, _| d.close());
+        dialog.show();
+    }
+
+    fn export_settings(&self) {
+        debug!("GtkApplication<Application>::export_settings");
+        let file_chooser =
+            self.settings_file_chooser(&gettext("Export Settings"), gtk::FileChooserAction::Save);
+        file_chooser.set_current_name("textedit2-settings.toml");
+        self.run_local_file_chooser(file_chooser, |app, path| {
+            let imp = imp::Application::from_instance(app);
+            let archive = settings_archive::export(&imp.settings, &config_dir());
+            let result = settings_archive::to_string(&archive)
+                .map_err(|e| e.to_string())
+                .and_then(|text| std::fs::write(&path, text).map_err(|e| e.to_string()));
+            if let Err(e) = result {
+                app.show_message(
+                    gtk::MessageType::Error,
+                    &gettext("Could not export settings"),
+                    &e,
+                );
+            }
+        });
+    }
+
+    fn import_settings(&self) {
+        debug!("GtkApplication<Application>::import_settings");
+        let file_chooser =
+            self.settings_file_chooser(&gettext("Import Settings"), gtk::FileChooserAction::Open);
+        self.run_local_file_chooser(file_chooser, |app, path| {
+            app.confirm_settings_import(&path);
+        });
+    }
+
+    // Lists what would change and asks before replacing anything.
+    fn confirm_settings_import(&self, path: &Path) {
+        let imp = imp::Application::from_instance(self);
+        let archive = match std::fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| settings_archive::parse(&text))
+        {
+            Ok(archive) => archive,
+            Err(e) => {
+                self.show_message(
+                    gtk::MessageType::Error,
+                    &gettext("Could not import settings"),
+                    &e,
+                );
+                return;
+            }
+        };
+        let plan = settings_archive::plan(&imp.settings, &config_dir(), &archive);
+        let mut summary = vec![format!(
+            "{}: {}",
+            gettext("Settings and files to import"),
+            plan.count()
+        )];
+        if !plan.conflicts.is_empty() {
+            summary.push(format!(
+                "{}: {}",
+                gettext("Replaces your changes to"),
+                plan.conflicts.join(", ")
+            ));
+        }
+        if !plan.invalid.is_empty() {
+            let skipped: Vec<String> = plan
+                .invalid
+                .iter()
+                .map(|(name, reason)| format!("{} ({})", name, reason))
+                .collect();
+            summary.push(format!("{}: {}", gettext("Skipped"), skipped.join(", ")));
+        }
+        let dialog = gtk::MessageDialog::new(
+            self.main_window().as_ref(),
+            gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+            gtk::MessageType::Question,
+            gtk::ButtonsType::None,
+            &gettext("Import settings?"),
+        );
+        dialog.set_secondary_text(Some(&summary.join("\n\n")));
+        dialog.add_button(&gettext("Cancel"), gtk::ResponseType::Cancel);
+        dialog.add_button(&gettext("Import"), gtk::ResponseType::Accept);
+        dialog.set_response_sensitive(gtk::ResponseType::Accept, plan.count() > 0);
+        dialog.connect_response(clone!(@weak self as app => move |d, response| {
+            d.close();
+            if response == gtk::ResponseType::Accept {
+                let imp = imp::Application::from_instance(&app);
+                if let Err(e) = settings_archive::apply(&imp.settings, &plan) {
+                    app.show_message(
+                        gtk::MessageType::Error,
+                        &gettext("Could not import settings"),
+                        &e,
+                    );
+                }
+            }
+        }));
+        dialog.show();
+    }
+
+    fn show_preferences(&self) {
+        let (window, model) = match (self.main_window(), self.model()) {
+            (Some(window), Some(model)) => (window, model),
+            _ => return,
+        };
+        let preferences = PreferencesWindow::new(window.upcast_ref());
+        preferences.set_project(model.borrow().project());
+        preferences.show();
+    }
+
     fn save_file(&self) {
         debug!("GtkApplication<Application>::save_file");
-        let model_rc = self.model();
-        let model = model_rc.borrow_mut();
-        match model.document().filepath() {
+        let model = match self.model() {
+            Some(model) => model,
+            None => return,
+        };
+        let file = model.borrow().document().file();
+        match file {
             None => {
                 self.save_file_as();
             }
-            Some(path) => {
-                model.send(SaveFile(path));
+            Some(file) => {
+                self.send(SaveFile(file));
             }
         }
     }
 
-    fn add_file_chooser_filters(file_chooser: &gtk::FileChooserDialog) {
-        let filter = gtk::FileFilter::new();
-        filter.add_mime_type("text/plain");
-        filter.set_name(Some(&gettext("Text Files")));
-        file_chooser.add_filter(&filter);
+    // The native chooser goes through the file chooser portal when
+    // sandboxed, which grants access to the chosen file.
+    fn file_chooser(
+        &self,
+        title: &str,
+        action: gtk::FileChooserAction,
+        accept: &str,
+    ) -> gtk::FileChooserNative {
+        gtk::FileChooserNative::new(
+            Some(title),
+            self.main_window().as_ref(),
+            action,
+            Some(accept),
+            Some(&gettext("Cancel")),
+        )
+    }
+
+    // Documents may be opened from and saved to remote locations, such as
+    // sftp:// and smb:// ones that GVfs reaches.
+    fn run_file_chooser<F>(&self, file_chooser: gtk::FileChooserNative, on_accept: F)
+    where
+        F: Fn(&Self, gio::File) + 'static,
+    {
+        file_chooser.connect_response(clone!(@weak self as app => move |d, response| {
+            if let (gtk::ResponseType::Accept, Some(file)) = (response, d.file()) {
+                on_accept(&app, file);
+            }
+            d.destroy();
+            // Native choosers are not owned by a window, so one is kept
+            // alive here until it responds.
+            imp::Application::from_instance(&app).file_chooser.replace(None);
+        }));
+        file_chooser.show();
+        let imp = imp::Application::from_instance(self);
+        imp.file_chooser.replace(Some(file_chooser));
+    }
+
+    // For files that are not documents, which are only read and written
+    // locally.
+    fn run_local_file_chooser<F>(&self, file_chooser: gtk::FileChooserNative, on_accept: F)
+    where
+        F: Fn(&Self, PathBuf) + 'static,
+    {
+        self.run_file_chooser(file_chooser, move |app, file| {
+            // Remote locations have no path unless they are mounted.
+            match file.path() {
+                Some(path) => on_accept(app, path),
+                None => {
+                    let e = Error::NotLocal(file.uri().to_string());
+                    warn!("{}", e);
+                    app.show_error(&e);
+                }
+            }
+        });
+    }
+
+    // Starts with the filter and directory last used, and remembers the
+    // ones chosen.
+    fn add_file_chooser_filters(&self, file_chooser: &gtk::FileChooserNative) {
+        let imp = imp::Application::from_instance(self);
+        let last = imp.settings.string(LAST_FILE_FILTER);
+        for id in FILE_FILTERS.iter() {
+            let filter = Self::file_filter(id);
+            file_chooser.add_filter(&filter);
+            if last.as_str() == *id {
+                file_chooser.set_filter(&filter);
+            }
+        }
+        if let Some(dir) = self.start_directory() {
+            if let Err(e) = file_chooser.set_current_folder(&gio::File::for_path(&dir)) {
+                warn!("Failed to open {:?} in the file chooser, {}", dir, e);
+            }
+        }
+        // This runs before the handler that destroys the chooser.
+        file_chooser.connect_response(clone!(@weak self as app => move |d, response| {
+            if response == gtk::ResponseType::Accept {
+                app.remember_file_chooser(d);
+            }
+        }));
+    }
 
+    // Quick notes and projects each remember their own directory. A project
+    // starts at its root unless the directory is inside it.
+    fn start_directory(&self) -> Option<PathBuf> {
+        let imp = imp::Application::from_instance(self);
+        let root = self
+            .model()
+            .and_then(|model| model.borrow().project().map(|p| p.root.clone()));
+        let key = Self::last_directory_key(root.is_some());
+        let dir = PathBuf::from(imp.settings.string(key).as_str());
+        let remembered = Some(dir).filter(|d| !d.as_os_str().is_empty() && d.is_dir());
+        match root {
+            Some(root) => remembered.filter(|d| d.starts_with(&root)).or(Some(root)),
+            None => remembered,
+        }
+    }
+
+    fn last_directory_key(in_project: bool) -> &'static str {
+        if in_project {
+            LAST_PROJECT_DIRECTORY
+        } else {
+            LAST_DIRECTORY
+        }
+    }
+
+    fn file_filter(id: &str) -> gtk::FileFilter {
         let filter = gtk::FileFilter::new();
-        filter.add_pattern("*");
-        filter.set_name(Some(&gettext("All Files")));
-        file_chooser.add_filter(&filter);
+        match id {
+            "text" => {
+                filter.add_mime_type("text/plain");
+                filter.set_name(Some(&gettext("Text Files")));
+            }
+            "markdown" => {
+                filter.add_mime_type("text/markdown");
+                filter.add_pattern("*.md");
+                filter.add_pattern("*.markdown");
+                filter.set_name(Some(&gettext("Markdown")));
+            }
+            "source" => {
+                for pattern in SOURCE_PATTERNS.iter() {
+                    filter.add_pattern(pattern);
+                }
+                filter.set_name(Some(&gettext("Source Code")));
+            }
+            _ => {
+                filter.add_pattern("*");
+                filter.set_name(Some(&gettext("All Files")));
+            }
+        }
+        filter
+    }
+
+    // What a name typed without an extension is saved as.
+    fn file_filter_extension(id: &str) -> &'static str {
+        match id {
+            "markdown" => "md",
+            _ => "txt",
+        }
+    }
+
+    fn remember_file_chooser(&self, file_chooser: &gtk::FileChooserNative) {
+        let imp = imp::Application::from_instance(self);
+        let name = file_chooser.filter().and_then(|f| f.name());
+        let id = FILE_FILTERS
+            .iter()
+            .find(|id| Self::file_filter(id).name() == name);
+        if let Some(id) = id {
+            if let Err(e) = imp.settings.set_string(LAST_FILE_FILTER, id) {
+                warn!("Failed to update {}, {}", LAST_FILE_FILTER, e);
+            }
+        }
+        // Portal paths are only meaningful for the file that was chosen.
+        if sandbox::is_sandboxed() {
+            return;
+        }
+        let path = file_chooser.file().and_then(|f| f.path());
+        let in_project = self
+            .model()
+            .is_some_and(|model| model.borrow().project().is_some());
+        let key = Self::last_directory_key(in_project);
+        if let Some(dir) = path.as_deref().and_then(Path::parent) {
+            if let Err(e) = imp.settings.set_string(key, &dir.to_string_lossy()) {
+                warn!("Failed to update {}, {}", key, e);
+            }
+        }
     }
 
     fn save_file_as(&self) {
         debug!("GtkApplication<Application>::save_file_as");
-        let file_chooser = gtk::FileChooserDialog::new(
-            Some(&gettext("Save As")),
-            Some(&self.main_window()),
+        let model = match self.model() {
+            Some(model) => model,
+            None => return,
+        };
+        let file_chooser = self.file_chooser(
+            &gettext("Save As"),
             gtk::FileChooserAction::Save,
-            &[
-                (&gettext("Save"), gtk::ResponseType::Ok),
-                (&gettext("Cancel"), gtk::ResponseType::Cancel),
-            ],
+            &gettext("Save"),
         );
-        Self::add_file_chooser_filters(&file_chooser);
+        self.add_file_chooser_filters(&file_chooser);
+        // Start next to the current document, under its name.
+        let current = model.borrow().document().file();
+        match current {
+            Some(file) => {
+                let portal = matches!(file.path(), Some(path) if sandbox::is_document(&path));
+                if let Some(dir) = file.parent().filter(|_| !portal) {
+                    if let Err(e) = file_chooser.set_current_folder(&dir) {
+                        warn!("Failed to open {} in the file chooser, {}", dir.uri(), e);
+                    }
+                }
+                if let Some(name) = file.basename() {
+                    file_chooser.set_current_name(&name.to_string_lossy());
+                }
+            }
+            None => {
+                let imp = imp::Application::from_instance(self);
+                let extension = Settings::new().new_document_extension().unwrap_or_else(|| {
+                    Self::file_filter_extension(&imp.settings.string(LAST_FILE_FILTER)).to_string()
+                });
+                // Only the start of the document is needed for a name.
+                let start = {
+                    let model = model.borrow();
+                    let text = model.document().text();
+                    let lines = text.len_lines().min(NAME_LINES);
+                    text.slice(..text.line_to_char(lines)).to_string()
+                };
+                let name =
+                    FileSystem::name_from_text(&start).unwrap_or_else(|| gettext("Untitled"));
+                file_chooser.set_current_name(&format!("{}.{}", name, extension));
+            }
+        }
+        self.run_file_chooser(file_chooser, |app, file| {
+            debug!("GtkApplication<Application>::save_file_as Ok");
+            // The portal only grants access to the name that was chosen,
+            // and remote names are saved as they were typed.
+            let path = match file.path() {
+                Some(path) if !sandbox::is_sandboxed() => path,
+                _ => {
+                    app.send(SaveFile(file));
+                    return;
+                }
+            };
+            let imp = imp::Application::from_instance(app);
+            let extension = Self::file_filter_extension(&imp.settings.string(LAST_FILE_FILTER));
+            let named = FileSystem::with_default_extension(&path, extension);
+            // The chooser only asked about replacing the name as typed.
+            if named != path && named.exists() {
+                app.confirm_overwrite(named);
+            } else {
+                app.send(SaveFile(gio::File::for_path(named)));
+            }
+        });
+    }
 
-        let model_rc = self.model();
+    // Copies are offered next to the document, and untitled ones where new
+    // documents are saved.
+    fn save_copy(&self) {
+        debug!("GtkApplication<Application>::save_copy");
+        let model = match self.model() {
+            Some(model) => model,
+            None => return,
+        };
+        let file_chooser = self.file_chooser(
+            &gettext("Save a Copy"),
+            gtk::FileChooserAction::Save,
+            &gettext("Save"),
+        );
+        let current = model.borrow().document().file();
+        let dir = match &current {
+            Some(file) => {
+                let portal = matches!(file.path(), Some(path) if sandbox::is_document(&path));
+                file.parent().filter(|_| !portal)
+            }
+            None => self.start_directory().map(gio::File::for_path),
+        };
+        if let Some(dir) = dir {
+            if let Err(e) = file_chooser.set_current_folder(&dir) {
+                warn!("Failed to open {} in the file chooser, {}", dir.uri(), e);
+            }
+        }
+        let name = current
+            .and_then(|file| file.basename())
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| gettext("Untitled"));
+        file_chooser.set_current_name(&FileSystem::copy_name(&name, &gettext("copy")));
+        self.run_file_chooser(file_chooser, |app, file| app.send(SaveCopy(file)));
+    }
 
-        file_chooser.connect_response(
-            move |d: &gtk::FileChooserDialog, response: gtk::ResponseType| {
-                if response == gtk::ResponseType::Ok {
-                    debug!("GtkApplication<Application>::open_file Ok");
-                    let file = d.file().expect("Couldn't get file");
-                    let model = model_rc.borrow();
-                    model.send(SaveFile(file.path().unwrap()));
-                }
-                d.close();
-            },
+    // Exports start next to the document, under its name.
+    fn export_file(&self, format: ExportFormat) {
+        debug!("GtkApplication<Application>::export_file {:?}", format);
+        let model = match self.model() {
+            Some(model) => model,
+            None => return,
+        };
+        let file_chooser = self.file_chooser(
+            &gettext("Export"),
+            gtk::FileChooserAction::Save,
+            &gettext("Export"),
         );
+        let current = model.borrow().document().filepath();
+        let dir = match &current {
+            Some(path) => path
+                .parent()
+                .filter(|_| !sandbox::is_document(path))
+                .map(Path::to_path_buf),
+            None => self.start_directory(),
+        };
+        if let Some(dir) = dir {
+            if let Err(e) = file_chooser.set_current_folder(&gio::File::for_path(&dir)) {
+                warn!("Failed to open {:?} in the file chooser, {}", dir, e);
+            }
+        }
+        let name = current
+            .as_deref()
+            .and_then(Path::file_stem)
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_else(|| gettext("Untitled"));
+        file_chooser.set_current_name(&format!(
+            "{}{}.{}",
+            name,
+            format.suffix(),
+            format.extension()
+        ));
+        self.run_local_file_chooser(file_chooser, move |app, path| match format {
+            ExportFormat::Pdf => app.export_pdf(path),
+            _ => app.send(Export(path, format)),
+        });
+    }
 
-        file_chooser.show();
+    // Printed through the print pipeline in a monospace font, so the
+    // columns line up as they do in the editor.
+    fn export_pdf(&self, path: PathBuf) {
+        debug!("GtkApplication<Application>::export_pdf {:?}", path);
+        let model = match self.model() {
+            Some(model) => model,
+            None => return,
+        };
+        let (text, tab_width) = {
+            let model = model.borrow();
+            (model.document().text().to_string(), model.tab_width())
+        };
+        let pages: Rc<RefCell<Vec<Vec<String>>>> = Rc::default();
+        let operation = gtk::PrintOperation::new();
+        operation.set_export_filename(&path);
+        operation.set_show_progress(true);
+        operation.connect_begin_print(clone!(@strong pages => move |operation, context| {
+            let extents = context.cairo_context().map(|cr| {
+                Self::set_pdf_font(&cr);
+                (cr.font_extents(), cr.text_extents("M"))
+            });
+            let (columns, rows) = match extents {
+                Some((Ok(font), Ok(m))) => (
+                    (context.width() / m.x_advance) as usize,
+                    (context.height() / font.height) as usize,
+                ),
+                _ => (80, 60),
+            };
+            pages.replace(export::paginate(&text, columns, rows, tab_width as usize));
+            operation.set_n_pages(pages.borrow().len() as i32);
+        }));
+        operation.connect_draw_page(clone!(@strong pages => move |_, context, page| {
+            let cr = match context.cairo_context() {
+                Some(cr) => cr,
+                None => return warn!("Failed to draw page {}, it has no surface", page),
+            };
+            Self::set_pdf_font(&cr);
+            let (ascent, height) = cr
+                .font_extents()
+                .map_or((PDF_FONT_SIZE, PDF_FONT_SIZE), |f| (f.ascent, f.height));
+            let pages = pages.borrow();
+            let lines = pages.get(page as usize).map_or(&[][..], Vec::as_slice);
+            for (i, line) in lines.iter().enumerate() {
+                cr.move_to(0.0, ascent + height * i as f64);
+                if let Err(e) = cr.show_text(line) {
+                    warn!("Failed to draw page {}, {}", page, e);
+                    return;
+                }
+            }
+        }));
+        let result = operation
+            .run(
+                gtk::PrintOperationAction::Export,
+                self.main_window().as_ref(),
+            )
+            .map(|_| path)
+            .map_err(|e| Error::Io {
+                kind: IoKind::Other,
+                message: e.to_string(),
+            });
+        self.send(ExportFinished(result));
     }
 
-    fn open_file(&self) {
-        debug!("GtkApplication<Application>::open_file");
-        let file_chooser = gtk::FileChooserDialog::new(
-            Some(&gettext("Open File")),
-            Some(&self.main_window()),
-            gtk::FileChooserAction::Open,
-            &[
-                (&gettext("Open"), gtk::ResponseType::Ok),
-                (&gettext("Cancel"), gtk::ResponseType::Cancel),
-            ],
+    fn set_pdf_font(cr: &cairo::Context) {
+        cr.select_font_face(
+            "monospace",
+            cairo::FontSlant::Normal,
+            cairo::FontWeight::Normal,
         );
-        Self::add_file_chooser_filters(&file_chooser);
+        cr.set_font_size(PDF_FONT_SIZE);
+    }
 
-        let model_rc = self.model();
+    // Reloads the file, dropping any edits once that is confirmed.
+    fn revert(&self) {
+        debug!("GtkApplication<Application>::revert");
+        let model = match self.model() {
+            Some(model) => model,
+            None => return,
+        };
+        let (modified, name) = {
+            let model = model.borrow();
+            (
+                model.document().modified(),
+                model.document().filename().unwrap_or_default(),
+            )
+        };
+        if !modified {
+            self.send(ReloadFile);
+            return;
+        }
+        let dialog = gtk::MessageDialog::new(
+            self.main_window().as_ref(),
+            gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+            gtk::MessageType::Question,
+            gtk::ButtonsType::None,
+            &format!("{}: \"{}\"", gettext("Revert to the saved file"), name),
+        );
+        dialog.set_secondary_text(Some(&gettext("Changes that were not saved will be lost.")));
+        dialog.add_button(&gettext("Cancel"), gtk::ResponseType::Cancel);
+        dialog.add_button(&gettext("Revert"), gtk::ResponseType::Accept);
+        if let Some(button) = dialog.widget_for_response(gtk::ResponseType::Accept) {
+            button.add_css_class("destructive-action");
+        }
+        dialog.connect_response(clone!(@weak self as app => move |d, response| {
+            d.close();
+            if response == gtk::ResponseType::Accept {
+                app.send(ReloadFile);
+            }
+        }));
+        dialog.show();
+    }
 
-        file_chooser.connect_response(
-            move |d: &gtk::FileChooserDialog, response: gtk::ResponseType| {
-                if response == gtk::ResponseType::Ok {
-                    debug!("GtkApplication<Application>::open_file Ok");
-                    let file = d.file().expect("Couldn't get file");
-                    let model = model_rc.borrow();
-                    model.send(OpenFile(file.path()));
-                }
-                d.close();
-            },
+    fn confirm_overwrite(&self, path: PathBuf) {
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let dialog = gtk::MessageDialog::new(
+            self.main_window().as_ref(),
+            gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+            gtk::MessageType::Question,
+            gtk::ButtonsType::None,
+            &format!("{}: \"{}\"", gettext("Replace the existing file"), name),
         );
+        dialog.set_secondary_text(Some(&gettext(
+            "A file with this name already exists. Replacing it will overwrite its contents.",
+        )));
+        dialog.add_button(&gettext("Cancel"), gtk::ResponseType::Cancel);
+        dialog.add_button(&gettext("Replace"), gtk::ResponseType::Accept);
+        if let Some(button) = dialog.widget_for_response(gtk::ResponseType::Accept) {
+            button.add_css_class("destructive-action");
+        }
+        dialog.connect_response(clone!(@weak self as app => move |d, response| {
+            d.close();
+            if response == gtk::ResponseType::Accept {
+                app.send(SaveFile(gio::File::for_path(&path)));
+            }
+        }));
+        dialog.show();
+    }
 
-        file_chooser.show();
+    fn open_file(&self, mode: OpenMode) {
+        debug!("GtkApplication<Application>::open_file {:?}", mode);
+        let file_chooser = self.file_chooser(
+            &gettext("Open File"),
+            gtk::FileChooserAction::Open,
+            &gettext("Open"),
+        );
+        self.add_file_chooser_filters(&file_chooser);
+        // One file takes the focused window's place, as it always has.
+        file_chooser.set_select_multiple(true);
+        let chooser = file_chooser.downgrade();
+        self.run_file_chooser(file_chooser, move |app, file| {
+            debug!("GtkApplication<Application>::open_file Ok");
+            let chosen: Vec<gio::File> = chooser
+                .upgrade()
+                .and_then(|c| c.files())
+                .map(|files| {
+                    (0..files.n_items())
+                        .filter_map(|i| files.item(i))
+                        .filter_map(|file| file.downcast::<gio::File>().ok())
+                        .collect()
+                })
+                .unwrap_or_default();
+            if chosen.len() > 1 {
+                app.open_batch(chosen, mode);
+            } else {
+                app.send(OpenFileAs(file, mode));
+            }
+        });
     }
 
     fn new_file(&self) {
         debug!("GtkApplication<Application>::new_file");
-        let model_rc = self.model();
-        let model = model_rc.borrow();
-        model.send(OpenFile(None));
+        self.send(OpenFile(None));
+    }
+
+    fn new_from_template(&self, path: PathBuf) {
+        debug!("GtkApplication<Application>::new_from_template {:?}", path);
+        self.send(NewFromTemplate(path));
+    }
+
+    // Templates are read as each window opens.
+    fn load_templates(&self, window: &ApplicationWindow) {
+        let templates = template::list(&templates_dir());
+        debug!(
+            "GtkApplication<Application>::load_templates {}",
+            templates.len()
+        );
+        window.set_templates(&templates);
+    }
+
+    // Created on first use, so there is somewhere to put templates.
+    fn open_templates_folder(&self) {
+        let dir = templates_dir();
+        if let Err(e) = std::fs::create_dir_all(&dir) {
+            warn!("Failed to create {:?}, {}", dir, e);
+            self.show_error(&e.into());
+            return;
+        }
+        let uri = gio::File::for_path(&dir).uri();
+        gtk::show_uri(self.main_window().as_ref(), &uri, gdk::CURRENT_TIME);
+    }
+
+    // Opened in the default file manager, through the folder's URI.
+    fn open_containing_folder(&self) {
+        let dir = self
+            .model()
+            .and_then(|model| model.borrow().document().file())
+            .and_then(|f| f.parent());
+        let dir = match dir {
+            Some(dir) => dir,
+            None => return,
+        };
+        debug!(
+            "GtkApplication<Application>::open_containing_folder {}",
+            dir.uri()
+        );
+        if let Err(e) =
+            gio::AppInfo::launch_default_for_uri(&dir.uri(), None::<&gio::AppLaunchContext>)
+        {
+            warn!("Failed to open {}, {}", dir.uri(), e);
+            self.show_error(&e.into());
+        }
+    }
+
+    fn copy_file_path(&self) {
+        let (window, model) = match (self.main_window(), self.model()) {
+            (Some(window), Some(model)) => (window, model),
+            _ => return,
+        };
+        let path = match model.borrow().document().filepath() {
+            Some(path) => path.to_string_lossy().to_string(),
+            None => return,
+        };
+        debug!("GtkApplication<Application>::copy_file_path {}", path);
+        window.clipboard().set_text(&path);
+    }
+
+    fn cancel_open(&self) {
+        debug!("GtkApplication<Application>::cancel_open");
+        self.send(CancelOpen);
+    }
+
+    fn cancel_save(&self) {
+        debug!("GtkApplication<Application>::cancel_save");
+        self.send(CancelSave);
+    }
+
+    fn word_frequency(&self) {
+        debug!("GtkApplication<Application>::word_frequency");
+        self.send(AnalyzeWordFrequency);
+    }
+
+    // Patches are read like documents, so they may come from remote
+    // locations too.
+    pub fn open_patch(&self, window: &ApplicationWindow) {
+        debug!("GtkApplication<Application>::open_patch");
+        let file_chooser = self.file_chooser(
+            &gettext("Apply Patch"),
+            gtk::FileChooserAction::Open,
+            &gettext("Apply"),
+        );
+        let window = window.downgrade();
+        self.run_file_chooser(file_chooser, move |_, file| {
+            let window = window.clone();
+            FileSystem::read(
+                file,
+                None,
+                &gio::Cancellable::new(),
+                |_, _| (),
+                move |result| {
+                    if let Some(window) = window.upgrade() {
+                        match result {
+                            Ok(patch) => window.apply_patch(&patch),
+                            Err(e) => window.show_error(&e),
+                        }
+                    }
+                },
+            );
+        });
+    }
+
+    // Also opened from the regex tester, with the pattern filled in.
+    pub fn find_in_files(&self, query: &Query) {
+        debug!("GtkApplication<Application>::find_in_files {:?}", query);
+        let dialog = gtk::MessageDialog::new(
+            self.main_window().as_ref(),
+            gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+            gtk::MessageType::Question,
+            gtk::ButtonsType::None,
+            &gettext("Find in Files"),
+        );
+        dialog.set_secondary_text(Some(&gettext(
+            "Searches the folder chosen, which starts as the project or the folder of the open file.",
+        )));
+        let entry = gtk::Entry::new();
+        entry.set_text(&query.pattern);
+        entry.set_activates_default(true);
+        let regex_check = gtk::CheckButton::with_label(&gettext("Regular expression"));
+        regex_check.set_active(query.regex);
+        let include_entry = gtk::Entry::new();
+        include_entry.set_text(&query.include);
+        include_entry.set_placeholder_text(Some(&gettext("Files to search, such as *.rs, *.md")));
+        include_entry.set_activates_default(true);
+        let directory = Rc::new(RefCell::new(
+            query
+                .directory
+                .clone()
+                .or_else(|| self.model()?.borrow().workspace_root()),
+        ));
+        let folder_button = gtk::Button::new();
+        Self::set_folder_label(&folder_button, directory.borrow().as_deref());
+        let chosen = directory.clone();
+        folder_button.connect_clicked(clone!(@weak self as app => move |button| {
+            let file_chooser = app.file_chooser(
+                &gettext("Search Folder"),
+                gtk::FileChooserAction::SelectFolder,
+                &gettext("Select"),
+            );
+            if let Some(dir) = chosen.borrow().as_deref() {
+                if let Err(e) = file_chooser.set_current_folder(&gio::File::for_path(dir)) {
+                    warn!("Failed to open {:?} in the file chooser, {}", dir, e);
+                }
+            }
+            let (button, chosen) = (button.clone(), chosen.clone());
+            app.run_local_file_chooser(file_chooser, move |_, path| {
+                Self::set_folder_label(&button, Some(&path));
+                chosen.replace(Some(path));
+            });
+        }));
+        if let Ok(area) = dialog.message_area().downcast::<gtk::Box>() {
+            area.append(&entry);
+            area.append(&regex_check);
+            area.append(&folder_button);
+            area.append(&include_entry);
+        }
+        dialog.add_button(&gettext("Cancel"), gtk::ResponseType::Cancel);
+        dialog.add_button(&gettext("Find"), gtk::ResponseType::Accept);
+        dialog.set_default_response(gtk::ResponseType::Accept);
+        dialog.connect_response(clone!(@weak self as app => move |d, response| {
+            let query = Query {
+                pattern: entry.text().to_string(),
+                regex: regex_check.is_active(),
+                directory: directory.borrow().clone(),
+                include: include_entry.text().to_string(),
+            };
+            if response == gtk::ResponseType::Accept && !query.pattern.is_empty() {
+                // The dialog stays open until the pattern is fixed.
+                if let Err(e) = query.compile() {
+                    d.set_secondary_text(Some(&e.to_string()));
+                    return;
+                }
+                app.send(FindInFiles(query));
+            }
+            d.close();
+        }));
+        dialog.show();
+    }
+
+    fn set_folder_label(button: &gtk::Button, dir: Option<&Path>) {
+        let name = dir
+            .and_then(Path::file_name)
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_else(|| gettext("Choose a Folder…"));
+        button.set_label(&name);
+        button.set_tooltip_text(dir.map(|d| d.display().to_string()).as_deref());
+    }
+
+    fn find_duplicates(&self) {
+        debug!("GtkApplication<Application>::find_duplicates");
+        self.send(FindDuplicates);
+    }
+
+    fn set_auto_reload(&self, enabled: bool) {
+        debug!("GtkApplication<Application>::set_auto_reload {}", enabled);
+        self.send(SetAutoReload(enabled));
     }
 
     fn undo(&self) {
         debug!("GtkApplication<Application>::undo");
-        self.main_window().undo();
+        self.send(Undo);
     }
 
     fn redo(&self) {
         debug!("GtkApplication<Application>::redo");
-        self.main_window().redo();
+        self.send(Redo);
     }
 
     pub fn run(&self) {
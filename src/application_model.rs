@@ -1,24 +1,143 @@
+use super::actions::Action;
 use super::actions::Action::*;
-use super::actions::{Action, Err, IOResult};
-use super::document::Document;
+use super::analysis::{self, Duplicate, TermCount};
+use super::companion;
+use super::config::APP_ID;
+use super::diff;
+use super::document::{Document, FileStamp, OpenMode, OpenOptions};
+use super::error::{self, Error};
+use super::export::{self, ExportFormat};
+use super::file_system::FileSystem;
+use super::formatting;
+use super::idle::{self, Idle};
+use super::jobs::{Job, Jobs};
+use super::markdown;
+use super::position::{self, Position};
+use super::project::{self, Project, ProjectSettings};
+use super::recent;
+use super::sandbox;
+use super::settings::Settings;
+use super::template;
+use super::workspace::{self, FileMatches, Filter, Query};
+use crate::gio;
+use crate::glib;
 use crate::glib::Sender;
-use std::fs::File;
+use gio::prelude::*;
+use log::{debug, warn};
+use ropey::Rope;
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::io;
-use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::thread;
 
-#[derive(Debug, Clone)]
+const TERM_LIMIT: usize = 500;
+const DUPLICATE_THRESHOLD: f64 = 0.5;
+const AUTO_RELOAD_ALWAYS: &str = "auto-reload-always";
+const AUTO_RELOAD_NEVER: &str = "auto-reload-never";
+const TRUSTED_PROJECTS: &str = "trusted-projects";
+pub const LAST_DOCUMENT: &str = "last-document";
+const RECENT_DOCUMENTS: &str = "recent-documents";
+const DOCUMENT_POSITIONS: &str = "document-positions";
+const COMPANION_RULES: &str = "companion-rules";
+// Seconds after the last edit that a note is saved.
+const NOTE_SAVE_SECONDS: u32 = 3;
+// Lines of context around each change in an exported patch.
+const PATCH_CONTEXT: usize = 3;
+// How many closed documents can be reopened.
+const CLOSED_LIMIT: usize = 10;
+const DOCUMENT_LABELS: &str = "document-labels";
+// Colors a document can be labelled with, matching classes in style.css.
+pub const LABEL_COLORS: [&str; 6] = ["red", "orange", "yellow", "green", "blue", "purple"];
+
+#[derive(Debug, Clone, Default)]
 pub enum StatusMessage {
+    #[default]
     None,
     OpeningFile,
     SavingFile,
-    FileSaveFinished(Result<(), Err>),
-    FileOpenFinished(Result<(), Err>),
+    AnalyzingText,
+    Searching,
+    Exporting,
+    ExportFinished(Result<PathBuf, Error>),
+    CopySaved(Result<gio::File, Error>),
+    FileSaveFinished(Result<(), Error>),
+    FileOpenFinished(Result<(), Error>),
+    Failed(Error),
+}
+
+#[derive(Debug, Clone)]
+pub enum Toast {
+    FileReloaded,
+    Merged(usize),
+    ReadOnly,
+    Replaced(usize),
+    ReplaceFailed,
+    InvalidPattern,
+    NoChanges,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotificationAction {
+    Retry,
+    OpenFolder,
+    Reload,
+}
+
+// Messages that stay until they are dismissed, unlike the status bar's,
+// and offer something to do about them.
+#[derive(Debug, Clone)]
+pub enum Notification {
+    OpenFailed(gio::File, OpenMode, Error),
+    // Several files opened at once, of which these failed.
+    OpenFailures(Vec<(gio::File, Error)>),
+    SaveFailed(gio::File, Error),
+    Exported(PathBuf),
+    // Only part of the file is open, so it is not reloaded on its own.
+    ChangedOnDisk(gio::File),
 }
 
-impl Default for StatusMessage {
-    fn default() -> Self {
-        StatusMessage::None
+impl Notification {
+    pub fn severity(&self) -> Severity {
+        match self {
+            Notification::OpenFailed(..)
+            | Notification::OpenFailures(_)
+            | Notification::SaveFailed(..) => Severity::Error,
+            Notification::ChangedOnDisk(_) => Severity::Warning,
+            Notification::Exported(_) => Severity::Info,
+        }
+    }
+
+    pub fn actions(&self) -> &'static [NotificationAction] {
+        match self {
+            Notification::OpenFailed(..) | Notification::SaveFailed(..) => {
+                &[NotificationAction::Retry, NotificationAction::OpenFolder]
+            }
+            Notification::OpenFailures(_) => &[],
+            Notification::Exported(_) => &[NotificationAction::OpenFolder],
+            Notification::ChangedOnDisk(_) => &[NotificationAction::Reload],
+        }
+    }
+
+    // The file the notification is about, unless it is about several.
+    pub fn file(&self) -> Option<gio::File> {
+        match self {
+            Notification::OpenFailed(file, ..)
+            | Notification::SaveFailed(file, _)
+            | Notification::ChangedOnDisk(file) => Some(file.clone()),
+            Notification::Exported(path) => Some(gio::File::for_path(path)),
+            Notification::OpenFailures(_) => None,
+        }
     }
 }
 
@@ -27,6 +146,26 @@ pub struct Changes {
     pub filename: bool,
     pub text: bool,
     pub status_message: bool,
+    pub external_change: bool,
+    pub compare: bool,
+    pub term_counts: bool,
+    pub duplicates: bool,
+    pub toast: bool,
+    pub conflict: bool,
+    pub merge: bool,
+    pub preferences: bool,
+    pub cursor: bool,
+    pub progress: bool,
+    pub large_file: bool,
+    pub file_matches: bool,
+    pub label: bool,
+    pub notification: bool,
+    pub open_elsewhere: bool,
+    // The document went from saved to modified, or back.
+    pub modified: bool,
+    // Background work on the text finished.
+    pub stats: bool,
+    pub links: bool,
 }
 
 impl Changes {
@@ -35,15 +174,130 @@ impl Changes {
             filename,
             text,
             status_message,
+            external_change: false,
+            compare: false,
+            term_counts: false,
+            duplicates: false,
+            toast: false,
+            conflict: false,
+            merge: false,
+            preferences: false,
+            cursor: false,
+            progress: false,
+            large_file: false,
+            file_matches: false,
+            label: false,
+            notification: false,
+            open_elsewhere: false,
+            modified: false,
+            stats: false,
+            links: false,
         }
     }
 }
 
+// The file each window's model has open, shared between the models so a
+// file is only open in one window at a time.
+#[derive(Debug, Default)]
+pub struct OpenFiles {
+    files: Vec<(usize, Option<gio::File>)>,
+    next_id: usize,
+}
+
+impl OpenFiles {
+    fn join(&mut self) -> usize {
+        self.next_id += 1;
+        self.files.push((self.next_id, None));
+        self.next_id
+    }
+
+    fn leave(&mut self, id: usize) {
+        self.files.retain(|(other, _)| *other != id);
+    }
+
+    fn set(&mut self, id: usize, file: Option<gio::File>) {
+        if let Some(entry) = self.files.iter_mut().find(|(other, _)| *other == id) {
+            entry.1 = file;
+        }
+    }
+
+    // The model, other than `id`, that has the file open.
+    fn owner(&self, file: &gio::File, id: usize) -> Option<usize> {
+        self.files
+            .iter()
+            .find(|(other, open)| *other != id && matches!(open, Some(open) if open.equal(file)))
+            .map(|(other, _)| *other)
+    }
+}
+
+// A find in files scan running on another thread.
+#[derive(Debug, Clone)]
+struct Search {
+    id: usize,
+    cancel: Arc<AtomicBool>,
+}
+
+// A file being read.
+#[derive(Debug, Clone)]
+struct Loading {
+    file: gio::File,
+    cancel: gio::Cancellable,
+    partial: bool,
+    mode: OpenMode,
+    // The line and column to put the cursor at, rather than where it was.
+    goto: Option<(usize, usize)>,
+}
+
+// A file being written.
+#[derive(Debug, Clone)]
+struct Saving {
+    file: gio::File,
+    cancel: gio::Cancellable,
+    // The text when the save started, before any trimming or formatting.
+    // Typing carries on while it is written.
+    text: Rope,
+}
+
 #[derive(Debug, Default, Clone)]
 pub struct ApplicationModel {
     document: Document,
     status_message: StatusMessage,
+    toast: Option<Toast>,
+    notification: Option<Notification>,
+    saving: Option<Saving>,
+    disk_contents: Option<String>,
+    pending_save: Option<gio::File>,
+    term_counts: Vec<TermCount>,
+    duplicates: Vec<Duplicate>,
+    file_matches: Vec<FileMatches>,
+    search_query: Query,
+    search: Option<Search>,
+    // Changes whenever the results are replaced rather than added to.
+    search_serial: usize,
+    project: Option<Project>,
+    cursor: Option<usize>,
+    // The first line to show once a document is opened again.
+    top_line: Option<usize>,
+    // Where the view was as last reported, to be restored on reopening.
+    view_position: Position,
+    // Documents replaced by another, with their positions, most recent last.
+    closed: Vec<(gio::File, Position)>,
+    reopening: Option<(gio::File, Position)>,
+    loading: Option<Loading>,
+    progress: Option<f64>,
+    large_file: Option<(gio::File, u64)>,
+    // How the large file was to be opened, once it is decided how much.
+    large_file_options: OpenOptions,
+    monitor: Option<gio::FileMonitor>,
     tx: Option<Sender<Action>>,
+    open_files: Rc<RefCell<OpenFiles>>,
+    id: usize,
+    // The model that already had the file asked for open.
+    open_elsewhere: Option<usize>,
+    // Changes with every edit, so only the last one saves the note.
+    note_serial: usize,
+    idle: Idle,
+    jobs: Jobs,
 }
 
 impl ApplicationModel {
@@ -51,97 +305,1697 @@ impl ApplicationModel {
         Self {
             document: Document::default(),
             status_message: StatusMessage::default(),
+            toast: None,
+            notification: None,
+            saving: None,
+            disk_contents: None,
+            pending_save: None,
+            term_counts: Vec::new(),
+            duplicates: Vec::new(),
+            file_matches: Vec::new(),
+            search_query: Query::default(),
+            search: None,
+            search_serial: 0,
+            project: None,
+            cursor: None,
+            top_line: None,
+            view_position: Position::default(),
+            closed: Vec::new(),
+            reopening: None,
+            loading: None,
+            progress: None,
+            large_file: None,
+            large_file_options: OpenOptions::default(),
+            monitor: None,
             tx: None,
+            open_files: Rc::default(),
+            id: 0,
+            open_elsewhere: None,
+            note_serial: 0,
+            idle: Idle::default(),
+            jobs: Jobs::default(),
         }
     }
 
+    pub fn recent_documents(&self) -> Vec<PathBuf> {
+        recent_documents()
+    }
+
+    // Only local files have companions looked for, as remote folders are
+    // slow to reach.
+    pub fn companions(&self) -> Vec<PathBuf> {
+        let path = match self.document.filepath() {
+            Some(path) if !sandbox::is_document(&path) => path,
+            _ => return Vec::new(),
+        };
+        let rules = gio::Settings::new(APP_ID)
+            .value(COMPANION_RULES)
+            .get::<HashMap<String, String>>()
+            .unwrap_or_default();
+        companion::find(&path, &rules, Path::is_file)
+    }
+
     pub fn status_message(&self) -> &StatusMessage {
         &self.status_message
     }
 
+    pub fn toast(&self) -> Option<&Toast> {
+        self.toast.as_ref()
+    }
+
+    pub fn notification(&self) -> Option<&Notification> {
+        self.notification.as_ref()
+    }
+
     pub fn document(&self) -> &Document {
         &self.document
     }
 
+    // Whether the open file is reloaded automatically, or None when the
+    // document has not been saved to a file.
+    pub fn auto_reload_file(&self) -> Option<bool> {
+        self.document.filepath().map(|path| auto_reload(&path))
+    }
+
+    // The open file's label color, empty when it has none, or None when the
+    // document has not been saved to a file.
+    pub fn document_label(&self) -> Option<String> {
+        self.document.filepath().map(|path| document_label(&path))
+    }
+
+    pub fn disk_contents(&self) -> Option<&String> {
+        self.disk_contents.as_ref()
+    }
+
+    pub fn term_counts(&self) -> &[TermCount] {
+        &self.term_counts
+    }
+
+    pub fn duplicates(&self) -> &[Duplicate] {
+        &self.duplicates
+    }
+
+    pub fn file_matches(&self) -> &[FileMatches] {
+        &self.file_matches
+    }
+
+    pub fn search_query(&self) -> &Query {
+        &self.search_query
+    }
+
+    pub fn search_serial(&self) -> usize {
+        self.search_serial
+    }
+
+    pub fn is_searching(&self) -> bool {
+        self.search.is_some()
+    }
+
+    // Where the cursor belongs after an undo or redo.
+    pub fn cursor(&self) -> Option<usize> {
+        self.cursor
+    }
+
+    pub fn top_line(&self) -> Option<usize> {
+        self.top_line
+    }
+
+    // Keeps where the open file was left, for when it is opened again.
+    pub fn save_position(&self) {
+        let settings = gio::Settings::new(APP_ID);
+        let path = match self.document.filepath() {
+            Some(path) if Settings::new().restore_position() => path,
+            _ => return,
+        };
+        let saved = document_positions(&settings);
+        let saved = position::remember(&saved, &path, self.view_position, position::LIMIT);
+        if let Err(e) = settings.set_value(DOCUMENT_POSITIONS, &saved.to_variant()) {
+            warn!("Failed to update {}, {}", DOCUMENT_POSITIONS, e);
+        }
+    }
+
+    pub fn stats(&self) -> Option<idle::Stats> {
+        self.idle.stats()
+    }
+
+    pub fn links(&self) -> &[(usize, usize)] {
+        self.idle.links()
+    }
+
+    // Typing drops the work in progress, so it only runs on the text once
+    // it settles.
+    fn restart_idle(&mut self) {
+        let serial = self.idle.restart();
+        self.queue_idle(serial);
+    }
+
+    // Each step waits for the main loop to have nothing more urgent to do.
+    fn queue_idle(&self, serial: usize) {
+        let tx = match self.sender() {
+            Ok(tx) => tx,
+            Err(_) => return,
+        };
+        glib::idle_add_local(move || {
+            tx.send(IdleStep(serial)).ok();
+            glib::Continue(false)
+        });
+    }
+
+    // An untitled document is saved to the notes folder, named by when it
+    // was first saved, and one saved there before is saved in place.
+    fn note_file(&self) -> Option<gio::File> {
+        let settings = Settings::new();
+        if !settings.autosave_notes() || !self.document.modified() || self.loading.is_some() {
+            return None;
+        }
+        let dir = notes_directory(&settings);
+        match self.document.file() {
+            Some(file) => Some(file)
+                .filter(|f| matches!(f.path(), Some(p) if p.parent() == Some(dir.as_path()))),
+            None if self.document.text().len_chars() == 0 => None,
+            None => {
+                let now = glib::DateTime::new_now_local().ok()?;
+                let name = now.format("%Y-%m-%d %H.%M.%S").ok()?;
+                let extension = settings
+                    .new_document_extension()
+                    .unwrap_or_else(|| "txt".to_string());
+                let path = dir.join(format!("{}.{}", name, extension));
+                Some(gio::File::for_path(path))
+            }
+        }
+    }
+
+    fn schedule_note(&mut self) {
+        self.note_serial += 1;
+        if self.note_file().is_none() {
+            return;
+        }
+        let tx = match self.sender() {
+            Ok(tx) => tx,
+            Err(_) => return,
+        };
+        let serial = self.note_serial;
+        glib::timeout_add_seconds_local(NOTE_SAVE_SECONDS, move || {
+            tx.send(SaveNote(serial)).ok();
+            glib::Continue(false)
+        });
+    }
+
+    // The text of an untitled document with unsaved changes, kept should
+    // they be discarded by mistake.
+    pub fn discardable_text(&self) -> Option<String> {
+        if self.document.file().is_some() || !self.document.modified() {
+            return None;
+        }
+        Some(self.document.text().to_string())
+    }
+
+    // Changes that closing the window would lose. Notes are saved as it
+    // closes.
+    pub fn unsaved(&self) -> bool {
+        self.document.modified() && self.note_file().is_none()
+    }
+
+    // Writes the note straight away, as the window or the application is
+    // closing and cannot wait for the timer.
+    pub fn flush_note(&self) {
+        let path = match self.note_file().and_then(|file| file.path()) {
+            Some(path) if self.saving.is_none() => path,
+            _ => return,
+        };
+        let written = match path.parent() {
+            Some(dir) => std::fs::create_dir_all(dir),
+            None => Ok(()),
+        }
+        .and_then(|_| std::fs::write(&path, self.document.text().to_string()));
+        if let Err(e) = written {
+            warn!("Failed to save the note to {}, {}", path.display(), e);
+        }
+    }
+
+    // Fraction of the file read so far while one is being opened.
+    pub fn progress(&self) -> Option<f64> {
+        self.progress
+    }
+
+    pub fn saving(&self) -> bool {
+        self.saving.is_some()
+    }
+
+    // A file too large to open without asking, with its size in bytes.
+    pub fn large_file(&self) -> Option<&(gio::File, u64)> {
+        self.large_file.as_ref()
+    }
+
+    pub fn project(&self) -> Option<&Project> {
+        self.project.as_ref()
+    }
+
+    // Whether each application action can be used on the document, by
+    // name. Actions that depend on the view, such as Paste, follow the
+    // window instead.
+    pub fn capabilities(&self) -> [(&'static str, bool); 8] {
+        let document = &self.document;
+        let path = document.filepath();
+        // An untitled document is worth saving once it has any text.
+        let untitled = document.file().is_none() && document.text().len_chars() > 0;
+        [
+            ("undo", document.can_undo()),
+            ("redo", document.can_redo()),
+            ("save", document.modified() || untitled),
+            ("revert", document.file().is_some()),
+            // The document portal's folder holds only the file itself.
+            (
+                "open-containing-folder",
+                matches!(&path, Some(path) if !sandbox::is_document(path)),
+            ),
+            ("copy-file-path", path.is_some()),
+            ("auto-reload-file", path.is_some()),
+            ("document-label", path.is_some()),
+        ]
+    }
+
+    // Project settings take precedence over the user's preferences.
+    fn project_settings(&self) -> Option<&ProjectSettings> {
+        self.project.as_ref().map(|p| &p.settings)
+    }
+
+    pub fn tab_width(&self) -> u32 {
+        self.project_settings()
+            .and_then(|s| s.tab_width)
+            .unwrap_or_else(|| Settings::new().tab_width())
+            .max(1)
+    }
+
+    pub fn insert_spaces(&self) -> bool {
+        self.project_settings()
+            .and_then(|s| s.insert_spaces)
+            .unwrap_or_else(|| Settings::new().insert_spaces())
+    }
+
+    // Which files of the workspace are indexed and searched.
+    pub fn workspace_filter(&self) -> Filter {
+        let settings = Settings::new();
+        let project = self.project_settings();
+        let excluded = project
+            .and_then(|s| s.excluded_directories.clone())
+            .unwrap_or_else(|| settings.excluded_directories());
+        let kilobytes = project
+            .and_then(|s| s.search_max_file_size)
+            .map_or_else(|| settings.search_max_kilobytes(), u64::from);
+        Filter::new(excluded, kilobytes * 1024)
+    }
+
+    // The folder chosen for the search, or else the workspace.
+    pub fn search_root(&self) -> Option<PathBuf> {
+        self.search_query
+            .directory
+            .clone()
+            .or_else(|| self.workspace_root())
+    }
+
+    // The project root, or the directory of the open file.
+    pub fn workspace_root(&self) -> Option<PathBuf> {
+        match &self.project {
+            Some(project) => Some(project.root.clone()),
+            None => self
+                .document
+                .filepath()
+                .and_then(|p| p.parent().map(Path::to_path_buf)),
+        }
+    }
+
+    fn trim_on_save(&self) -> bool {
+        self.project_settings()
+            .and_then(|s| s.trim_on_save)
+            .unwrap_or_else(|| Settings::new().trim_on_save())
+    }
+
+    fn final_newline(&self) -> bool {
+        self.project_settings()
+            .and_then(|s| s.final_newline)
+            .unwrap_or_else(|| Settings::new().final_newline())
+    }
+
+    // A project's command only runs once the user trusts the project, as
+    // opening a file must not be enough to run someone else's commands.
+    fn format_on_save(&self) -> Option<String> {
+        // Programs on the host cannot be run from the sandbox.
+        if sandbox::is_sandboxed() {
+            return None;
+        }
+        let settings = gio::Settings::new(APP_ID);
+        let project_command = self.project.as_ref().and_then(|p| {
+            let command = p.settings.format_on_save.clone()?;
+            if project::is_trusted(&p.root, &trusted_projects(&settings)) {
+                Some(command)
+            } else {
+                warn!(
+                    "Not running format command of untrusted project {:?}",
+                    p.root
+                );
+                None
+            }
+        });
+        match project_command {
+            Some(command) => Some(command.trim().to_string()).filter(|c| !c.is_empty()),
+            None => Settings::new().format_on_save(),
+        }
+    }
+
     pub fn transmit(&mut self, tx: Sender<Action>) {
         self.tx = Some(tx);
+        self.apply_preferences();
+    }
+
+    fn apply_preferences(&mut self) {
+        self.document
+            .set_history_limit(Settings::new().undo_limit());
+    }
+
+    // Shares the open files and the writes in flight with the other
+    // windows.
+    pub fn join(&mut self, open_files: Rc<RefCell<OpenFiles>>, jobs: Jobs) {
+        self.id = open_files.borrow_mut().join();
+        self.open_files = open_files;
+        self.jobs = jobs;
+    }
+
+    // Called as the window closes, so its file can be opened elsewhere.
+    pub fn leave(&self) {
+        self.open_files.borrow_mut().leave(self.id);
+    }
+
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    pub fn open_elsewhere(&self) -> Option<usize> {
+        self.open_elsewhere
+    }
+
+    fn register_file(&self) {
+        self.open_files
+            .borrow_mut()
+            .set(self.id, self.document.file());
+    }
+
+    pub fn send(&self, action: Action) -> error::Result<()> {
+        self.sender()?.send(action).map_err(|_| Error::Disconnected)
+    }
+
+    // Remembers the document being replaced so it can be reopened.
+    fn close_document(&mut self, replacement: Option<&gio::File>) {
+        let file = match self.document.file() {
+            Some(file) if !matches!(replacement, Some(r) if r.equal(&file)) => file,
+            _ => return,
+        };
+        self.save_position();
+        self.closed.retain(|(closed, _)| !closed.equal(&file));
+        self.closed.push((file, self.view_position));
+        if self.closed.len() > CLOSED_LIMIT {
+            self.closed.remove(0);
+        }
+    }
+
+    fn new_document(&mut self, text: &str) -> Changes {
+        self.close_document(None);
+        self.unwatch();
+        self.disk_contents = None;
+        self.pending_save = None;
+        self.document.reset_to(text);
+        self.register_file();
+        self.project = None;
+        remember_document(None);
+        self.status_message = StatusMessage::OpeningFile;
+        Changes {
+            external_change: true,
+            preferences: true,
+            ..Changes::new(true, true, true)
+        }
+    }
+
+    fn sender(&self) -> error::Result<Sender<Action>> {
+        self.tx.clone().ok_or(Error::Disconnected)
     }
 
-    pub fn send(&self, action: Action) {
-        self.tx.as_ref().unwrap().send(action).ok();
+    // Trimming or formatting may have changed the text that was written,
+    // which then replaces the document's. Anything typed while saving is
+    // kept instead, and leaves the document modified.
+    fn saved(&mut self, file: gio::File, contents: String) -> bool {
+        let text = self.document.text();
+        let formatted = matches!(
+            &self.saving,
+            Some(saving) if &saving.text == text && saving.text != contents
+        );
+        if formatted {
+            self.document.update(&contents);
+        }
+        self.document.save(file, contents);
+        formatted
+    }
+
+    // Reports a failure in the status bar.
+    fn fail(&mut self, error: Error) -> Changes {
+        warn!("{}", error);
+        self.status_message = StatusMessage::Failed(error);
+        Changes::new(false, false, true)
     }
 
     pub fn update(&mut self, action: Action) -> Changes {
+        let modified = self.document.modified();
+        let edited = matches!(action, TextInserted { .. } | TextDeleted { .. });
+        let changes = self.apply(action);
+        if edited || changes.text {
+            self.restart_idle();
+        }
+        Changes {
+            modified: changes.modified || self.document.modified() != modified,
+            ..changes
+        }
+    }
+
+    fn apply(&mut self, action: Action) -> Changes {
         match action {
-            OpenFile(Some(path)) => {
-                let tx = self.tx.as_ref().unwrap().clone();
-                thread::spawn(move || {
-                    let mut contents = String::new();
-                    let r = match FileSystem::read_to_string(path.clone(), &mut contents) {
-                        Ok(()) => IOResult::Ok((path, contents)),
-                        Err(_) => IOResult::Err(Err::IOError()),
+            OpenFile(Some(file)) => self.update(OpenFileAs(file, OpenMode::Normal)),
+            OpenFileAs(file, mode) => self.update(OpenFileWith(
+                file,
+                OpenOptions {
+                    mode,
+                    ..OpenOptions::default()
+                },
+            )),
+            // Files opened as root are read whole, they are rarely large.
+            // Remote files are not asked about, as their size would have to
+            // be fetched first.
+            // Two copies of a file would overwrite each other's changes, so
+            // the window that has it is shown instead.
+            OpenFileWith(file, _) if self.open_files.borrow().owner(&file, self.id).is_some() => {
+                self.open_elsewhere = self.open_files.borrow().owner(&file, self.id);
+                self.reopening = None;
+                Changes {
+                    open_elsewhere: true,
+                    ..Changes::default()
+                }
+            }
+            OpenFileWith(file, options) => {
+                let threshold = Settings::new().large_file_bytes();
+                match file.path().map(std::fs::metadata) {
+                    Some(Ok(metadata))
+                        if threshold > 0
+                            && metadata.len() > threshold
+                            && options.mode != OpenMode::Elevated =>
+                    {
+                        self.large_file = Some((file, metadata.len()));
+                        self.large_file_options = options;
+                        Changes {
+                            large_file: true,
+                            ..Changes::default()
+                        }
+                    }
+                    _ => self.load(file, false, options),
+                }
+            }
+            OpenLargeFile(partial) => match self.large_file.take() {
+                Some((file, _)) => Changes {
+                    large_file: true,
+                    ..self.load(file, partial, self.large_file_options.clone())
+                },
+                None => Changes::default(),
+            },
+            FileOpenProgress(fraction) => {
+                if self.loading.is_none() {
+                    return Changes::default();
+                }
+                self.progress = Some(fraction);
+                Changes {
+                    progress: true,
+                    ..Changes::default()
+                }
+            }
+            CancelOpen => {
+                if let Some(loading) = self.loading.take() {
+                    loading.cancel.cancel();
+                }
+                self.large_file = None;
+                self.progress = None;
+                self.status_message = StatusMessage::FileOpenFinished(Err(Error::Cancelled));
+                Changes {
+                    progress: true,
+                    large_file: true,
+                    ..Changes::new(false, false, true)
+                }
+            }
+            OpenFile(None) => self.new_document(&new_document_text()),
+            // The text is an edit, so closing asks first.
+            RestoreDiscarded(text) | StandardInputRead(Ok(text)) => {
+                let changes = self.new_document("");
+                self.document.update(&text);
+                changes
+            }
+            StandardInputRead(Err(e)) => self.fail(e),
+            OpenFailures(failures) => {
+                self.notification = Some(Notification::OpenFailures(failures));
+                Changes {
+                    notification: true,
+                    ..Changes::default()
+                }
+            }
+            ReopenClosed => match self.closed.pop() {
+                Some((file, position)) => {
+                    self.reopening = Some((file.clone(), position));
+                    self.update(OpenFileAs(file, OpenMode::Normal))
+                }
+                None => Changes::default(),
+            },
+            ViewMoved(position) => {
+                self.view_position = position;
+                Changes::default()
+            }
+            // The document stays untitled, so saving asks where to.
+            NewFromTemplate(path) => {
+                let mut contents = String::new();
+                if let Err(e) = FileSystem::read_to_string(path, &mut contents) {
+                    return self.fail(e.into());
+                }
+                self.new_document(&expand_template(&contents))
+            }
+            SaveFile(file) => {
+                let named = matches!(file.basename(), Some(name) if name.file_name().is_some());
+                if !named {
+                    return self.fail(Error::NoFileName(file.parse_name().as_str().into()));
+                }
+                let same_file = self.document.is_file(&file);
+                if same_file && self.document.read_only() {
+                    self.toast = Some(Toast::ReadOnly);
+                    return Changes {
+                        toast: true,
+                        ..Changes::default()
+                    };
+                }
+                if same_file && self.disk_contents.is_none() && self.document.changed_on_disk() {
+                    // The monitor may miss changes, such as those made
+                    // while the file was on an unmounted drive.
+                    if let Some(Ok(contents)) = file.path().map(read_file) {
+                        if self.document.original() == &contents {
+                            self.record_stamp();
+                        } else {
+                            self.disk_contents = Some(contents);
+                        }
+                    }
+                }
+                if self.disk_contents.is_some() && same_file {
+                    // Saving would silently overwrite the changes on disk.
+                    self.pending_save = Some(file);
+                    return Changes {
+                        external_change: true,
+                        conflict: true,
+                        ..Changes::default()
+                    };
+                }
+                let tx = match self.sender() {
+                    Ok(tx) => tx,
+                    Err(e) => return self.fail(e),
+                };
+                // Ropes clone cheaply, the copy for writing is made on the
+                // other thread.
+                let text = self.document.text().clone();
+                // The document portal only exposes the file itself, so a
+                // backup next to it would be lost.
+                let local = file.path();
+                let backup = Settings::new().create_backup()
+                    && !matches!(&local, Some(path) if sandbox::is_document(path));
+                let elevated = same_file && self.document.mode() == OpenMode::Elevated;
+                let trim = self.trim_on_save();
+                let final_newline = self.final_newline();
+                // Formatters run from the project root, or next to the file,
+                // so remote files are saved as they are.
+                let formatter = local.and_then(|path| {
+                    let dir = match project::find_root(&path) {
+                        Some(root) => root,
+                        None => path.parent().map(Path::to_path_buf).unwrap_or_default(),
                     };
-                    tx.send(FileOpenFinished(r)).ok()
+                    Some((self.format_on_save()?, dir))
                 });
-                self.status_message = StatusMessage::OpeningFile;
-                Changes::new(false, false, true)
+                let cancel = gio::Cancellable::new();
+                self.saving = Some(Saving {
+                    file: file.clone(),
+                    cancel: cancel.clone(),
+                    text: text.clone(),
+                });
+                // Formatters are programs that may take a while, so the text
+                // is prepared on another thread and written from the main
+                // context.
+                let job = self.jobs.start();
+                thread::spawn(move || {
+                    let mut contents = text.to_string();
+                    if trim {
+                        contents = formatting::trim_trailing_whitespace(&contents);
+                    }
+                    if final_newline {
+                        contents = formatting::ensure_final_newline(&contents);
+                    }
+                    if let Some((command, dir)) = formatter {
+                        match formatting::run_formatter(&command, &dir, &contents) {
+                            Ok(formatted) => contents = formatted,
+                            Err(e) => warn!("Format on save with {:?} failed, {}", command, e),
+                        }
+                    }
+                    glib::MainContext::default().invoke(move || {
+                        write_file(file, contents, backup, elevated, &cancel, tx, job)
+                    });
+                });
+                if !elevated {
+                    self.progress = Some(0.0);
+                }
+                self.status_message = StatusMessage::SavingFile;
+                Changes {
+                    progress: true,
+                    ..Changes::new(false, false, true)
+                }
             }
-            OpenFile(None) => {
-                self.document.reset();
-                self.status_message = StatusMessage::OpeningFile;
-                Changes::new(false, false, true)
+            FileSaveProgress(fraction) => {
+                if self.saving.is_none() {
+                    return Changes::default();
+                }
+                self.progress = Some(fraction);
+                Changes {
+                    progress: true,
+                    ..Changes::default()
+                }
             }
-            SaveFile(path) => {
-                let tx = self.tx.as_ref().unwrap().clone();
-                let contents = self.document.text().clone();
-                thread::spawn(move || {
-                    let r = match FileSystem::write_string(path.clone(), &contents) {
-                        Ok(()) => IOResult::Ok((path, contents)),
-                        Err(_) => IOResult::Err(Err::IOError()),
-                    };
-                    tx.send(FileSaveFinished(r)).ok()
+            // The file is left as it was, the write reports back once it
+            // has stopped.
+            CancelSave => {
+                if let Some(saving) = &self.saving {
+                    saving.cancel.cancel();
+                }
+                Changes::default()
+            }
+            TextInserted { offset, text } => {
+                self.document.insert(offset, &text);
+                self.schedule_note();
+                Changes::new(false, false, false)
+            }
+            TextDeleted { start, end } => {
+                self.document.delete(start, end);
+                self.schedule_note();
+                Changes::new(false, false, false)
+            }
+            UserActionStarted => {
+                self.document.begin_user_action();
+                Changes::default()
+            }
+            UserActionEnded => {
+                self.document.end_user_action();
+                Changes::default()
+            }
+            SaveNote(serial) => {
+                let file = match self.note_file() {
+                    Some(file) if serial == self.note_serial && self.saving.is_none() => file,
+                    _ => return Changes::default(),
+                };
+                let tx = match self.sender() {
+                    Ok(tx) => tx,
+                    Err(e) => return self.fail(e),
+                };
+                if let Some(Err(e)) = file
+                    .path()
+                    .as_deref()
+                    .and_then(Path::parent)
+                    .map(std::fs::create_dir_all)
+                {
+                    return self.fail(e.into());
+                }
+                let cancel = gio::Cancellable::new();
+                // Notes are saved while they are being written, so the text
+                // is left as it is rather than trimmed or formatted.
+                self.saving = Some(Saving {
+                    file: file.clone(),
+                    cancel: cancel.clone(),
+                    text: self.document.text().clone(),
                 });
+                let contents = self.document.text().to_string();
+                let job = self.jobs.start();
+                write_file(file, contents, false, false, &cancel, tx, job);
                 self.status_message = StatusMessage::SavingFile;
                 Changes::new(false, false, true)
             }
-            DocumentChanged(value) => {
-                self.document.update(value.as_str());
-                Changes::new(false, false, false)
+            IdleStep(serial) if serial == self.idle.serial() => {
+                let finished = self.idle.step(self.document.text());
+                if !self.idle.is_done() {
+                    self.queue_idle(serial);
+                }
+                Changes {
+                    stats: finished == Some(idle::Task::Stats),
+                    links: finished == Some(idle::Task::Links),
+                    ..Changes::default()
+                }
+            }
+            IdleStep(_) => Changes::default(),
+            Undo => {
+                self.cursor = self.document.undo();
+                self.top_line = None;
+                Changes {
+                    cursor: self.cursor.is_some(),
+                    ..Changes::new(false, self.cursor.is_some(), false)
+                }
             }
-            FileOpenFinished(Ok((path, contents))) => {
-                self.document.open(path, contents);
+            Redo => {
+                self.cursor = self.document.redo();
+                self.top_line = None;
+                Changes {
+                    cursor: self.cursor.is_some(),
+                    ..Changes::new(false, self.cursor.is_some(), false)
+                }
+            }
+            FileOpenFinished(Ok((file, contents))) => {
+                // The read finished just as it was cancelled or replaced.
+                let loading = match self.loading.take() {
+                    Some(loading) if loading.file.equal(&file) => loading,
+                    other => {
+                        self.loading = other;
+                        return Changes::default();
+                    }
+                };
+                self.progress = None;
+                self.close_document(Some(&file));
+                let local = file.path();
+                let restored = match self.reopening.take() {
+                    Some((reopened, position)) if reopened.equal(&file) => Some(position),
+                    _ => local.as_deref().and_then(saved_position),
+                };
+                self.cursor = restored.map(|p| p.cursor);
+                self.top_line = restored.map(|p| p.top_line);
+                self.view_position = restored.unwrap_or_default();
+                let goto = loading.goto;
+                self.watch(&file);
+                self.project = local.as_deref().and_then(project::load);
+                self.disk_contents = None;
+                self.pending_save = None;
+                remember_document(local.as_deref());
+                if loading.partial {
+                    self.document.open_partial(file, contents);
+                } else {
+                    self.document.open(file, contents);
+                }
+                self.document.set_mode(loading.mode);
+                if let Some((line, column)) = goto {
+                    self.cursor = Some(position::offset(self.document.text(), line, column));
+                    self.top_line = None;
+                }
+                self.register_file();
+                self.record_stamp();
+                self.notification = None;
                 self.status_message = StatusMessage::FileOpenFinished(Ok(()));
-                Changes::new(true, true, true)
+                Changes {
+                    external_change: true,
+                    preferences: true,
+                    progress: true,
+                    notification: true,
+                    cursor: self.cursor.is_some(),
+                    ..Changes::new(true, true, true)
+                }
             }
-            FileSaveFinished(Ok((path, contents))) => {
-                self.document.save(path, contents);
+            FileSaveFinished(Ok((file, contents))) => {
+                self.watch(&file);
+                let local = file.path();
+                self.project = local.as_deref().and_then(project::load);
+                self.disk_contents = None;
+                remember_document(local.as_deref());
+                // A read-only file saved as a copy can be edited.
+                let read_only = self.document.read_only();
+                let formatted = self.saved(file, contents);
+                self.register_file();
+                let editable = read_only && !self.document.read_only();
+                self.record_stamp();
+                self.saving = None;
+                self.progress = None;
+                self.notification = None;
                 self.status_message = StatusMessage::FileSaveFinished(Ok(()));
-                Changes::new(true, false, true)
+                Changes {
+                    external_change: true,
+                    preferences: true,
+                    progress: true,
+                    notification: true,
+                    ..Changes::new(true, formatted || editable, true)
+                }
             }
+            FileOpenFinished(Err(Error::Cancelled)) => Changes::default(),
             FileOpenFinished(Err(e)) => {
+                if let Some(loading) = self.loading.take() {
+                    self.notification = Some(Notification::OpenFailed(
+                        loading.file,
+                        loading.mode,
+                        e.clone(),
+                    ));
+                }
+                self.progress = None;
                 self.status_message = StatusMessage::FileOpenFinished(Err(e));
-                Changes::new(false, false, true)
+                Changes {
+                    progress: true,
+                    notification: true,
+                    ..Changes::new(false, false, true)
+                }
             }
             FileSaveFinished(Err(e)) => {
+                if let Some(saving) = self.saving.take() {
+                    if !matches!(e, Error::Cancelled) {
+                        self.notification = Some(Notification::SaveFailed(saving.file, e.clone()));
+                    }
+                }
+                self.progress = None;
                 self.status_message = StatusMessage::FileSaveFinished(Err(e));
+                Changes {
+                    progress: true,
+                    notification: true,
+                    ..Changes::new(false, false, true)
+                }
+            }
+            FileChangedOnDisk(Ok((file, contents))) => {
+                if !self.document.is_file(&file) || self.document.original() == &contents {
+                    // Not our file, or the event echoes our own save.
+                    return Changes::default();
+                }
+                if self.document.partial() {
+                    self.notification = Some(Notification::ChangedOnDisk(file));
+                    return Changes {
+                        notification: true,
+                        ..Changes::default()
+                    };
+                }
+                // Only local files have their own setting.
+                let reload = match file.path() {
+                    Some(path) => auto_reload(&path),
+                    None => Settings::new().auto_reload(),
+                };
+                if self.document.modified() || !reload {
+                    self.disk_contents = Some(contents);
+                    Changes {
+                        external_change: true,
+                        ..Changes::default()
+                    }
+                } else {
+                    debug!("ApplicationModel::update reloading {}", file.uri());
+                    self.document.open(file, contents);
+                    self.record_stamp();
+                    self.toast = Some(Toast::FileReloaded);
+                    Changes {
+                        toast: true,
+                        ..Changes::new(false, true, false)
+                    }
+                }
+            }
+            FileChangedOnDisk(Err(_)) => Changes::default(),
+            ReloadFile => {
+                self.disk_contents = None;
+                self.pending_save = None;
+                let changes = match self.document.file() {
+                    Some(file) => self.update(OpenFileAs(file, self.document.mode())),
+                    None => Changes::default(),
+                };
+                Changes {
+                    external_change: true,
+                    ..changes
+                }
+            }
+            KeepFile => {
+                if let Some(disk_contents) = self.disk_contents.take() {
+                    self.document.set_original(disk_contents);
+                    self.record_stamp();
+                }
+                let changes = match self.pending_save.take() {
+                    Some(file) => self.update(SaveFile(file)),
+                    None => Changes::default(),
+                };
+                Changes {
+                    external_change: true,
+                    ..changes
+                }
+            }
+            MergeFile => {
+                self.pending_save = None;
+                match self.disk_contents.take() {
+                    Some(disk_contents) => {
+                        let merge = diff::merge3(
+                            &self.document.original().to_string(),
+                            &self.document.text().to_string(),
+                            &disk_contents,
+                        );
+                        self.document.set_original(disk_contents);
+                        self.record_stamp();
+                        self.document.update(&merge.text);
+                        self.toast = Some(Toast::Merged(merge.conflicts));
+                        Changes {
+                            external_change: true,
+                            merge: true,
+                            toast: true,
+                            ..Changes::new(false, true, false)
+                        }
+                    }
+                    None => Changes::default(),
+                }
+            }
+            SetAutoReload(enabled) => {
+                if let Some(path) = self.document.filepath() {
+                    set_auto_reload(&path, enabled);
+                }
+                Changes::default()
+            }
+            SetDocumentLabel(label) => {
+                if let Some(path) = self.document.filepath() {
+                    set_document_label(&path, &label);
+                }
+                Changes {
+                    label: true,
+                    ..Changes::default()
+                }
+            }
+            Export(_, ExportFormat::Patch) if !self.document.modified() => {
+                self.toast = Some(Toast::NoChanges);
+                Changes {
+                    toast: true,
+                    ..Changes::default()
+                }
+            }
+            SaveCopy(file) => {
+                if self.document.is_file(&file) {
+                    return self.update(SaveFile(file));
+                }
+                let tx = match self.sender() {
+                    Ok(tx) => tx,
+                    Err(e) => return self.fail(e),
+                };
+                let contents = self.document.text().to_string();
+                let copy = file.clone();
+                let job = self.jobs.start();
+                FileSystem::write(
+                    file,
+                    contents,
+                    false,
+                    &gio::Cancellable::new(),
+                    |_, _| (),
+                    move |r| {
+                        tx.send(CopySaved(r.map(|_| copy))).ok();
+                        drop(job);
+                    },
+                );
+                self.status_message = StatusMessage::SavingFile;
+                Changes::new(false, false, true)
+            }
+            CopySaved(result) => {
+                self.status_message = StatusMessage::CopySaved(result);
+                Changes::new(false, false, true)
+            }
+            Export(path, format) => {
+                let tx = match self.sender() {
+                    Ok(tx) => tx,
+                    Err(e) => return self.fail(e),
+                };
+                let text = self.document.text().to_string();
+                let source = self.document.filepath();
+                let markdown = matches!(&source, Some(source) if markdown::is_markdown(source));
+                let title = source
+                    .as_deref()
+                    .unwrap_or(&path)
+                    .file_stem()
+                    .map(|s| s.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                // Changes since the file was opened or saved, against what
+                // is on disk.
+                let original = match format {
+                    ExportFormat::Patch => self.document.original().to_string(),
+                    _ => String::new(),
+                };
+                let name = self.document.filename().unwrap_or_else(|| title.clone());
+                let job = self.jobs.start();
+                thread::spawn(move || {
+                    let contents = match format {
+                        ExportFormat::Annotated => export::to_annotated(&text),
+                        ExportFormat::SideBySide => export::to_side_by_side(&title, &text),
+                        ExportFormat::Patch => diff::unified(
+                            &format!("a/{}", name),
+                            &format!("b/{}", name),
+                            &original,
+                            &text,
+                            PATCH_CONTEXT,
+                        ),
+                        _ => export::to_html(&title, &text, markdown),
+                    };
+                    glib::MainContext::default().invoke(move || {
+                        let file = gio::File::for_path(&path);
+                        let cancel = gio::Cancellable::new();
+                        FileSystem::write(
+                            file,
+                            contents,
+                            false,
+                            &cancel,
+                            |_, _| (),
+                            move |r| {
+                                tx.send(ExportFinished(r.map(|_| path))).ok();
+                                drop(job);
+                            },
+                        )
+                    });
+                });
+                self.status_message = StatusMessage::Exporting;
+                Changes::new(false, false, true)
+            }
+            ExportFinished(result) => {
+                if let Ok(path) = &result {
+                    self.notification = Some(Notification::Exported(path.clone()));
+                }
+                self.status_message = StatusMessage::ExportFinished(result);
+                Changes {
+                    notification: true,
+                    ..Changes::new(false, false, true)
+                }
+            }
+            PreferencesChanged => {
+                self.apply_preferences();
+                Changes {
+                    preferences: true,
+                    ..Changes::default()
+                }
+            }
+            CompareFile => Changes {
+                compare: self.disk_contents.is_some(),
+                ..Changes::default()
+            },
+            AnalyzeWordFrequency => {
+                let tx = match self.sender() {
+                    Ok(tx) => tx,
+                    Err(e) => return self.fail(e),
+                };
+                let text = self.document.text().clone();
+                let language = glib::language_names()
+                    .first()
+                    .map(|l| l.to_string())
+                    .unwrap_or_default();
+                thread::spawn(move || {
+                    let text = text.to_string();
+                    let counts = analysis::term_frequencies(&text, &language, TERM_LIMIT);
+                    tx.send(WordFrequencyFinished(counts)).ok()
+                });
+                self.status_message = StatusMessage::AnalyzingText;
+                Changes::new(false, false, true)
+            }
+            FindDuplicates => {
+                let tx = match self.sender() {
+                    Ok(tx) => tx,
+                    Err(e) => return self.fail(e),
+                };
+                let text = self.document.text().clone();
+                thread::spawn(move || {
+                    let text = text.to_string();
+                    let duplicates = analysis::find_duplicates(&text, DUPLICATE_THRESHOLD);
+                    tx.send(DuplicatesFinished(duplicates)).ok()
+                });
+                self.status_message = StatusMessage::AnalyzingText;
                 Changes::new(false, false, true)
             }
+            DuplicatesFinished(duplicates) => {
+                self.duplicates = duplicates;
+                self.status_message = StatusMessage::None;
+                Changes {
+                    duplicates: true,
+                    ..Changes::new(false, false, true)
+                }
+            }
+            FindInFiles(query) => {
+                self.cancel_search();
+                self.search_serial += 1;
+                self.file_matches.clear();
+                self.search_query = query.clone();
+                let pattern = match query.compile() {
+                    Ok(pattern) => pattern,
+                    Err(e) => {
+                        warn!("Invalid search pattern {:?}, {}", query.pattern, e);
+                        self.toast = Some(Toast::InvalidPattern);
+                        return Changes {
+                            file_matches: true,
+                            toast: true,
+                            ..Changes::default()
+                        };
+                    }
+                };
+                let root = match self.search_root() {
+                    Some(root) => root,
+                    None => {
+                        return Changes {
+                            file_matches: true,
+                            ..Changes::default()
+                        }
+                    }
+                };
+                let tx = match self.sender() {
+                    Ok(tx) => tx,
+                    Err(e) => return self.fail(e),
+                };
+                let id = self.search_serial;
+                let cancel = Arc::new(AtomicBool::new(false));
+                self.search = Some(Search {
+                    id,
+                    cancel: cancel.clone(),
+                });
+                let filter = self.workspace_filter().with_included(query.included());
+                let context = Settings::new().search_context_lines();
+                thread::spawn(move || {
+                    workspace::search(&root, &filter, &pattern, context, |matches| {
+                        !cancel.load(Ordering::Relaxed)
+                            && tx.send(FindInFilesFound(id, matches)).is_ok()
+                    });
+                    tx.send(FindInFilesFinished(id)).ok()
+                });
+                self.status_message = StatusMessage::Searching;
+                Changes {
+                    file_matches: true,
+                    ..Changes::new(false, false, true)
+                }
+            }
+            FindInFilesFound(id, matches) => {
+                if !matches!(&self.search, Some(search) if search.id == id) {
+                    return Changes::default();
+                }
+                self.file_matches.push(matches);
+                Changes {
+                    file_matches: true,
+                    ..Changes::default()
+                }
+            }
+            FindInFilesFinished(id) => {
+                if !matches!(&self.search, Some(search) if search.id == id) {
+                    return Changes::default();
+                }
+                self.search = None;
+                self.status_message = StatusMessage::None;
+                Changes {
+                    file_matches: true,
+                    ..Changes::new(false, false, true)
+                }
+            }
+            CancelFindInFiles => {
+                if self.search.is_none() {
+                    return Changes::default();
+                }
+                self.cancel_search();
+                self.status_message = StatusMessage::None;
+                Changes {
+                    file_matches: true,
+                    ..Changes::new(false, false, true)
+                }
+            }
+            ReplaceInFile(path, replacement) => {
+                // Regular expressions may refer to their groups.
+                let expand = self.search_query.regex;
+                let pattern = match self.search_query.compile() {
+                    Ok(pattern) => pattern,
+                    Err(_) => return Changes::default(),
+                };
+                if self.document.filepath().as_ref() == Some(&path) {
+                    // The open file is changed in the editor, so the
+                    // replacement can be undone or left unsaved.
+                    if self.document.read_only() {
+                        self.toast = Some(Toast::ReadOnly);
+                        return Changes {
+                            toast: true,
+                            ..Changes::default()
+                        };
+                    }
+                    let text = self.document.text().to_string();
+                    let (replaced, count) =
+                        workspace::replace_text(&text, &pattern, &replacement, expand);
+                    self.document.update(&replaced);
+                    return Changes {
+                        text: true,
+                        ..self.update(ReplaceInFileFinished(Ok((path, count))))
+                    };
+                }
+                let tx = match self.sender() {
+                    Ok(tx) => tx,
+                    Err(e) => return self.fail(e),
+                };
+                let file = gio::File::for_path(&path);
+                let cancel = gio::Cancellable::new();
+                let job = self.jobs.start();
+                FileSystem::read(
+                    file.clone(),
+                    None,
+                    &cancel,
+                    |_, _| (),
+                    move |r| {
+                        let text = match r {
+                            Ok(text) => text,
+                            Err(e) => {
+                                tx.send(ReplaceInFileFinished(Err(e))).ok();
+                                return;
+                            }
+                        };
+                        let (replaced, count) =
+                            workspace::replace_text(&text, &pattern, &replacement, expand);
+                        let cancel = gio::Cancellable::new();
+                        FileSystem::write(
+                            file,
+                            replaced,
+                            false,
+                            &cancel,
+                            |_, _| (),
+                            move |r| {
+                                tx.send(ReplaceInFileFinished(r.map(|_| (path, count))))
+                                    .ok();
+                                drop(job);
+                            },
+                        );
+                    },
+                );
+                Changes::default()
+            }
+            ReplaceInFileFinished(Ok((path, count))) => {
+                // The replaced matches are gone, so is their group.
+                self.file_matches.retain(|m| m.path != path);
+                self.search_serial += 1;
+                self.toast = Some(Toast::Replaced(count));
+                Changes {
+                    file_matches: true,
+                    toast: true,
+                    ..Changes::default()
+                }
+            }
+            ReplaceInFileFinished(Err(_)) => {
+                self.toast = Some(Toast::ReplaceFailed);
+                Changes {
+                    toast: true,
+                    ..Changes::default()
+                }
+            }
+            WordFrequencyFinished(counts) => {
+                self.term_counts = counts;
+                self.status_message = StatusMessage::None;
+                Changes {
+                    term_counts: true,
+                    ..Changes::new(false, false, true)
+                }
+            }
         }
     }
+
+    fn cancel_search(&mut self) {
+        if let Some(search) = self.search.take() {
+            search.cancel.store(true, Ordering::Relaxed);
+        }
+    }
+
+    fn load(&mut self, file: gio::File, partial: bool, options: OpenOptions) -> Changes {
+        let mode = options.mode;
+        let tx = match self.sender() {
+            Ok(tx) => tx,
+            Err(e) => return self.fail(e),
+        };
+        if let Some(loading) = self.loading.take() {
+            loading.cancel.cancel();
+        }
+        let cancel = gio::Cancellable::new();
+        self.loading = Some(Loading {
+            file: file.clone(),
+            cancel: cancel.clone(),
+            partial,
+            mode,
+            goto: options.line.map(|line| (line, options.column.unwrap_or(1))),
+        });
+        // A partial load reads up to the size that would have warned.
+        let limit = if partial {
+            Some(Settings::new().large_file_bytes())
+        } else {
+            None
+        };
+        let progress = tx.clone();
+        let opened = file.clone();
+        let done = move |r: error::Result<String>| {
+            tx.send(FileOpenFinished(r.map(|contents| (opened, contents))))
+                .ok();
+        };
+        // pkexec only reaches local files, which are read as UTF-8.
+        if mode == OpenMode::Elevated {
+            match file.path() {
+                Some(path) => FileSystem::read_elevated(&path, &cancel, done),
+                None => done(Err(Error::NotLocal(file.uri().to_string()))),
+            }
+        } else {
+            FileSystem::read_in(
+                file,
+                limit,
+                options.encoding,
+                &cancel,
+                move |read, total| {
+                    progress
+                        .send(FileOpenProgress(read as f64 / total.max(1) as f64))
+                        .ok();
+                },
+                done,
+            );
+        }
+        self.progress = Some(0.0);
+        self.status_message = StatusMessage::OpeningFile;
+        Changes {
+            progress: true,
+            ..Changes::new(false, false, true)
+        }
+    }
+
+    // Remembers the file's current state as the one the document is based on.
+    fn record_stamp(&mut self) {
+        let stamp = self
+            .document
+            .filepath()
+            .and_then(|path| FileStamp::of(&path).ok());
+        self.document.set_stamp(stamp);
+    }
+
+    fn watch(&mut self, file: &gio::File) {
+        self.unwatch();
+        match file.monitor_file(gio::FileMonitorFlags::NONE, gio::NONE_CANCELLABLE) {
+            Ok(monitor) => {
+                let tx = match self.sender() {
+                    Ok(tx) => tx,
+                    Err(e) => {
+                        warn!("Failed to monitor {}, {}", file.uri(), e);
+                        return;
+                    }
+                };
+                monitor.connect_changed(move |_, file, _, event| {
+                    if event != gio::FileMonitorEvent::ChangesDoneHint
+                        && event != gio::FileMonitorEvent::Created
+                    {
+                        return;
+                    }
+                    let tx = tx.clone();
+                    let changed = file.clone();
+                    let cancel = gio::Cancellable::new();
+                    FileSystem::read(
+                        file.clone(),
+                        None,
+                        &cancel,
+                        |_, _| (),
+                        move |r| {
+                            tx.send(FileChangedOnDisk(r.map(|contents| (changed, contents))))
+                                .ok();
+                        },
+                    );
+                });
+                self.monitor = Some(monitor);
+            }
+            Err(e) => warn!("Failed to monitor {}, {}", file.uri(), e),
+        }
+    }
+
+    fn unwatch(&mut self) {
+        if let Some(monitor) = self.monitor.take() {
+            monitor.cancel();
+        }
+    }
+}
+
+// pkexec takes the whole text at once, so only direct writes report how
+// far along they are.
+fn write_file(
+    file: gio::File,
+    contents: String,
+    backup: bool,
+    elevated: bool,
+    cancel: &gio::Cancellable,
+    tx: Sender<Action>,
+    job: Job,
+) {
+    let progress = tx.clone();
+    let saved = file.clone();
+    let done = move |r: error::Result<String>| {
+        tx.send(FileSaveFinished(r.map(|contents| (saved, contents))))
+            .ok();
+        drop(job);
+    };
+    if elevated {
+        match file.path() {
+            Some(path) => FileSystem::write_elevated(&path, contents, cancel, done),
+            None => done(Err(Error::NotLocal(file.uri().to_string()))),
+        }
+    } else {
+        FileSystem::write(
+            file,
+            contents,
+            backup,
+            cancel,
+            move |written, total| {
+                progress
+                    .send(FileSaveProgress(written as f64 / total.max(1) as f64))
+                    .ok();
+            },
+            done,
+        );
+    }
+}
+
+// Notes go in ~/Notes unless another folder is chosen.
+fn notes_directory(settings: &Settings) -> PathBuf {
+    let home = glib::home_dir();
+    match settings.notes_directory() {
+        Some(dir) => FileSystem::expand_home(&dir, &home),
+        None => home.join("Notes"),
+    }
+}
+
+// New documents start from the template in the preferences, with `{date}`,
+// `{time}` and `{datetime}` filled in from the current time.
+fn new_document_text() -> String {
+    expand_template(&Settings::new().new_document_template())
+}
+
+// Fills in the date and time the document is created.
+fn expand_template(template: &str) -> String {
+    let now = match glib::DateTime::new_now_local() {
+        Ok(now) => now,
+        Err(_) => return template.to_string(),
+    };
+    let datetime_format = Settings::new().datetime_format();
+    template::expand(template, |name| {
+        let format = match name {
+            "date" => "%Y-%m-%d",
+            "time" => "%H:%M",
+            "datetime" => datetime_format.as_str(),
+            _ => return None,
+        };
+        now.format(format).ok().map(|text| text.to_string())
+    })
+}
+
+// The document to reopen on the next start, which is also the most
+// recently used.
+fn remember_document(path: Option<&Path>) {
+    let settings = gio::Settings::new(APP_ID);
+    if let Some(path) = path {
+        let recent: Vec<PathBuf> = settings
+            .strv(RECENT_DOCUMENTS)
+            .iter()
+            .map(|p| PathBuf::from(p.as_str()))
+            .collect();
+        let recent: Vec<String> = recent::touch(&recent, path, recent::LIMIT)
+            .iter()
+            .map(|p| p.to_string_lossy().to_string())
+            .collect();
+        let recent: Vec<&str> = recent.iter().map(String::as_str).collect();
+        if let Err(e) = settings.set_strv(RECENT_DOCUMENTS, &recent) {
+            warn!("Failed to update {}, {}", RECENT_DOCUMENTS, e);
+        }
+    }
+    let path = path
+        .map(|p| p.to_string_lossy().to_string())
+        .unwrap_or_default();
+    if let Err(e) = settings.set_string(LAST_DOCUMENT, &path) {
+        warn!("Failed to update {}, {}", LAST_DOCUMENT, e);
+    }
+}
+
+// Most recently used first, leaving out files that were since removed.
+pub fn recent_documents() -> Vec<PathBuf> {
+    gio::Settings::new(APP_ID)
+        .strv(RECENT_DOCUMENTS)
+        .iter()
+        .map(|p| PathBuf::from(p.as_str()))
+        .filter(|p| p.exists())
+        .collect()
+}
+
+// Per-file overrides take precedence over the global preference.
+fn auto_reload(path: &Path) -> bool {
+    let settings = gio::Settings::new(APP_ID);
+    let path = path.to_string_lossy();
+    let listed = |key: &str| settings.strv(key).iter().any(|p| p.as_str() == path);
+    if listed(AUTO_RELOAD_ALWAYS) {
+        true
+    } else if listed(AUTO_RELOAD_NEVER) {
+        false
+    } else {
+        Settings::new().auto_reload()
+    }
+}
+
+fn set_auto_reload(path: &Path, enabled: bool) {
+    let settings = gio::Settings::new(APP_ID);
+    let path = path.to_string_lossy().to_string();
+    let update = |key: &str, include: bool| {
+        let mut paths: Vec<String> = settings
+            .strv(key)
+            .iter()
+            .map(|p| p.to_string())
+            .filter(|p| p != &path)
+            .collect();
+        if include {
+            paths.push(path.clone());
+        }
+        let paths: Vec<&str> = paths.iter().map(String::as_str).collect();
+        if let Err(e) = settings.set_strv(key, &paths) {
+            warn!("Failed to update {}, {}", key, e);
+        }
+    };
+    update(AUTO_RELOAD_ALWAYS, enabled);
+    update(AUTO_RELOAD_NEVER, !enabled);
+}
+
+fn document_positions(settings: &gio::Settings) -> position::Saved {
+    settings
+        .value(DOCUMENT_POSITIONS)
+        .get::<position::Saved>()
+        .unwrap_or_default()
+}
+
+fn saved_position(path: &Path) -> Option<Position> {
+    if !Settings::new().restore_position() {
+        return None;
+    }
+    position::find(&document_positions(&gio::Settings::new(APP_ID)), path)
+}
+
+fn document_labels(settings: &gio::Settings) -> HashMap<String, String> {
+    settings
+        .value(DOCUMENT_LABELS)
+        .get::<HashMap<String, String>>()
+        .unwrap_or_default()
+}
+
+fn document_label(path: &Path) -> String {
+    let labels = document_labels(&gio::Settings::new(APP_ID));
+    labels
+        .get(path.to_string_lossy().as_ref())
+        .filter(|l| LABEL_COLORS.contains(&l.as_str()))
+        .cloned()
+        .unwrap_or_default()
+}
+
+fn set_document_label(path: &Path, label: &str) {
+    let settings = gio::Settings::new(APP_ID);
+    let mut labels = document_labels(&settings);
+    let path = path.to_string_lossy().to_string();
+    if LABEL_COLORS.contains(&label) {
+        labels.insert(path, label.to_string());
+    } else {
+        labels.remove(&path);
+    }
+    if let Err(e) = settings.set_value(DOCUMENT_LABELS, &labels.to_variant()) {
+        warn!("Failed to update {}, {}", DOCUMENT_LABELS, e);
+    }
 }
 
-struct FileSystem {}
+// Where user files such as the personal dictionary are kept.
+pub fn config_dir() -> PathBuf {
+    glib::user_config_dir().join("textedit2")
+}
+
+// Each file here is listed under New from Template.
+pub fn templates_dir() -> PathBuf {
+    glib::user_data_dir().join("textedit2").join("templates")
+}
+
+pub fn trusted_projects(settings: &gio::Settings) -> Vec<String> {
+    settings
+        .strv(TRUSTED_PROJECTS)
+        .iter()
+        .map(|p| p.to_string())
+        .collect()
+}
 
-impl FileSystem {
-    fn read_to_string(path: std::path::PathBuf, contents: &mut String) -> io::Result<()> {
-        let file = File::open(path)?;
-        let mut reader = io::BufReader::new(file);
-        reader.read_to_string(contents)?;
-        Ok(())
+pub fn set_project_trusted(root: &Path, trusted: bool) {
+    let settings = gio::Settings::new(APP_ID);
+    let root = root.to_string_lossy().to_string();
+    let mut roots: Vec<String> = trusted_projects(&settings)
+        .into_iter()
+        .filter(|p| p != &root)
+        .collect();
+    if trusted {
+        roots.push(root);
     }
-    fn write_string(path: std::path::PathBuf, contents: &str) -> io::Result<()> {
-        let mut file = File::create(path)?;
-        file.write_all(contents.as_bytes())?;
-        Ok(())
+    let roots: Vec<&str> = roots.iter().map(String::as_str).collect();
+    if let Err(e) = settings.set_strv(TRUSTED_PROJECTS, &roots) {
+        warn!("Failed to update {}, {}", TRUSTED_PROJECTS, e);
+    }
+}
+
+fn read_file(path: PathBuf) -> io::Result<String> {
+    let mut contents = String::new();
+    FileSystem::read_to_string(path, &mut contents)?;
+    Ok(contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn enabled(model: &ApplicationModel) -> [bool; 3] {
+        let capabilities = model.capabilities();
+        let find = |name| capabilities.iter().any(|&(n, on)| n == name && on);
+        [find("save"), find("revert"), find("open-containing-folder")]
+    }
+
+    #[test]
+    fn test_capabilities() {
+        let mut model = ApplicationModel::new();
+        assert_eq!([false, false, false], enabled(&model), "Untitled, empty");
+
+        model.document.reset_to("# Notes\n");
+        assert_eq!([true, false, false], enabled(&model), "Untitled, with text");
+
+        let file = gio::File::for_path("/home/ada/notes.txt");
+        model.document.open(file, "a\n".into());
+        assert_eq!([false, true, true], enabled(&model), "File");
+        model.document.update("b\n");
+        assert_eq!([true, true, true], enabled(&model), "Modified file");
+
+        let portal = glib::user_runtime_dir().join("doc/1a2b3c4d/notes.txt");
+        model
+            .document
+            .open(gio::File::for_path(portal), "a\n".into());
+        assert_eq!([false, true, false], enabled(&model), "Portal file");
+    }
+
+    // Saving trims the trailing space while "c" is typed.
+    #[test]
+    fn test_edit_while_saving() {
+        let file = gio::File::for_path("/home/ada/notes.txt");
+        let mut model = ApplicationModel::new();
+        model.document.open(file.clone(), "a".into());
+        model.document.insert(1, "b ");
+        model.saving = Some(Saving {
+            file: file.clone(),
+            cancel: gio::Cancellable::new(),
+            text: model.document.text().clone(),
+        });
+        model.document.insert(3, "c");
+        assert!(!model.saved(file.clone(), "ab".into()));
+        assert_eq!("ab c", model.document.text(), "Typing is kept");
+        assert_eq!("ab", model.document.original());
+        assert!(model.document.modified());
+
+        model.saving.as_mut().unwrap().text = model.document.text().clone();
+        assert!(model.saved(file, "ab c\n".into()));
+        assert_eq!("ab c\n", model.document.text(), "Formatted");
+        assert!(!model.document.modified());
     }
 }
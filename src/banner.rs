@@ -0,0 +1,144 @@
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+
+// Fonts are in the FIGlet format, drawn at full width without smushing.
+pub static BLOCK: Lazy<Font> = Lazy::new(|| Font::parse(include_str!("fonts/block.flf")).unwrap());
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Font {
+    height: usize,
+    glyphs: HashMap<char, Vec<String>>,
+}
+
+impl Font {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut lines = text.lines();
+        let header = lines.next().ok_or("Empty font")?;
+        let signature = header.get(..5).filter(|s| *s == "flf2a");
+        let hardblank = match (signature, header.chars().nth(5)) {
+            (Some(_), Some(c)) => c,
+            _ => return Err("Not a FIGlet font".into()),
+        };
+        let fields: Vec<usize> = header
+            .split_whitespace()
+            .skip(1)
+            .filter_map(|f| f.parse().ok())
+            .collect();
+        let (height, comments) = match fields.as_slice() {
+            [height, _, _, comments, ..] if *height > 0 => (*height, *comments),
+            _ => return Err("Invalid font header".into()),
+        };
+        let mut lines = lines.skip(comments);
+        let glyph = |lines: &mut dyn Iterator<Item = &str>| -> Option<Vec<String>> {
+            (0..height)
+                .map(|_| {
+                    let line = lines.next()?.trim_end();
+                    let endmark = line.chars().last()?;
+                    Some(line.trim_end_matches(endmark).replace(hardblank, " "))
+                })
+                .collect()
+        };
+        let mut glyphs = HashMap::new();
+        // The printable ASCII characters come first, in order, then any
+        // others tagged with their code.
+        for code in 32..=126u8 {
+            match glyph(&mut lines) {
+                Some(rows) => glyphs.insert(code as char, rows),
+                None => break,
+            };
+        }
+        while let Some(tag) = lines.next() {
+            let code = tag.split_whitespace().next().and_then(parse_code);
+            match (code.and_then(char::from_u32), glyph(&mut lines)) {
+                (Some(c), Some(rows)) => glyphs.insert(c, rows),
+                (None, Some(_)) => continue,
+                (_, None) => break,
+            };
+        }
+        if glyphs.is_empty() {
+            return Err("The font has no characters".into());
+        }
+        Ok(Self { height, glyphs })
+    }
+
+    // Each line of the text becomes a row of letters. Missing letters fall
+    // back to capitals, then to a question mark.
+    pub fn render(&self, text: &str) -> String {
+        let mut lines = Vec::new();
+        for line in text.lines() {
+            let mut rows = vec![String::new(); self.height];
+            for c in line.chars() {
+                let c = if c.is_whitespace() { ' ' } else { c };
+                let glyph = self
+                    .glyphs
+                    .get(&c)
+                    .or_else(|| c.to_uppercase().next().and_then(|u| self.glyphs.get(&u)))
+                    .or_else(|| self.glyphs.get(&'?'));
+                if let Some(glyph) = glyph {
+                    for (row, part) in rows.iter_mut().zip(glyph) {
+                        row.push_str(part);
+                    }
+                }
+            }
+            lines.extend(rows.iter().map(|row| row.trim_end().to_string()));
+        }
+        lines.join("\n")
+    }
+}
+
+fn parse_code(code: &str) -> Option<u32> {
+    if let Some(hex) = code.strip_prefix("0x").or_else(|| code.strip_prefix("0X")) {
+        u32::from_str_radix(hex, 16).ok()
+    } else if code.len() > 1 && code.starts_with('0') {
+        u32::from_str_radix(&code[1..], 8).ok()
+    } else {
+        code.parse().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        // Every printable character is a letter x, except for the space.
+        let mut font = String::from("flf2a$ 2 2 4 -1 1\nA comment\n$$@\n$$@@\n");
+        for _ in 33..=126 {
+            font.push_str("x$@\nx$@@\n");
+        }
+        font.push_str("0x263A smile\n:)@\n##@@\n");
+        let font = Font::parse(&font).unwrap();
+        assert_eq!(2, font.height);
+        assert_eq!(
+            Some(&vec!["x ".to_string(), "x ".to_string()]),
+            font.glyphs.get(&'!')
+        );
+        assert_eq!(
+            Some(&vec![":)".to_string(), "##".to_string()]),
+            font.glyphs.get(&'\u{263a}'),
+            "Code tagged"
+        );
+        assert_eq!("x   x\nx   x", font.render("! !"));
+        assert!(Font::parse("not a font").is_err());
+        assert!(Font::parse("flf2a$ 0 0 0 0 0").is_err());
+    }
+
+    #[test]
+    fn test_render_block() {
+        assert_eq!(
+            "#   # ###\n\
+             #   #  #\n\
+             ##### \x20#\n\
+             #   #  #\n\
+             #   # ###",
+            BLOCK.render("hi")
+        );
+        assert_eq!(
+            10,
+            BLOCK.render("a\nb").lines().count(),
+            "One banner per line"
+        );
+        assert_eq!(BLOCK.render("?"), BLOCK.render("é"), "Unknown characters");
+    }
+}
@@ -0,0 +1,164 @@
+// Characters offered by the character map, by their Unicode names.
+pub const SYMBOLS: [(char, &str); 96] = [
+    ('←', "LEFTWARDS ARROW"),
+    ('↑', "UPWARDS ARROW"),
+    ('→', "RIGHTWARDS ARROW"),
+    ('↓', "DOWNWARDS ARROW"),
+    ('↔', "LEFT RIGHT ARROW"),
+    ('↕', "UP DOWN ARROW"),
+    ('⇐', "LEFTWARDS DOUBLE ARROW"),
+    ('⇒', "RIGHTWARDS DOUBLE ARROW"),
+    ('⇔', "LEFT RIGHT DOUBLE ARROW"),
+    ('↩', "LEFTWARDS ARROW WITH HOOK"),
+    ('↵', "DOWNWARDS ARROW WITH CORNER LEFTWARDS"),
+    ('‐', "HYPHEN"),
+    ('‑', "NON-BREAKING HYPHEN"),
+    ('‒', "FIGURE DASH"),
+    ('–', "EN DASH"),
+    ('—', "EM DASH"),
+    ('―', "HORIZONTAL BAR"),
+    ('−', "MINUS SIGN"),
+    ('…', "HORIZONTAL ELLIPSIS"),
+    ('•', "BULLET"),
+    ('·', "MIDDLE DOT"),
+    ('‘', "LEFT SINGLE QUOTATION MARK"),
+    ('’', "RIGHT SINGLE QUOTATION MARK"),
+    ('“', "LEFT DOUBLE QUOTATION MARK"),
+    ('”', "RIGHT DOUBLE QUOTATION MARK"),
+    ('«', "LEFT-POINTING DOUBLE ANGLE QUOTATION MARK"),
+    ('»', "RIGHT-POINTING DOUBLE ANGLE QUOTATION MARK"),
+    ('§', "SECTION SIGN"),
+    ('¶', "PILCROW SIGN"),
+    ('†', "DAGGER"),
+    ('‡', "DOUBLE DAGGER"),
+    ('©', "COPYRIGHT SIGN"),
+    ('®', "REGISTERED SIGN"),
+    ('™', "TRADE MARK SIGN"),
+    ('°', "DEGREE SIGN"),
+    ('×', "MULTIPLICATION SIGN"),
+    ('÷', "DIVISION SIGN"),
+    ('±', "PLUS-MINUS SIGN"),
+    ('≈', "ALMOST EQUAL TO"),
+    ('≠', "NOT EQUAL TO"),
+    ('≤', "LESS-THAN OR EQUAL TO"),
+    ('≥', "GREATER-THAN OR EQUAL TO"),
+    ('∞', "INFINITY"),
+    ('√', "SQUARE ROOT"),
+    ('∑', "N-ARY SUMMATION"),
+    ('∏', "N-ARY PRODUCT"),
+    ('∫', "INTEGRAL"),
+    ('∂', "PARTIAL DIFFERENTIAL"),
+    ('∆', "INCREMENT"),
+    ('∇', "NABLA"),
+    ('∈', "ELEMENT OF"),
+    ('∉', "NOT AN ELEMENT OF"),
+    ('∩', "INTERSECTION"),
+    ('∪', "UNION"),
+    ('⊂', "SUBSET OF"),
+    ('∅', "EMPTY SET"),
+    ('∀', "FOR ALL"),
+    ('∃', "THERE EXISTS"),
+    ('¬', "NOT SIGN"),
+    ('∧', "LOGICAL AND"),
+    ('∨', "LOGICAL OR"),
+    ('½', "VULGAR FRACTION ONE HALF"),
+    ('¼', "VULGAR FRACTION ONE QUARTER"),
+    ('¾', "VULGAR FRACTION THREE QUARTERS"),
+    ('²', "SUPERSCRIPT TWO"),
+    ('³', "SUPERSCRIPT THREE"),
+    ('µ', "MICRO SIGN"),
+    ('α', "GREEK SMALL LETTER ALPHA"),
+    ('β', "GREEK SMALL LETTER BETA"),
+    ('γ', "GREEK SMALL LETTER GAMMA"),
+    ('δ', "GREEK SMALL LETTER DELTA"),
+    ('λ', "GREEK SMALL LETTER LAMDA"),
+    ('π', "GREEK SMALL LETTER PI"),
+    ('σ', "GREEK SMALL LETTER SIGMA"),
+    ('Ω', "GREEK CAPITAL LETTER OMEGA"),
+    ('€', "EURO SIGN"),
+    ('£', "POUND SIGN"),
+    ('¥', "YEN SIGN"),
+    ('¢', "CENT SIGN"),
+    ('✓', "CHECK MARK"),
+    ('✗', "BALLOT X"),
+    ('★', "BLACK STAR"),
+    ('á', "LATIN SMALL LETTER A WITH ACUTE"),
+    ('à', "LATIN SMALL LETTER A WITH GRAVE"),
+    ('â', "LATIN SMALL LETTER A WITH CIRCUMFLEX"),
+    ('ä', "LATIN SMALL LETTER A WITH DIAERESIS"),
+    ('å', "LATIN SMALL LETTER A WITH RING ABOVE"),
+    ('æ', "LATIN SMALL LETTER AE"),
+    ('ç', "LATIN SMALL LETTER C WITH CEDILLA"),
+    ('é', "LATIN SMALL LETTER E WITH ACUTE"),
+    ('è', "LATIN SMALL LETTER E WITH GRAVE"),
+    ('ñ', "LATIN SMALL LETTER N WITH TILDE"),
+    ('ö', "LATIN SMALL LETTER O WITH DIAERESIS"),
+    ('ø', "LATIN SMALL LETTER O WITH STROKE"),
+    ('ü', "LATIN SMALL LETTER U WITH DIAERESIS"),
+    ('ß', "LATIN SMALL LETTER SHARP S"),
+];
+
+pub fn name(c: char) -> Option<&'static str> {
+    SYMBOLS.iter().find(|(s, _)| *s == c).map(|(_, name)| *name)
+}
+
+// `U+2192` and `u+2192` give that character, whether or not it is listed.
+// Otherwise the characters whose names have every word of the query.
+pub fn search(query: &str) -> Vec<char> {
+    let query = query.trim();
+    let code = query
+        .strip_prefix("U+")
+        .or_else(|| query.strip_prefix("u+"))
+        .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+        .and_then(char::from_u32);
+    if let Some(c) = code {
+        return vec![c];
+    }
+    let words: Vec<String> = query.split_whitespace().map(str::to_uppercase).collect();
+    SYMBOLS
+        .iter()
+        .filter(|(c, name)| {
+            words
+                .iter()
+                .all(|word| name.split([' ', '-']).any(|w| w.starts_with(word.as_str())))
+                || query.chars().eq(std::iter::once(*c))
+        })
+        .map(|(c, _)| *c)
+        .collect()
+}
+
+// Pinned characters are stored as strings, in the order they were pinned.
+// Pinning one that is already pinned unpins it.
+pub fn toggle_pin(pinned: &[String], c: char) -> Vec<String> {
+    let c = c.to_string();
+    if pinned.contains(&c) {
+        pinned.iter().filter(|p| **p != c).cloned().collect()
+    } else {
+        pinned.iter().cloned().chain(std::iter::once(c)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_search() {
+        assert_eq!(vec!['→', '↔', '⇒', '⇔'], search("right arrow"));
+        assert_eq!(vec!['–'], search("en dash"));
+        assert_eq!(vec!['∅'], search("emp"), "Words from their start");
+        assert_eq!(vec!['é'], search("é"), "The character itself");
+        assert_eq!(vec!['\u{2603}'], search("U+2603"), "Not listed");
+        assert_eq!(SYMBOLS.len(), search("").len());
+        assert!(search("zebra").is_empty());
+        assert_eq!(Some("EM DASH"), name('—'));
+    }
+
+    #[test]
+    fn test_toggle_pin() {
+        let pinned = toggle_pin(&[], '→');
+        let pinned = toggle_pin(&pinned, '—');
+        assert_eq!(vec!["→".to_string(), "—".into()], pinned);
+        assert_eq!(vec!["—".to_string()], toggle_pin(&pinned, '→'));
+    }
+}
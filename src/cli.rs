@@ -0,0 +1,56 @@
+use std::ffi::{OsStr, OsString};
+
+// Stands for standard input where a file is named, as in `cmd | textedit2 -`.
+pub const STDIN_ARG: &str = "-";
+
+// Files named on the command line, each with the line a `+N` before it
+// asked for, as in vi and gedit. A `+` without a number is a file name.
+pub fn file_args(args: &[OsString]) -> Vec<(OsString, Option<usize>)> {
+    let mut files = Vec::new();
+    let mut line = None;
+    for arg in args {
+        match line_arg(arg) {
+            Some(number) => line = Some(number),
+            None => files.push((arg.clone(), line.take())),
+        }
+    }
+    files
+}
+
+fn line_arg(arg: &OsStr) -> Option<usize> {
+    let number = arg.to_str()?.strip_prefix('+')?;
+    if number.is_empty() || !number.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    number.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<OsString> {
+        args.iter().map(OsString::from).collect()
+    }
+
+    #[test]
+    fn test_file_args() {
+        assert_eq!(
+            vec![
+                (OsString::from("a.txt"), Some(12)),
+                (OsString::from("b.txt"), None),
+            ],
+            file_args(&args(&["+12", "a.txt", "b.txt"]))
+        );
+        assert_eq!(
+            vec![(OsString::from("+"), None), (OsString::from("+x"), None)],
+            file_args(&args(&["+", "+x"])),
+            "Not line numbers"
+        );
+        assert!(file_args(&args(&["+3"])).is_empty(), "No file to go with");
+        assert_eq!(
+            vec![(OsString::from(STDIN_ARG), Some(2))],
+            file_args(&args(&["+2", "-"]))
+        );
+    }
+}
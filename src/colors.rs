@@ -0,0 +1,296 @@
+use once_cell::sync::Lazy;
+use regex::{Captures, Regex};
+
+// A hex color must not follow a word character, so `a#fff` or an anchor
+// like `page#top` are not colors.
+static COLOR_LITERAL: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?i)\B#([0-9a-f]{3,8})\b|\b(rgba?|hsla?)\(([^()]*)\)").unwrap());
+
+// Components range from 0 to 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rgba {
+    pub red: f64,
+    pub green: f64,
+    pub blue: f64,
+    pub alpha: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Notation {
+    Hex,
+    Rgb,
+    Hsl,
+}
+
+// A color written in the text, with character offsets for the buffer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColorLiteral {
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+    pub notation: Notation,
+    pub color: Rgba,
+}
+
+pub fn find_colors(text: &str) -> Vec<ColorLiteral> {
+    let mut chars = 0;
+    let mut counted = 0;
+    let mut char_offset = |byte: usize| {
+        chars += text[counted..byte].chars().count();
+        counted = byte;
+        chars
+    };
+    COLOR_LITERAL
+        .captures_iter(text)
+        .filter_map(|captures| {
+            let (notation, color) = parse(&captures)?;
+            let whole = captures.get(0)?;
+            Some(ColorLiteral {
+                start: char_offset(whole.start()),
+                end: char_offset(whole.end()),
+                text: whole.as_str().to_string(),
+                notation,
+                color,
+            })
+        })
+        .collect()
+}
+
+// The literal at a character offset, including just past its end.
+pub fn color_at(text: &str, offset: usize) -> Option<ColorLiteral> {
+    find_colors(text)
+        .into_iter()
+        .find(|c| c.start <= offset && offset <= c.end)
+}
+
+fn parse(captures: &Captures) -> Option<(Notation, Rgba)> {
+    if let Some(hex) = captures.get(1) {
+        return Some((Notation::Hex, parse_hex(hex.as_str())?));
+    }
+    let function = captures.get(2)?.as_str().to_lowercase();
+    let args: Vec<&str> = captures
+        .get(3)?
+        .as_str()
+        .split(|c: char| c == ',' || c == '/' || c.is_whitespace())
+        .filter(|a| !a.is_empty())
+        .collect();
+    let alpha = match args.len() {
+        3 => 1.0,
+        4 => fraction(args[3], 1.0)?,
+        _ => return None,
+    };
+    if function.starts_with("rgb") {
+        let channel = |arg| fraction(arg, 255.0);
+        let color = Rgba {
+            red: channel(args[0])?,
+            green: channel(args[1])?,
+            blue: channel(args[2])?,
+            alpha,
+        };
+        Some((Notation::Rgb, color))
+    } else {
+        let hue = args[0].trim_end_matches("deg").parse::<f64>().ok()?;
+        let color = from_hsl(
+            hue,
+            fraction(args[1], 100.0)?,
+            fraction(args[2], 100.0)?,
+            alpha,
+        );
+        Some((Notation::Hsl, color))
+    }
+}
+
+fn parse_hex(hex: &str) -> Option<Rgba> {
+    let digits: Vec<u8> = hex
+        .chars()
+        .map(|c| c.to_digit(16).map(|d| d as u8))
+        .collect::<Option<_>>()?;
+    let channels: Vec<u8> = match digits.len() {
+        3 | 4 => digits.iter().map(|d| d * 17).collect(),
+        6 | 8 => digits.chunks(2).map(|p| p[0] * 16 + p[1]).collect(),
+        _ => return None,
+    };
+    let channel = |i: usize| f64::from(channels[i]) / 255.0;
+    Some(Rgba {
+        red: channel(0),
+        green: channel(1),
+        blue: channel(2),
+        alpha: if channels.len() == 4 { channel(3) } else { 1.0 },
+    })
+}
+
+// A number out of `scale`, or a percentage, clamped to 0 to 1.
+fn fraction(arg: &str, scale: f64) -> Option<f64> {
+    let value = match arg.strip_suffix('%') {
+        Some(percent) => percent.parse::<f64>().ok()? / 100.0,
+        None => arg.parse::<f64>().ok()? / scale,
+    };
+    Some(value.clamp(0.0, 1.0))
+}
+
+fn from_hsl(hue: f64, saturation: f64, lightness: f64, alpha: f64) -> Rgba {
+    let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+    let sector = hue.rem_euclid(360.0) / 60.0;
+    let x = chroma * (1.0 - (sector % 2.0 - 1.0).abs());
+    let (red, green, blue) = match sector as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let m = lightness - chroma / 2.0;
+    Rgba {
+        red: red + m,
+        green: green + m,
+        blue: blue + m,
+        alpha,
+    }
+}
+
+// Hue in degrees, saturation and lightness from 0 to 1.
+fn to_hsl(color: Rgba) -> (f64, f64, f64) {
+    let max = color.red.max(color.green).max(color.blue);
+    let min = color.red.min(color.green).min(color.blue);
+    let lightness = (max + min) / 2.0;
+    let chroma = max - min;
+    if chroma == 0.0 {
+        return (0.0, 0.0, lightness);
+    }
+    let saturation = chroma / (1.0 - (2.0 * lightness - 1.0).abs());
+    let hue = if max == color.red {
+        ((color.green - color.blue) / chroma).rem_euclid(6.0)
+    } else if max == color.green {
+        (color.blue - color.red) / chroma + 2.0
+    } else {
+        (color.red - color.green) / chroma + 4.0
+    };
+    (hue * 60.0, saturation, lightness)
+}
+
+// Writes the color back in the notation the literal used.
+pub fn format(color: Rgba, notation: Notation) -> String {
+    let byte = |c: f64| (c * 255.0).round() as u8;
+    let opaque = byte(color.alpha) == 255;
+    let alpha = format!("{:.2}", color.alpha)
+        .trim_end_matches('0')
+        .trim_end_matches('.')
+        .to_string();
+    match (notation, opaque) {
+        (Notation::Hex, true) => format!(
+            "#{:02x}{:02x}{:02x}",
+            byte(color.red),
+            byte(color.green),
+            byte(color.blue)
+        ),
+        (Notation::Hex, false) => format!(
+            "#{:02x}{:02x}{:02x}{:02x}",
+            byte(color.red),
+            byte(color.green),
+            byte(color.blue),
+            byte(color.alpha)
+        ),
+        (Notation::Rgb, true) => format!(
+            "rgb({}, {}, {})",
+            byte(color.red),
+            byte(color.green),
+            byte(color.blue)
+        ),
+        (Notation::Rgb, false) => format!(
+            "rgba({}, {}, {}, {})",
+            byte(color.red),
+            byte(color.green),
+            byte(color.blue),
+            alpha
+        ),
+        (Notation::Hsl, _) => {
+            let (hue, saturation, lightness) = to_hsl(color);
+            let components = format!(
+                "{}, {}%, {}%",
+                hue.round(),
+                (saturation * 100.0).round(),
+                (lightness * 100.0).round()
+            );
+            if opaque {
+                format!("hsl({})", components)
+            } else {
+                format!("hsla({}, {})", components, alpha)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rgba(red: f64, green: f64, blue: f64, alpha: f64) -> Rgba {
+        Rgba {
+            red,
+            green,
+            blue,
+            alpha,
+        }
+    }
+
+    #[test]
+    fn test_find_colors() {
+        let css = "é { color: #F00; background: rgba(0, 0, 255, 50%); }";
+        let colors = find_colors(css);
+        assert_eq!(2, colors.len());
+        assert_eq!(
+            ColorLiteral {
+                start: 11,
+                end: 15,
+                text: "#F00".into(),
+                notation: Notation::Hex,
+                color: rgba(1.0, 0.0, 0.0, 1.0),
+            },
+            colors[0],
+            "Offsets count characters"
+        );
+        assert_eq!(Notation::Rgb, colors[1].notation);
+        assert_eq!(rgba(0.0, 0.0, 1.0, 0.5), colors[1].color);
+        assert_eq!(Some(colors[0].clone()), color_at(css, 15));
+        assert_eq!(None, color_at(css, 5));
+    }
+
+    #[test]
+    fn test_find_colors_invalid() {
+        assert!(find_colors("#abcde").is_empty(), "Five hex digits");
+        assert!(find_colors("page#fff").is_empty(), "Part of a word");
+        assert!(find_colors("#fffffffff").is_empty(), "Too long");
+        assert!(find_colors("rgb(1, 2)").is_empty(), "Too few components");
+        assert!(find_colors("rgb(a, b, c)").is_empty());
+    }
+
+    #[test]
+    fn test_parse() {
+        let color = |text: &str| find_colors(text).pop().map(|c| c.color);
+        assert_eq!(Some(rgba(1.0, 1.0, 1.0, 0.0)), color("#ffffff00"));
+        assert_eq!(Some(rgba(0.0, 1.0, 0.0, 1.0)), color("hsl(120, 100%, 50%)"));
+        assert_eq!(
+            Some(rgba(1.0, 0.0, 0.0, 0.25)),
+            color("rgb(255 0 0 / 25%)"),
+            "Space separated components"
+        );
+        assert_eq!(
+            Some(rgba(1.0, 0.0, 0.0, 1.0)),
+            color("rgb(300, -5, 0)"),
+            "Out of range components are clamped"
+        );
+    }
+
+    #[test]
+    fn test_format() {
+        let orange = rgba(1.0, 0.6, 0.0, 1.0);
+        assert_eq!("#ff9900", format(orange, Notation::Hex));
+        assert_eq!("rgb(255, 153, 0)", format(orange, Notation::Rgb));
+        assert_eq!("hsl(36, 100%, 50%)", format(orange, Notation::Hsl));
+        let clear = rgba(0.0, 0.0, 1.0, 0.5);
+        assert_eq!("#0000ff80", format(clear, Notation::Hex));
+        assert_eq!("rgba(0, 0, 255, 0.5)", format(clear, Notation::Rgb));
+        assert_eq!("hsla(240, 100%, 50%, 0.5)", format(clear, Notation::Hsl));
+    }
+}
@@ -0,0 +1,134 @@
+use std::path::Path;
+
+// Line comment tokens by file extension, or by name for files that have
+// none. Languages with only block comments, such as CSS, are left out.
+const TOKENS: [(&str, &[&str]); 6] = [
+    (
+        "//",
+        &[
+            "c", "cc", "cpp", "cs", "dart", "go", "h", "hpp", "java", "js", "jsx", "kt", "php",
+            "rs", "scss", "swift", "ts", "tsx", "vala", "zig",
+        ],
+    ),
+    (
+        "#",
+        &[
+            "bash",
+            "cfg",
+            "cmake",
+            "conf",
+            "desktop",
+            "Dockerfile",
+            "fish",
+            "Makefile",
+            "nix",
+            "pl",
+            "py",
+            "r",
+            "rb",
+            "sh",
+            "toml",
+            "yaml",
+            "yml",
+            "zsh",
+        ],
+    ),
+    ("--", &["hs", "lua", "sql"]),
+    (";", &["asm", "clj", "el", "ini", "lisp", "scm"]),
+    ("%", &["erl", "m", "tex"]),
+    ("\"", &["vim"]),
+];
+
+// The comment token for a file, if its language is known.
+pub fn token_for(path: &Path) -> Option<&'static str> {
+    let key = match path.extension() {
+        Some(extension) => extension.to_str()?,
+        None => path.file_name()?.to_str()?,
+    };
+    token_for_language(key)
+}
+
+// Languages are named by their file extension.
+pub fn token_for_language(key: &str) -> Option<&'static str> {
+    TOKENS
+        .iter()
+        .find(|(_, keys)| keys.contains(&key))
+        .map(|(token, _)| *token)
+}
+
+// Uncomments the lines when every line with text is commented, and
+// otherwise comments them, with the tokens lined up at the smallest
+// indentation. Blank lines are left alone.
+pub fn toggle(text: &str, token: &str) -> String {
+    let lines: Vec<&str> = text.split('\n').collect();
+    let filled = || lines.iter().filter(|l| !l.trim().is_empty());
+    let commented = filled().all(|l| l.trim_start().starts_with(token));
+    let indent = filled()
+        .map(|l| l.len() - l.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    lines
+        .iter()
+        .map(|line| {
+            if line.trim().is_empty() {
+                line.to_string()
+            } else if commented {
+                let start = line.len() - line.trim_start().len();
+                let rest = &line[start + token.len()..];
+                format!(
+                    "{}{}",
+                    &line[..start],
+                    rest.strip_prefix(' ').unwrap_or(rest)
+                )
+            } else {
+                format!("{}{} {}", &line[..indent], token, &line[indent..])
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_for() {
+        assert_eq!(Some("//"), token_for(Path::new("src/main.rs")));
+        assert_eq!(Some("#"), token_for(Path::new("/home/user/Makefile")));
+        assert_eq!(Some("--"), token_for(Path::new("schema.sql")));
+        assert_eq!(
+            None,
+            token_for(Path::new("style.css")),
+            "Block comments only"
+        );
+        assert_eq!(None, token_for(Path::new("notes")));
+        assert_eq!(Some("#"), token_for_language("py"));
+    }
+
+    #[test]
+    fn test_comment() {
+        assert_eq!(
+            "    // let a = 1;\n\n    //     b();\n",
+            toggle("    let a = 1;\n\n        b();\n", "//"),
+            "Lined up at the smallest indentation"
+        );
+        assert_eq!(
+            "# a\n# # b",
+            toggle("a\n# b", "#"),
+            "Mixed lines are all commented"
+        );
+    }
+
+    #[test]
+    fn test_uncomment() {
+        assert_eq!(
+            "    let a = 1;\n\n        b();",
+            toggle("    // let a = 1;\n\n    //     b();", "//")
+        );
+        assert_eq!("a\n  b", toggle("#a\n  # b", "#"), "The space is optional");
+        let text = "x = 1\n  y = 2\n";
+        assert_eq!(text, toggle(&toggle(text, "--"), "--"));
+        assert_eq!("  ", toggle("  ", "#"), "Only blank lines");
+    }
+}
@@ -0,0 +1,154 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+// Names a folder's readme goes by, in order of preference.
+const READMES: [&str; 5] = [
+    "README.md",
+    "README",
+    "README.txt",
+    "README.rst",
+    "readme.md",
+];
+// Folders that tests are kept in, next to the source or above it.
+const TEST_DIRS: [&str; 2] = ["tests", "test"];
+
+// Files that belong with `path` and exist: the same name with an extension
+// from `rules`, such as `file.h` for `file.c`, the tests of a source file or
+// the source of a test, and the folder's readme. `rules` lists companion
+// extensions by extension, separated by commas.
+pub fn find<E>(path: &Path, rules: &HashMap<String, String>, exists: E) -> Vec<PathBuf>
+where
+    E: Fn(&Path) -> bool,
+{
+    let dir = match path.parent() {
+        Some(dir) => dir,
+        None => return Vec::new(),
+    };
+    let mut candidates = Vec::new();
+    let name = path.file_stem().and_then(|s| s.to_str());
+    let extension = path.extension().and_then(|e| e.to_str());
+    if let (Some(stem), Some(extension)) = (name, extension) {
+        if let Some(companions) = rules.get(&extension.to_lowercase()) {
+            let companions = companions.split(',').map(str::trim);
+            for companion in companions.filter(|c| !c.is_empty()) {
+                candidates.push(dir.join(format!("{}.{}", stem, companion)));
+            }
+        }
+        candidates.extend(tests_of(dir, stem, extension));
+        candidates.extend(source_of(dir, stem, extension));
+    }
+    let mut found: Vec<PathBuf> = Vec::new();
+    for candidate in candidates {
+        if candidate != path && !found.contains(&candidate) && exists(&candidate) {
+            found.push(candidate);
+        }
+    }
+    // A readme is not a companion of another.
+    if !READMES.iter().any(|name| path.ends_with(name)) {
+        let readme = READMES
+            .iter()
+            .map(|name| dir.join(name))
+            .find(|r| exists(r));
+        found.extend(readme);
+    }
+    found
+}
+
+// The naming conventions of Go, Python and JavaScript, and test folders.
+fn tests_of(dir: &Path, stem: &str, extension: &str) -> Vec<PathBuf> {
+    let mut tests = vec![
+        dir.join(format!("{}_test.{}", stem, extension)),
+        dir.join(format!("test_{}.{}", stem, extension)),
+        dir.join(format!("{}.test.{}", stem, extension)),
+        dir.join(format!("{}.spec.{}", stem, extension)),
+    ];
+    let parents = [Some(dir), dir.parent()];
+    for parent in parents.iter().flatten() {
+        for tests_dir in TEST_DIRS.iter() {
+            let tests_dir = parent.join(tests_dir);
+            if tests_dir != dir {
+                tests.push(tests_dir.join(format!("{}.{}", stem, extension)));
+                tests.push(tests_dir.join(format!("test_{}.{}", stem, extension)));
+            }
+        }
+    }
+    tests
+}
+
+fn source_of(dir: &Path, stem: &str, extension: &str) -> Vec<PathBuf> {
+    let source = stem
+        .strip_suffix("_test")
+        .or_else(|| stem.strip_prefix("test_"))
+        .or_else(|| stem.strip_suffix(".test"))
+        .or_else(|| stem.strip_suffix(".spec"))
+        .unwrap_or(stem);
+    let name = format!("{}.{}", source, extension);
+    let mut sources = Vec::new();
+    if source != stem {
+        sources.push(dir.join(&name));
+    }
+    let in_tests = matches!(dir.file_name(), Some(d) if TEST_DIRS.iter().any(|t| d == *t));
+    if let Some(parent) = dir.parent().filter(|_| in_tests) {
+        sources.push(parent.join(&name));
+        sources.push(parent.join("src").join(&name));
+    }
+    sources
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find_in(path: &str, files: &[&str]) -> Vec<PathBuf> {
+        let mut rules = HashMap::new();
+        rules.insert("c".to_string(), "h".to_string());
+        rules.insert("h".to_string(), "c, cpp".to_string());
+        find(Path::new(path), &rules, |p| {
+            files.iter().any(|f| Path::new(f) == p)
+        })
+    }
+
+    #[test]
+    fn test_find() {
+        let files = ["/p/list.c", "/p/list.h", "/p/README.md", "/p/README"];
+        assert_eq!(
+            vec![PathBuf::from("/p/list.h"), PathBuf::from("/p/README.md")],
+            find_in("/p/list.c", &files)
+        );
+        assert!(find_in("/p/README.md", &files).is_empty(), "Readmes");
+        assert_eq!(
+            vec![PathBuf::from("/p/LIST.c")],
+            find_in("/p/LIST.H", &["/p/LIST.c"]),
+            "Extensions in either case"
+        );
+        assert!(find_in("/p/notes.txt", &["/p/notes.md"]).is_empty());
+    }
+
+    #[test]
+    fn test_find_tests() {
+        assert_eq!(
+            vec![PathBuf::from("/p/parse_test.go")],
+            find_in("/p/parse.go", &["/p/parse_test.go"])
+        );
+        assert_eq!(
+            vec![PathBuf::from("/p/parse.go")],
+            find_in("/p/parse_test.go", &["/p/parse.go"])
+        );
+        assert_eq!(
+            vec![PathBuf::from("/p/app.spec.ts")],
+            find_in("/p/app.ts", &["/p/app.spec.ts"])
+        );
+        assert_eq!(
+            vec![PathBuf::from("/p/tests/test_util.py")],
+            find_in("/p/util.py", &["/p/tests/test_util.py"])
+        );
+        assert_eq!(
+            vec![PathBuf::from("/p/tests/diff.rs")],
+            find_in("/p/src/diff.rs", &["/p/tests/diff.rs"])
+        );
+        assert_eq!(
+            vec![PathBuf::from("/p/src/diff.rs")],
+            find_in("/p/tests/diff.rs", &["/p/src/diff.rs"])
+        );
+    }
+}
@@ -0,0 +1,509 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffLine<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+impl<'a> DiffLine<'a> {
+    pub fn text(&self) -> &'a str {
+        match self {
+            DiffLine::Same(s) | DiffLine::Removed(s) | DiffLine::Added(s) => s,
+        }
+    }
+    pub fn prefix(&self) -> char {
+        match self {
+            DiffLine::Same(_) => ' ',
+            DiffLine::Removed(_) => '-',
+            DiffLine::Added(_) => '+',
+        }
+    }
+}
+
+pub fn diff_lines<'a>(old: &'a str, new: &'a str) -> Vec<DiffLine<'a>> {
+    let a: Vec<&str> = old.lines().collect();
+    let b: Vec<&str> = new.lines().collect();
+    let prefix = a.iter().zip(b.iter()).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let mut result: Vec<DiffLine> = a[..prefix].iter().map(|s| DiffLine::Same(s)).collect();
+    result.extend(myers(
+        &a[prefix..a.len() - suffix],
+        &b[prefix..b.len() - suffix],
+    ));
+    result.extend(a[a.len() - suffix..].iter().map(|s| DiffLine::Same(s)));
+    result
+}
+
+pub fn has_changes(lines: &[DiffLine]) -> bool {
+    lines.iter().any(|l| !matches!(l, DiffLine::Same(_)))
+}
+
+// A unified diff as `diff -u` writes it, with `context` lines around each
+// change, or nothing when the texts have the same lines.
+pub fn unified(old_name: &str, new_name: &str, old: &str, new: &str, context: usize) -> String {
+    let lines = diff_lines(old, new);
+    if !has_changes(&lines) {
+        return String::new();
+    }
+    // How many lines of each side come before each line of the diff.
+    let mut before = Vec::with_capacity(lines.len() + 1);
+    let (mut o, mut n) = (0, 0);
+    for line in &lines {
+        before.push((o, n));
+        match line {
+            DiffLine::Same(_) => {
+                o += 1;
+                n += 1;
+            }
+            DiffLine::Removed(_) => o += 1,
+            DiffLine::Added(_) => n += 1,
+        }
+    }
+    before.push((o, n));
+    let (old_len, new_len) = (o, n);
+
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for (i, _) in lines
+        .iter()
+        .enumerate()
+        .filter(|(_, l)| !matches!(l, DiffLine::Same(_)))
+    {
+        let start = i.saturating_sub(context);
+        let end = (i + 1 + context).min(lines.len());
+        match hunks.last_mut() {
+            Some(hunk) if start <= hunk.1 => hunk.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    // An empty range is given by the line before it.
+    let range = |first: usize, count: usize| {
+        let first = if count == 0 { first } else { first + 1 };
+        format!("{},{}", first, count)
+    };
+    let mut patch = format!("--- {}\n+++ {}\n", old_name, new_name);
+    for (start, end) in hunks {
+        let ((o0, n0), (o1, n1)) = (before[start], before[end]);
+        patch.push_str(&format!(
+            "@@ -{} +{} @@\n",
+            range(o0, o1 - o0),
+            range(n0, n1 - n0)
+        ));
+        for (i, line) in lines.iter().enumerate().take(end).skip(start) {
+            patch.push(line.prefix());
+            patch.push_str(line.text());
+            patch.push('\n');
+            let (o, n) = before[i + 1];
+            let old_end = !matches!(line, DiffLine::Added(_)) && o == old_len;
+            let new_end = !matches!(line, DiffLine::Removed(_)) && n == new_len;
+            if (old_end && !old.ends_with('\n')) || (new_end && !new.ends_with('\n')) {
+                patch.push_str("\\ No newline at end of file\n");
+            }
+        }
+    }
+    patch
+}
+
+// Flags the removed and added lines that only change whitespace. Within
+// each run of changes, removed lines are paired in order with added lines
+// that have the same words. Blank lines added or removed count as well.
+pub fn whitespace_changes(lines: &[DiffLine]) -> Vec<bool> {
+    let mut flags = vec![false; lines.len()];
+    let mut start = 0;
+    while start < lines.len() {
+        if let DiffLine::Same(_) = lines[start] {
+            start += 1;
+            continue;
+        }
+        let end = lines[start..]
+            .iter()
+            .position(|l| matches!(l, DiffLine::Same(_)))
+            .map_or(lines.len(), |i| start + i);
+        let added: Vec<usize> = (start..end)
+            .filter(|&i| matches!(lines[i], DiffLine::Added(_)))
+            .collect();
+        let mut next = 0;
+        for removed in (start..end).filter(|&i| matches!(lines[i], DiffLine::Removed(_))) {
+            let words = |i: usize| lines[i].text().split_whitespace();
+            let found = added[next..]
+                .iter()
+                .position(|&a| words(removed).eq(words(a)));
+            if let Some(j) = found {
+                flags[removed] = true;
+                flags[added[next + j]] = true;
+                next += j + 1;
+            }
+        }
+        for (i, line) in lines.iter().enumerate().take(end).skip(start) {
+            if line.text().trim().is_empty() {
+                flags[i] = true;
+            }
+        }
+        start = end;
+    }
+    flags
+}
+
+// The diff as it would be if whitespace-only changes had not been made to
+// the new text.
+pub fn ignore_whitespace<'a>(lines: &[DiffLine<'a>]) -> Vec<DiffLine<'a>> {
+    lines
+        .iter()
+        .zip(whitespace_changes(lines))
+        .filter_map(|(line, whitespace)| match (line, whitespace) {
+            (DiffLine::Removed(_), true) => None,
+            (DiffLine::Added(s), true) => Some(DiffLine::Same(s)),
+            _ => Some(*line),
+        })
+        .collect()
+}
+
+// Myers' O(ND) shortest edit script over the trimmed middle section.
+fn myers<'a>(a: &[&'a str], b: &[&'a str]) -> Vec<DiffLine<'a>> {
+    let n = a.len() as isize;
+    let m = b.len() as isize;
+    let max = n + m;
+    let offset = max as usize;
+    let mut v = vec![0isize; 2 * offset + 2];
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+    'outer: for d in 0..=max {
+        trace.push(v.clone());
+        let mut k = -d;
+        while k <= d {
+            let i = (k + max) as usize;
+            let mut x = if k == -d || (k != d && v[i - 1] < v[i + 1]) {
+                v[i + 1]
+            } else {
+                v[i - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[i] = x;
+            if x >= n && y >= m {
+                break 'outer;
+            }
+            k += 2;
+        }
+    }
+
+    let mut edits = Vec::new();
+    let mut x = n;
+    let mut y = m;
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let k = x - y;
+        let i = (k + max) as usize;
+        let prev_k = if k == -d || (k != d && v[i - 1] < v[i + 1]) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = v[(prev_k + max) as usize];
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            edits.push(DiffLine::Same(a[(x - 1) as usize]));
+            x -= 1;
+            y -= 1;
+        }
+        if d > 0 {
+            if x == prev_x {
+                edits.push(DiffLine::Added(b[(y - 1) as usize]));
+                y -= 1;
+            } else {
+                edits.push(DiffLine::Removed(a[(x - 1) as usize]));
+                x -= 1;
+            }
+        }
+    }
+    edits.reverse();
+    edits
+}
+
+pub const CONFLICT_MINE: &str = "<<<<<<< Mine";
+pub const CONFLICT_SEPARATOR: &str = "=======";
+pub const CONFLICT_DISK: &str = ">>>>>>> Disk";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Merge {
+    pub text: String,
+    pub conflicts: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Hunk<'a> {
+    start: usize,
+    end: usize,
+    lines: Vec<&'a str>,
+}
+
+// Changed regions of `new`, as ranges of base line indices and their
+// replacement lines.
+fn hunks<'a>(base: &'a str, new: &'a str) -> Vec<Hunk<'a>> {
+    let mut result: Vec<Hunk> = Vec::new();
+    let mut current: Option<Hunk> = None;
+    let mut index = 0;
+    for line in diff_lines(base, new) {
+        match line {
+            DiffLine::Same(_) => {
+                result.extend(current.take());
+                index += 1;
+            }
+            DiffLine::Removed(_) => {
+                index += 1;
+                current
+                    .get_or_insert(Hunk {
+                        start: index - 1,
+                        end: index - 1,
+                        lines: Vec::new(),
+                    })
+                    .end = index;
+            }
+            DiffLine::Added(s) => current
+                .get_or_insert(Hunk {
+                    start: index,
+                    end: index,
+                    lines: Vec::new(),
+                })
+                .lines
+                .push(s),
+        }
+    }
+    result.extend(current);
+    result
+}
+
+fn apply<'a>(base: &[&'a str], start: usize, end: usize, hunks: &[&Hunk<'a>]) -> Vec<&'a str> {
+    let mut result = Vec::new();
+    let mut pos = start;
+    for hunk in hunks {
+        result.extend_from_slice(&base[pos..hunk.start]);
+        result.extend_from_slice(&hunk.lines);
+        pos = hunk.end;
+    }
+    result.extend_from_slice(&base[pos..end]);
+    result
+}
+
+// Three-way line merge of two descendants of `base`. Changes that touch
+// the same region differently are wrapped in conflict markers.
+pub fn merge3(base: &str, mine: &str, theirs: &str) -> Merge {
+    let base_lines: Vec<&str> = base.lines().collect();
+    let ours = hunks(base, mine);
+    let others = hunks(base, theirs);
+    let (mut a, mut b) = (0, 0);
+    let mut pos = 0;
+    let mut lines: Vec<&str> = Vec::new();
+    let mut conflicts = 0;
+    loop {
+        let first = match (ours.get(a), others.get(b)) {
+            (Some(x), Some(y)) => x.start.min(y.start),
+            (Some(x), None) => x.start,
+            (None, Some(y)) => y.start,
+            (None, None) => break,
+        };
+        let mut end = first;
+        let (mut group_a, mut group_b) = (Vec::new(), Vec::new());
+        loop {
+            if let Some(h) = ours.get(a).filter(|h| h.start <= end) {
+                end = end.max(h.end);
+                group_a.push(h);
+                a += 1;
+            } else if let Some(h) = others.get(b).filter(|h| h.start <= end) {
+                end = end.max(h.end);
+                group_b.push(h);
+                b += 1;
+            } else {
+                break;
+            }
+        }
+        lines.extend_from_slice(&base_lines[pos..first]);
+        let version_a = apply(&base_lines, first, end, &group_a);
+        let version_b = apply(&base_lines, first, end, &group_b);
+        if group_b.is_empty() || version_a == version_b {
+            lines.extend(version_a);
+        } else if group_a.is_empty() {
+            lines.extend(version_b);
+        } else {
+            conflicts += 1;
+            lines.push(CONFLICT_MINE);
+            lines.extend(version_a);
+            lines.push(CONFLICT_SEPARATOR);
+            lines.extend(version_b);
+            lines.push(CONFLICT_DISK);
+        }
+        pos = end;
+    }
+    lines.extend_from_slice(&base_lines[pos..]);
+    let mut text = lines.join("\n");
+    if !text.is_empty() && (mine.ends_with('\n') || theirs.ends_with('\n')) {
+        text.push('\n');
+    }
+    Merge { text, conflicts }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use DiffLine::*;
+
+    #[test]
+    fn test_identical() {
+        let lines = diff_lines("a\nb\nc", "a\nb\nc");
+        assert_eq!(vec![Same("a"), Same("b"), Same("c")], lines);
+        assert!(!has_changes(&lines), "Identical text has no changes");
+    }
+
+    #[test]
+    fn test_empty() {
+        assert_eq!(Vec::<DiffLine>::new(), diff_lines("", ""));
+        assert_eq!(vec![Added("a")], diff_lines("", "a"));
+        assert_eq!(vec![Removed("a")], diff_lines("a", ""));
+    }
+
+    #[test]
+    fn test_changed_line() {
+        let lines = diff_lines("one\ntwo\nthree", "one\n2\nthree");
+        assert_eq!(
+            vec![Same("one"), Removed("two"), Added("2"), Same("three")],
+            lines
+        );
+        assert!(has_changes(&lines));
+    }
+
+    #[test]
+    fn test_insert_and_delete() {
+        let lines = diff_lines("a\nb\nc\nd", "a\nc\nd\ne");
+        assert_eq!(
+            vec![Same("a"), Removed("b"), Same("c"), Same("d"), Added("e")],
+            lines
+        );
+    }
+
+    #[test]
+    fn test_interleaved() {
+        let old = "a\nb\nc\na\nb\nb\na";
+        let new = "c\nb\na\nb\na\nc";
+        let lines = diff_lines(old, new);
+        let rebuilt_old: Vec<&str> = lines
+            .iter()
+            .filter(|l| !matches!(l, Added(_)))
+            .map(|l| l.text())
+            .collect();
+        let rebuilt_new: Vec<&str> = lines
+            .iter()
+            .filter(|l| !matches!(l, Removed(_)))
+            .map(|l| l.text())
+            .collect();
+        assert_eq!(old.lines().collect::<Vec<_>>(), rebuilt_old);
+        assert_eq!(new.lines().collect::<Vec<_>>(), rebuilt_new);
+        let edits = lines.iter().filter(|l| !matches!(l, Same(_))).count();
+        assert_eq!(5, edits, "Shortest edit script is used");
+    }
+
+    #[test]
+    fn test_unified() {
+        let old = "1\n2\n3\n4\n5\n6\n7\n8\n9\n";
+        let new = "1\n2\nthree\n4\n5\n6\n7\n8\n9\n10\n";
+        assert_eq!(
+            "--- a/n\n+++ b/n\n@@ -2,3 +2,3 @@\n 2\n-3\n+three\n 4\n@@ -9,1 +9,2 @@\n 9\n+10\n",
+            unified("a/n", "b/n", old, new, 1)
+        );
+        assert_eq!(
+            "--- a\n+++ b\n@@ -2,1 +2,1 @@\n-b\n+B\n@@ -3,0 +4,1 @@\n+d\n",
+            unified("a", "b", "a\nb\nc\n", "a\nB\nc\nd\n", 0),
+            "No context"
+        );
+        assert_eq!("", unified("a", "b", "same\n", "same\n", 3));
+        assert_eq!(
+            "--- a\n+++ b\n@@ -0,0 +1,1 @@\n+new\n\\ No newline at end of file\n",
+            unified("a", "b", "", "new", 3)
+        );
+    }
+
+    #[test]
+    fn test_whitespace_changes() {
+        let lines = diff_lines("fn a() {\n  x\n}\nold", "fn a() {\n    x \n}\n\nnew");
+        assert_eq!(
+            vec![
+                Same("fn a() {"),
+                Removed("  x"),
+                Added("    x "),
+                Same("}"),
+                Removed("old"),
+                Added(""),
+                Added("new")
+            ],
+            lines
+        );
+        assert_eq!(
+            vec![false, true, true, false, false, true, false],
+            whitespace_changes(&lines),
+            "Indentation, trailing space and blank lines"
+        );
+        assert_eq!(
+            vec![
+                Same("fn a() {"),
+                Same("    x "),
+                Same("}"),
+                Removed("old"),
+                Same(""),
+                Added("new")
+            ],
+            ignore_whitespace(&lines)
+        );
+        let lines = diff_lines("a b", "a  c");
+        assert_eq!(vec![false, false], whitespace_changes(&lines));
+    }
+
+    #[test]
+    fn test_merge_independent_changes() {
+        let base = "a\nb\nc\nd\ne\n";
+        let mine = "A\nb\nc\nd\ne\n";
+        let theirs = "a\nb\nc\nd\nE\nf\n";
+        let merge = merge3(base, mine, theirs);
+        assert_eq!("A\nb\nc\nd\nE\nf\n", merge.text);
+        assert_eq!(0, merge.conflicts);
+    }
+
+    #[test]
+    fn test_merge_same_change() {
+        let merge = merge3("a\nb\nc", "a\nB\nc", "a\nB\nc");
+        assert_eq!("a\nB\nc", merge.text);
+        assert_eq!(0, merge.conflicts, "Identical changes do not conflict");
+    }
+
+    #[test]
+    fn test_merge_one_side() {
+        assert_eq!("a\nb\nx", merge3("a\nb", "a\nb", "a\nb\nx").text);
+        assert_eq!("b", merge3("a\nb", "b", "a\nb").text);
+        assert_eq!("", merge3("", "", "").text);
+    }
+
+    #[test]
+    fn test_merge_conflict() {
+        let base = "one\ntwo\nthree\n";
+        let mine = "one\n2\nthree\n";
+        let theirs = "one\nTWO\nthree\n";
+        let merge = merge3(base, mine, theirs);
+        assert_eq!(1, merge.conflicts);
+        assert_eq!(
+            "one\n<<<<<<< Mine\n2\n=======\nTWO\n>>>>>>> Disk\nthree\n",
+            merge.text
+        );
+    }
+
+    #[test]
+    fn test_merge_delete_against_edit() {
+        let merge = merge3("a\nb\nc", "a\nc", "a\nbee\nc");
+        assert_eq!(1, merge.conflicts);
+        assert_eq!("a\n<<<<<<< Mine\n=======\nbee\n>>>>>>> Disk\nc", merge.text);
+    }
+}
@@ -1,57 +1,223 @@
+use crate::gio;
+use gio::prelude::*;
+use ropey::Rope;
 use std::default::Default;
 use std::fmt::Debug;
+use std::time::SystemTime;
 
+use super::history::History;
+
+// Modification time and size of a file, used to tell whether it was
+// changed by someone else since it was last read or written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileStamp {
+    modified: SystemTime,
+    len: u64,
+}
+
+impl FileStamp {
+    pub fn of(path: &std::path::Path) -> std::io::Result<Self> {
+        let metadata = std::fs::metadata(path)?;
+        Ok(Self {
+            modified: metadata.modified()?,
+            len: metadata.len(),
+        })
+    }
+}
+
+// How a file was chosen to be opened, which decides how it may be saved.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OpenMode {
+    #[default]
+    Normal,
+    ReadOnly,
+    // Read and written with administrator rights.
+    Elevated,
+}
+
+// How a file named on the command line is opened.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct OpenOptions {
+    pub mode: OpenMode,
+    // Starting from 1, as editors count them.
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    // The character set the file is read in, rather than UTF-8.
+    pub encoding: Option<String>,
+}
+
+// Texts are kept in ropes, so edits to large files stay cheap and copies
+// share their storage.
 #[derive(Debug, Default, Clone)]
 pub struct Document {
-    original: String,
-    text: String,
-    file_path: Option<std::path::PathBuf>,
+    original: Rope,
+    text: Rope,
+    // A gio location, which may be remote, such as sftp://host/notes.txt.
+    file: Option<gio::File>,
+    stamp: Option<FileStamp>,
+    history: History,
+    // Only part of the file was loaded, so saving over it would lose the rest.
+    partial: bool,
+    mode: OpenMode,
 }
 
 impl Document {
+    pub fn file(&self) -> Option<gio::File> {
+        self.file.clone()
+    }
+    // None for untitled documents, and for remote files GVfs has not
+    // mounted locally.
     pub fn filepath(&self) -> Option<std::path::PathBuf> {
-        self.file_path.clone()
+        self.file.as_ref()?.path()
     }
+    // Whether the document was opened from or saved to this file.
+    pub fn is_file(&self, file: &gio::File) -> bool {
+        matches!(&self.file, Some(own) if own.equal(file))
+    }
+    // None for paths without a name, such as the root directory.
     pub fn filename(&self) -> Option<String> {
-        match &self.file_path {
-            None => None,
-            Some(path) => match path.file_name().unwrap().to_os_string().into_string() {
-                Ok(s) => Some(s),
-                _ => None,
-            },
-        }
+        let name = self.file.as_ref()?.basename()?;
+        name.file_name()?.to_os_string().into_string().ok()
     }
-    pub fn text(&self) -> &String {
+    pub fn text(&self) -> &Rope {
         &self.text
     }
-    pub fn original(&self) -> &String {
+    pub fn original(&self) -> &Rope {
         &self.original
     }
     pub fn modified(&self) -> bool {
-        !self.text().eq(self.original())
+        self.text != self.original
     }
+    // Replaces the whole text.
     pub fn update(&mut self, value: &str) {
-        self.text = value.to_string()
+        self.history.record(&self.text.to_string(), value);
+        self.text = Rope::from_str(value);
+    }
+    // Offsets count characters, and are clamped to the text.
+    pub fn insert(&mut self, offset: usize, value: &str) {
+        let offset = offset.min(self.text.len_chars());
+        self.history.record_insert(offset, value);
+        self.text.insert(offset, value);
+    }
+    pub fn delete(&mut self, start: usize, end: usize) {
+        let end = end.min(self.text.len_chars());
+        let start = start.min(end);
+        let removed = self.text.slice(start..end).to_string();
+        self.history.record_delete(start, &removed);
+        self.text.remove(start..end);
     }
     pub fn reset(&mut self) {
-        self.text.clear();
-        self.original.clear();
-        self.file_path = None;
+        self.text = Rope::new();
+        self.original = Rope::new();
+        self.file = None;
+        self.stamp = None;
+        self.history.clear();
+        self.partial = false;
+        self.mode = OpenMode::Normal;
+    }
+    // A new unsaved document that starts with some text. It is not
+    // modified until that text is changed.
+    pub fn reset_to(&mut self, contents: &str) {
+        self.reset();
+        self.original = Rope::from_str(contents);
+        self.text = self.original.clone();
+    }
+    // Reopening the same file keeps its history, so a reload can be undone.
+    pub fn open(&mut self, file: gio::File, contents: String) {
+        if self.is_file(&file) {
+            self.history.break_group();
+            self.history.record(&self.text.to_string(), &contents);
+            self.history.break_group();
+        } else {
+            self.history.clear();
+        }
+        self.file = Some(file);
+        self.original = Rope::from(contents);
+        self.text = self.original.clone();
+        self.partial = false;
+    }
+    pub fn open_partial(&mut self, file: gio::File, contents: String) {
+        self.open(file, contents);
+        self.history.clear();
+        self.partial = true;
+    }
+    // Whether the file may be edited and saved over.
+    pub fn read_only(&self) -> bool {
+        self.partial || self.mode == OpenMode::ReadOnly
+    }
+    pub fn partial(&self) -> bool {
+        self.partial
+    }
+    pub fn mode(&self) -> OpenMode {
+        self.mode
+    }
+    pub fn set_mode(&mut self, mode: OpenMode) {
+        self.mode = mode;
+    }
+    // A copy saved somewhere else is an ordinary file.
+    pub fn save(&mut self, file: gio::File, contents: String) {
+        if !self.is_file(&file) {
+            self.mode = OpenMode::Normal;
+        }
+        self.file = Some(file);
+        self.original = if self.text == contents {
+            self.text.clone()
+        } else {
+            Rope::from(contents)
+        };
+    }
+    pub fn set_original(&mut self, contents: String) {
+        self.original = Rope::from(contents);
+    }
+    pub fn can_undo(&self) -> bool {
+        self.history.can_undo()
     }
-    pub fn open(&mut self, path: std::path::PathBuf, contents: String) {
-        self.file_path = Some(path);
-        self.original = contents.clone();
-        self.text = contents;
+    pub fn can_redo(&self) -> bool {
+        self.history.can_redo()
     }
-    pub fn save(&mut self, path: std::path::PathBuf, contents: String) {
-        self.file_path = Some(path);
-        self.original = contents;
+    pub fn set_history_limit(&mut self, limit: usize) {
+        self.history.set_limit(limit);
+    }
+    // Edits until the action ends undo together.
+    pub fn begin_user_action(&mut self) {
+        self.history.begin_action();
+    }
+    pub fn end_user_action(&mut self) {
+        self.history.end_action();
+    }
+    // Returns the cursor offset after the change that was undone.
+    pub fn undo(&mut self) -> Option<usize> {
+        self.history.undo(&mut self.text)
+    }
+    pub fn redo(&mut self) -> Option<usize> {
+        self.history.redo(&mut self.text)
+    }
+    pub fn stamp(&self) -> Option<FileStamp> {
+        self.stamp
+    }
+    pub fn set_stamp(&mut self, stamp: Option<FileStamp>) {
+        self.stamp = stamp;
+    }
+    // True when the file on disk no longer matches the recorded stamp.
+    pub fn changed_on_disk(&self) -> bool {
+        match (self.filepath(), self.stamp) {
+            (Some(path), Some(stamp)) => match FileStamp::of(&path) {
+                Ok(current) => current != stamp,
+                Err(_) => false,
+            },
+            _ => false,
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::indent;
+
+    fn file(path: &str) -> gio::File {
+        gio::File::for_path(path)
+    }
 
     #[test]
     fn test_default() {
@@ -65,6 +231,38 @@ mod tests {
         assert_eq!(&text, d.text(), "Default text is empty");
     }
 
+    #[test]
+    fn test_filename() {
+        let mut d = Document::default();
+        d.open(file("/home/user/notes.txt"), String::new());
+        assert_eq!(Some("notes.txt".to_string()), d.filename());
+        d.open(file("/"), String::new());
+        assert_eq!(None, d.filename(), "The root has no file name");
+    }
+
+    #[test]
+    fn test_remote_file() {
+        let uri = "sftp://example.com/home/user/notes.txt";
+        let mut d = Document::default();
+        d.open(gio::File::for_uri(uri), "Mary".into());
+        assert_eq!(Some("notes.txt".to_string()), d.filename());
+        assert!(d.is_file(&gio::File::for_uri(uri)));
+        assert!(!d.is_file(&file("/home/user/notes.txt")));
+    }
+
+    #[test]
+    fn test_modified_against_original() {
+        let mut d = Document::default();
+        d.open(file("/home/user/notes.txt"), "Mary".into());
+        d.insert(4, "!");
+        assert!(d.modified());
+        d.delete(4, 5);
+        assert!(!d.modified(), "Typed back to the original");
+        d.delete(0, 1);
+        d.undo();
+        assert!(!d.modified(), "Undone back to the original");
+    }
+
     #[test]
     fn test_one_update() {
         let mut d = Document::default();
@@ -127,7 +325,7 @@ mod tests {
         d.update("Mary had a little lamb, little lamb");
         d.update("Mary had a little lamb, little lamb, little lamb");
         d.open(
-            std::path::PathBuf::from("/home/user/sometext.txt"),
+            file("/home/user/sometext.txt"),
             "There once was an old lady who swallowed a fly.".into(),
         );
         assert_eq!(
@@ -154,7 +352,7 @@ mod tests {
         d.update("Mary had a little lamb, little lamb");
         d.update("Mary had a little lamb, little lamb, little lamb");
         d.save(
-            std::path::PathBuf::from("/home/user/sometext.txt"),
+            file("/home/user/sometext.txt"),
             "Mary had a little lamb, little lamb, little lamb".into(),
         );
         assert_eq!(
@@ -172,4 +370,162 @@ mod tests {
         assert_eq!(&text, d.text(), "Text matches last update");
         assert!(!d.modified());
     }
+
+    #[test]
+    fn test_set_original() {
+        let mut d = Document::default();
+        d.open(
+            file("/home/user/sometext.txt"),
+            "Mary had a little lamb".into(),
+        );
+        d.update("Mary had a little lamb, little lamb");
+        d.set_original("Mary had a little lamb, little lamb".into());
+        assert!(!d.modified(), "Text matches the new original");
+        d.set_original("Jack jumped over the bean stalk".into());
+        assert!(d.modified());
+        assert_eq!(
+            Some(std::path::PathBuf::from("/home/user/sometext.txt")),
+            d.filepath(),
+            "File path is unchanged"
+        );
+        let text = String::from("Mary had a little lamb, little lamb");
+        assert_eq!(&text, d.text(), "Text is unchanged");
+    }
+
+    #[test]
+    fn test_changed_on_disk() {
+        let path = std::env::temp_dir().join(format!("textedit2-stamp-{}", std::process::id()));
+        std::fs::write(&path, "Mary had a little lamb").unwrap();
+        let mut d = Document::default();
+        d.open(gio::File::for_path(&path), "Mary had a little lamb".into());
+        assert!(!d.changed_on_disk(), "No stamp is recorded yet");
+        d.set_stamp(FileStamp::of(&path).ok());
+        assert!(d.stamp().is_some());
+        assert!(!d.changed_on_disk(), "File matches the stamp");
+        std::fs::write(&path, "Mary had a little lamb, little lamb").unwrap();
+        assert!(d.changed_on_disk(), "File size differs from the stamp");
+        std::fs::remove_file(&path).unwrap();
+        assert!(!d.changed_on_disk(), "A deleted file can be written freely");
+        d.reset();
+        assert_eq!(None, d.stamp(), "Reset clears the stamp");
+    }
+
+    #[test]
+    fn test_undo_redo() {
+        let mut d = Document::default();
+        d.open(
+            file("/home/user/sometext.txt"),
+            "Mary had a little lamb".into(),
+        );
+        assert!(!d.can_undo(), "Opening a file starts a new history");
+        d.update("Mary had a little lamb, little lamb");
+        assert!(d.can_undo());
+        assert_eq!(Some(22), d.undo(), "Cursor is placed after the change");
+        assert_eq!("Mary had a little lamb", d.text());
+        assert!(!d.modified());
+        assert_eq!(Some(35), d.redo());
+        assert_eq!("Mary had a little lamb, little lamb", d.text());
+        assert_eq!(None, d.redo(), "Nothing left to redo");
+    }
+
+    #[test]
+    fn test_undo_reload() {
+        let path = file("/home/user/sometext.txt");
+        let mut d = Document::default();
+        d.open(path.clone(), "Mary had a little lamb".into());
+        d.update("Mary had a little lamb, little lamb");
+        d.open(path, "Jack jumped over the bean stalk".into());
+        d.undo();
+        assert_eq!(
+            "Mary had a little lamb, little lamb",
+            d.text(),
+            "Reloading the same file can be undone"
+        );
+        d.open(
+            file("/home/user/other.txt"),
+            "There once was an old lady".into(),
+        );
+        assert!(!d.can_undo(), "Another file has its own history");
+        d.update("There once was an old lady who swallowed a fly.");
+        d.reset();
+        assert!(!d.can_undo(), "Reset clears the history");
+    }
+
+    #[test]
+    fn test_insert_delete() {
+        let mut d = Document::default();
+        d.open(file("/home/user/sometext.txt"), "Mary had a lamb".into());
+        d.insert(11, "little ");
+        assert_eq!("Mary had a little lamb", d.text());
+        assert!(d.modified());
+        d.delete(4, 8);
+        assert_eq!("Mary a little lamb", d.text());
+        d.insert(100, "!");
+        assert_eq!("Mary a little lamb!", d.text(), "Offsets are clamped");
+        d.undo();
+        d.undo();
+        d.undo();
+        assert_eq!("Mary had a lamb", d.text(), "Deltas are undone");
+        assert!(!d.modified());
+    }
+
+    #[test]
+    fn test_shift_lines_undo() {
+        let mut d = Document::default();
+        d.open(file("/home/user/list.md"), "- a\n- b\nc".into());
+        // Shifting lines replaces them, as a delete then an insert.
+        let shifted = indent::indent_lines("- a\n- b", "    ");
+        d.begin_user_action();
+        d.delete(0, 7);
+        d.insert(0, &shifted);
+        d.end_user_action();
+        assert_eq!("    - a\n    - b\nc", d.text());
+        assert_eq!(Some(7), d.undo());
+        assert_eq!("- a\n- b\nc", d.text(), "One undo restores the block");
+        assert!(!d.modified());
+    }
+
+    #[test]
+    fn test_open_partial() {
+        let path = file("/home/user/large.log");
+        let mut d = Document::default();
+        d.open_partial(path.clone(), "Mary had".into());
+        assert!(d.read_only(), "Partially loaded documents are read-only");
+        assert!(!d.can_undo());
+        d.open(path, "Mary had a little lamb".into());
+        assert!(!d.read_only(), "Loading the whole file allows editing");
+        assert!(!d.partial());
+    }
+
+    #[test]
+    fn test_mode() {
+        let path = file("/etc/hosts");
+        let mut d = Document::default();
+        d.open(path.clone(), "127.0.0.1 localhost".into());
+        d.set_mode(OpenMode::ReadOnly);
+        assert!(d.read_only());
+        assert!(!d.partial(), "Chosen read-only is not a partial load");
+        d.open(path.clone(), "::1 localhost".into());
+        assert_eq!(OpenMode::ReadOnly, d.mode(), "Reloading keeps the mode");
+        d.set_mode(OpenMode::Elevated);
+        d.save(path, "::1 localhost".into());
+        assert_eq!(OpenMode::Elevated, d.mode());
+        d.save(file("/home/user/hosts"), "::1 localhost".into());
+        assert_eq!(OpenMode::Normal, d.mode(), "Copies are ordinary files");
+        d.set_mode(OpenMode::ReadOnly);
+        d.reset();
+        assert_eq!(OpenMode::Normal, d.mode());
+    }
+
+    #[test]
+    fn test_reset_to() {
+        let mut d = Document::default();
+        d.open(file("/home/user/notes.txt"), "Mary".into());
+        d.update("Mary had");
+        d.reset_to("# Notes\n");
+        assert_eq!("# Notes\n", d.text());
+        assert_eq!(None, d.filepath());
+        assert!(!d.modified(), "The starting text is not a change");
+        assert!(!d.can_undo());
+    }
 }
@@ -0,0 +1,142 @@
+use crate::gio;
+use crate::glib;
+use std::io;
+use std::path::PathBuf;
+use thiserror::Error;
+
+// Linux error numbers that io::ErrorKind has no stable kind for.
+const ENOSPC: i32 = 28;
+const EDQUOT: i32 = 122;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IoKind {
+    NotFound,
+    PermissionDenied,
+    DiskFull,
+    Other,
+}
+
+// Failures that are reported to the user instead of aborting. The window
+// shows translated messages, these are for the log.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum Error {
+    // The message is the system's own, which is not translated.
+    #[error("{message}")]
+    Io { kind: IoKind, message: String },
+    #[error("cancelled")]
+    Cancelled,
+    #[error("{0} is not a local file")]
+    NotLocal(String),
+    #[error("{0:?} has no file name")]
+    NoFileName(PathBuf),
+    #[error("{0} is a folder")]
+    Folder(String),
+    #[error("the document is not connected to the window")]
+    Disconnected,
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        let kind = match (error.kind(), error.raw_os_error()) {
+            (io::ErrorKind::NotFound, _) => IoKind::NotFound,
+            (io::ErrorKind::PermissionDenied, _) => IoKind::PermissionDenied,
+            (_, Some(ENOSPC)) | (_, Some(EDQUOT)) => IoKind::DiskFull,
+            _ => IoKind::Other,
+        };
+        Error::Io {
+            kind,
+            message: error.to_string(),
+        }
+    }
+}
+
+// gio reports cancelled operations as errors too.
+impl From<glib::Error> for Error {
+    fn from(error: glib::Error) -> Self {
+        let kind = match error.kind::<gio::IOErrorEnum>() {
+            Some(gio::IOErrorEnum::Cancelled) => return Error::Cancelled,
+            Some(gio::IOErrorEnum::NotFound) => IoKind::NotFound,
+            Some(gio::IOErrorEnum::PermissionDenied) => IoKind::PermissionDenied,
+            Some(gio::IOErrorEnum::NoSpace) => IoKind::DiskFull,
+            _ => IoKind::Other,
+        };
+        Error::Io {
+            kind,
+            message: error.to_string(),
+        }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        assert_eq!(
+            "sftp://host/notes.txt is not a local file",
+            Error::NotLocal("sftp://host/notes.txt".into()).to_string()
+        );
+        assert_eq!(
+            "\"/\" has no file name",
+            Error::NoFileName(PathBuf::from("/")).to_string()
+        );
+        assert_eq!(
+            "file:///home/notes is a folder",
+            Error::Folder("file:///home/notes".into()).to_string()
+        );
+    }
+
+    #[test]
+    fn test_from_io() {
+        let error = Error::from(io::Error::new(io::ErrorKind::NotFound, "gone"));
+        assert_eq!(
+            Error::Io {
+                kind: IoKind::NotFound,
+                message: "gone".into()
+            },
+            error
+        );
+        assert!(
+            matches!(
+                Error::from(io::Error::from_raw_os_error(ENOSPC)),
+                Error::Io {
+                    kind: IoKind::DiskFull,
+                    ..
+                }
+            ),
+            "No space left on device"
+        );
+        assert!(matches!(
+            Error::from(io::Error::new(io::ErrorKind::InvalidData, "not UTF-8")),
+            Error::Io {
+                kind: IoKind::Other,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_from_glib() {
+        assert_eq!(
+            Error::Cancelled,
+            Error::from(glib::Error::new(gio::IOErrorEnum::Cancelled, "stopped"))
+        );
+        assert_eq!(
+            Error::Io {
+                kind: IoKind::NotFound,
+                message: "gone".into()
+            },
+            Error::from(glib::Error::new(gio::IOErrorEnum::NotFound, "gone"))
+        );
+        assert!(matches!(
+            Error::from(glib::Error::new(gio::IOErrorEnum::NoSpace, "full")),
+            Error::Io {
+                kind: IoKind::DiskFull,
+                ..
+            }
+        ));
+    }
+}
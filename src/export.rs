@@ -0,0 +1,224 @@
+use super::markdown;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Html,
+    Pdf,
+    // Each paragraph followed by a line for its translation or notes.
+    Annotated,
+    // The paragraphs beside an empty column, as a page.
+    SideBySide,
+    // The unsaved changes as a unified diff.
+    Patch,
+}
+
+impl ExportFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Html | Self::SideBySide => "html",
+            Self::Pdf => "pdf",
+            Self::Annotated => "txt",
+            Self::Patch => "patch",
+        }
+    }
+
+    // Keeps the suggested name from replacing the document itself.
+    pub fn suffix(self) -> &'static str {
+        match self {
+            Self::Html | Self::Pdf | Self::Patch => "",
+            Self::Annotated | Self::SideBySide => "-translation",
+        }
+    }
+}
+
+// Classes match the preview's tags, so an exported page looks like the
+// preview.
+const STYLE: &str = "body { margin: 2em auto; max-width: 48em; padding: 0 1em; }
+.document { white-space: pre-wrap; font-family: sans-serif; line-height: 1.4; }
+pre { font-family: monospace; white-space: pre-wrap; }
+.h1, .h2, .h3, .h4, .h5, .h6, .strong { font-weight: bold; }
+.h1 { font-size: 2em; } .h2 { font-size: 1.7em; } .h3 { font-size: 1.4em; }
+.h4 { font-size: 1.2em; } .h5 { font-size: 1.1em; }
+.emphasis { font-style: italic; }
+.code { font-family: monospace; }
+.code-block { font-family: monospace; display: inline-block; margin-left: 2em; }
+.quote { font-style: italic; display: inline-block; margin-left: 2em; }
+.link { color: #3584e4; text-decoration: underline; }
+";
+
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// A standalone page. Markdown is rendered as it is in the preview, any
+// other text is kept exactly as written.
+pub fn to_html(title: &str, text: &str, markdown: bool) -> String {
+    let body = if markdown {
+        let spans: String = markdown::render(text)
+            .iter()
+            .map(|span| {
+                let names = span.style.names();
+                if names.is_empty() {
+                    escape(&span.text)
+                } else {
+                    format!(
+                        "<span class=\"{}\">{}</span>",
+                        names.join(" "),
+                        escape(&span.text)
+                    )
+                }
+            })
+            .collect();
+        format!("<div class=\"document\">{}</div>", spans)
+    } else {
+        format!("<pre>{}</pre>", escape(text))
+    };
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>\n{}</style>\n</head>\n<body>\n{}\n</body>\n</html>\n",
+        escape(title),
+        STYLE,
+        body
+    )
+}
+
+// Paragraphs are separated by blank lines. Their own line breaks are kept.
+pub fn paragraphs(text: &str) -> Vec<String> {
+    let mut paragraphs = Vec::new();
+    let mut current: Vec<&str> = Vec::new();
+    for line in text.lines() {
+        if line.trim().is_empty() {
+            if !current.is_empty() {
+                paragraphs.push(current.join("\n"));
+                current.clear();
+            }
+        } else {
+            current.push(line);
+        }
+    }
+    if !current.is_empty() {
+        paragraphs.push(current.join("\n"));
+    }
+    paragraphs
+}
+
+const ANNOTATION: &str = "» ";
+
+pub fn to_annotated(text: &str) -> String {
+    paragraphs(text)
+        .iter()
+        .map(|p| format!("{}\n\n{}\n", p, ANNOTATION))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// A table with the original paragraphs on the left and an empty cell
+// beside each one to write in, on screen or once printed.
+pub fn to_side_by_side(title: &str, text: &str) -> String {
+    let rows: String = paragraphs(text)
+        .iter()
+        .map(|p| format!("<tr><td>{}</td><td></td></tr>\n", escape(p)))
+        .collect();
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>\n{}</style>\n</head>\n<body>\n<table>\n{}</table>\n</body>\n</html>\n",
+        escape(title),
+        COLUMNS_STYLE,
+        rows
+    )
+}
+
+const COLUMNS_STYLE: &str = "body { margin: 2em; font-family: sans-serif; line-height: 1.4; }
+table { border-collapse: collapse; width: 100%; table-layout: fixed; }
+td { border: 1px solid #c0bfbc; padding: 0.5em; vertical-align: top; white-space: pre-wrap; }
+";
+
+// Lines for each printed page. Tabs are expanded to the tab width and
+// lines longer than a page is wide continue on the next line.
+pub fn paginate(text: &str, columns: usize, rows: usize, tab_width: usize) -> Vec<Vec<String>> {
+    let (columns, rows, tab_width) = (columns.max(1), rows.max(1), tab_width.max(1));
+    let mut lines = Vec::new();
+    for line in text.lines() {
+        let mut expanded = Vec::new();
+        for c in line.chars() {
+            if c == '\t' {
+                let spaces = tab_width - expanded.len() % tab_width;
+                expanded.resize(expanded.len() + spaces, ' ');
+            } else {
+                expanded.push(c);
+            }
+        }
+        if expanded.is_empty() {
+            lines.push(String::new());
+        }
+        lines.extend(expanded.chunks(columns).map(|chunk| chunk.iter().collect()));
+    }
+    let mut pages: Vec<Vec<String>> = lines.chunks(rows).map(<[String]>::to_vec).collect();
+    if pages.is_empty() {
+        pages.push(Vec::new());
+    }
+    pages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_html() {
+        let html = to_html("a & b", "if x < 1 {\n\treturn;\n}", false);
+        assert!(html.contains("<title>a &amp; b</title>"));
+        assert!(
+            html.contains("<pre>if x &lt; 1 {\n\treturn;\n}</pre>"),
+            "Text is kept as written"
+        );
+        let html = to_html("notes", "# Title\n\nSome **bold** text", true);
+        assert!(html.contains("<span class=\"h1\">Title</span>"));
+        assert!(html.contains("Some <span class=\"strong\">bold</span> text"));
+    }
+
+    #[test]
+    fn test_paragraphs() {
+        assert_eq!(
+            vec!["one\ntwo".to_string(), "three".into()],
+            paragraphs("\none\ntwo\n\n  \nthree\n")
+        );
+        assert!(paragraphs("\n\n").is_empty());
+    }
+
+    #[test]
+    fn test_to_annotated() {
+        assert_eq!("a\nb\n\n» \n\nc\n\n» \n", to_annotated("a\nb\n\n\nc"));
+    }
+
+    #[test]
+    fn test_to_side_by_side() {
+        let html = to_side_by_side("notes", "a < b\n\nc");
+        assert!(html.contains("<tr><td>a &lt; b</td><td></td></tr>\n<tr><td>c</td><td></td></tr>"));
+    }
+
+    #[test]
+    fn test_paginate() {
+        assert_eq!(
+            vec![
+                vec!["ab".to_string(), "cd".into()],
+                vec!["e".into(), "".into()]
+            ],
+            paginate("abcde\n\n", 2, 2, 4)
+        );
+        assert_eq!(
+            vec![vec!["a   b".to_string(), "x".into()]],
+            paginate("a\tb\r\nx", 10, 10, 4),
+            "Tabs are expanded"
+        );
+        assert_eq!(vec![Vec::<String>::new()], paginate("", 80, 60, 4));
+    }
+}
@@ -0,0 +1,878 @@
+use super::error::Error;
+use crate::gio;
+use crate::glib;
+use gio::prelude::*;
+use std::ffi::{OsStr, OsString};
+use std::fs::File;
+use std::future::Future;
+use std::io;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+
+pub const CHUNK_SIZE: usize = 1 << 20;
+
+// pkexec asks for an administrator's password and exits with this when
+// the request is dismissed or refused.
+const PKEXEC_NOT_AUTHORIZED: i32 = 126;
+
+pub struct FileSystem {}
+
+// Reads and writes go through gio and finish on the main context, so they
+// work anywhere gio can reach, such as GVfs mounts, and stop when their
+// cancellable is cancelled.
+impl FileSystem {
+    pub fn read_to_string(path: PathBuf, contents: &mut String) -> io::Result<()> {
+        let file = File::open(path)?;
+        let mut reader = io::BufReader::new(file);
+        reader.read_to_string(contents)?;
+        Ok(())
+    }
+
+    // Reads at most `limit` bytes. `progress` gets the bytes read so far
+    // and the total.
+    pub fn read<P, F>(
+        file: gio::File,
+        limit: Option<u64>,
+        cancellable: &gio::Cancellable,
+        progress: P,
+        done: F,
+    ) where
+        P: FnMut(u64, u64) + 'static,
+        F: FnOnce(Result<String, Error>) + 'static,
+    {
+        Self::read_in(file, limit, None, cancellable, progress, done)
+    }
+
+    // Reads a file in another character set than UTF-8, such as
+    // ISO-8859-1, when `encoding` names one.
+    pub fn read_in<P, F>(
+        file: gio::File,
+        limit: Option<u64>,
+        encoding: Option<String>,
+        cancellable: &gio::Cancellable,
+        progress: P,
+        done: F,
+    ) where
+        P: FnMut(u64, u64) + 'static,
+        F: FnOnce(Result<String, Error>) + 'static,
+    {
+        let read = Self::load(file, limit, encoding, cancellable.clone(), progress);
+        Self::spawn(read, done);
+    }
+
+    async fn load(
+        file: gio::File,
+        limit: Option<u64>,
+        encoding: Option<String>,
+        cancellable: gio::Cancellable,
+        mut progress: impl FnMut(u64, u64),
+    ) -> Result<String, Error> {
+        let info = Self::operation(&file, &cancellable, |file, cancellable, result| {
+            file.query_info_async(
+                "standard::size",
+                gio::FileQueryInfoFlags::NONE,
+                glib::PRIORITY_DEFAULT,
+                Some(cancellable),
+                move |info| result.resolve(info),
+            )
+        })
+        .await?;
+        let size = info.size().max(0) as u64;
+        let total = limit.map_or(size, |limit| limit.min(size));
+        let stream = Self::operation(&file, &cancellable, |file, cancellable, result| {
+            file.read_async(glib::PRIORITY_DEFAULT, Some(cancellable), move |stream| {
+                result.resolve(stream)
+            })
+        })
+        .await?;
+        let mut bytes = Vec::new();
+        while limit.is_none() || (bytes.len() as u64) < total {
+            let chunk = Self::operation(&stream, &cancellable, |stream, cancellable, result| {
+                stream.read_bytes_async(
+                    CHUNK_SIZE,
+                    glib::PRIORITY_DEFAULT,
+                    Some(cancellable),
+                    move |chunk| result.resolve(chunk),
+                )
+            })
+            .await?;
+            if chunk.is_empty() {
+                break;
+            }
+            bytes.extend_from_slice(&chunk);
+            progress((bytes.len() as u64).min(total), total);
+        }
+        let partial = total < size;
+        if partial {
+            bytes.truncate(total as usize);
+        }
+        if let Some(encoding) = &encoding {
+            bytes = Self::convert(bytes, encoding)?;
+        }
+        Self::decode(bytes, partial).map_err(Error::from)
+    }
+
+    // Reads a stream to its end, such as standard input, which has no size
+    // to report progress against.
+    pub fn read_stream<F>(
+        stream: gio::InputStream,
+        encoding: Option<String>,
+        cancellable: &gio::Cancellable,
+        done: F,
+    ) where
+        F: FnOnce(Result<String, Error>) + 'static,
+    {
+        let cancellable = cancellable.clone();
+        let read = async move {
+            let output = gio::MemoryOutputStream::new_resizable();
+            Self::operation(&output, &cancellable, move |output, cancellable, result| {
+                output.splice_async(
+                    &stream,
+                    gio::OutputStreamSpliceFlags::CLOSE_SOURCE
+                        | gio::OutputStreamSpliceFlags::CLOSE_TARGET,
+                    glib::PRIORITY_DEFAULT,
+                    Some(cancellable),
+                    move |count| result.resolve(count),
+                )
+            })
+            .await?;
+            let mut bytes = output.steal_as_bytes().to_vec();
+            if let Some(encoding) = &encoding {
+                bytes = Self::convert(bytes, encoding)?;
+            }
+            Self::decode(bytes, false).map_err(Error::from)
+        };
+        Self::spawn(read, done);
+    }
+
+    // Runs on the thread's main context, so `done` may hold what cannot be
+    // sent to another thread, such as widgets.
+    fn spawn<T: 'static>(task: impl Future<Output = T> + 'static, done: impl FnOnce(T) + 'static) {
+        glib::MainContext::ref_thread_default().spawn_local(async move { done(task.await) });
+    }
+
+    // A gio operation as a future that stops with the caller's cancellable.
+    // gio's callbacks must be Send, so only the result is sent to them.
+    fn operation<O, T, E>(
+        object: &O,
+        cancellable: &gio::Cancellable,
+        start: impl FnOnce(&O, &gio::Cancellable, gio::GioFutureResult<T, E>) + 'static,
+    ) -> impl Future<Output = Result<T, E>>
+    where
+        O: Clone + 'static,
+        T: 'static,
+        E: 'static,
+    {
+        let cancellable = cancellable.clone();
+        gio::GioFuture::new(object, move |object, _, result| {
+            start(object, &cancellable, result)
+        })
+    }
+
+    fn convert(bytes: Vec<u8>, encoding: &str) -> Result<Vec<u8>, Error> {
+        let converter = gio::CharsetConverter::new("UTF-8", encoding)?;
+        let input = gio::MemoryInputStream::from_bytes(&glib::Bytes::from_owned(bytes));
+        let stream = gio::ConverterInputStream::new(&input, &converter);
+        let mut converted = Vec::new();
+        stream.into_read().read_to_end(&mut converted)?;
+        Ok(converted)
+    }
+
+    fn decode(mut bytes: Vec<u8>, partial: bool) -> io::Result<String> {
+        // A partial read may end inside a character.
+        if partial {
+            if let Err(e) = std::str::from_utf8(&bytes) {
+                if e.error_len().is_none() {
+                    bytes.truncate(e.valid_up_to());
+                }
+            }
+        }
+        String::from_utf8(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    // gio writes to a temporary file and renames it into place, so a crash
+    // mid-write never leaves a truncated file behind. Symlinks are written
+    // through and the file keeps its permissions. `progress` works as it
+    // does for reads, and a cancelled write leaves the file as it was.
+    // `done` gets the contents back once they are written.
+    pub fn write<P, F>(
+        file: gio::File,
+        contents: String,
+        backup: bool,
+        cancellable: &gio::Cancellable,
+        progress: P,
+        done: F,
+    ) where
+        P: FnMut(u64, u64) + 'static,
+        F: FnOnce(Result<String, Error>) + 'static,
+    {
+        let write = Self::replace(file, contents, backup, cancellable.clone(), progress);
+        Self::spawn(write, done);
+    }
+
+    // Writes a chunk at a time. The file is only replaced once the stream
+    // is closed.
+    async fn replace(
+        file: gio::File,
+        contents: String,
+        backup: bool,
+        cancellable: gio::Cancellable,
+        mut progress: impl FnMut(u64, u64),
+    ) -> Result<String, Error> {
+        let stream = Self::operation(&file, &cancellable, move |file, cancellable, result| {
+            file.replace_async(
+                None,
+                backup,
+                gio::FileCreateFlags::NONE,
+                glib::PRIORITY_DEFAULT,
+                Some(cancellable),
+                move |stream| result.resolve(stream),
+            )
+        })
+        .await?;
+        let mut written = 0;
+        while written < contents.len() {
+            let end = (written + CHUNK_SIZE).min(contents.len());
+            let chunk = glib::Bytes::from(&contents.as_bytes()[written..end]);
+            let count =
+                Self::operation(&stream, &cancellable, move |stream, cancellable, result| {
+                    stream.write_bytes_async(
+                        &chunk,
+                        glib::PRIORITY_DEFAULT,
+                        Some(cancellable),
+                        move |count| result.resolve(count),
+                    )
+                })
+                .await;
+            match count {
+                Ok(count) => {
+                    written += count as usize;
+                    progress(written as u64, contents.len() as u64);
+                }
+                // Closing a cancelled stream discards what was written.
+                Err(e) => {
+                    cancellable.cancel();
+                    Self::close(&stream, &cancellable).await.ok();
+                    return Err(e.into());
+                }
+            }
+        }
+        Self::close(&stream, &cancellable).await?;
+        Ok(contents)
+    }
+
+    async fn close(
+        stream: &gio::FileOutputStream,
+        cancellable: &gio::Cancellable,
+    ) -> Result<(), glib::Error> {
+        Self::operation(stream, cancellable, |stream, cancellable, result| {
+            stream.close_async(glib::PRIORITY_DEFAULT, Some(cancellable), move |closed| {
+                result.resolve(closed)
+            })
+        })
+        .await
+    }
+
+    // Reads a file as root, for files the user may not read.
+    pub fn read_elevated<F>(path: &Path, cancellable: &gio::Cancellable, done: F)
+    where
+        F: FnOnce(Result<String, Error>) + 'static,
+    {
+        let argv = [
+            OsStr::new("pkexec"),
+            OsStr::new("cat"),
+            OsStr::new("--"),
+            path.as_os_str(),
+        ];
+        Self::run(&argv, None, cancellable, done);
+    }
+
+    // Writes in place as root. A temporary file would belong to the user,
+    // so the write is not atomic, and no backup is made.
+    pub fn write_elevated<F>(path: &Path, contents: String, cancellable: &gio::Cancellable, done: F)
+    where
+        F: FnOnce(Result<String, Error>) + 'static,
+    {
+        let mut target = OsString::from("of=");
+        target.push(path);
+        let argv = [
+            OsStr::new("pkexec"),
+            OsStr::new("dd"),
+            OsStr::new("status=none"),
+            target.as_os_str(),
+        ];
+        Self::run(&argv, Some(contents.clone()), cancellable, move |r| {
+            done(r.map(|_| contents))
+        });
+    }
+
+    // Runs a command with the input on stdin and gives `done` its output.
+    // A cancelled command is stopped.
+    fn run<F>(argv: &[&OsStr], input: Option<String>, cancellable: &gio::Cancellable, done: F)
+    where
+        F: FnOnce(Result<String, Error>) + 'static,
+    {
+        let mut flags = gio::SubprocessFlags::STDOUT_PIPE | gio::SubprocessFlags::STDERR_PIPE;
+        if input.is_some() {
+            flags |= gio::SubprocessFlags::STDIN_PIPE;
+        }
+        let process = match gio::Subprocess::newv(argv, flags) {
+            Ok(process) => process,
+            Err(e) => return done(Err(e.into())),
+        };
+        let cancellable = cancellable.clone();
+        let run = async move {
+            let output = Self::operation(&process, &cancellable, |process, cancellable, result| {
+                process.communicate_utf8_async(input, Some(cancellable), move |output| {
+                    result.resolve(output)
+                })
+            })
+            .await;
+            match output {
+                Ok((stdout, stderr)) => {
+                    let code = if process.has_exited() {
+                        Some(process.exit_status())
+                    } else {
+                        None
+                    };
+                    Self::exit_result(
+                        code,
+                        stdout.map(String::from).unwrap_or_default(),
+                        stderr.as_deref().unwrap_or(""),
+                    )
+                    .map_err(Error::from)
+                }
+                Err(e) => {
+                    process.force_exit();
+                    Err(e.into())
+                }
+            }
+        };
+        Self::spawn(run, done);
+    }
+
+    fn exit_result(code: Option<i32>, stdout: String, stderr: &str) -> io::Result<String> {
+        match code {
+            Some(0) => Ok(stdout),
+            Some(PKEXEC_NOT_AUTHORIZED) => Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "Not authorized",
+            )),
+            _ => Err(io::Error::other(stderr.trim().to_string())),
+        }
+    }
+
+    // A dropped `text/uri-list`, one URI a line. Lines starting with `#`
+    // are comments.
+    pub fn uri_list(text: &str) -> Vec<&str> {
+        text.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .collect()
+    }
+
+    // Names without an extension get the default one. Hidden files such as
+    // `.bashrc` are left alone.
+    pub fn with_default_extension(path: &Path, extension: &str) -> PathBuf {
+        let visible =
+            matches!(path.file_name(), Some(name) if !name.to_string_lossy().starts_with('.'));
+        if visible && path.extension().is_none() {
+            path.with_extension(extension)
+        } else {
+            path.to_path_buf()
+        }
+    }
+
+    // A leading `~` is the home folder, as written in the preferences.
+    pub fn expand_home(path: &str, home: &Path) -> PathBuf {
+        match path.strip_prefix('~') {
+            Some("") => home.to_path_buf(),
+            Some(rest) if rest.starts_with('/') => home.join(&rest[1..]),
+            _ => PathBuf::from(path),
+        }
+    }
+
+    // Folders under the home folder start with `~`, and long ones keep their
+    // first two parts and their last, as in `~/projects/…/src`.
+    pub fn abbreviate_dir(dir: &Path, home: Option<&Path>, max_chars: usize) -> String {
+        let dir = match home.and_then(|home| dir.strip_prefix(home).ok()) {
+            Some(rest) if rest.as_os_str().is_empty() => PathBuf::from("~"),
+            Some(rest) => Path::new("~").join(rest),
+            None => dir.to_path_buf(),
+        };
+        let text = dir.to_string_lossy().to_string();
+        let parts: Vec<String> = dir
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().to_string())
+            .collect();
+        if text.chars().count() <= max_chars || parts.len() <= 3 {
+            return text;
+        }
+        let start = Path::new(&parts[0]).join(&parts[1]);
+        let start = start.to_string_lossy();
+        format!(
+            "{}/…/{}",
+            start.trim_end_matches('/'),
+            parts[parts.len() - 1]
+        )
+    }
+
+    // `notes.txt` becomes `notes (copy).txt`, keeping the extension so the
+    // copy opens the same way.
+    pub fn copy_name(name: &str, copy: &str) -> String {
+        let path = Path::new(name);
+        match (path.file_stem(), path.extension()) {
+            (Some(stem), Some(extension)) => format!(
+                "{} ({}).{}",
+                stem.to_string_lossy(),
+                copy,
+                extension.to_string_lossy()
+            ),
+            _ => format!("{} ({})", name, copy),
+        }
+    }
+
+    // A file name suggested by the first line with text, without markup
+    // such as `# ` and characters that are not allowed in names.
+    pub fn name_from_text(text: &str) -> Option<String> {
+        text.lines().find_map(Self::name_from_line)
+    }
+
+    fn name_from_line(line: &str) -> Option<String> {
+        const MAX_CHARS: usize = 60;
+        let line = line.trim_start_matches(['#', '*', '-', '>', ' ', '\t']);
+        let cleaned: String = line
+            .chars()
+            .map(|c| {
+                if c.is_control() || ['/', '\\', ':', '*', '?', '"', '<', '>', '|'].contains(&c) {
+                    ' '
+                } else {
+                    c
+                }
+            })
+            .collect();
+        let name: String = cleaned
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .chars()
+            .take(MAX_CHARS)
+            .collect();
+        let name = name.trim_matches(['.', ' ']);
+        if name.is_empty() {
+            None
+        } else {
+            Some(name.to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::IoKind;
+    use std::cell::RefCell;
+    use std::fs;
+    use std::rc::Rc;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("textedit2-{}-{}", name, std::process::id()));
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // Runs an operation on a main context of its own until it is done.
+    fn finish<T: 'static>(start: impl FnOnce(Box<dyn FnOnce(T)>)) -> T {
+        let context = glib::MainContext::new();
+        let result = Rc::new(RefCell::new(None));
+        context.with_thread_default(|| {
+            let r = result.clone();
+            start(Box::new(move |value| *r.borrow_mut() = Some(value)));
+            while result.borrow().is_none() {
+                context.iteration(true);
+            }
+        });
+        result.take().unwrap()
+    }
+
+    fn write(path: &Path, contents: &str, backup: bool) -> Result<String, Error> {
+        let file = gio::File::for_path(path);
+        let contents = contents.to_string();
+        finish(|done| {
+            FileSystem::write(
+                file,
+                contents,
+                backup,
+                &gio::Cancellable::new(),
+                |_, _| (),
+                done,
+            )
+        })
+    }
+
+    #[test]
+    fn test_write_new_file() {
+        let dir = test_dir("write-new");
+        let path = dir.join("new.txt");
+        assert_eq!(
+            Ok("Mary had a little lamb".to_string()),
+            write(&path, "Mary had a little lamb", false)
+        );
+        assert_eq!("Mary had a little lamb", fs::read_to_string(&path).unwrap());
+        assert_eq!(
+            1,
+            fs::read_dir(&dir).unwrap().count(),
+            "No temp file is left"
+        );
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_write_backup() {
+        let dir = test_dir("write-backup");
+        let path = dir.join("notes.txt");
+        fs::write(&path, "old").unwrap();
+        write(&path, "new", true).unwrap();
+        assert_eq!("new", fs::read_to_string(&path).unwrap());
+        assert_eq!("old", fs::read_to_string(dir.join("notes.txt~")).unwrap());
+
+        write(&path, "newer", false).unwrap();
+        assert_eq!("newer", fs::read_to_string(&path).unwrap());
+        assert_eq!(
+            "old",
+            fs::read_to_string(dir.join("notes.txt~")).unwrap(),
+            "Backup is untouched when disabled"
+        );
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_write_with_progress() {
+        let dir = test_dir("write-progress");
+        let path = dir.join("large.txt");
+        fs::write(&path, "old").unwrap();
+        let text = "a".repeat(CHUNK_SIZE * 2 + 1);
+        let calls = Rc::new(RefCell::new(0));
+        let counted = calls.clone();
+        let file = gio::File::for_path(&path);
+        let contents = text.clone();
+        finish(|done| {
+            FileSystem::write(
+                file,
+                contents,
+                false,
+                &gio::Cancellable::new(),
+                move |_, _| *counted.borrow_mut() += 1,
+                done,
+            )
+        })
+        .unwrap();
+        assert_eq!(3, *calls.borrow());
+        assert_eq!(text, fs::read_to_string(&path).unwrap());
+
+        let cancellable = gio::Cancellable::new();
+        let cancel = cancellable.clone();
+        let file = gio::File::for_path(&path);
+        let cancelled = finish(|done| {
+            FileSystem::write(
+                file,
+                "b".repeat(CHUNK_SIZE * 2),
+                false,
+                &cancellable,
+                move |_, _| cancel.cancel(),
+                done,
+            )
+        });
+        assert_eq!(Err(Error::Cancelled), cancelled);
+        assert_eq!(
+            text,
+            fs::read_to_string(&path).unwrap(),
+            "A cancelled write leaves the file as it was"
+        );
+        assert_eq!(1, fs::read_dir(&dir).unwrap().count());
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_preserves_permissions_and_links() {
+        use std::os::unix::fs::{symlink, PermissionsExt};
+        let dir = test_dir("write-perms");
+        let path = dir.join("script.sh");
+        fs::write(&path, "echo old").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o750)).unwrap();
+        let link = dir.join("link.sh");
+        symlink(&path, &link).unwrap();
+
+        write(&link, "echo new", false).unwrap();
+        assert_eq!("echo new", fs::read_to_string(&path).unwrap());
+        assert!(fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(0o750, mode & 0o777, "Permissions are preserved");
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_read_to_string() {
+        let dir = test_dir("read");
+        let path = dir.join("read.txt");
+        fs::write(&path, "There once was an old lady").unwrap();
+        let mut contents = String::new();
+        FileSystem::read_to_string(path, &mut contents).unwrap();
+        assert_eq!("There once was an old lady", contents);
+        let mut contents = String::new();
+        assert!(FileSystem::read_to_string(dir.join("missing.txt"), &mut contents).is_err());
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_read() {
+        let dir = test_dir("read-progress");
+        let path = dir.join("large.txt");
+        let text = "naïve café ".repeat(CHUNK_SIZE / 4);
+        fs::write(&path, &text).unwrap();
+        let calls = Rc::new(RefCell::new(Vec::new()));
+        let recorded = calls.clone();
+        let file = gio::File::for_path(&path);
+        let contents = finish(|done| {
+            FileSystem::read(
+                file,
+                None,
+                &gio::Cancellable::new(),
+                move |read, total| recorded.borrow_mut().push((read, total)),
+                done,
+            )
+        })
+        .unwrap();
+        assert_eq!(text, contents);
+        assert!(calls.borrow().len() > 1, "Large files are read in chunks");
+        assert_eq!(
+            Some(&(text.len() as u64, text.len() as u64)),
+            calls.borrow().last()
+        );
+
+        let file = gio::File::for_path(&path);
+        let partial = finish(|done| {
+            FileSystem::read(file, Some(3), &gio::Cancellable::new(), |_, _| (), done)
+        });
+        assert_eq!(
+            Ok("na".to_string()),
+            partial,
+            "Partial reads end on a character boundary"
+        );
+
+        let cancellable = gio::Cancellable::new();
+        cancellable.cancel();
+        let file = gio::File::for_path(&path);
+        let cancelled = finish(|done| FileSystem::read(file, None, &cancellable, |_, _| (), done));
+        assert_eq!(
+            Err(Error::Cancelled),
+            cancelled,
+            "Reading stops when cancelled"
+        );
+
+        let path = dir.join("latin1.txt");
+        fs::write(&path, b"caf\xe9").unwrap();
+        let read = |encoding: Option<&str>| {
+            let file = gio::File::for_path(&path);
+            let encoding = encoding.map(String::from);
+            finish(|done| {
+                FileSystem::read_in(
+                    file,
+                    None,
+                    encoding,
+                    &gio::Cancellable::new(),
+                    |_, _| (),
+                    done,
+                )
+            })
+        };
+        assert_eq!(Ok("café".to_string()), read(Some("ISO-8859-1")));
+        assert!(read(None).is_err(), "Not UTF-8");
+        assert!(read(Some("no-such-charset")).is_err());
+
+        let file = gio::File::for_path(dir.join("missing.txt"));
+        let missing =
+            finish(|done| FileSystem::read(file, None, &gio::Cancellable::new(), |_, _| (), done));
+        assert!(matches!(
+            missing,
+            Err(Error::Io {
+                kind: IoKind::NotFound,
+                ..
+            })
+        ));
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_read_stream() {
+        let read = |bytes: &'static [u8], encoding: Option<&str>| {
+            let stream = gio::MemoryInputStream::from_bytes(&glib::Bytes::from_static(bytes));
+            let encoding = encoding.map(String::from);
+            finish(|done| {
+                FileSystem::read_stream(stream.upcast(), encoding, &gio::Cancellable::new(), done)
+            })
+        };
+        assert_eq!(Ok("naïve\n".to_string()), read("naïve\n".as_bytes(), None));
+        assert_eq!(Ok(String::new()), read(b"", None));
+        assert_eq!(Ok("café".to_string()), read(b"caf\xe9", Some("ISO-8859-1")));
+        assert!(read(b"caf\xe9", None).is_err(), "Not UTF-8");
+    }
+
+    #[test]
+    fn test_with_default_extension() {
+        assert_eq!(
+            PathBuf::from("/home/notes.txt"),
+            FileSystem::with_default_extension(Path::new("/home/notes"), "txt")
+        );
+        assert_eq!(
+            PathBuf::from("/home/notes.md"),
+            FileSystem::with_default_extension(Path::new("/home/notes.md"), "txt")
+        );
+        assert_eq!(
+            PathBuf::from("/home/.profile"),
+            FileSystem::with_default_extension(Path::new("/home/.profile"), "txt"),
+            "Hidden files"
+        );
+    }
+
+    #[test]
+    fn test_uri_list() {
+        assert_eq!(
+            vec!["file:///home/ada/a.txt", "sftp://host/b.md"],
+            FileSystem::uri_list("# dropped\r\nfile:///home/ada/a.txt\r\nsftp://host/b.md\r\n")
+        );
+        assert!(FileSystem::uri_list("\r\n").is_empty());
+    }
+
+    #[test]
+    fn test_abbreviate_dir() {
+        let home = Some(Path::new("/home/ada"));
+        assert_eq!(
+            "~/notes",
+            FileSystem::abbreviate_dir(Path::new("/home/ada/notes"), home, 40)
+        );
+        assert_eq!(
+            "~",
+            FileSystem::abbreviate_dir(Path::new("/home/ada"), home, 40)
+        );
+        assert_eq!(
+            "/etc/systemd",
+            FileSystem::abbreviate_dir(Path::new("/etc/systemd"), home, 40)
+        );
+        assert_eq!(
+            "~/projects/…/src",
+            FileSystem::abbreviate_dir(
+                Path::new("/home/ada/projects/textedit/crates/editor/src"),
+                home,
+                20
+            )
+        );
+        assert_eq!(
+            "/usr/…/doc",
+            FileSystem::abbreviate_dir(Path::new("/usr/local/share/doc"), None, 10),
+            "The root is a part"
+        );
+    }
+
+    #[test]
+    fn test_expand_home() {
+        let home = Path::new("/home/ada");
+        assert_eq!(
+            PathBuf::from("/home/ada/Notes"),
+            FileSystem::expand_home("~/Notes", home)
+        );
+        assert_eq!(
+            PathBuf::from("/home/ada"),
+            FileSystem::expand_home("~", home)
+        );
+        assert_eq!(
+            PathBuf::from("/srv/notes"),
+            FileSystem::expand_home("/srv/notes", home)
+        );
+        assert_eq!(
+            PathBuf::from("~ada/notes"),
+            FileSystem::expand_home("~ada/notes", home),
+            "Other users' folders"
+        );
+    }
+
+    #[test]
+    fn test_copy_name() {
+        assert_eq!(
+            "notes (copy).txt",
+            FileSystem::copy_name("notes.txt", "copy")
+        );
+        assert_eq!("notes (copy)", FileSystem::copy_name("notes", "copy"));
+        assert_eq!(
+            ".bashrc (copy)",
+            FileSystem::copy_name(".bashrc", "copy"),
+            "Hidden files"
+        );
+        assert_eq!(
+            "archive.tar (copy).gz",
+            FileSystem::copy_name("archive.tar.gz", "copy")
+        );
+    }
+
+    #[test]
+    fn test_name_from_text() {
+        assert_eq!(
+            Some("Shopping list".into()),
+            FileSystem::name_from_text("\n  \n# Shopping list\nmilk\n")
+        );
+        assert_eq!(
+            Some("a b c d".into()),
+            FileSystem::name_from_text("a/b:c\t  d?"),
+            "Characters not allowed in names"
+        );
+        assert_eq!(
+            Some("hidden".into()),
+            FileSystem::name_from_text("..hidden."),
+            "Not a hidden file"
+        );
+        assert_eq!(
+            60,
+            FileSystem::name_from_text(&"é".repeat(100))
+                .unwrap()
+                .chars()
+                .count()
+        );
+        assert_eq!(
+            Some("Notes".into()),
+            FileSystem::name_from_text("---\nNotes"),
+            "Lines of only markup are skipped"
+        );
+        assert_eq!(None, FileSystem::name_from_text("\n# \n"));
+        assert_eq!(None, FileSystem::name_from_text(""));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run() {
+        let run = |argv: &[&str], input: Option<&str>| {
+            let argv: Vec<&OsStr> = argv.iter().map(OsStr::new).collect();
+            let input = input.map(String::from);
+            finish(|done| FileSystem::run(&argv, input, &gio::Cancellable::new(), done))
+        };
+        assert_eq!(
+            Ok("Mary had a little lamb".to_string()),
+            run(&["cat"], Some("Mary had a little lamb"))
+        );
+        assert!(
+            matches!(
+                run(&["sh", "-c", "exit 126"], None),
+                Err(Error::Io {
+                    kind: IoKind::PermissionDenied,
+                    ..
+                })
+            ),
+            "Dismissing the password prompt"
+        );
+        assert!(run(&["false"], None).is_err());
+    }
+}
@@ -0,0 +1,161 @@
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+
+// Each line keeps its own line ending, so CRLF files stay CRLF.
+pub fn trim_trailing_whitespace(text: &str) -> String {
+    text.split_inclusive('\n')
+        .map(|line| {
+            let ending = if line.ends_with("\r\n") {
+                "\r\n"
+            } else if line.ends_with('\n') {
+                "\n"
+            } else {
+                ""
+            };
+            line[..line.len() - ending.len()].trim_end().to_string() + ending
+        })
+        .collect()
+}
+
+// Ends the text with exactly one line ending, in the style the text
+// already uses. Empty text stays empty.
+pub fn ensure_final_newline(text: &str) -> String {
+    let body = text.trim_end_matches(['\r', '\n']);
+    if body.is_empty() {
+        return String::new();
+    }
+    let ending = match text.find('\n') {
+        Some(i) if text[..i].ends_with('\r') => "\r\n",
+        _ => "\n",
+    };
+    body.to_string() + ending
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineEnding {
+    Lf,
+    Crlf,
+}
+
+impl LineEnding {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "lf" | "unix" => Some(LineEnding::Lf),
+            "crlf" | "dos" | "windows" => Some(LineEnding::Crlf),
+            _ => None,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::Crlf => "\r\n",
+        }
+    }
+}
+
+// Every line ends the same way. A lone `\r` is not a line ending.
+pub fn convert_line_endings(text: &str, ending: LineEnding) -> String {
+    text.split_inclusive('\n')
+        .map(|line| match line.strip_suffix('\n') {
+            Some(line) => line.strip_suffix('\r').unwrap_or(line).to_string() + ending.as_str(),
+            None => line.to_string(),
+        })
+        .collect()
+}
+
+// Pipes the text through a shell command and returns its output. The
+// command must exit successfully for its output to be used.
+pub fn run_formatter(command: &str, dir: &Path, text: &str) -> io::Result<String> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .current_dir(dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    // Write from another thread so a formatter that streams its output
+    // cannot fill the pipe and deadlock.
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let input = text.to_string();
+    let writer = thread::spawn(move || stdin.write_all(input.as_bytes()));
+    let output = child.wait_with_output()?;
+    writer.join().ok();
+    if !output.status.success() {
+        return Err(io::Error::other(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+    String::from_utf8(output.stdout).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trim_trailing_whitespace() {
+        assert_eq!("a\n b\n\nc", trim_trailing_whitespace("a  \n b\t\n \nc "));
+        assert_eq!(
+            "a\nb\n",
+            trim_trailing_whitespace("a \nb \n"),
+            "Final newline is kept"
+        );
+        assert_eq!("", trim_trailing_whitespace(""));
+    }
+
+    #[test]
+    fn test_trim_trailing_whitespace_crlf() {
+        assert_eq!(
+            "a\r\n b\r\n\r\nc",
+            trim_trailing_whitespace("a \r\n b\t\r\n \r\nc \r"),
+            "Line endings are kept"
+        );
+        assert_eq!("a\r\nb\n", trim_trailing_whitespace("a\t\r\nb \n"));
+    }
+
+    #[test]
+    fn test_ensure_final_newline() {
+        assert_eq!("a\nb\n", ensure_final_newline("a\nb"));
+        assert_eq!("a\n", ensure_final_newline("a\n\n\n"), "Extra blank lines");
+        assert_eq!("a\r\nb\r\n", ensure_final_newline("a\r\nb"));
+        assert_eq!("a\r\nb\r\n", ensure_final_newline("a\r\nb\r\n\r\n"));
+        assert_eq!("a\n", ensure_final_newline("a"));
+        assert_eq!("", ensure_final_newline(""));
+        assert_eq!("", ensure_final_newline("\n\n"));
+        assert_eq!(
+            "  \n",
+            ensure_final_newline("  "),
+            "Whitespace is not trimmed"
+        );
+    }
+
+    #[test]
+    fn test_convert_line_endings() {
+        assert_eq!(
+            "a\r\nb\r\n\r\nc",
+            convert_line_endings("a\nb\r\n\nc", LineEnding::Crlf)
+        );
+        assert_eq!("a\nb\n", convert_line_endings("a\r\nb\n", LineEnding::Lf));
+        assert_eq!("a\rb", convert_line_endings("a\rb", LineEnding::Crlf));
+        assert_eq!(Some(LineEnding::Crlf), LineEnding::parse("CRLF"));
+        assert_eq!(None, LineEnding::parse("cr"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_formatter() {
+        let dir = std::env::temp_dir();
+        assert_eq!(
+            "MARY HAD A LITTLE LAMB",
+            run_formatter("tr a-z A-Z", &dir, "Mary had a little lamb").unwrap()
+        );
+        assert!(
+            run_formatter("cat > /dev/null; exit 1", &dir, "lamb").is_err(),
+            "A failing command is an error"
+        );
+    }
+}
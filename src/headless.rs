@@ -0,0 +1,186 @@
+use super::cli;
+use super::formatting::{self, LineEnding};
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+const LINE_ENDINGS_OPTION: &str = "--convert-line-endings";
+const STRIP_OPTION: &str = "--strip-trailing-whitespace";
+const FINAL_NEWLINE_OPTION: &str = "--ensure-final-newline";
+
+// Changes made to files without starting the editor, for scripts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transform {
+    LineEndings(LineEnding),
+    StripTrailingWhitespace,
+    EnsureFinalNewline,
+}
+
+impl Transform {
+    fn apply(self, text: &str) -> String {
+        match self {
+            Transform::LineEndings(ending) => formatting::convert_line_endings(text, ending),
+            Transform::StripTrailingWhitespace => formatting::trim_trailing_whitespace(text),
+            Transform::EnsureFinalNewline => formatting::ensure_final_newline(text),
+        }
+    }
+}
+
+// The transforms asked for and the files to make them to.
+pub type Job = (Vec<Transform>, Vec<PathBuf>);
+
+// Without any transform the arguments are the editor's, and are left for
+// it.
+pub fn parse(args: &[OsString]) -> Result<Option<Job>, String> {
+    let mut transforms = Vec::new();
+    let mut files = Vec::new();
+    let mut unknown = None;
+    let mut options = true;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.to_str().filter(|_| options) {
+            Some("--") => options = false,
+            Some(STRIP_OPTION) => transforms.push(Transform::StripTrailingWhitespace),
+            Some(FINAL_NEWLINE_OPTION) => transforms.push(Transform::EnsureFinalNewline),
+            Some(option) if option.split('=').next() == Some(LINE_ENDINGS_OPTION) => {
+                let name = match option.split_once('=') {
+                    Some((_, name)) => Some(name),
+                    None => args.next().and_then(|arg| arg.to_str()),
+                };
+                let ending = name
+                    .and_then(LineEnding::parse)
+                    .ok_or_else(|| format!("{} takes lf or crlf", LINE_ENDINGS_OPTION))?;
+                transforms.push(Transform::LineEndings(ending));
+            }
+            Some(option) if option.starts_with('-') && option != cli::STDIN_ARG => {
+                unknown.get_or_insert_with(|| option.to_string());
+            }
+            _ => files.push(PathBuf::from(arg)),
+        }
+    }
+    if transforms.is_empty() {
+        return Ok(None);
+    }
+    match unknown {
+        Some(option) => Err(format!("Unknown option {}", option)),
+        None => Ok(Some((transforms, files))),
+    }
+}
+
+// Makes the transforms to each file in turn and returns the exit status.
+// A file that fails is reported, and the rest are still changed. Without
+// files, or for `-`, standard input is written to standard output.
+pub fn run(transforms: &[Transform], files: &[PathBuf]) -> i32 {
+    let stdin = [PathBuf::from(cli::STDIN_ARG)];
+    let files = if files.is_empty() { &stdin[..] } else { files };
+    let mut status = 0;
+    for path in files {
+        let done = if path.as_os_str() == cli::STDIN_ARG {
+            filter(transforms)
+        } else {
+            transform_file(path, transforms).map(|_| ())
+        };
+        if let Err(e) = done {
+            eprintln!("{}: {}", path.display(), e);
+            status = 1;
+        }
+    }
+    status
+}
+
+fn apply(text: &str, transforms: &[Transform]) -> String {
+    transforms
+        .iter()
+        .fold(text.to_string(), |text, transform| transform.apply(&text))
+}
+
+fn filter(transforms: &[Transform]) -> io::Result<()> {
+    let mut text = String::new();
+    io::stdin().read_to_string(&mut text)?;
+    io::stdout().write_all(apply(&text, transforms).as_bytes())
+}
+
+// Files are only written when they change. They are written beside
+// themselves and renamed into place with their permissions, so a failure
+// leaves them as they were, and symlinks are written through.
+fn transform_file(path: &Path, transforms: &[Transform]) -> io::Result<bool> {
+    let path = fs::canonicalize(path)?;
+    let text = fs::read_to_string(&path)?;
+    let changed = apply(&text, transforms);
+    if changed == text {
+        return Ok(false);
+    }
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".partial");
+    let partial = path.with_file_name(name);
+    fs::write(&partial, changed)
+        .and_then(|_| fs::set_permissions(&partial, fs::metadata(&path)?.permissions()))
+        .and_then(|_| fs::rename(&partial, &path))
+        .inspect_err(|_| {
+            fs::remove_file(&partial).ok();
+        })?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<OsString> {
+        args.iter().map(OsString::from).collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            Ok(None),
+            parse(&args(&["--new-window", "a.txt"])),
+            "For the editor"
+        );
+        assert_eq!(
+            Ok(Some((
+                vec![
+                    Transform::LineEndings(LineEnding::Crlf),
+                    Transform::StripTrailingWhitespace,
+                    Transform::LineEndings(LineEnding::Lf),
+                ],
+                vec![PathBuf::from("a.txt"), "-".into(), "--b.txt".into()],
+            ))),
+            parse(&args(&[
+                "--convert-line-endings=crlf",
+                "a.txt",
+                "--strip-trailing-whitespace",
+                "--convert-line-endings",
+                "lf",
+                "-",
+                "--",
+                "--b.txt",
+            ]))
+        );
+        assert!(parse(&args(&["--convert-line-endings=cr"])).is_err());
+        assert!(parse(&args(&["--convert-line-endings"])).is_err());
+        assert!(
+            parse(&args(&["--ensure-final-newline", "--read-only"])).is_err(),
+            "Not a transform"
+        );
+    }
+
+    #[test]
+    fn test_transform_file() {
+        let dir = std::env::temp_dir().join(format!("textedit2-headless-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("a.txt");
+        fs::write(&path, "a \r\nb\t\n").unwrap();
+        let transforms = [
+            Transform::StripTrailingWhitespace,
+            Transform::LineEndings(LineEnding::Lf),
+        ];
+        assert!(transform_file(&path, &transforms).unwrap());
+        assert_eq!("a\nb\n", fs::read_to_string(&path).unwrap());
+        assert!(!transform_file(&path, &transforms).unwrap(), "Unchanged");
+        assert!(transform_file(&dir.join("missing.txt"), &transforms).is_err());
+        assert_eq!(1, run(&transforms, &[dir.join("missing.txt"), path]));
+        fs::remove_dir_all(&dir).ok();
+    }
+}
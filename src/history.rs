@@ -0,0 +1,377 @@
+use ropey::Rope;
+use std::collections::VecDeque;
+
+pub const DEFAULT_LIMIT: usize = 1000;
+
+// A single change to the text. Offsets count characters, matching the
+// offsets used by the text buffer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Edit {
+    offset: usize,
+    removed: String,
+    inserted: String,
+}
+
+impl Edit {
+    fn between(old: &str, new: &str) -> Option<Self> {
+        if old == new {
+            return None;
+        }
+        let prefix = old
+            .char_indices()
+            .zip(new.chars())
+            .take_while(|((_, a), b)| a == b)
+            .last()
+            .map_or(0, |((i, c), _)| i + c.len_utf8());
+        let suffix = old[prefix..]
+            .chars()
+            .rev()
+            .zip(new[prefix..].chars().rev())
+            .take_while(|(a, b)| a == b)
+            .map(|(c, _)| c.len_utf8())
+            .sum::<usize>();
+        Some(Self {
+            offset: old[..prefix].chars().count(),
+            removed: old[prefix..old.len() - suffix].to_string(),
+            inserted: new[prefix..new.len() - suffix].to_string(),
+        })
+    }
+
+    // Typing or deleting a run of characters undoes as one step. A word
+    // boundary starts a new step.
+    fn merge(&mut self, next: &Edit) -> bool {
+        if self.removed.is_empty() && next.removed.is_empty() {
+            let end = self.offset + self.inserted.chars().count();
+            let new_word = next.inserted.starts_with(char::is_whitespace)
+                && self.inserted.chars().any(|c| !c.is_whitespace());
+            if next.offset == end && !new_word {
+                self.inserted.push_str(&next.inserted);
+                return true;
+            }
+        } else if self.inserted.is_empty() && next.inserted.is_empty() {
+            if next.offset + next.removed.chars().count() == self.offset {
+                // Backspace
+                self.removed.insert_str(0, &next.removed);
+                self.offset = next.offset;
+                return true;
+            }
+            if next.offset == self.offset {
+                // Delete
+                self.removed.push_str(&next.removed);
+                return true;
+            }
+        }
+        false
+    }
+
+    fn replace(text: &mut Rope, offset: usize, remove: &str, insert: &str) {
+        text.remove(offset..offset + remove.chars().count());
+        text.insert(offset, insert);
+    }
+}
+
+// Each undo step is one or more edits, made in order.
+#[derive(Debug, Clone)]
+pub struct History {
+    undo: VecDeque<Vec<Edit>>,
+    redo: Vec<Vec<Edit>>,
+    limit: usize,
+    group: bool,
+    // How many user actions are open, and the edits made in them so far.
+    actions: usize,
+    compound: Vec<Edit>,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new(DEFAULT_LIMIT)
+    }
+}
+
+impl History {
+    // A limit of 0 keeps every change.
+    pub fn new(limit: usize) -> Self {
+        Self {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            limit,
+            group: true,
+            actions: 0,
+            compound: Vec::new(),
+        }
+    }
+
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit;
+        self.trim();
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.compound.clear();
+    }
+
+    // Edits until the matching `end_action` undo as one step, such as a
+    // replace made as a delete and then an insert. Actions may nest.
+    pub fn begin_action(&mut self) {
+        self.actions += 1;
+    }
+
+    pub fn end_action(&mut self) {
+        self.actions = self.actions.saturating_sub(1);
+        if self.actions == 0 {
+            self.commit();
+        }
+    }
+
+    // A single edit still joins the typing around it.
+    fn commit(&mut self) {
+        let mut edits = std::mem::take(&mut self.compound);
+        match edits.len() {
+            0 => (),
+            1 => self.push_step(edits.remove(0)),
+            _ => {
+                self.undo.push_back(edits);
+                self.trim();
+                self.group = false;
+            }
+        }
+    }
+
+    // Records a change to the whole text.
+    pub fn record(&mut self, old: &str, new: &str) {
+        if let Some(edit) = Edit::between(old, new) {
+            self.push(edit);
+        }
+    }
+
+    pub fn record_insert(&mut self, offset: usize, inserted: &str) {
+        if !inserted.is_empty() {
+            self.push(Edit {
+                offset,
+                removed: String::new(),
+                inserted: inserted.to_string(),
+            });
+        }
+    }
+
+    pub fn record_delete(&mut self, offset: usize, removed: &str) {
+        if !removed.is_empty() {
+            self.push(Edit {
+                offset,
+                removed: removed.to_string(),
+                inserted: String::new(),
+            });
+        }
+    }
+
+    fn push(&mut self, edit: Edit) {
+        self.redo.clear();
+        if self.actions > 0 {
+            self.compound.push(edit);
+        } else {
+            self.push_step(edit);
+        }
+    }
+
+    fn push_step(&mut self, edit: Edit) {
+        let merged = match self.undo.back_mut() {
+            Some(last) if self.group && last.len() == 1 => last[0].merge(&edit),
+            _ => false,
+        };
+        if !merged {
+            self.undo.push_back(vec![edit]);
+            self.trim();
+        }
+        self.group = true;
+    }
+
+    // Keeps the next change from joining the previous undo step.
+    pub fn break_group(&mut self) {
+        self.group = false;
+    }
+
+    // Restores the text and returns the cursor offset after the change.
+    pub fn undo(&mut self, text: &mut Rope) -> Option<usize> {
+        self.commit();
+        let step = self.undo.pop_back()?;
+        for edit in step.iter().rev() {
+            Edit::replace(text, edit.offset, &edit.inserted, &edit.removed);
+        }
+        let cursor = step
+            .first()
+            .map(|edit| edit.offset + edit.removed.chars().count());
+        self.redo.push(step);
+        self.group = false;
+        cursor
+    }
+
+    pub fn redo(&mut self, text: &mut Rope) -> Option<usize> {
+        self.commit();
+        let step = self.redo.pop()?;
+        for edit in &step {
+            Edit::replace(text, edit.offset, &edit.removed, &edit.inserted);
+        }
+        let cursor = step
+            .last()
+            .map(|edit| edit.offset + edit.inserted.chars().count());
+        self.undo.push_back(step);
+        self.group = false;
+        cursor
+    }
+
+    fn trim(&mut self) {
+        while self.limit > 0 && self.undo.len() > self.limit {
+            self.undo.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Records each state in turn, as typing would.
+    fn typed(states: &[&str]) -> History {
+        let mut history = History::default();
+        for pair in states.windows(2) {
+            history.record(pair[0], pair[1]);
+        }
+        history
+    }
+
+    fn undo(history: &mut History, text: &str) -> (String, usize) {
+        let mut rope = Rope::from_str(text);
+        let cursor = history.undo(&mut rope).unwrap();
+        (rope.to_string(), cursor)
+    }
+
+    fn redo(history: &mut History, text: &str) -> (String, usize) {
+        let mut rope = Rope::from_str(text);
+        let cursor = history.redo(&mut rope).unwrap();
+        (rope.to_string(), cursor)
+    }
+
+    #[test]
+    fn test_edit_between() {
+        assert_eq!(None, Edit::between("lamb", "lamb"));
+        assert_eq!(
+            Some(Edit {
+                offset: 7,
+                removed: "d".into(),
+                inserted: "s".into()
+            }),
+            Edit::between("Mary had a lamb", "Mary has a lamb")
+        );
+        let edit = Edit::between("naïve café", "naïve cafés").unwrap();
+        assert_eq!(10, edit.offset, "Offsets count characters");
+        assert_eq!("s", edit.inserted);
+    }
+
+    #[test]
+    fn test_undo_redo() {
+        let mut history = typed(&["Mary", "Mary had", "Mary had a lamb"]);
+        history.break_group();
+        let (text, cursor) = undo(&mut history, "Mary had a lamb");
+        assert_eq!("Mary had", text);
+        assert_eq!(8, cursor);
+        assert!(history.can_redo());
+        let (text, cursor) = redo(&mut history, &text);
+        assert_eq!("Mary had a lamb", text);
+        assert_eq!(15, cursor);
+        assert!(!history.can_redo());
+    }
+
+    #[test]
+    fn test_typing_groups_by_word() {
+        let mut history = typed(&["", "M", "Ma", "Mar", "Mary", "Mary ", "Mary h", "Mary ha"]);
+        let (text, _) = undo(&mut history, "Mary ha");
+        assert_eq!("Mary", text, "The second word undoes as one step");
+        let (text, _) = undo(&mut history, &text);
+        assert_eq!("", text);
+        assert!(!history.can_undo());
+    }
+
+    #[test]
+    fn test_deletes_group() {
+        let mut history = typed(&["lamb", "lam", "la", "l"]);
+        let (text, cursor) = undo(&mut history, "l");
+        assert_eq!("lamb", text, "Backspaces undo together");
+        assert_eq!(4, cursor);
+
+        let mut history = typed(&["lamb", "amb", "mb"]);
+        let (text, _) = undo(&mut history, "mb");
+        assert_eq!("lamb", text, "Forward deletes undo together");
+    }
+
+    #[test]
+    fn test_new_edit_clears_redo() {
+        let mut history = typed(&["a", "a b"]);
+        let (text, _) = undo(&mut history, "a b");
+        history.record(&text, "a c");
+        assert!(!history.can_redo(), "Redo is discarded after an edit");
+    }
+
+    #[test]
+    fn test_limit() {
+        let mut history = History::new(2);
+        history.record("a", "a\nb");
+        history.record("a\nb", "a\nb\nc");
+        history.record("a\nb\nc", "a\nb\nc\nd");
+        let (text, _) = undo(&mut history, "a\nb\nc\nd");
+        let (text, _) = undo(&mut history, &text);
+        assert_eq!("a\nb", text);
+        assert!(!history.can_undo(), "Oldest changes are dropped");
+        history.set_limit(0);
+        history.record("a\nb", "x");
+        assert!(history.can_undo(), "No limit");
+    }
+
+    #[test]
+    fn test_record_deltas() {
+        let mut history = History::default();
+        for (i, c) in "lamb".chars().enumerate() {
+            history.record_insert(i, &c.to_string());
+        }
+        history.record_delete(3, "b");
+        history.record_delete(2, "m");
+        let (text, cursor) = undo(&mut history, "la");
+        assert_eq!("lamb", text, "Deletes undo together");
+        assert_eq!(4, cursor);
+        let (text, _) = undo(&mut history, &text);
+        assert_eq!("", text, "Typing a word undoes as one step");
+        history.record_insert(0, "");
+        assert!(!history.can_undo(), "Empty changes are not recorded");
+    }
+
+    #[test]
+    fn test_actions() {
+        let mut history = History::default();
+        history.record_insert(0, "a\nb");
+        history.begin_action();
+        history.record_delete(0, "a\nb");
+        history.record_insert(0, "b\na");
+        history.end_action();
+        let (text, cursor) = undo(&mut history, "b\na");
+        assert_eq!("a\nb", text, "A replace undoes as one step");
+        assert_eq!(3, cursor);
+        let (text, _) = redo(&mut history, &text);
+        assert_eq!("b\na", text);
+
+        history.begin_action();
+        history.record_insert(3, " ");
+        history.end_action();
+        history.record_insert(4, "c");
+        let (text, _) = undo(&mut history, "b\na c");
+        assert_eq!("b\na", text, "A typed key still groups with the next");
+    }
+}
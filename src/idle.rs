@@ -0,0 +1,169 @@
+use ropey::Rope;
+
+// Lines handled in one step, so typing never waits long for one to finish.
+pub const CHUNK_LINES: usize = 1000;
+
+// Background work on the text, most urgent first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Task {
+    Stats,
+    Links,
+}
+
+const TASKS: [Task; 2] = [Task::Stats, Task::Links];
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    pub lines: usize,
+    pub words: usize,
+    pub characters: usize,
+}
+
+// Work on the text as it was when it last changed, done a chunk at a time
+// while the main loop is idle. Every change starts it over, and results
+// are only replaced once the tasks that make them finish.
+#[derive(Debug, Default, Clone)]
+pub struct Idle {
+    serial: usize,
+    pending: Vec<Task>,
+    // How far the first pending task has come.
+    line: usize,
+    partial_stats: Stats,
+    partial_links: Vec<(usize, usize)>,
+    finished_stats: Option<Stats>,
+    finished_links: Vec<(usize, usize)>,
+}
+
+impl Idle {
+    // Drops the work in progress. Steps for an earlier serial are ignored.
+    pub fn restart(&mut self) -> usize {
+        self.serial += 1;
+        self.pending = TASKS.to_vec();
+        self.line = 0;
+        self.partial_stats = Stats::default();
+        self.partial_links.clear();
+        self.serial
+    }
+
+    pub fn serial(&self) -> usize {
+        self.serial
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    pub fn stats(&self) -> Option<Stats> {
+        self.finished_stats
+    }
+
+    // Character offsets of the web addresses in the text.
+    pub fn links(&self) -> &[(usize, usize)] {
+        &self.finished_links
+    }
+
+    // Works through the next lines of the most urgent task, and returns it
+    // once it is finished.
+    pub fn step(&mut self, text: &Rope) -> Option<Task> {
+        let task = *self.pending.first()?;
+        let end = (self.line + CHUNK_LINES).min(text.len_lines());
+        for index in self.line..end {
+            let line = text.line(index);
+            match task {
+                Task::Stats => {
+                    self.partial_stats.characters += line.len_chars();
+                    self.partial_stats.words += line.to_string().split_whitespace().count();
+                }
+                Task::Links => {
+                    let start = text.line_to_char(index);
+                    let links = find_links(&line.to_string());
+                    self.partial_links
+                        .extend(links.into_iter().map(|(s, e)| (start + s, start + e)));
+                }
+            }
+        }
+        self.line = end;
+        if end < text.len_lines() {
+            return None;
+        }
+        self.pending.remove(0);
+        self.line = 0;
+        match task {
+            Task::Stats => {
+                self.partial_stats.lines = text.len_lines();
+                self.finished_stats = Some(std::mem::take(&mut self.partial_stats));
+            }
+            Task::Links => self.finished_links = std::mem::take(&mut self.partial_links),
+        }
+        Some(task)
+    }
+}
+
+// Addresses end at a space, and punctuation at their end belongs to the
+// sentence around them.
+pub fn find_links(line: &str) -> Vec<(usize, usize)> {
+    let mut links = Vec::new();
+    let mut rest = 0;
+    while let Some(found) = ["https://", "http://"]
+        .iter()
+        .filter_map(|scheme| line[rest..].find(scheme))
+        .min()
+    {
+        let start = rest + found;
+        let length = line[start..]
+            .find(|c: char| c.is_whitespace() || "<>\"'`".contains(c))
+            .unwrap_or(line.len() - start);
+        let address = line[start..start + length].trim_end_matches(|c| ".,;:!?)]".contains(c));
+        let end = start + address.len();
+        if !address.ends_with("//") {
+            let chars = |byte: usize| line[..byte].chars().count();
+            links.push((chars(start), chars(end)));
+        }
+        rest = start + length.max(1);
+    }
+    links
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_links() {
+        assert_eq!(
+            vec![(5, 26)],
+            find_links("See: https://example.com/a, then")
+        );
+        assert_eq!(vec![(6, 24)], find_links("Café (http://example.org)."));
+        assert!(find_links("http:// alone").is_empty());
+        assert_eq!(2, find_links("http://a.b http://c.d").len());
+    }
+
+    #[test]
+    fn test_step() {
+        let text = Rope::from_str(&"one two\nhttp://a.b\n".repeat(CHUNK_LINES));
+        let mut idle = Idle::default();
+        idle.restart();
+        assert_eq!(None, idle.step(&text), "A chunk at a time");
+        assert_eq!(None, idle.stats());
+        let mut finished = Vec::new();
+        while !idle.is_done() {
+            finished.extend(idle.step(&text));
+        }
+        assert_eq!(vec![Task::Stats, Task::Links], finished);
+        assert_eq!(
+            Some(Stats {
+                lines: CHUNK_LINES * 2 + 1,
+                words: CHUNK_LINES * 3,
+                characters: text.len_chars(),
+            }),
+            idle.stats()
+        );
+        assert_eq!(CHUNK_LINES, idle.links().len());
+        assert_eq!((8, 18), idle.links()[0]);
+
+        idle.restart();
+        assert!(!idle.is_done());
+        assert!(idle.stats().is_some(), "Kept until replaced");
+    }
+}
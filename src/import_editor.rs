@@ -0,0 +1,205 @@
+use crate::gio;
+use gio::prelude::*;
+use log::warn;
+
+const GEDIT_SCHEMA: &str = "org.gnome.gedit.preferences.editor";
+const TEXT_EDITOR_SCHEMA: &str = "org.gnome.TextEditor";
+
+// Preferences of another editor, translated to the values of this one's
+// settings. Unset values are left alone when importing.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct EditorSettings {
+    pub editor: &'static str,
+    pub font: Option<String>,
+    pub wrap_text: Option<bool>,
+    pub tab_width: Option<i32>,
+    pub color_scheme: Option<&'static str>,
+}
+
+impl EditorSettings {
+    pub fn from_gedit(
+        use_default_font: bool,
+        editor_font: &str,
+        wrap_mode: &str,
+        tabs_size: u32,
+        scheme: &str,
+    ) -> Self {
+        Self {
+            editor: "gedit",
+            font: custom_font(use_default_font, editor_font),
+            wrap_text: Some(wrap_mode != "none"),
+            tab_width: tab_width(tabs_size),
+            // Only dark schemes say so in their name.
+            color_scheme: if scheme.contains("dark") {
+                Some("dark")
+            } else {
+                None
+            },
+        }
+    }
+
+    pub fn from_text_editor(
+        use_system_font: bool,
+        custom: &str,
+        wrap_text: bool,
+        tabs_size: u32,
+        style_variant: &str,
+    ) -> Self {
+        Self {
+            editor: "GNOME Text Editor",
+            font: custom_font(use_system_font, custom),
+            wrap_text: Some(wrap_text),
+            tab_width: tab_width(tabs_size),
+            color_scheme: match style_variant {
+                "light" => Some("light"),
+                "dark" => Some("dark"),
+                "follow" => Some("system"),
+                _ => None,
+            },
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.font.is_none()
+            && self.wrap_text.is_none()
+            && self.tab_width.is_none()
+            && self.color_scheme.is_none()
+    }
+
+    pub fn apply(&self, settings: &gio::Settings) -> Result<(), gio::glib::BoolError> {
+        if let Some(font) = &self.font {
+            settings.set_string("font", font)?;
+        }
+        if let Some(wrap_text) = self.wrap_text {
+            settings.set_boolean("wrap-text", wrap_text)?;
+        }
+        if let Some(tab_width) = self.tab_width {
+            settings.set_int("tab-width", tab_width)?;
+        }
+        if let Some(color_scheme) = self.color_scheme {
+            settings.set_string("color-scheme", color_scheme)?;
+        }
+        Ok(())
+    }
+}
+
+fn custom_font(use_default: bool, font: &str) -> Option<String> {
+    if use_default || font.trim().is_empty() {
+        None
+    } else {
+        Some(font.trim().to_string())
+    }
+}
+
+// Keeps the width within the range the schema allows.
+fn tab_width(size: u32) -> Option<i32> {
+    if (1..=32).contains(&size) {
+        Some(size as i32)
+    } else {
+        None
+    }
+}
+
+// Settings of an installed editor, if the user changed any of them.
+fn customized(schema_id: &str, keys: &[&str]) -> Option<gio::Settings> {
+    let schema = gio::SettingsSchemaSource::default()?.lookup(schema_id, true)?;
+    if !keys.iter().all(|key| schema.has_key(key)) {
+        warn!("Skipping {}, it lacks the expected keys", schema_id);
+        return None;
+    }
+    let settings = gio::Settings::new(schema_id);
+    if keys.iter().any(|key| settings.user_value(key).is_some()) {
+        Some(settings)
+    } else {
+        None
+    }
+}
+
+// Preferences of the other editors installed on this system. GNOME Text
+// Editor comes first, as gedit users who switched to it have newer ones.
+pub fn detect() -> Vec<EditorSettings> {
+    let mut found = Vec::new();
+    let keys = [
+        "use-system-font",
+        "custom-font",
+        "wrap-text",
+        "tab-width",
+        "style-variant",
+    ];
+    if let Some(s) = customized(TEXT_EDITOR_SCHEMA, &keys) {
+        found.push(EditorSettings::from_text_editor(
+            s.boolean("use-system-font"),
+            &s.string("custom-font"),
+            s.boolean("wrap-text"),
+            s.uint("tab-width"),
+            &s.string("style-variant"),
+        ));
+    }
+    let keys = [
+        "use-default-font",
+        "editor-font",
+        "wrap-mode",
+        "tabs-size",
+        "scheme",
+    ];
+    if let Some(s) = customized(GEDIT_SCHEMA, &keys) {
+        found.push(EditorSettings::from_gedit(
+            s.boolean("use-default-font"),
+            &s.string("editor-font"),
+            &s.string("wrap-mode"),
+            s.uint("tabs-size"),
+            &s.string("scheme"),
+        ));
+    }
+    found.retain(|s| !s.is_empty());
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_gedit() {
+        let settings =
+            EditorSettings::from_gedit(false, "Monospace 12", "word", 8, "solarized-dark");
+        assert_eq!(
+            EditorSettings {
+                editor: "gedit",
+                font: Some("Monospace 12".into()),
+                wrap_text: Some(true),
+                tab_width: Some(8),
+                color_scheme: Some("dark"),
+            },
+            settings
+        );
+        let settings = EditorSettings::from_gedit(true, "Monospace 12", "none", 0, "classic");
+        assert_eq!(None, settings.font, "The default font is not imported");
+        assert_eq!(Some(false), settings.wrap_text);
+        assert_eq!(None, settings.tab_width, "Out of range widths are skipped");
+        assert_eq!(None, settings.color_scheme, "Light schemes keep our choice");
+    }
+
+    #[test]
+    fn test_from_text_editor() {
+        let settings =
+            EditorSettings::from_text_editor(false, "Source Code Pro 10", false, 4, "follow");
+        assert_eq!(Some("Source Code Pro 10".to_string()), settings.font);
+        assert_eq!(Some(false), settings.wrap_text);
+        assert_eq!(Some(4), settings.tab_width);
+        assert_eq!(Some("system"), settings.color_scheme);
+        let settings = EditorSettings::from_text_editor(true, "", true, 2, "dark");
+        assert_eq!(None, settings.font);
+        assert_eq!(Some("dark"), settings.color_scheme);
+    }
+
+    #[test]
+    fn test_is_empty() {
+        assert!(EditorSettings::default().is_empty());
+        let settings = EditorSettings {
+            tab_width: Some(2),
+            ..EditorSettings::default()
+        };
+        assert!(!settings.is_empty());
+    }
+}
@@ -0,0 +1,157 @@
+// What pressing Enter does to the indentation, given the text between the
+// start of the line and the cursor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Newline {
+    // Copied exactly, so tabs stay tabs and spaces stay spaces.
+    pub indent: String,
+    // Characters of whitespace just before the cursor to remove, so no
+    // line is left ending in whitespace.
+    pub trailing: usize,
+}
+
+pub fn leading_whitespace(line: &str) -> &str {
+    &line[..line.len() - line.trim_start_matches([' ', '\t']).len()]
+}
+
+pub fn newline(before: &str) -> Newline {
+    let indent = leading_whitespace(before);
+    let trailing = before.len() - before.trim_end_matches([' ', '\t']).len();
+    Newline {
+        indent: indent.to_string(),
+        trailing: before[before.len() - trailing..].chars().count(),
+    }
+}
+
+// One level of indentation.
+pub fn indent_unit(insert_spaces: bool, tab_width: usize) -> String {
+    if insert_spaces {
+        " ".repeat(tab_width.max(1))
+    } else {
+        "\t".into()
+    }
+}
+
+// The display column after the text, with tabs reaching the next tab stop.
+pub fn visual_column(text: &str, tab_width: usize) -> usize {
+    let tab_width = tab_width.max(1);
+    text.chars().fold(0, |column, c| {
+        if c == '\t' {
+            column + tab_width - column % tab_width
+        } else {
+            column + 1
+        }
+    })
+}
+
+// Spaces that take the cursor to the next tab stop.
+pub fn spaces_to_tab_stop(before: &str, tab_width: usize) -> String {
+    let tab_width = tab_width.max(1);
+    " ".repeat(tab_width - visual_column(before, tab_width) % tab_width)
+}
+
+// Blank lines are left alone so they do not gain trailing whitespace. A
+// final newline does not start another line.
+pub fn indent_lines(text: &str, unit: &str) -> String {
+    map_lines(text, |line| {
+        if line.trim().is_empty() {
+            line.to_string()
+        } else {
+            format!("{}{}", unit, line)
+        }
+    })
+}
+
+// Removes a tab, or up to a tab's width of spaces, from each line.
+pub fn dedent_lines(text: &str, tab_width: usize) -> String {
+    let tab_width = tab_width.max(1);
+    map_lines(text, |line| {
+        let spaces = line.len() - line.trim_start_matches(' ').len();
+        if spaces >= tab_width {
+            line[tab_width..].to_string()
+        } else {
+            let rest = &line[spaces..];
+            rest.strip_prefix('\t').unwrap_or(rest).to_string()
+        }
+    })
+}
+
+fn map_lines<F: Fn(&str) -> String>(text: &str, f: F) -> String {
+    let (body, newline) = match text.strip_suffix('\n') {
+        Some(body) => (body, "\n"),
+        None => (text, ""),
+    };
+    body.split('\n').map(f).collect::<Vec<_>>().join("\n") + newline
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn newline_from(before: &str) -> (String, usize) {
+        let newline = newline(before);
+        (newline.indent, newline.trailing)
+    }
+
+    #[test]
+    fn test_newline_spaces() {
+        assert_eq!(("    ".into(), 0), newline_from("    let x = 1;"));
+        assert_eq!(("".into(), 0), newline_from("fn main() {"));
+        assert_eq!(("".into(), 0), newline_from(""));
+        assert_eq!(
+            ("  ".into(), 2),
+            newline_from("  text  "),
+            "Trailing whitespace is removed"
+        );
+    }
+
+    #[test]
+    fn test_newline_tabs() {
+        assert_eq!(("\t\t".into(), 0), newline_from("\t\tif x {"));
+        assert_eq!(
+            ("\t  ".into(), 0),
+            newline_from("\t  * item"),
+            "Mixed indentation is kept as it is"
+        );
+        assert_eq!(
+            ("\t".into(), 1),
+            newline_from("\t"),
+            "A blank line's indentation moves to the new line"
+        );
+    }
+
+    #[test]
+    fn test_indent_lines() {
+        let unit = indent_unit(false, 4);
+        assert_eq!("\ta\n\n\tb\n", indent_lines("a\n\nb\n", &unit));
+        let unit = indent_unit(true, 2);
+        assert_eq!("  a\n    b", indent_lines("a\n  b", &unit));
+    }
+
+    #[test]
+    fn test_dedent_lines() {
+        assert_eq!("a\n\tb\nc", dedent_lines("\ta\n\t\tb\nc", 4));
+        assert_eq!(
+            "a\n  b\nc\n",
+            dedent_lines("    a\n      b\n  c\n", 4),
+            "Fewer spaces than a tab are all removed"
+        );
+        assert_eq!("a", dedent_lines("  \ta", 4), "Spaces before a tab");
+        let unit = indent_unit(true, 4);
+        assert_eq!("x\ny", dedent_lines(&indent_lines("x\ny", &unit), 4));
+    }
+
+    #[test]
+    fn test_tab_stops() {
+        assert_eq!(0, visual_column("", 4));
+        assert_eq!(8, visual_column("ab\tcd\t", 4));
+        assert_eq!("    ", spaces_to_tab_stop("", 4));
+        assert_eq!(" ", spaces_to_tab_stop("abc", 4));
+        assert_eq!("  ", spaces_to_tab_stop("\tx\tab", 4));
+    }
+
+    #[test]
+    fn test_leading_whitespace() {
+        assert_eq!(" \t", leading_whitespace(" \tx "));
+        assert_eq!("", leading_whitespace("\u{a0}x"), "Only spaces and tabs");
+    }
+}
@@ -0,0 +1,126 @@
+// Characters that take up no room on screen, as often come along with
+// text copied from web pages, chats and documents.
+
+const NAMED: [(char, &str); 23] = [
+    ('\u{00AD}', "Soft hyphen"),
+    ('\u{061C}', "Arabic letter mark"),
+    ('\u{180E}', "Mongolian vowel separator"),
+    ('\u{200B}', "Zero width space"),
+    ('\u{200C}', "Zero width non-joiner"),
+    ('\u{200D}', "Zero width joiner"),
+    ('\u{200E}', "Left-to-right mark"),
+    ('\u{200F}', "Right-to-left mark"),
+    ('\u{202A}', "Left-to-right embedding"),
+    ('\u{202B}', "Right-to-left embedding"),
+    ('\u{202C}', "Pop directional formatting"),
+    ('\u{202D}', "Left-to-right override"),
+    ('\u{202E}', "Right-to-left override"),
+    ('\u{2060}', "Word joiner"),
+    ('\u{2061}', "Function application"),
+    ('\u{2062}', "Invisible times"),
+    ('\u{2063}', "Invisible separator"),
+    ('\u{2064}', "Invisible plus"),
+    ('\u{2066}', "Left-to-right isolate"),
+    ('\u{2067}', "Right-to-left isolate"),
+    ('\u{2068}', "First strong isolate"),
+    ('\u{2069}', "Pop directional isolate"),
+    ('\u{FEFF}', "Byte order mark"),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Invisible {
+    // In characters, from the start of the text.
+    pub offset: usize,
+    pub line: usize,
+    pub character: char,
+    pub name: &'static str,
+}
+
+impl Invisible {
+    // As in "U+200B".
+    pub fn code(&self) -> String {
+        format!("U+{:04X}", u32::from(self.character))
+    }
+}
+
+pub fn find(text: &str) -> Vec<Invisible> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut found = Vec::new();
+    let mut line = 1;
+    for (offset, &c) in chars.iter().enumerate() {
+        if c == '\n' {
+            line += 1;
+            continue;
+        }
+        let name = match name(c) {
+            Some(name) => name,
+            None => continue,
+        };
+        // A byte order mark only belongs at the very start.
+        if c == '\u{FEFF}' && offset == 0 {
+            continue;
+        }
+        // Joiners are needed between the letters of some scripts and in
+        // emoji sequences, so only those touching plain ASCII are suspect.
+        if matches!(c, '\u{200C}' | '\u{200D}') {
+            let ascii = |i: Option<usize>| match i.and_then(|i| chars.get(i)) {
+                Some(c) => c.is_ascii(),
+                None => true,
+            };
+            if !ascii(offset.checked_sub(1)) && !ascii(Some(offset + 1)) {
+                continue;
+            }
+        }
+        found.push(Invisible {
+            offset,
+            line,
+            character: c,
+            name,
+        });
+    }
+    found
+}
+
+fn name(c: char) -> Option<&'static str> {
+    if let Some((_, name)) = NAMED.iter().find(|(x, _)| *x == c) {
+        return Some(name);
+    }
+    match c {
+        '\t' | '\n' | '\r' => None,
+        c if c.is_control() => Some("Control character"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find() {
+        let found = find("let x\u{200B} = 1;\nif admin \u{202E}&& ok\u{7} {}");
+        let names: Vec<&str> = found.iter().map(|i| i.name).collect();
+        assert_eq!(
+            vec![
+                "Zero width space",
+                "Right-to-left override",
+                "Control character"
+            ],
+            names
+        );
+        assert_eq!((1, 5), (found[0].line, found[0].offset));
+        assert_eq!(2, found[1].line);
+        assert_eq!("U+0007", found[2].code());
+        assert!(find("\u{FEFF}text\twith\r\ntabs").is_empty(), "Leading BOM");
+        assert_eq!(1, find("a\u{FEFF}b").len(), "BOM mid-file");
+    }
+
+    #[test]
+    fn test_joiners() {
+        assert!(
+            find("👩\u{200D}💻 می\u{200C}خواهم").is_empty(),
+            "Emoji and Persian need their joiners"
+        );
+        assert_eq!(1, find("pass\u{200D}word").len());
+    }
+}
@@ -0,0 +1,46 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+// Writes still in flight, shared by every window, so quitting can wait for
+// them to finish rather than leave a file half saved.
+#[derive(Debug, Default, Clone)]
+pub struct Jobs(Arc<AtomicUsize>);
+
+impl Jobs {
+    // The job is counted until it is dropped, which is when whatever holds
+    // it, usually the callback of a write, is done with.
+    pub fn start(&self) -> Job {
+        self.0.fetch_add(1, Ordering::SeqCst);
+        Job(self.0.clone())
+    }
+
+    pub fn count(&self) -> usize {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+#[derive(Debug)]
+pub struct Job(Arc<AtomicUsize>);
+
+impl Drop for Job {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_jobs() {
+        let jobs = Jobs::default();
+        let first = jobs.start();
+        let second = jobs.clone().start();
+        assert_eq!(2, jobs.count(), "Shared by clones");
+        drop(first);
+        assert_eq!(1, jobs.count());
+        std::thread::spawn(move || drop(second)).join().unwrap();
+        assert_eq!(0, jobs.count(), "Finished on another thread");
+    }
+}
@@ -0,0 +1,264 @@
+use super::markdown;
+use std::collections::HashMap;
+use std::path::Path;
+
+// Languages by the file extension that stands for them, which is also the
+// key the comment tokens are found by.
+pub const LANGUAGES: [(&str, &str); 24] = [
+    ("c", "C"),
+    ("cpp", "C++"),
+    ("cs", "C#"),
+    ("css", "CSS"),
+    ("diff", "Diff"),
+    ("go", "Go"),
+    ("html", "HTML"),
+    ("ini", "INI"),
+    ("java", "Java"),
+    ("js", "JavaScript"),
+    ("json", "JSON"),
+    ("lua", "Lua"),
+    ("md", "Markdown"),
+    ("pl", "Perl"),
+    ("php", "PHP"),
+    ("py", "Python"),
+    ("rb", "Ruby"),
+    ("rs", "Rust"),
+    ("sh", "Shell"),
+    ("sql", "SQL"),
+    ("toml", "TOML"),
+    ("ts", "TypeScript"),
+    ("xml", "XML"),
+    ("yaml", "YAML"),
+];
+
+// Other extensions for the same languages.
+const ALIASES: [(&str, &str); 15] = [
+    ("bash", "sh"),
+    ("cc", "cpp"),
+    ("cxx", "cpp"),
+    ("h", "c"),
+    ("hpp", "cpp"),
+    ("htm", "html"),
+    ("jsx", "js"),
+    ("ksh", "sh"),
+    ("markdown", "md"),
+    ("mkd", "md"),
+    ("patch", "diff"),
+    ("svg", "xml"),
+    ("tsx", "ts"),
+    ("yml", "yaml"),
+    ("zsh", "sh"),
+];
+
+// Interpreters named on a shebang line, without version numbers.
+const INTERPRETERS: [(&str, &str); 13] = [
+    ("sh", "sh"),
+    ("bash", "sh"),
+    ("dash", "sh"),
+    ("ksh", "sh"),
+    ("zsh", "sh"),
+    ("python", "py"),
+    ("ruby", "rb"),
+    ("perl", "pl"),
+    ("node", "js"),
+    ("nodejs", "js"),
+    ("deno", "js"),
+    ("lua", "lua"),
+    ("php", "php"),
+];
+
+// Only the start of a document is looked at.
+pub const DETECT_LINES: usize = 50;
+
+pub fn name(id: &str) -> Option<&'static str> {
+    LANGUAGES
+        .iter()
+        .find(|(key, _)| *key == id)
+        .map(|(_, name)| *name)
+}
+
+pub fn from_path(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_str()?.to_lowercase();
+    LANGUAGES
+        .iter()
+        .map(|(id, _)| (*id, *id))
+        .chain(ALIASES.iter().copied())
+        .find(|(key, _)| *key == extension)
+        .map(|(_, id)| id)
+}
+
+// Every extension a file name ends in, longest first, so `site.conf.j2`
+// gives `conf.j2` and then `j2`.
+pub fn extensions(path: &Path) -> Vec<String> {
+    let name = match path.file_name().and_then(|n| n.to_str()) {
+        Some(name) => name.trim_start_matches('.').to_lowercase(),
+        None => return Vec::new(),
+    };
+    name.match_indices('.')
+        .map(|(i, _)| name[i + 1..].to_string())
+        .filter(|e| !e.is_empty())
+        .collect()
+}
+
+// A language the user chose for an extension, where the longest extension
+// wins. `Some(None)` keeps the file as plain text.
+pub fn from_associations(
+    path: &Path,
+    associations: &HashMap<String, String>,
+) -> Option<Option<&'static str>> {
+    extensions(path)
+        .iter()
+        .find_map(|e| associations.get(e))
+        .map(|id| {
+            LANGUAGES
+                .iter()
+                .find(|(key, _)| key == id)
+                .map(|(key, _)| *key)
+        })
+}
+
+// The language from the text itself, for files whose name does not tell.
+pub fn detect(text: &str) -> Option<&'static str> {
+    let first = text.lines().next().unwrap_or("");
+    from_shebang(first).or_else(|| from_syntax(text))
+}
+
+// `#!/bin/bash`, `#!/usr/bin/env python3` or `#!/usr/bin/env -S node --flag`.
+pub fn from_shebang(line: &str) -> Option<&'static str> {
+    let command = line.strip_prefix("#!")?;
+    let mut words = command.split_whitespace();
+    let mut program = words.next()?.rsplit('/').next()?;
+    if program == "env" {
+        program = words.find(|w| !w.starts_with('-') && !w.contains('='))?;
+    }
+    let program = program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    INTERPRETERS
+        .iter()
+        .find(|(name, _)| *name == program)
+        .map(|(_, id)| *id)
+}
+
+// Telltale lines of common formats. Anything less certain is left as
+// plain text.
+fn from_syntax(text: &str) -> Option<&'static str> {
+    let lines: Vec<&str> = text
+        .lines()
+        .take(DETECT_LINES)
+        .filter(|l| !l.trim().is_empty())
+        .collect();
+    let first = lines.first()?.trim_start();
+    let lower = first.to_lowercase();
+    if first.starts_with("<?php") {
+        return Some("php");
+    }
+    if first.starts_with("<?xml") {
+        return Some("xml");
+    }
+    if lower.starts_with("<!doctype html") || lower.starts_with("<html") {
+        return Some("html");
+    }
+    if first.starts_with("diff --git")
+        || (first.starts_with("--- ") && matches!(lines.get(1), Some(l) if l.starts_with("+++ ")))
+    {
+        return Some("diff");
+    }
+    if first.starts_with("%YAML") {
+        return Some("yaml");
+    }
+    let body = text.trim_start();
+    if (body.starts_with('{') || body.starts_with('['))
+        && body[1..].trim_start().starts_with(['"', '{', '}', ']'])
+    {
+        return Some("json");
+    }
+    let starts = |prefixes: &[&str]| {
+        lines
+            .iter()
+            .any(|l| prefixes.iter().any(|prefix| l.starts_with(prefix)))
+    };
+    if starts(&["#include <", "#include \""]) {
+        return Some("c");
+    }
+    if starts(&["fn main()", "use std::", "#![", "pub fn ", "impl "]) {
+        return Some("rs");
+    }
+    if first.starts_with("package ") && !first.ends_with(';') && starts(&["func ", "import "]) {
+        return Some("go");
+    }
+    // A heading together with other markup, as a lone `#` line may just be
+    // a comment.
+    let headings = lines
+        .iter()
+        .filter(|l| markdown::heading(l).is_some())
+        .count();
+    let markup = starts(&["```", "- ", "* ", "> "]) || lines.iter().any(|l| l.contains("]("));
+    if headings > 0 && (headings > 1 || markup) {
+        return Some("md");
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_path() {
+        assert_eq!(Some("rs"), from_path(Path::new("src/main.rs")));
+        assert_eq!(Some("md"), from_path(Path::new("README.Markdown")));
+        assert_eq!(Some("yaml"), from_path(Path::new("ci.yml")));
+        assert_eq!(None, from_path(Path::new("notes")));
+        assert_eq!(Some("Markdown"), name("md"));
+    }
+
+    #[test]
+    fn test_from_associations() {
+        assert_eq!(
+            vec!["conf.j2".to_string(), "j2".into()],
+            extensions(Path::new("/etc/site.Conf.j2"))
+        );
+        assert!(extensions(Path::new(".bashrc")).is_empty());
+        let associations: HashMap<String, String> = vec![
+            ("j2".to_string(), "html".to_string()),
+            ("conf.j2".into(), "ini".into()),
+            ("log".into(), "text".into()),
+        ]
+        .into_iter()
+        .collect();
+        let language = |name| from_associations(Path::new(name), &associations);
+        assert_eq!(Some(Some("ini")), language("site.conf.j2"), "Longest wins");
+        assert_eq!(Some(Some("html")), language("page.j2"));
+        assert_eq!(Some(None), language("app.log"), "Plain text");
+        assert_eq!(None, language("main.rs"));
+    }
+
+    #[test]
+    fn test_from_shebang() {
+        assert_eq!(Some("sh"), from_shebang("#!/bin/bash"));
+        assert_eq!(Some("py"), from_shebang("#!/usr/bin/env python3.11"));
+        assert_eq!(
+            Some("js"),
+            from_shebang("#!/usr/bin/env -S NODE_ENV=test node --inspect"),
+            "env options are skipped"
+        );
+        assert_eq!(Some("sh"), from_shebang("#! /bin/sh -e"));
+        assert_eq!(None, from_shebang("#![allow(unused)]"));
+        assert_eq!(None, from_shebang("#!/usr/bin/awk -f"));
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(Some("py"), detect("#!/usr/bin/python\nprint('hi')\n"));
+        assert_eq!(Some("php"), detect("<?php echo 1;"));
+        assert_eq!(Some("html"), detect("\n<!DOCTYPE html>\n<html>"));
+        assert_eq!(Some("json"), detect("{\n  \"name\": \"textedit\"\n}"));
+        assert_eq!(Some("diff"), detect("--- a/x\n+++ b/x\n@@ -1 +1 @@"));
+        assert_eq!(Some("c"), detect("#include <stdio.h>\n\nint main() {}"));
+        assert_eq!(Some("rs"), detect("#![allow(unused)]\n\nfn main() {}"));
+        assert_eq!(Some("go"), detect("package main\n\nimport \"fmt\""));
+        assert_eq!(Some("md"), detect("# Notes\n\n- milk\n- eggs\n"));
+        assert_eq!(None, detect("# a comment\nvalue = 1\n"), "One heading");
+        assert_eq!(None, detect("Mary had a little lamb"));
+        assert_eq!(None, detect(""));
+    }
+}
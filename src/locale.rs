@@ -0,0 +1,322 @@
+use regex::Regex;
+use std::env;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateOrder {
+    MonthDayYear,
+    DayMonthYear,
+    YearMonthDay,
+}
+
+// How a locale writes dates and numbers. Only four digit years are
+// recognised, as two digit years are too easily confused with days.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LocaleFormat {
+    pub id: &'static str,
+    pub name: &'static str,
+    pub date_order: DateOrder,
+    pub date_separator: char,
+    pub group: Option<char>,
+    pub decimal: char,
+}
+
+pub const LOCALES: [LocaleFormat; 5] = [
+    LocaleFormat {
+        id: "en_US",
+        name: "English (US), 02/01/2024, 1,234.5",
+        date_order: DateOrder::MonthDayYear,
+        date_separator: '/',
+        group: Some(','),
+        decimal: '.',
+    },
+    LocaleFormat {
+        id: "en_GB",
+        name: "English (UK), 01/02/2024, 1,234.5",
+        date_order: DateOrder::DayMonthYear,
+        date_separator: '/',
+        group: Some(','),
+        decimal: '.',
+    },
+    LocaleFormat {
+        id: "de_DE",
+        name: "German, 01.02.2024, 1.234,5",
+        date_order: DateOrder::DayMonthYear,
+        date_separator: '.',
+        group: Some('.'),
+        decimal: ',',
+    },
+    // French groups digits with a no-break space, so plain spaces between
+    // numbers are not mistaken for grouping.
+    LocaleFormat {
+        id: "fr_FR",
+        name: "French, 01/02/2024, 1\u{a0}234,5",
+        date_order: DateOrder::DayMonthYear,
+        date_separator: '/',
+        group: Some('\u{a0}'),
+        decimal: ',',
+    },
+    LocaleFormat {
+        id: "iso",
+        name: "ISO 8601, 2024-02-01, 1234.5",
+        date_order: DateOrder::YearMonthDay,
+        date_separator: '-',
+        group: None,
+        decimal: '.',
+    },
+];
+
+impl LocaleFormat {
+    pub fn find(id: &str) -> Option<&'static LocaleFormat> {
+        LOCALES.iter().find(|l| l.id == id)
+    }
+
+    // The format of the user's locale for numbers, if it is one of ours.
+    pub fn current() -> Option<&'static LocaleFormat> {
+        ["LC_ALL", "LC_NUMERIC", "LANG"]
+            .iter()
+            .filter_map(|var| env::var(var).ok())
+            .find(|value| !value.is_empty())
+            .and_then(|value| Self::for_locale(&value))
+    }
+
+    // Matches a POSIX locale name such as `de_AT.UTF-8`, falling back to
+    // another country with the same language.
+    fn for_locale(name: &str) -> Option<&'static LocaleFormat> {
+        let name = name.split(['.', '@']).next()?;
+        let language = name.split('_').next()?;
+        Self::find(name).or_else(|| {
+            LOCALES
+                .iter()
+                .find(|l| l.id.split('_').next() == Some(language))
+        })
+    }
+}
+
+// A change to the text, with character offsets.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conversion {
+    pub start: usize,
+    pub end: usize,
+    pub original: String,
+    pub converted: String,
+}
+
+pub fn convert(text: &str, from: &LocaleFormat, to: &LocaleFormat) -> Vec<Conversion> {
+    let mut found: Vec<(usize, usize, String)> = Vec::new();
+    for m in date_pattern(from).find_iter(text) {
+        if let Some(date) = parse_date(m.as_str(), from) {
+            found.push((m.start(), m.end(), format_date(date, to)));
+        }
+    }
+    for m in number_pattern(from).find_iter(text) {
+        let overlaps = found.iter().any(|&(s, e, _)| m.start() < e && s < m.end());
+        if !overlaps && is_whole_number(text, m.start(), m.end()) {
+            found.push((m.start(), m.end(), convert_number(m.as_str(), from, to)));
+        }
+    }
+    found.sort_by_key(|&(start, _, _)| start);
+    let char_offset = |byte: usize| text[..byte].chars().count();
+    found
+        .into_iter()
+        .filter(|(start, end, converted)| text[*start..*end] != *converted)
+        .map(|(start, end, converted)| Conversion {
+            start: char_offset(start),
+            end: char_offset(end),
+            original: text[start..end].to_string(),
+            converted,
+        })
+        .collect()
+}
+
+fn date_pattern(locale: &LocaleFormat) -> Regex {
+    let sep = regex::escape(&locale.date_separator.to_string());
+    let pattern = match locale.date_order {
+        DateOrder::YearMonthDay => format!(r"\b\d{{4}}{0}\d{{1,2}}{0}\d{{1,2}}\b", sep),
+        _ => format!(r"\b\d{{1,2}}{0}\d{{1,2}}{0}\d{{4}}\b", sep),
+    };
+    Regex::new(&pattern).unwrap()
+}
+
+// Numbers are only converted when they have a separator, as plain digits
+// read the same everywhere.
+fn number_pattern(locale: &LocaleFormat) -> Regex {
+    let decimal = regex::escape(&locale.decimal.to_string());
+    let pattern = match locale.group {
+        Some(group) => format!(
+            r"\d{{1,3}}(?:{0}\d{{3}})+(?:{1}\d+)?|\d+{1}\d+",
+            regex::escape(&group.to_string()),
+            decimal
+        ),
+        None => format!(r"\d+{}\d+", decimal),
+    };
+    Regex::new(&pattern).unwrap()
+}
+
+// Rejects matches inside longer words or numbers, such as `v1.5` or the
+// version number 1.2.3.
+fn is_whole_number(text: &str, start: usize, end: usize) -> bool {
+    let before = text[..start].chars().next_back();
+    let mut after = text[end..].chars();
+    let word = |c: Option<char>| matches!(c, Some(c) if c.is_alphanumeric() || c == '_');
+    if word(before) || matches!(before, Some('.') | Some(',')) {
+        return false;
+    }
+    match after.next() {
+        Some(c) if c.is_ascii_punctuation() => {
+            !matches!(after.next(), Some(c) if c.is_ascii_digit())
+        }
+        next => !word(next),
+    }
+}
+
+// Year, month and day.
+fn parse_date(text: &str, locale: &LocaleFormat) -> Option<(u32, u32, u32)> {
+    let parts: Vec<u32> = text
+        .split(locale.date_separator)
+        .map(|p| p.parse().ok())
+        .collect::<Option<_>>()?;
+    let (year, month, day) = match locale.date_order {
+        DateOrder::MonthDayYear => (parts[2], parts[0], parts[1]),
+        DateOrder::DayMonthYear => (parts[2], parts[1], parts[0]),
+        DateOrder::YearMonthDay => (parts[0], parts[1], parts[2]),
+    };
+    if (1..=12).contains(&month) && (1..=days_in_month(year, month)).contains(&day) {
+        Some((year, month, day))
+    } else {
+        None
+    }
+}
+
+fn days_in_month(year: u32, month: u32) -> u32 {
+    match month {
+        2 if year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400)) => {
+            29
+        }
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+fn format_date((year, month, day): (u32, u32, u32), locale: &LocaleFormat) -> String {
+    let sep = locale.date_separator;
+    match locale.date_order {
+        DateOrder::MonthDayYear => format!("{:02}{}{:02}{}{}", month, sep, day, sep, year),
+        DateOrder::DayMonthYear => format!("{:02}{}{:02}{}{}", day, sep, month, sep, year),
+        DateOrder::YearMonthDay => format!("{}{}{:02}{}{:02}", year, sep, month, sep, day),
+    }
+}
+
+// Works on the digits rather than a float, so no precision is lost. Digits
+// stay grouped if they were grouped and the locale groups them.
+fn convert_number(text: &str, from: &LocaleFormat, to: &LocaleFormat) -> String {
+    let (integer, fraction) = match text.split_once(from.decimal) {
+        Some((integer, fraction)) => (integer, Some(fraction)),
+        None => (text, None),
+    };
+    let group = match from.group {
+        Some(g) if integer.contains(g) => to.group,
+        _ => None,
+    };
+    let digits: Vec<char> = integer.chars().filter(char::is_ascii_digit).collect();
+    let mut converted = String::new();
+    for (i, digit) in digits.iter().enumerate() {
+        if let Some(group) = group {
+            if i > 0 && (digits.len() - i).is_multiple_of(3) {
+                converted.push(group);
+            }
+        }
+        converted.push(*digit);
+    }
+    if let Some(fraction) = fraction {
+        converted.push(to.decimal);
+        converted.push_str(fraction);
+    }
+    converted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn locale(id: &str) -> &'static LocaleFormat {
+        LocaleFormat::find(id).unwrap()
+    }
+
+    fn converted(text: &str, from: &str, to: &str) -> Vec<String> {
+        convert(text, locale(from), locale(to))
+            .into_iter()
+            .map(|c| c.converted)
+            .collect()
+    }
+
+    #[test]
+    fn test_convert_dates() {
+        assert_eq!(
+            vec!["2024-02-01"],
+            converted("Due 01/02/2024.", "en_GB", "iso")
+        );
+        assert_eq!(vec!["01/02/2024"], converted("2024-02-01", "iso", "en_GB"));
+        assert_eq!(vec!["02/01/2024"], converted("1.2.2024", "de_DE", "en_US"));
+        assert!(
+            converted("13/31/2024 and 29/02/2023", "en_GB", "iso").is_empty(),
+            "Invalid dates are left alone"
+        );
+        assert_eq!(vec!["2024-02-29"], converted("29/02/2024", "en_GB", "iso"));
+    }
+
+    #[test]
+    fn test_convert_numbers() {
+        assert_eq!(vec!["1.234,5"], converted("1,234.5", "en_US", "de_DE"));
+        assert_eq!(vec!["1,234.5"], converted("1.234,5", "de_DE", "en_US"));
+        assert_eq!(
+            vec!["1\u{a0}234\u{a0}567,89"],
+            converted("1,234,567.89", "en_US", "fr_FR")
+        );
+        assert_eq!(vec!["1234.5"], converted("1.234,5", "de_DE", "iso"));
+        assert_eq!(
+            vec!["3,14"],
+            converted("pi is 3.14 and 2024 is a year", "en_US", "de_DE"),
+            "Plain integers are not changed"
+        );
+        assert!(
+            converted("version 1.2.3 or v1.5", "en_US", "de_DE").is_empty(),
+            "Parts of other words"
+        );
+    }
+
+    #[test]
+    fn test_convert_offsets() {
+        let changes = convert("é 1,5 and 2,5", locale("de_DE"), locale("en_US"));
+        assert_eq!(
+            Conversion {
+                start: 2,
+                end: 5,
+                original: "1,5".into(),
+                converted: "1.5".into(),
+            },
+            changes[0],
+            "Offsets count characters"
+        );
+        assert_eq!(10, changes[1].start);
+        assert!(
+            convert("2024-02-01", locale("iso"), locale("iso")).is_empty(),
+            "Unchanged text is not a change"
+        );
+    }
+
+    #[test]
+    fn test_for_locale() {
+        assert_eq!(
+            Some(locale("de_DE")),
+            LocaleFormat::for_locale("de_DE.UTF-8")
+        );
+        assert_eq!(
+            Some(locale("de_DE")),
+            LocaleFormat::for_locale("de_AT"),
+            "Same language"
+        );
+        assert_eq!(Some(locale("en_US")), LocaleFormat::for_locale("en_US"));
+        assert_eq!(None, LocaleFormat::for_locale("C"));
+    }
+}
@@ -0,0 +1,219 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+// How far a recorded cursor move goes, after GTK's movement steps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MoveUnit {
+    Characters,
+    Words,
+    Lines,
+    LineEnds,
+    Paragraphs,
+    ParagraphEnds,
+    Pages,
+    DocumentEnds,
+}
+
+// One recorded edit. Positions are counted from the cursor, so a macro
+// replays wherever the cursor is, such as on the next line.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "step", rename_all = "kebab-case")]
+pub enum Step {
+    Insert {
+        text: String,
+    },
+    // Characters before and after the cursor.
+    Delete {
+        before: usize,
+        after: usize,
+    },
+    Move {
+        unit: MoveUnit,
+        count: i32,
+        // Whether the selection grows with the cursor, as with Shift.
+        #[serde(default)]
+        extend: bool,
+    },
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Macro {
+    #[serde(default)]
+    pub steps: Vec<Step>,
+}
+
+impl Step {
+    // Folds the next step into this one when they are the same kind.
+    fn merge(&mut self, next: &Step) -> bool {
+        match (self, next) {
+            (Step::Insert { text }, Step::Insert { text: more }) => {
+                text.push_str(more);
+                true
+            }
+            (
+                Step::Move {
+                    unit,
+                    count,
+                    extend,
+                },
+                Step::Move {
+                    unit: next_unit,
+                    count: more,
+                    extend: next_extend,
+                },
+            ) if unit == next_unit && extend == next_extend && count.signum() == more.signum() => {
+                *count += more;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Macro {
+    // Typing arrives a character at a time and held arrow keys a move at a
+    // time, so each run is kept as one step.
+    pub fn push(&mut self, step: Step) {
+        if let Some(last) = self.steps.last_mut() {
+            if last.merge(&step) {
+                return;
+            }
+        }
+        self.steps.push(step);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+}
+
+// Deleting from `start` to `end` around the cursor, or None when the cursor
+// is elsewhere and the deletion was not the user's.
+pub fn delete_step(cursor: usize, start: usize, end: usize) -> Option<Step> {
+    if cursor < start || cursor > end {
+        return None;
+    }
+    Some(Step::Delete {
+        before: cursor - start,
+        after: end - cursor,
+    })
+}
+
+pub fn to_string(recorded: &Macro) -> Result<String, toml::ser::Error> {
+    toml::to_string(recorded)
+}
+
+pub fn parse(text: &str) -> Result<Macro, String> {
+    toml::from_str(text).map_err(|e| e.to_string())
+}
+
+// The last macro recorded is kept for the next time the editor starts.
+pub fn save(path: &Path, recorded: &Macro) -> Result<(), String> {
+    let text = to_string(recorded).map_err(|e| e.to_string())?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    fs::write(path, text).map_err(|e| e.to_string())
+}
+
+// Nothing recorded yet is not a failure.
+pub fn load(path: &Path) -> Result<Option<Macro>, String> {
+    match fs::read_to_string(path) {
+        Ok(text) => parse(&text).map(Some),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn moved(count: i32) -> Step {
+        Step::Move {
+            unit: MoveUnit::Characters,
+            count,
+            extend: false,
+        }
+    }
+
+    #[test]
+    fn test_push() {
+        let mut recorded = Macro::default();
+        for step in [
+            Step::Insert { text: "- ".into() },
+            Step::Insert { text: "[ ]".into() },
+            moved(1),
+            moved(2),
+            moved(-1),
+            Step::Insert { text: " ".into() },
+        ]
+        .iter()
+        {
+            recorded.push(step.clone());
+        }
+        assert_eq!(
+            vec![
+                Step::Insert {
+                    text: "- [ ]".into()
+                },
+                moved(3),
+                moved(-1),
+                Step::Insert { text: " ".into() },
+            ],
+            recorded.steps
+        );
+    }
+
+    #[test]
+    fn test_delete_step() {
+        assert_eq!(
+            Some(Step::Delete {
+                before: 1,
+                after: 0
+            }),
+            delete_step(5, 4, 5),
+            "Backspace"
+        );
+        assert_eq!(
+            Some(Step::Delete {
+                before: 0,
+                after: 3
+            }),
+            delete_step(4, 4, 7)
+        );
+        assert_eq!(None, delete_step(2, 4, 7), "Elsewhere");
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let recorded = Macro {
+            steps: vec![
+                Step::Move {
+                    unit: MoveUnit::LineEnds,
+                    count: -1,
+                    extend: false,
+                },
+                Step::Insert {
+                    text: "> \"a\"\n".into(),
+                },
+                Step::Delete {
+                    before: 0,
+                    after: 2,
+                },
+                Step::Move {
+                    unit: MoveUnit::Lines,
+                    count: 1,
+                    extend: true,
+                },
+            ],
+        };
+        let text = to_string(&recorded).unwrap();
+        assert_eq!(Ok(recorded), parse(&text));
+        assert_eq!(Ok(Macro::default()), parse(""));
+        assert!(parse("[[steps]]\nstep = \"jump\"\n").is_err());
+    }
+}
@@ -2,17 +2,71 @@ mod application;
 #[rustfmt::skip]
 mod config;
 mod actions;
+mod analysis;
 mod application_model;
+mod banner;
+mod charmap;
+mod cli;
+mod colors;
+mod comment;
+mod companion;
+mod diff;
 mod document;
+mod error;
+mod export;
+mod file_system;
+mod formatting;
+mod headless;
+mod history;
+mod idle;
+mod import_editor;
+mod indent;
+mod invisible;
+mod jobs;
+mod language;
+mod locale;
+mod macros;
+mod markdown;
+mod normalize;
+mod paste;
+mod patch;
+mod placeholder;
+mod position;
+mod preferences;
+mod project;
+mod reading;
+mod recent;
+mod regex_tester;
+mod sandbox;
+mod selection;
+mod settings;
+mod settings_archive;
+mod spell;
+mod startup;
+mod template;
+mod text_box;
+mod text_ops;
+mod timestamp;
+mod toc;
+mod token;
+mod transforms;
+mod tray;
+mod upgrade;
 mod window;
+mod workspace;
 
 use gettextrs::{gettext, LocaleCategory};
 use gtk::{gio, glib};
+use std::ffi::OsString;
+use std::process;
+use std::time::Instant;
 
 use self::application::Application;
 use self::config::{GETTEXT_PACKAGE, LOCALEDIR, RESOURCES_FILE};
 
 fn main() {
+    let launched = Instant::now();
+
     // Initialize logger
     pretty_env_logger::init();
 
@@ -21,6 +75,17 @@ fn main() {
     gettextrs::bindtextdomain(GETTEXT_PACKAGE, LOCALEDIR).expect("Unable to bind the text domain");
     gettextrs::textdomain(GETTEXT_PACKAGE).expect("Unable to switch to the text domain");
 
+    // Transforms are made without starting GTK, so scripts need no display.
+    let args: Vec<OsString> = std::env::args_os().skip(1).collect();
+    match headless::parse(&args) {
+        Ok(Some((transforms, files))) => process::exit(headless::run(&transforms, &files)),
+        Ok(None) => (),
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(2);
+        }
+    }
+
     glib::set_application_name(&gettext("TextEdit 2"));
 
     gtk::init().expect("Unable to start GTK4");
@@ -29,5 +94,6 @@ fn main() {
     gio::resources_register(&res);
 
     let app = Application::new();
+    app.time_startup(launched);
     app.run();
 }
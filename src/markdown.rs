@@ -0,0 +1,402 @@
+// Renders the common parts of Markdown into styled runs of text for the
+// preview. It is not a full CommonMark parser: HTML, tables and setext
+// headings are shown as written.
+
+use std::path::Path;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Style {
+    // 1 to 6, or 0 outside headings.
+    pub heading: u8,
+    pub strong: bool,
+    pub emphasis: bool,
+    pub code: bool,
+    pub code_block: bool,
+    pub quote: bool,
+    pub link: bool,
+}
+
+impl Style {
+    // The preview's tag for each part of the style that applies.
+    pub fn names(&self) -> Vec<String> {
+        let parts = [
+            (self.heading > 0, format!("h{}", self.heading)),
+            (self.strong, "strong".into()),
+            (self.emphasis, "emphasis".into()),
+            (self.code, "code".into()),
+            (self.code_block, "code-block".into()),
+            (self.quote, "quote".into()),
+            (self.link, "link".into()),
+        ];
+        parts
+            .iter()
+            .filter(|(applied, _)| *applied)
+            .map(|(_, name)| name.clone())
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Span {
+    pub text: String,
+    pub style: Style,
+}
+
+enum Block {
+    Paragraph { lines: Vec<String>, quote: bool },
+    Code(Vec<String>),
+}
+
+pub fn is_markdown(path: &Path) -> bool {
+    let extension = path.extension().map(|e| e.to_string_lossy().to_lowercase());
+    matches!(
+        extension.as_deref(),
+        Some("md") | Some("markdown") | Some("mkd")
+    )
+}
+
+pub fn render(text: &str) -> Vec<Span> {
+    let mut spans = Vec::new();
+    let mut block: Option<Block> = None;
+    let mut fence: Option<String> = None;
+    // The previous line was a list item.
+    let mut list = false;
+    for line in text.lines() {
+        if let Some(marker) = &fence {
+            if line.trim_start().starts_with(marker.as_str()) {
+                fence = None;
+                finish(&mut spans, block.take());
+            } else if let Some(Block::Code(lines)) = &mut block {
+                lines.push(line.to_string());
+            }
+            continue;
+        }
+        let trimmed = line.trim_start();
+        let in_paragraph = matches!(block, Some(Block::Paragraph { .. }));
+        let in_list = std::mem::take(&mut list);
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            finish(&mut spans, block.take());
+            fence = Some(trimmed[..3].to_string());
+            block = Some(Block::Code(Vec::new()));
+        } else if trimmed.is_empty() {
+            finish(&mut spans, block.take());
+        } else if !in_paragraph && (line.starts_with("    ") || line.starts_with('\t')) {
+            let code = line.strip_prefix('\t').unwrap_or_else(|| &line[4..]);
+            match &mut block {
+                Some(Block::Code(lines)) => lines.push(code.to_string()),
+                _ => block = Some(Block::Code(vec![code.to_string()])),
+            }
+        } else if let Some((level, title)) = heading(trimmed) {
+            finish(&mut spans, block.take());
+            let style = Style {
+                heading: level,
+                ..Style::default()
+            };
+            push_block(&mut spans, inline(title, style));
+        } else if is_rule(trimmed) {
+            finish(&mut spans, block.take());
+            push_block(
+                &mut spans,
+                vec![plain("\u{2015}".repeat(24), Style::default())],
+            );
+        } else if let Some((marker, item)) = list_item(line) {
+            finish(&mut spans, block.take());
+            if !in_list && !spans.is_empty() {
+                spans.push(plain("\n".into(), Style::default()));
+            }
+            list = true;
+            let mut item_spans = vec![plain(marker, Style::default())];
+            item_spans.extend(inline(item, Style::default()));
+            item_spans.push(plain("\n".into(), Style::default()));
+            spans.extend(item_spans);
+        } else {
+            let (content, quote) = match trimmed.strip_prefix('>') {
+                Some(rest) => (rest.strip_prefix(' ').unwrap_or(rest), true),
+                None => (trimmed, false),
+            };
+            match &mut block {
+                Some(Block::Paragraph { lines, quote: q }) if *q == quote => {
+                    lines.push(line_with_break(line, content))
+                }
+                _ => {
+                    finish(&mut spans, block.take());
+                    block = Some(Block::Paragraph {
+                        lines: vec![line_with_break(line, content)],
+                        quote,
+                    });
+                }
+            }
+        }
+    }
+    finish(&mut spans, block);
+    merge(spans)
+}
+
+fn plain(text: String, style: Style) -> Span {
+    Span { text, style }
+}
+
+// Two spaces at the end of a line break it.
+fn line_with_break(line: &str, content: &str) -> String {
+    if line.ends_with("  ") {
+        format!("{}\n", content.trim_end())
+    } else {
+        content.trim_end().to_string()
+    }
+}
+
+fn finish(spans: &mut Vec<Span>, block: Option<Block>) {
+    match block {
+        Some(Block::Paragraph { lines, quote }) => {
+            let mut text = String::new();
+            for line in lines {
+                if !text.is_empty() && !text.ends_with('\n') {
+                    text.push(' ');
+                }
+                text.push_str(&line);
+            }
+            let style = Style {
+                quote,
+                ..Style::default()
+            };
+            push_block(spans, inline(text.trim_end(), style));
+        }
+        Some(Block::Code(lines)) => {
+            let style = Style {
+                code_block: true,
+                ..Style::default()
+            };
+            push_block(spans, vec![plain(lines.join("\n"), style)]);
+        }
+        None => {}
+    }
+}
+
+// Blocks are separated by a blank line.
+fn push_block(spans: &mut Vec<Span>, block: Vec<Span>) {
+    if !spans.is_empty() {
+        spans.push(plain("\n".into(), Style::default()));
+    }
+    spans.extend(block);
+    spans.push(plain("\n".into(), Style::default()));
+}
+
+// The level and title of an ATX heading.
+pub fn heading(line: &str) -> Option<(u8, &str)> {
+    let level = line.len() - line.trim_start_matches('#').len();
+    let rest = &line[level..];
+    if !(1..=6).contains(&level) || !(rest.is_empty() || rest.starts_with(' ')) {
+        return None;
+    }
+    let title = rest.trim().trim_end_matches('#').trim_end();
+    Some((level as u8, title))
+}
+
+fn is_rule(line: &str) -> bool {
+    let marks: String = line.chars().filter(|c| !c.is_whitespace()).collect();
+    marks.len() >= 3
+        && ['-', '*', '_']
+            .iter()
+            .any(|&m| marks.chars().all(|c| c == m))
+}
+
+// The bullet or number to show, indented by nesting, and the item text.
+fn list_item(line: &str) -> Option<(String, &str)> {
+    let trimmed = line.trim_start();
+    let depth = (line.len() - trimmed.len()) / 2;
+    let indent = "    ".repeat(depth);
+    for bullet in ["- ", "* ", "+ "].iter() {
+        if let Some(item) = trimmed.strip_prefix(bullet) {
+            return Some((format!("{}\u{2022} ", indent), item));
+        }
+    }
+    let digits = trimmed.len()
+        - trimmed
+            .trim_start_matches(|c: char| c.is_ascii_digit())
+            .len();
+    let rest = &trimmed[digits..];
+    if digits > 0 && digits < 10 && (rest.starts_with(". ") || rest.starts_with(") ")) {
+        return Some((format!("{}{}. ", indent, &trimmed[..digits]), &rest[2..]));
+    }
+    None
+}
+
+// Inline text, such as a heading's title, without its markup.
+pub fn plain_text(text: &str) -> String {
+    inline(text, Style::default())
+        .into_iter()
+        .map(|span| span.text)
+        .collect()
+}
+
+// Code spans, strong and emphasis with `*` or `_`, links and images. An
+// underscore inside a word is not emphasis, so snake_case stays as it is.
+fn inline(text: &str, base: Style) -> Vec<Span> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut spans = Vec::new();
+    let mut style = base;
+    let mut current = String::new();
+    let mut i = 0;
+    let flush = |current: &mut String, spans: &mut Vec<Span>, style: Style| {
+        if !current.is_empty() {
+            spans.push(plain(std::mem::take(current), style));
+        }
+    };
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        let word = |c: Option<char>| matches!(c, Some(c) if c.is_alphanumeric());
+        match c {
+            '\\' if matches!(next, Some(n) if n.is_ascii_punctuation()) => {
+                current.push(chars[i + 1]);
+                i += 2;
+                continue;
+            }
+            '`' => {
+                if let Some(end) = chars[i + 1..].iter().position(|&c| c == '`') {
+                    flush(&mut current, &mut spans, style);
+                    let code: String = chars[i + 1..i + 1 + end].iter().collect();
+                    spans.push(plain(
+                        code,
+                        Style {
+                            code: true,
+                            ..style
+                        },
+                    ));
+                    i += end + 2;
+                    continue;
+                }
+            }
+            '*' | '_' if next == Some(c) => {
+                flush(&mut current, &mut spans, style);
+                style.strong = !style.strong;
+                i += 2;
+                continue;
+            }
+            '*' | '_' => {
+                let before = i.checked_sub(1).map(|j| chars[j]);
+                if c == '*' || !(word(before) && word(next)) {
+                    flush(&mut current, &mut spans, style);
+                    style.emphasis = !style.emphasis;
+                    i += 1;
+                    continue;
+                }
+            }
+            '[' | '!' => {
+                let start = if c == '!' && next == Some('[') {
+                    i + 1
+                } else {
+                    i
+                };
+                if chars[start] == '[' {
+                    if let Some((label, end)) = link(&chars, start) {
+                        flush(&mut current, &mut spans, style);
+                        let link_style = Style {
+                            link: c == '[',
+                            ..style
+                        };
+                        spans.push(plain(label, link_style));
+                        i = end;
+                        continue;
+                    }
+                }
+            }
+            _ => {}
+        }
+        current.push(c);
+        i += 1;
+    }
+    flush(&mut current, &mut spans, style);
+    spans
+}
+
+// `[label](url)` starting at `start`, and the index just past it.
+fn link(chars: &[char], start: usize) -> Option<(String, usize)> {
+    let close = start + chars[start..].iter().position(|&c| c == ']')?;
+    if chars.get(close + 1) != Some(&'(') {
+        return None;
+    }
+    let end = close + 1 + chars[close + 1..].iter().position(|&c| c == ')')?;
+    Some((chars[start + 1..close].iter().collect(), end + 1))
+}
+
+// Neighbouring runs in the same style become one.
+fn merge(spans: Vec<Span>) -> Vec<Span> {
+    let mut merged: Vec<Span> = Vec::new();
+    for span in spans {
+        match merged.last_mut() {
+            Some(last) if last.style == span.style => last.text.push_str(&span.text),
+            _ => merged.push(span),
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(spans: &[Span]) -> String {
+        spans.iter().map(|s| s.text.as_str()).collect()
+    }
+
+    fn styled(spans: &[Span], f: fn(&Style) -> bool) -> Vec<&str> {
+        spans
+            .iter()
+            .filter(|s| f(&s.style))
+            .map(|s| s.text.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_blocks() {
+        let spans =
+            render("# Title #\n\nSome\ntext.  \nBroken\n\n- one\n  - two\n3. three\n\n***\n");
+        let rule = "\u{2015}".repeat(24);
+        assert_eq!(
+            format!(
+                "Title\n\nSome text.\nBroken\n\n\u{2022} one\n    \u{2022} two\n3. three\n\n{}\n",
+                rule
+            ),
+            text(&spans)
+        );
+        assert_eq!(vec!["Title"], styled(&spans, |s| s.heading == 1));
+        assert_eq!(None, heading("#hashtag"), "Not a heading without a space");
+    }
+
+    #[test]
+    fn test_code() {
+        let spans =
+            render("Run `cargo test`:\n\n```sh\n# not a heading\n\n*x*\n```\n\n    indented\n");
+        assert_eq!(vec!["cargo test"], styled(&spans, |s| s.code));
+        assert_eq!(
+            vec!["# not a heading\n\n*x*", "indented"],
+            styled(&spans, |s| s.code_block)
+        );
+    }
+
+    #[test]
+    fn test_inline() {
+        let spans = render("**Bold *both***, _em_, snake_case_name and \\*stars\\*");
+        assert_eq!("Bold both, em, snake_case_name and *stars*\n", text(&spans));
+        assert_eq!(vec!["Bold ", "both"], styled(&spans, |s| s.strong));
+        assert_eq!(vec!["both", "em"], styled(&spans, |s| s.emphasis));
+        let spans = render("> See [the docs](https://example.com) ![logo](logo.png)");
+        assert_eq!("See the docs logo\n", text(&spans));
+        assert_eq!(vec!["the docs"], styled(&spans, |s| s.link));
+        assert!(spans[0].style.quote);
+    }
+
+    #[test]
+    fn test_style_names() {
+        let style = Style {
+            heading: 2,
+            emphasis: true,
+            ..Style::default()
+        };
+        assert_eq!(vec!["h2", "emphasis"], style.names());
+        assert!(Style::default().names().is_empty());
+        assert!(is_markdown(Path::new("README.MD")));
+        assert!(!is_markdown(Path::new("notes.txt")));
+    }
+}
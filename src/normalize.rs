@@ -0,0 +1,198 @@
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Form {
+    Nfc,
+    Nfd,
+    Nfkc,
+    Nfkd,
+}
+
+pub const FORMS: [Form; 4] = [Form::Nfc, Form::Nfd, Form::Nfkc, Form::Nfkd];
+
+impl Form {
+    pub fn name(self) -> &'static str {
+        match self {
+            Form::Nfc => "NFC",
+            Form::Nfd => "NFD",
+            Form::Nfkc => "NFKC",
+            Form::Nfkd => "NFKD",
+        }
+    }
+
+    fn apply(self, text: &str) -> String {
+        match self {
+            Form::Nfc => text.nfc().collect(),
+            Form::Nfd => text.nfd().collect(),
+            Form::Nfkc => text.nfkc().collect(),
+            Form::Nfkd => text.nfkd().collect(),
+        }
+    }
+}
+
+// The normalized text, and how many characters of the original are
+// written differently in it. Characters are counted with the marks that
+// combine with them, as those change together.
+pub fn normalize(text: &str, form: Form) -> (String, usize) {
+    let mut changed = 0;
+    let mut cluster = String::new();
+    let mut count = |cluster: &str| {
+        if !cluster.is_empty() && form.apply(cluster) != cluster {
+            changed += cluster.chars().count();
+        }
+    };
+    for c in text.chars() {
+        if !is_combining_mark(c) {
+            count(&cluster);
+            cluster.clear();
+        }
+        cluster.push(c);
+    }
+    count(&cluster);
+    (form.apply(text), changed)
+}
+
+// Greek and Cyrillic letters that look like Latin ones.
+const CONFUSABLES: [(char, char, &str); 47] = [
+    ('а', 'a', "Cyrillic"),
+    ('в', 'B', "Cyrillic"),
+    ('е', 'e', "Cyrillic"),
+    ('к', 'k', "Cyrillic"),
+    ('м', 'M', "Cyrillic"),
+    ('н', 'H', "Cyrillic"),
+    ('о', 'o', "Cyrillic"),
+    ('р', 'p', "Cyrillic"),
+    ('с', 'c', "Cyrillic"),
+    ('т', 'T', "Cyrillic"),
+    ('у', 'y', "Cyrillic"),
+    ('х', 'x', "Cyrillic"),
+    ('і', 'i', "Cyrillic"),
+    ('ј', 'j', "Cyrillic"),
+    ('ѕ', 's', "Cyrillic"),
+    ('ԁ', 'd', "Cyrillic"),
+    ('А', 'A', "Cyrillic"),
+    ('В', 'B', "Cyrillic"),
+    ('Е', 'E', "Cyrillic"),
+    ('К', 'K', "Cyrillic"),
+    ('М', 'M', "Cyrillic"),
+    ('Н', 'H', "Cyrillic"),
+    ('О', 'O', "Cyrillic"),
+    ('Р', 'P', "Cyrillic"),
+    ('С', 'C', "Cyrillic"),
+    ('Т', 'T', "Cyrillic"),
+    ('Х', 'X', "Cyrillic"),
+    ('І', 'I', "Cyrillic"),
+    ('Ј', 'J', "Cyrillic"),
+    ('Ѕ', 'S', "Cyrillic"),
+    ('α', 'a', "Greek"),
+    ('ο', 'o', "Greek"),
+    ('ν', 'v', "Greek"),
+    ('ρ', 'p', "Greek"),
+    ('Α', 'A', "Greek"),
+    ('Β', 'B', "Greek"),
+    ('Ε', 'E', "Greek"),
+    ('Ζ', 'Z', "Greek"),
+    ('Η', 'H', "Greek"),
+    ('Ι', 'I', "Greek"),
+    ('Κ', 'K', "Greek"),
+    ('Μ', 'M', "Greek"),
+    ('Ν', 'N', "Greek"),
+    ('Ο', 'O', "Greek"),
+    ('Ρ', 'P', "Greek"),
+    ('Τ', 'T', "Greek"),
+    ('Χ', 'X', "Greek"),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Confusable {
+    // In characters, from the start of the text.
+    pub offset: usize,
+    pub line: usize,
+    pub character: char,
+    pub looks_like: char,
+    pub script: &'static str,
+    pub word: String,
+}
+
+// Look-alike letters in words that are otherwise Latin, as in a spoofed
+// address. Words written wholly in another script are left alone.
+pub fn confusables(text: &str) -> Vec<Confusable> {
+    let mut found = Vec::new();
+    let mut offset = 0;
+    for (line, content) in text.split('\n').enumerate() {
+        let mut word: Vec<(usize, char)> = Vec::new();
+        for c in content.chars().chain(std::iter::once('\n')) {
+            if c.is_alphanumeric() {
+                word.push((offset, c));
+            } else {
+                found.extend(mixed_script(&word, line + 1));
+                word.clear();
+            }
+            offset += 1;
+        }
+    }
+    found
+}
+
+fn mixed_script(word: &[(usize, char)], line: usize) -> Vec<Confusable> {
+    let latin = word.iter().any(|(_, c)| is_latin(*c));
+    if !latin {
+        return Vec::new();
+    }
+    let text: String = word.iter().map(|(_, c)| c).collect();
+    word.iter()
+        .filter_map(|(offset, c)| {
+            let (_, looks_like, script) = CONFUSABLES.iter().find(|(x, _, _)| x == c)?;
+            Some(Confusable {
+                offset: *offset,
+                line,
+                character: *c,
+                looks_like: *looks_like,
+                script,
+                word: text.clone(),
+            })
+        })
+        .collect()
+}
+
+fn is_latin(c: char) -> bool {
+    c.is_ascii_alphabetic() || matches!(c, 'À'..='ɏ' if c.is_alphabetic())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        let decomposed = "cafe\u{301} noe\u{308}l";
+        assert_eq!(
+            ("café noël".to_string(), 4),
+            normalize(decomposed, Form::Nfc),
+            "Each letter counts with its mark"
+        );
+        assert_eq!(
+            (decomposed.to_string(), 2),
+            normalize("café noël", Form::Nfd)
+        );
+        assert_eq!(("fi 2".to_string(), 2), normalize("ﬁ ²", Form::Nfkc));
+        assert_eq!(0, normalize("plain text", Form::Nfkd).1);
+    }
+
+    #[test]
+    fn test_confusables() {
+        let found = confusables("Log in at\nwww.pаypal.com now");
+        assert_eq!(1, found.len());
+        let confusable = &found[0];
+        assert_eq!(('а', 'a'), (confusable.character, confusable.looks_like));
+        assert_eq!((2, 15), (confusable.line, confusable.offset));
+        assert_eq!("pаypal", confusable.word);
+        assert_eq!("Cyrillic", confusable.script);
+        assert!(
+            confusables("Привет, κόσμε").is_empty(),
+            "Words in one script"
+        );
+        assert_eq!(2, confusables("ΑΒC").len(), "Greek among Latin capitals");
+    }
+}
@@ -0,0 +1,84 @@
+// Text copied from a terminal carries the shell's prompts, and long
+// commands are broken over lines with a backslash and a "> " prompt.
+
+// The command after a prompt such as "$ " or "user@host:~/src$ ".
+fn command(line: &str) -> Option<&str> {
+    let (prompt, command) = line.split_once("$ ")?;
+    let host = prompt.contains('@') && !prompt.contains(char::is_whitespace);
+    if prompt.is_empty() || host {
+        Some(command)
+    } else {
+        None
+    }
+}
+
+// Commands lose their prompts and continued lines are joined into one,
+// while the output between them is kept. None when no line has a prompt.
+pub fn strip_prompts(text: &str) -> Option<String> {
+    let mut lines = Vec::new();
+    let mut found = false;
+    let mut continued: Option<String> = None;
+    for line in text.lines() {
+        let line = match continued.take() {
+            Some(mut joined) => {
+                joined.push_str(line.strip_prefix("> ").unwrap_or(line).trim_start());
+                joined
+            }
+            None => match command(line) {
+                Some(command) => {
+                    found = true;
+                    command.to_string()
+                }
+                None => {
+                    lines.push(line.to_string());
+                    continue;
+                }
+            },
+        };
+        match line.strip_suffix('\\') {
+            Some(head) => continued = Some(format!("{} ", head.trim_end())),
+            None => lines.push(line),
+        }
+    }
+    if let Some(joined) = continued {
+        lines.push(joined.trim_end().to_string());
+    }
+    if !found {
+        return None;
+    }
+    let mut stripped = lines.join("\n");
+    if text.ends_with('\n') {
+        stripped.push('\n');
+    }
+    Some(stripped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_prompts() {
+        assert_eq!(
+            Some("ls -l\ntotal 0\ncd src\n".into()),
+            strip_prompts("$ ls -l\ntotal 0\n$ cd src\n"),
+            "Output is kept"
+        );
+        assert_eq!(
+            Some("make install".into()),
+            strip_prompts("me@laptop:~/src$ make install")
+        );
+        assert_eq!(
+            Some("cargo build --release --locked\n".into()),
+            strip_prompts("$ cargo build \\\n>   --release \\\n> --locked\n"),
+            "Continued lines"
+        );
+    }
+
+    #[test]
+    fn test_not_terminal() {
+        assert_eq!(None, strip_prompts("It costs 5$ each"));
+        assert_eq!(None, strip_prompts("> quoted reply\n> more"));
+        assert_eq!(None, strip_prompts(""));
+    }
+}
@@ -0,0 +1,242 @@
+// One change of a unified diff: the lines it expects to find and what they
+// become.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunk {
+    // The `@@ -1,3 +1,4 @@` line, to report the hunk by.
+    pub header: String,
+    // Starting from 1, or the line before an insertion.
+    pub old_start: usize,
+    pub old: Vec<String>,
+    pub new: Vec<String>,
+}
+
+impl Hunk {
+    // The line index the hunk's lines start at, in the text it was made from.
+    fn expected(&self) -> usize {
+        if self.old.is_empty() {
+            self.old_start
+        } else {
+            self.old_start.saturating_sub(1)
+        }
+    }
+
+    // How many lines the text grows by when it is applied.
+    pub fn growth(&self) -> isize {
+        self.new.len() as isize - self.old.len() as isize
+    }
+}
+
+// Where a hunk applies to the text, as a replacement of characters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edit {
+    // Starting from 0.
+    pub line: usize,
+    // Character offsets.
+    pub start: usize,
+    pub end: usize,
+    pub replacement: String,
+    // How far from where the patch expected the lines were found.
+    pub offset: isize,
+}
+
+// Patches for a single file are read, with or without the `---` and `+++`
+// header lines.
+pub fn parse(patch: &str) -> Result<Vec<Hunk>, String> {
+    let mut hunks = Vec::new();
+    let mut files = 0;
+    let mut lines = patch.lines().enumerate().peekable();
+    while let Some((number, line)) = lines.next() {
+        if line.starts_with("+++ ") {
+            files += 1;
+            if files > 1 {
+                return Err("The patch changes more than one file".into());
+            }
+            continue;
+        }
+        if !line.starts_with("@@ ") {
+            continue;
+        }
+        let (old_start, mut old_count, mut new_count) = parse_header(line)
+            .ok_or_else(|| format!("Line {}: malformed hunk header", number + 1))?;
+        let mut hunk = Hunk {
+            header: line.to_string(),
+            old_start,
+            old: Vec::new(),
+            new: Vec::new(),
+        };
+        while old_count > 0 || new_count > 0 {
+            let (number, line) = match lines.next() {
+                Some(line) => line,
+                None => return Err(format!("{}: the hunk ends early", hunk.header)),
+            };
+            // Some tools drop the space from blank context lines.
+            let (kind, text) = match line.chars().next() {
+                Some(kind) => (kind, &line[kind.len_utf8()..]),
+                None => (' ', ""),
+            };
+            match kind {
+                ' ' if old_count > 0 && new_count > 0 => {
+                    hunk.old.push(text.to_string());
+                    hunk.new.push(text.to_string());
+                    old_count -= 1;
+                    new_count -= 1;
+                }
+                '-' if old_count > 0 => {
+                    hunk.old.push(text.to_string());
+                    old_count -= 1;
+                }
+                '+' if new_count > 0 => {
+                    hunk.new.push(text.to_string());
+                    new_count -= 1;
+                }
+                '\\' => (),
+                _ => return Err(format!("Line {}: unexpected line in hunk", number + 1)),
+            }
+        }
+        while matches!(lines.peek(), Some((_, l)) if l.starts_with('\\')) {
+            lines.next();
+        }
+        hunks.push(hunk);
+    }
+    if hunks.is_empty() {
+        return Err("No changes were found in the patch".into());
+    }
+    Ok(hunks)
+}
+
+// `@@ -12,3 +12,4 @@ context`, where a missing count is 1.
+fn parse_header(line: &str) -> Option<(usize, usize, usize)> {
+    let mut parts = line.split_whitespace().skip(1);
+    let range = |part: Option<&str>, sign: char| -> Option<(usize, usize)> {
+        let range = part?.strip_prefix(sign)?;
+        let mut numbers = range.splitn(2, ',');
+        let start = numbers.next()?.parse().ok()?;
+        let count = match numbers.next() {
+            Some(count) => count.parse().ok()?,
+            None => 1,
+        };
+        Some((start, count))
+    };
+    let (old_start, old_count) = range(parts.next(), '-')?;
+    let (_, new_count) = range(parts.next(), '+')?;
+    Some((old_start, old_count, new_count))
+}
+
+// Finds the hunk's lines nearest to where the patch expects them, moved by
+// `shift` lines for the hunks before it.
+pub fn locate(text: &str, hunk: &Hunk, shift: isize) -> Option<Edit> {
+    let mut lines: Vec<&str> = text.split('\n').collect();
+    let newline = text.ends_with('\n') || text.is_empty();
+    if newline {
+        lines.pop();
+    }
+    let count = hunk.old.len();
+    if count > lines.len() {
+        return None;
+    }
+    let last = lines.len() - count;
+    let expected = (hunk.expected() as isize + shift).max(0).min(last as isize) as usize;
+    let matches = |i: usize| lines[i..i + count].iter().eq(hunk.old.iter());
+    let line = (0..=last)
+        .flat_map(|d| [expected.checked_add(d), expected.checked_sub(d)])
+        .flatten()
+        .find(|&i| i <= last && matches(i))?;
+
+    let starts: Vec<usize> = lines
+        .iter()
+        .scan(0, |offset, l| {
+            let start = *offset;
+            *offset += l.chars().count() + 1;
+            Some(start)
+        })
+        .collect();
+    let length = text.chars().count();
+    let start_of = |i: usize| starts.get(i).copied().unwrap_or(length);
+    let mut start = start_of(line);
+    let end = start_of(line + count);
+    let mut replacement: String = hunk.new.iter().map(|l| format!("{}\n", l)).collect();
+    // The last line has no newline of its own to replace.
+    if !newline && line + count == lines.len() {
+        if count == 0 {
+            replacement = format!("\n{}", hunk.new.join("\n"));
+        } else if hunk.new.is_empty() {
+            start = start.saturating_sub(1);
+        } else {
+            replacement.pop();
+        }
+    }
+    Some(Edit {
+        line,
+        start,
+        end,
+        replacement,
+        offset: line as isize - hunk.expected() as isize,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(text: &str, edit: &Edit) -> String {
+        let chars: Vec<char> = text.chars().collect();
+        let mut applied: String = chars[..edit.start].iter().collect();
+        applied.push_str(&edit.replacement);
+        applied.extend(&chars[edit.end..]);
+        applied
+    }
+
+    const PATCH: &str = "--- a/notes\n+++ b/notes\n@@ -1,3 +1,3 @@\n one\n-two\n+2\n three\n@@ -5 +5,2 @@\n five\n+six\n";
+
+    #[test]
+    fn test_parse() {
+        let hunks = parse(PATCH).unwrap();
+        assert_eq!(2, hunks.len());
+        assert_eq!(
+            Hunk {
+                header: "@@ -1,3 +1,3 @@".into(),
+                old_start: 1,
+                old: vec!["one".into(), "two".into(), "three".into()],
+                new: vec!["one".into(), "2".into(), "three".into()],
+            },
+            hunks[0]
+        );
+        assert_eq!(vec!["five".to_string(), "six".into()], hunks[1].new);
+        assert!(parse("not a patch").is_err());
+        assert!(parse("@@ -1,2 +1,2 @@\n a\n").is_err(), "Ends early");
+        assert!(parse("+++ b/a\n@@ -1 +1 @@\n-a\n+b\n+++ b/c\n").is_err());
+    }
+
+    #[test]
+    fn test_locate() {
+        let hunks = parse(PATCH).unwrap();
+        let text = "one\ntwo\nthree\nfour\nfive\n";
+        let edit = locate(text, &hunks[0], 0).unwrap();
+        assert_eq!(0, edit.offset);
+        let text = apply(text, &edit);
+        assert_eq!("one\n2\nthree\nfour\nfive\n", text);
+        let text = apply(&text, &locate(&text, &hunks[1], 0).unwrap());
+        assert_eq!("one\n2\nthree\nfour\nfive\nsix\n", text);
+
+        let moved = "zero\none\ntwo\nthree\n";
+        let edit = locate(moved, &hunks[0], 0).unwrap();
+        assert_eq!(1, edit.offset, "Found where the lines moved to");
+        assert_eq!("zero\none\n2\nthree\n", apply(moved, &edit));
+        assert_eq!(None, locate("one\nthree\n", &hunks[0], 0), "Rejected");
+    }
+
+    #[test]
+    fn test_locate_at_end() {
+        let hunk = |old: &[&str], new: &[&str], old_start| Hunk {
+            header: String::new(),
+            old_start,
+            old: old.iter().map(|l| l.to_string()).collect(),
+            new: new.iter().map(|l| l.to_string()).collect(),
+        };
+        let change = |text, hunk| apply(text, &locate(text, &hunk, 0).unwrap());
+        assert_eq!("a\nB", change("a\nb", hunk(&["b"], &["B"], 2)));
+        assert_eq!("a", change("a\nb", hunk(&["b"], &[], 2)));
+        assert_eq!("a\nb\nc", change("a\nb", hunk(&[], &["c"], 2)));
+        assert_eq!("new\n", change("", hunk(&[], &["new"], 0)));
+    }
+}
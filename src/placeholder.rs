@@ -0,0 +1,288 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::timestamp;
+use super::token;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Paragraphs,
+    Names,
+    Emails,
+    Uuids,
+    Timestamps,
+}
+
+impl Kind {
+    pub fn from_id(id: &str) -> Option<Self> {
+        match id {
+            "paragraphs" => Some(Kind::Paragraphs),
+            "names" => Some(Kind::Names),
+            "emails" => Some(Kind::Emails),
+            "uuids" => Some(Kind::Uuids),
+            "timestamps" => Some(Kind::Timestamps),
+            _ => None,
+        }
+    }
+}
+
+// SplitMix64, which is plenty for made up text and reproducible from a
+// seed in tests.
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub fn from_time() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos() as u64);
+        Self::new(nanos)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n.max(1) as u64) as usize
+    }
+
+    fn between(&mut self, low: usize, high: usize) -> usize {
+        low + self.below(high - low + 1)
+    }
+
+    fn pick<'a>(&mut self, items: &[&'a str]) -> &'a str {
+        items[self.below(items.len())]
+    }
+}
+
+const WORDS: [&str; 64] = [
+    "lorem",
+    "ipsum",
+    "dolor",
+    "sit",
+    "amet",
+    "consectetur",
+    "adipiscing",
+    "elit",
+    "sed",
+    "do",
+    "eiusmod",
+    "tempor",
+    "incididunt",
+    "ut",
+    "labore",
+    "et",
+    "dolore",
+    "magna",
+    "aliqua",
+    "enim",
+    "ad",
+    "minim",
+    "veniam",
+    "quis",
+    "nostrud",
+    "exercitation",
+    "ullamco",
+    "laboris",
+    "nisi",
+    "aliquip",
+    "ex",
+    "ea",
+    "commodo",
+    "consequat",
+    "duis",
+    "aute",
+    "irure",
+    "in",
+    "reprehenderit",
+    "voluptate",
+    "velit",
+    "esse",
+    "cillum",
+    "fugiat",
+    "nulla",
+    "pariatur",
+    "excepteur",
+    "sint",
+    "occaecat",
+    "cupidatat",
+    "non",
+    "proident",
+    "sunt",
+    "culpa",
+    "qui",
+    "officia",
+    "deserunt",
+    "mollit",
+    "anim",
+    "id",
+    "est",
+    "laborum",
+    "vitae",
+    "integer",
+];
+
+const FIRST_NAMES: [&str; 20] = [
+    "Ada", "Amara", "Ben", "Chen", "Diego", "Elena", "Farah", "Grace", "Hugo", "Ines", "Jonas",
+    "Kofi", "Lena", "Mateo", "Nadia", "Omar", "Priya", "Ravi", "Sofia", "Tomas",
+];
+
+const LAST_NAMES: [&str; 20] = [
+    "Adeyemi", "Bauer", "Costa", "Dubois", "Eriksen", "Fischer", "Garcia", "Haddad", "Ivanova",
+    "Jensen", "Kim", "Lopez", "Mensah", "Novak", "Okafor", "Patel", "Rossi", "Silva", "Tanaka",
+    "Wong",
+];
+
+// Domains reserved for examples, so the addresses can never reach anyone.
+const DOMAINS: [&str; 3] = ["example.com", "example.org", "example.net"];
+
+const FIRST_SENTENCE: &str = "Lorem ipsum dolor sit amet, consectetur adipiscing elit.";
+
+// Timestamps fall between 2000 and the end of 2029.
+const TIMESTAMP_START: u64 = 946_684_800;
+const TIMESTAMP_RANGE: u64 = 946_771_200;
+
+// Paragraphs are separated by blank lines and everything else is one per
+// line, without a final newline.
+pub fn generate(kind: Kind, count: usize, rng: &mut Rng) -> String {
+    let items: Vec<String> = (0..count)
+        .map(|i| match kind {
+            Kind::Paragraphs => paragraph(rng, i == 0),
+            Kind::Names => format!("{} {}", rng.pick(&FIRST_NAMES), rng.pick(&LAST_NAMES)),
+            Kind::Emails => email(rng),
+            Kind::Uuids => uuid(rng),
+            Kind::Timestamps => timestamp(TIMESTAMP_START + rng.next_u64() % TIMESTAMP_RANGE),
+        })
+        .collect();
+    let separator = if kind == Kind::Paragraphs {
+        "\n\n"
+    } else {
+        "\n"
+    };
+    items.join(separator)
+}
+
+// The first paragraph starts the way lorem ipsum is expected to.
+fn paragraph(rng: &mut Rng, first: bool) -> String {
+    let length = rng.between(4, 7);
+    let mut sentences = Vec::new();
+    if first {
+        sentences.push(FIRST_SENTENCE.to_string());
+    }
+    while sentences.len() < length {
+        let words: Vec<&str> = (0..rng.between(6, 14)).map(|_| rng.pick(&WORDS)).collect();
+        let comma = rng.between(2, words.len() - 2);
+        let mut sentence = String::new();
+        for (i, word) in words.iter().enumerate() {
+            if i > 0 {
+                sentence.push_str(if i == comma { ", " } else { " " });
+            }
+            sentence.push_str(word);
+        }
+        sentence[..1].make_ascii_uppercase();
+        sentence.push('.');
+        sentences.push(sentence);
+    }
+    sentences.join(" ")
+}
+
+fn email(rng: &mut Rng) -> String {
+    let first = rng.pick(&FIRST_NAMES).to_lowercase();
+    let last = rng.pick(&LAST_NAMES).to_lowercase();
+    let domain = rng.pick(&DOMAINS);
+    match rng.below(3) {
+        0 => format!("{}.{}@{}", first, last, domain),
+        1 => format!("{}{}@{}", &first[..1], last, domain),
+        _ => format!("{}{}@{}", first, rng.between(1, 99), domain),
+    }
+}
+
+fn uuid(rng: &mut Rng) -> String {
+    let mut bytes = [0; 16];
+    bytes[..8].copy_from_slice(&rng.next_u64().to_be_bytes());
+    bytes[8..].copy_from_slice(&rng.next_u64().to_be_bytes());
+    token::uuid_v4(bytes)
+}
+
+fn timestamp(seconds: u64) -> String {
+    timestamp::format_utc(seconds as i64 * 1000)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seeded() {
+        for kind in [Kind::Paragraphs, Kind::Names, Kind::Emails, Kind::Uuids] {
+            assert_eq!(
+                generate(kind, 3, &mut Rng::new(7)),
+                generate(kind, 3, &mut Rng::new(7)),
+                "The same seed gives the same text"
+            );
+        }
+        assert_ne!(
+            generate(Kind::Uuids, 1, &mut Rng::new(1)),
+            generate(Kind::Uuids, 1, &mut Rng::new(2))
+        );
+        assert_eq!("", generate(Kind::Names, 0, &mut Rng::new(1)));
+    }
+
+    #[test]
+    fn test_paragraphs() {
+        let text = generate(Kind::Paragraphs, 3, &mut Rng::new(42));
+        let paragraphs: Vec<&str> = text.split("\n\n").collect();
+        assert_eq!(3, paragraphs.len());
+        assert!(paragraphs[0].starts_with(FIRST_SENTENCE));
+        for paragraph in paragraphs {
+            assert!(paragraph.ends_with('.'));
+            assert!(paragraph.starts_with(|c: char| c.is_ascii_uppercase()));
+        }
+    }
+
+    #[test]
+    fn test_lines() {
+        let mut rng = Rng::new(3);
+        let emails = generate(Kind::Emails, 5, &mut rng);
+        assert_eq!(5, emails.lines().count());
+        assert!(emails
+            .lines()
+            .all(|e| DOMAINS.iter().any(|d| e.ends_with(&format!("@{}", d)))));
+        let names = generate(Kind::Names, 2, &mut rng);
+        assert!(names.lines().all(|n| n.split(' ').count() == 2));
+        for stamp in generate(Kind::Timestamps, 20, &mut rng).lines() {
+            assert_eq!(20, stamp.len(), "{}", stamp);
+            assert!(("2000".."2030").contains(&&stamp[..4]), "{}", stamp);
+        }
+    }
+
+    #[test]
+    fn test_uuid() {
+        let uuid = uuid(&mut Rng::new(9));
+        let groups: Vec<usize> = uuid.split('-').map(str::len).collect();
+        assert_eq!(vec![8, 4, 4, 4, 12], groups);
+        assert_eq!(Some('4'), uuid.chars().nth(14), "Version");
+        assert!(
+            matches!(uuid.chars().nth(19), Some('8'..='9') | Some('a'..='b')),
+            "Variant"
+        );
+    }
+
+    #[test]
+    fn test_timestamp() {
+        assert_eq!("1970-01-01T00:00:00Z", timestamp(0));
+        assert_eq!("2000-02-29T12:30:05Z", timestamp(951_827_405));
+        assert_eq!(
+            "2029-12-31T23:59:59Z",
+            timestamp(TIMESTAMP_START + TIMESTAMP_RANGE - 1)
+        );
+    }
+}
@@ -0,0 +1,102 @@
+use ropey::Rope;
+use std::path::Path;
+
+// How many files have their position remembered.
+pub const LIMIT: usize = 200;
+
+// Where a file was left. The top line is kept rather than a scroll offset,
+// which would change with the font and window size.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    // Characters from the start.
+    pub cursor: usize,
+    // Starting from 0.
+    pub top_line: usize,
+}
+
+// Positions are stored as (path, cursor, top line), most recent first.
+pub type Saved = Vec<(String, i32, i32)>;
+
+pub fn find(saved: &[(String, i32, i32)], path: &Path) -> Option<Position> {
+    let path = path.to_string_lossy();
+    saved
+        .iter()
+        .find(|(p, _, _)| *p == path)
+        .map(|&(_, cursor, top_line)| Position {
+            cursor: cursor.max(0) as usize,
+            top_line: top_line.max(0) as usize,
+        })
+}
+
+pub fn remember(
+    saved: &[(String, i32, i32)],
+    path: &Path,
+    position: Position,
+    limit: usize,
+) -> Saved {
+    let path = path.to_string_lossy().to_string();
+    let clamp = |n: usize| n.min(i32::MAX as usize) as i32;
+    let mut remembered = vec![(
+        path.clone(),
+        clamp(position.cursor),
+        clamp(position.top_line),
+    )];
+    remembered.extend(saved.iter().filter(|(p, _, _)| *p != path).cloned());
+    remembered.truncate(limit);
+    remembered
+}
+
+// The character offset of a line and column starting from 1. Both are
+// kept within the text, so a column past the end is the line's end.
+pub fn offset(text: &Rope, line: usize, column: usize) -> usize {
+    let line = line.saturating_sub(1).min(text.len_lines() - 1);
+    let content = text.line(line);
+    let length = content.len_chars();
+    let newline = content
+        .chars_at(length)
+        .reversed()
+        .take_while(|c| *c == '\n' || *c == '\r')
+        .count();
+    text.line_to_char(line) + column.saturating_sub(1).min(length - newline)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_remember() {
+        let a = Position {
+            cursor: 12,
+            top_line: 3,
+        };
+        let saved = remember(&[], Path::new("/a"), a, LIMIT);
+        assert_eq!(Some(a), find(&saved, Path::new("/a")));
+        assert_eq!(None, find(&saved, Path::new("/b")));
+
+        let saved = remember(&saved, Path::new("/b"), Position::default(), LIMIT);
+        let saved = remember(&saved, Path::new("/a"), Position::default(), LIMIT);
+        assert_eq!(
+            vec![("/a".to_string(), 0, 0), ("/b".to_string(), 0, 0)],
+            saved,
+            "Each file is kept once, most recent first"
+        );
+        let saved = remember(&saved, Path::new("/c"), a, 2);
+        assert_eq!(
+            None,
+            find(&saved, Path::new("/b")),
+            "The oldest are forgotten"
+        );
+    }
+
+    #[test]
+    fn test_offset() {
+        let text = Rope::from_str("one\ntwo\r\nthree");
+        assert_eq!(0, offset(&text, 1, 1));
+        assert_eq!(5, offset(&text, 2, 2));
+        assert_eq!(7, offset(&text, 2, 40), "The end of the line");
+        assert_eq!(0, offset(&text, 0, 0), "Counted from 1");
+        assert_eq!(9, offset(&text, 3, 1));
+        assert_eq!(14, offset(&text, 99, 99), "The end of the text");
+    }
+}
@@ -0,0 +1,347 @@
+use gettextrs::*;
+use glib::clone;
+use gtk::prelude::*;
+use gtk::subclass::prelude::*;
+use gtk::{gio, glib};
+use log::warn;
+
+use super::application_model::{set_project_trusted, trusted_projects};
+use super::project::{self, Project};
+use super::sandbox;
+use super::spell;
+use crate::config::APP_ID;
+
+mod imp {
+    use super::*;
+
+    use gtk::CompositeTemplate;
+
+    #[derive(Debug, CompositeTemplate)]
+    #[template(resource = "/com/bernardigiri/TextEdit2/ui/preferences.ui")]
+    pub struct PreferencesWindow {
+        #[template_child]
+        pub color_scheme_combo: TemplateChild<gtk::ComboBoxText>,
+        #[template_child]
+        pub font_button: TemplateChild<gtk::FontButton>,
+        #[template_child]
+        pub wrap_text_switch: TemplateChild<gtk::Switch>,
+        #[template_child]
+        pub label_header_tint_switch: TemplateChild<gtk::Switch>,
+        #[template_child]
+        pub spell_check_switch: TemplateChild<gtk::Switch>,
+        #[template_child]
+        pub spell_language_combo: TemplateChild<gtk::ComboBoxText>,
+        #[template_child]
+        pub create_backup_switch: TemplateChild<gtk::Switch>,
+        #[template_child]
+        pub auto_reload_switch: TemplateChild<gtk::Switch>,
+        #[template_child]
+        pub restore_position_switch: TemplateChild<gtk::Switch>,
+        #[template_child]
+        pub restore_session_switch: TemplateChild<gtk::Switch>,
+        #[template_child]
+        pub large_file_size_spin: TemplateChild<gtk::SpinButton>,
+        #[template_child]
+        pub tab_width_spin: TemplateChild<gtk::SpinButton>,
+        #[template_child]
+        pub excluded_entry: TemplateChild<gtk::Entry>,
+        #[template_child]
+        pub search_max_file_size_spin: TemplateChild<gtk::SpinButton>,
+        #[template_child]
+        pub search_context_spin: TemplateChild<gtk::SpinButton>,
+        #[template_child]
+        pub insert_spaces_switch: TemplateChild<gtk::Switch>,
+        #[template_child]
+        pub auto_indent_switch: TemplateChild<gtk::Switch>,
+        #[template_child]
+        pub trim_on_save_switch: TemplateChild<gtk::Switch>,
+        #[template_child]
+        pub final_newline_switch: TemplateChild<gtk::Switch>,
+        #[template_child]
+        pub new_document_extension_entry: TemplateChild<gtk::Entry>,
+        #[template_child]
+        pub new_document_template_view: TemplateChild<gtk::TextView>,
+        #[template_child]
+        pub autosave_notes_switch: TemplateChild<gtk::Switch>,
+        #[template_child]
+        pub notes_directory_entry: TemplateChild<gtk::Entry>,
+        #[template_child]
+        pub tray_icon_switch: TemplateChild<gtk::Switch>,
+        #[template_child]
+        pub comment_token_entry: TemplateChild<gtk::Entry>,
+        #[template_child]
+        pub toc_depth_spin: TemplateChild<gtk::SpinButton>,
+        #[template_child]
+        pub toc_numbered_switch: TemplateChild<gtk::Switch>,
+        #[template_child]
+        pub format_on_save_entry: TemplateChild<gtk::Entry>,
+        #[template_child]
+        pub trust_project_label: TemplateChild<gtk::Label>,
+        #[template_child]
+        pub trust_project_switch: TemplateChild<gtk::Switch>,
+        #[template_child]
+        pub project_label: TemplateChild<gtk::Label>,
+        pub settings: gio::Settings,
+    }
+
+    impl Default for PreferencesWindow {
+        fn default() -> Self {
+            Self {
+                color_scheme_combo: TemplateChild::default(),
+                font_button: TemplateChild::default(),
+                wrap_text_switch: TemplateChild::default(),
+                label_header_tint_switch: TemplateChild::default(),
+                spell_check_switch: TemplateChild::default(),
+                spell_language_combo: TemplateChild::default(),
+                create_backup_switch: TemplateChild::default(),
+                auto_reload_switch: TemplateChild::default(),
+                restore_position_switch: TemplateChild::default(),
+                restore_session_switch: TemplateChild::default(),
+                large_file_size_spin: TemplateChild::default(),
+                tab_width_spin: TemplateChild::default(),
+                excluded_entry: TemplateChild::default(),
+                search_max_file_size_spin: TemplateChild::default(),
+                search_context_spin: TemplateChild::default(),
+                insert_spaces_switch: TemplateChild::default(),
+                auto_indent_switch: TemplateChild::default(),
+                trim_on_save_switch: TemplateChild::default(),
+                final_newline_switch: TemplateChild::default(),
+                new_document_extension_entry: TemplateChild::default(),
+                new_document_template_view: TemplateChild::default(),
+                autosave_notes_switch: TemplateChild::default(),
+                notes_directory_entry: TemplateChild::default(),
+                tray_icon_switch: TemplateChild::default(),
+                comment_token_entry: TemplateChild::default(),
+                toc_depth_spin: TemplateChild::default(),
+                toc_numbered_switch: TemplateChild::default(),
+                format_on_save_entry: TemplateChild::default(),
+                trust_project_label: TemplateChild::default(),
+                trust_project_switch: TemplateChild::default(),
+                project_label: TemplateChild::default(),
+                settings: gio::Settings::new(APP_ID),
+            }
+        }
+    }
+
+    #[glib::object_subclass]
+    impl ObjectSubclass for PreferencesWindow {
+        const NAME: &'static str = "PreferencesWindow";
+        type Type = super::PreferencesWindow;
+        type ParentType = gtk::Window;
+
+        fn class_init(klass: &mut Self::Class) {
+            Self::bind_template(klass);
+        }
+
+        fn instance_init(obj: &glib::subclass::InitializingObject<Self>) {
+            obj.init_template();
+        }
+    }
+
+    impl ObjectImpl for PreferencesWindow {
+        fn constructed(&self, obj: &Self::Type) {
+            self.parent_constructed(obj);
+            obj.bind_settings();
+        }
+    }
+
+    impl WidgetImpl for PreferencesWindow {}
+    impl WindowImpl for PreferencesWindow {}
+}
+
+glib::wrapper! {
+    pub struct PreferencesWindow(ObjectSubclass<imp::PreferencesWindow>)
+        @extends gtk::Widget, gtk::Window;
+}
+
+impl PreferencesWindow {
+    pub fn new(parent: &gtk::Window) -> Self {
+        glib::Object::new(&[("transient-for", parent)]).expect("Failed to create PreferencesWindow")
+    }
+
+    fn bind_settings(&self) {
+        let window = imp::PreferencesWindow::from_instance(self);
+        let settings = &window.settings;
+
+        // An empty language picks a dictionary from the system locale.
+        let combo = &window.spell_language_combo;
+        combo.append(Some(""), &gettext("Automatic"));
+        for language in spell::available_languages() {
+            combo.append(Some(&language), &language);
+        }
+
+        settings
+            .bind("color-scheme", &*window.color_scheme_combo, "active-id")
+            .build();
+        settings.bind("font", &*window.font_button, "font").build();
+        settings
+            .bind("wrap-text", &*window.wrap_text_switch, "active")
+            .build();
+        settings
+            .bind(
+                "label-header-tint",
+                &*window.label_header_tint_switch,
+                "active",
+            )
+            .build();
+        settings
+            .bind("spell-check", &*window.spell_check_switch, "active")
+            .build();
+        settings
+            .bind("spell-language", &**combo, "active-id")
+            .build();
+        settings
+            .bind("create-backup", &*window.create_backup_switch, "active")
+            .build();
+        settings
+            .bind("auto-reload", &*window.auto_reload_switch, "active")
+            .build();
+        settings
+            .bind(
+                "restore-position",
+                &*window.restore_position_switch,
+                "active",
+            )
+            .build();
+        settings
+            .bind("restore-session", &*window.restore_session_switch, "active")
+            .build();
+        settings
+            .bind("large-file-size", &*window.large_file_size_spin, "value")
+            .build();
+        settings
+            .bind("tab-width", &*window.tab_width_spin, "value")
+            .build();
+        settings
+            .bind("insert-spaces", &*window.insert_spaces_switch, "active")
+            .build();
+        settings
+            .bind("auto-indent", &*window.auto_indent_switch, "active")
+            .build();
+        settings
+            .bind("trim-on-save", &*window.trim_on_save_switch, "active")
+            .build();
+        settings
+            .bind("final-newline", &*window.final_newline_switch, "active")
+            .build();
+        settings
+            .bind(
+                "new-document-extension",
+                &*window.new_document_extension_entry,
+                "text",
+            )
+            .build();
+        settings
+            .bind("autosave-notes", &*window.autosave_notes_switch, "active")
+            .build();
+        settings
+            .bind("notes-directory", &*window.notes_directory_entry, "text")
+            .build();
+        settings
+            .bind("tray-icon", &*window.tray_icon_switch, "active")
+            .build();
+        settings
+            .bind("comment-token", &*window.comment_token_entry, "text")
+            .build();
+        settings
+            .bind("toc-depth", &*window.toc_depth_spin, "value")
+            .build();
+        settings
+            .bind("toc-numbered", &*window.toc_numbered_switch, "active")
+            .build();
+        settings
+            .bind("format-on-save", &*window.format_on_save_entry, "text")
+            .build();
+        settings
+            .bind(
+                "search-max-file-size",
+                &*window.search_max_file_size_spin,
+                "value",
+            )
+            .build();
+        settings
+            .bind(
+                "search-context-lines",
+                &*window.search_context_spin,
+                "value",
+            )
+            .build();
+
+        // The list is edited as comma separated text.
+        let entry = &window.excluded_entry;
+        entry.set_text(&settings.strv("excluded-directories").join(", "));
+        entry.connect_changed(clone!(@weak settings => move |entry| {
+            let text = entry.text();
+            let globs: Vec<&str> = text
+                .split(',')
+                .map(str::trim)
+                .filter(|g| !g.is_empty())
+                .collect();
+            if let Err(e) = settings.set_strv("excluded-directories", &globs) {
+                warn!("Failed to update excluded-directories, {}", e);
+            }
+        }));
+
+        // Text views have no property holding their text to bind.
+        let buffer = window.new_document_template_view.buffer();
+        buffer.set_text(&settings.string("new-document-template"));
+        buffer.connect_changed(clone!(@weak settings => move |buffer| {
+            let (start, end) = buffer.bounds();
+            let text = buffer.text(&start, &end, false);
+            if let Err(e) = settings.set_string("new-document-template", &text) {
+                warn!("Failed to update new-document-template, {}", e);
+            }
+        }));
+
+        if sandbox::is_sandboxed() {
+            let entry = &window.format_on_save_entry;
+            entry.set_sensitive(false);
+            entry.set_tooltip_text(Some(&gettext("Not available in the sandbox")));
+        }
+    }
+
+    // Settings from a project file win over these preferences, so their
+    // widgets are disabled while that project's document is open.
+    pub fn set_project(&self, project: Option<&Project>) {
+        let window = imp::PreferencesWindow::from_instance(self);
+        let project = match project {
+            Some(project) if !project.settings.overrides().is_empty() => project,
+            _ => return,
+        };
+        let tooltip = gettext("Overridden by project");
+        for key in project.settings.overrides() {
+            let widget: Option<&gtk::Widget> = match key {
+                "tab-width" => Some(window.tab_width_spin.upcast_ref()),
+                "insert-spaces" => Some(window.insert_spaces_switch.upcast_ref()),
+                "trim-on-save" => Some(window.trim_on_save_switch.upcast_ref()),
+                "final-newline" => Some(window.final_newline_switch.upcast_ref()),
+                "format-on-save" => Some(window.format_on_save_entry.upcast_ref()),
+                "excluded-directories" => Some(window.excluded_entry.upcast_ref()),
+                "search-max-file-size" => Some(window.search_max_file_size_spin.upcast_ref()),
+                _ => None,
+            };
+            if let Some(widget) = widget {
+                widget.set_sensitive(false);
+                widget.set_tooltip_text(Some(&tooltip));
+            }
+        }
+        window.project_label.set_text(&format!(
+            "{}: {}",
+            gettext("Some settings are overridden by project"),
+            project.file().display()
+        ));
+        window.project_label.set_visible(true);
+
+        if project.settings.format_on_save.is_some() {
+            let trusted = project::is_trusted(&project.root, &trusted_projects(&window.settings));
+            let switch = &window.trust_project_switch;
+            switch.set_active(trusted);
+            let root = project.root.clone();
+            switch.connect_active_notify(move |switch| {
+                set_project_trusted(&root, switch.is_active());
+            });
+            switch.set_visible(true);
+            window.trust_project_label.set_visible(true);
+        }
+    }
+}
@@ -0,0 +1,197 @@
+use log::warn;
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const FILE_NAME: &str = ".textedit2.toml";
+
+// Settings from a project file. Keys that are not set fall back to the
+// user's preferences.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ProjectSettings {
+    pub tab_width: Option<u32>,
+    pub insert_spaces: Option<bool>,
+    pub trim_on_save: Option<bool>,
+    pub final_newline: Option<bool>,
+    pub format_on_save: Option<String>,
+    pub excluded_directories: Option<Vec<String>>,
+    pub search_max_file_size: Option<u32>,
+}
+
+impl ProjectSettings {
+    // Names of the GSettings keys this project overrides.
+    pub fn overrides(&self) -> Vec<&'static str> {
+        let mut keys = Vec::new();
+        if self.tab_width.is_some() {
+            keys.push("tab-width");
+        }
+        if self.insert_spaces.is_some() {
+            keys.push("insert-spaces");
+        }
+        if self.trim_on_save.is_some() {
+            keys.push("trim-on-save");
+        }
+        if self.final_newline.is_some() {
+            keys.push("final-newline");
+        }
+        if self.format_on_save.is_some() {
+            keys.push("format-on-save");
+        }
+        if self.excluded_directories.is_some() {
+            keys.push("excluded-directories");
+        }
+        if self.search_max_file_size.is_some() {
+            keys.push("search-max-file-size");
+        }
+        keys
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Project {
+    pub root: PathBuf,
+    pub settings: ProjectSettings,
+}
+
+impl Project {
+    pub fn file(&self) -> PathBuf {
+        self.root.join(FILE_NAME)
+    }
+}
+
+pub fn is_trusted(root: &Path, trusted: &[String]) -> bool {
+    trusted.iter().any(|t| Path::new(t) == root)
+}
+
+pub fn parse(text: &str) -> Result<ProjectSettings, toml::de::Error> {
+    toml::from_str(text)
+}
+
+// The nearest directory above the file that holds a project file.
+pub fn find_root(path: &Path) -> Option<PathBuf> {
+    path.parent()?
+        .ancestors()
+        .find(|dir| dir.join(FILE_NAME).is_file())
+        .map(Path::to_path_buf)
+}
+
+pub fn load(path: &Path) -> Option<Project> {
+    let root = find_root(path)?;
+    let file = root.join(FILE_NAME);
+    let text = match fs::read_to_string(&file) {
+        Ok(text) => text,
+        Err(e) => {
+            warn!("Failed to read {:?}, {}", file, e);
+            return None;
+        }
+    };
+    match parse(&text) {
+        Ok(settings) => Some(Project { root, settings }),
+        Err(e) => {
+            warn!("Ignoring invalid project file {:?}, {}", file, e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("textedit2-{}-{}", name, std::process::id()));
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_parse() {
+        let settings = parse(
+            "tab-width = 2\n\
+             insert-spaces = true\n\
+             trim-on-save = true\n\
+             final-newline = false\n\
+             format-on-save = \"rustfmt --emit stdout\"\n\
+             excluded-directories = [\"target\", \".git\"]\n\
+             search-max-file-size = 512\n",
+        )
+        .unwrap();
+        assert_eq!(Some(2), settings.tab_width);
+        assert_eq!(Some(true), settings.insert_spaces);
+        assert_eq!(Some(true), settings.trim_on_save);
+        assert_eq!(Some(false), settings.final_newline);
+        assert_eq!(
+            Some("rustfmt --emit stdout".to_string()),
+            settings.format_on_save
+        );
+        assert_eq!(
+            Some(vec!["target".to_string(), ".git".to_string()]),
+            settings.excluded_directories
+        );
+        assert_eq!(Some(512), settings.search_max_file_size);
+        assert_eq!(
+            vec![
+                "tab-width",
+                "insert-spaces",
+                "trim-on-save",
+                "final-newline",
+                "format-on-save",
+                "excluded-directories",
+                "search-max-file-size"
+            ],
+            settings.overrides()
+        );
+    }
+
+    #[test]
+    fn test_parse_partial() {
+        let settings = parse("tab-width = 8").unwrap();
+        assert_eq!(Some(8), settings.tab_width);
+        assert_eq!(None, settings.trim_on_save, "Unset keys are not overridden");
+        assert_eq!(vec!["tab-width"], settings.overrides());
+        assert_eq!(ProjectSettings::default(), parse("").unwrap());
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(parse("tab-width = \"wide\"").is_err(), "Wrong type");
+        assert!(parse("tabwidth = 4").is_err(), "Unknown keys are rejected");
+        assert!(parse("tab-width = ").is_err(), "Malformed TOML");
+    }
+
+    #[test]
+    fn test_is_trusted() {
+        let trusted = vec!["/home/user/code".to_string()];
+        assert!(is_trusted(Path::new("/home/user/code"), &trusted));
+        assert!(
+            is_trusted(Path::new("/home/user/code/"), &trusted),
+            "Trailing separators are ignored"
+        );
+        assert!(!is_trusted(Path::new("/home/user/code/lib"), &trusted));
+        assert!(!is_trusted(Path::new("/home/user/code"), &[]));
+    }
+
+    #[test]
+    fn test_load() {
+        let dir = test_dir("project");
+        let nested = dir.join("src").join("ui");
+        fs::create_dir_all(&nested).unwrap();
+        let file = nested.join("main.rs");
+        assert_eq!(None, load(&file), "No project file");
+
+        fs::write(dir.join(FILE_NAME), "trim-on-save = true").unwrap();
+        let project = load(&file).unwrap();
+        assert_eq!(dir, project.root, "Project root is found above the file");
+        assert_eq!(Some(true), project.settings.trim_on_save);
+
+        fs::write(nested.join(FILE_NAME), "tab-width = 3").unwrap();
+        let project = load(&file).unwrap();
+        assert_eq!(nested, project.root, "The nearest project file wins");
+
+        fs::write(nested.join(FILE_NAME), "tab-width = ").unwrap();
+        assert_eq!(None, load(&file), "Invalid project files are ignored");
+        fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,52 @@
+// Scroll positions are in pixels, as the view's adjustment measures them.
+
+// Where the top of the view goes when turning a page. The last line of
+// the page stays in view so the reader keeps their place.
+pub fn turn_page(value: f64, upper: f64, page_size: f64, line: f64, forward: bool) -> f64 {
+    let step = (page_size - line).max(line);
+    let target = if forward { value + step } else { value - step };
+    target.min(upper - page_size).max(0.0)
+}
+
+// How much of the document has been read, down to the bottom of the view.
+pub fn progress(value: f64, upper: f64, page_size: f64) -> u32 {
+    if upper <= page_size {
+        return 100;
+    }
+    ((value + page_size) / upper * 100.0).round().min(100.0) as u32
+}
+
+// The margin on each side that keeps the text to a comfortable column.
+pub fn column_margin(width: i32, column: i32, min: i32) -> i32 {
+    ((width - column) / 2).max(min)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_turn_page() {
+        assert_eq!(380.0, turn_page(0.0, 2000.0, 400.0, 20.0, true));
+        assert_eq!(
+            1600.0,
+            turn_page(1500.0, 2000.0, 400.0, 20.0, true),
+            "Stops at the last page"
+        );
+        assert_eq!(0.0, turn_page(100.0, 2000.0, 400.0, 20.0, false));
+        assert_eq!(0.0, turn_page(0.0, 300.0, 400.0, 20.0, true), "One page");
+    }
+
+    #[test]
+    fn test_progress() {
+        assert_eq!(20, progress(0.0, 2000.0, 400.0));
+        assert_eq!(100, progress(1600.0, 2000.0, 400.0));
+        assert_eq!(100, progress(0.0, 300.0, 400.0));
+    }
+
+    #[test]
+    fn test_column_margin() {
+        assert_eq!(200, column_margin(1000, 600, 12));
+        assert_eq!(12, column_margin(500, 600, 12), "Narrow windows");
+    }
+}
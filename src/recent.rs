@@ -0,0 +1,53 @@
+use std::path::{Path, PathBuf};
+
+// How many documents the switcher remembers.
+pub const LIMIT: usize = 10;
+
+// Most recently used first. Using a document moves it to the front.
+pub fn touch(recent: &[PathBuf], path: &Path, limit: usize) -> Vec<PathBuf> {
+    let mut touched = vec![path.to_path_buf()];
+    touched.extend(recent.iter().filter(|p| p.as_path() != path).cloned());
+    touched.truncate(limit);
+    touched
+}
+
+// The next entry when cycling, wrapping around at either end.
+pub fn step(index: usize, len: usize, backward: bool) -> usize {
+    match (len, backward) {
+        (0, _) => 0,
+        (_, true) => (index + len - 1) % len,
+        (_, false) => (index + 1) % len,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(names: &[&str]) -> Vec<PathBuf> {
+        names.iter().map(PathBuf::from).collect()
+    }
+
+    #[test]
+    fn test_touch() {
+        let recent = paths(&["/a", "/b", "/c"]);
+        assert_eq!(
+            paths(&["/c", "/a", "/b"]),
+            touch(&recent, Path::new("/c"), LIMIT)
+        );
+        assert_eq!(
+            paths(&["/d", "/a"]),
+            touch(&recent, Path::new("/d"), 2),
+            "The oldest are forgotten"
+        );
+        assert_eq!(paths(&["/a"]), touch(&[], Path::new("/a"), LIMIT));
+    }
+
+    #[test]
+    fn test_step() {
+        assert_eq!(1, step(0, 3, false));
+        assert_eq!(0, step(2, 3, false));
+        assert_eq!(2, step(0, 3, true), "Wraps backward");
+        assert_eq!(0, step(0, 0, false));
+    }
+}
@@ -0,0 +1,79 @@
+use regex::Regex;
+
+// More matches than this are not shown, as patterns like `a*` match at
+// every position.
+pub const MATCH_LIMIT: usize = 500;
+
+// A match or capture group, with character offsets into the tested text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Group {
+    pub name: Option<String>,
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+}
+
+// The whole match comes first, then each capture group, which is None when
+// that group took no part in the match.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestMatch {
+    pub groups: Vec<Option<Group>>,
+}
+
+pub fn test(regex: &Regex, text: &str) -> Vec<TestMatch> {
+    let names: Vec<Option<&str>> = regex.capture_names().collect();
+    let char_offset = |byte: usize| text[..byte].chars().count();
+    regex
+        .captures_iter(text)
+        .take(MATCH_LIMIT)
+        .map(|captures| TestMatch {
+            groups: captures
+                .iter()
+                .enumerate()
+                .map(|(i, group)| {
+                    group.map(|m| Group {
+                        name: names[i].map(str::to_string),
+                        start: char_offset(m.start()),
+                        end: char_offset(m.end()),
+                        text: m.as_str().to_string(),
+                    })
+                })
+                .collect(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_groups() {
+        let regex = Regex::new(r"(?P<name>\w+)@(\w+)?\.org").unwrap();
+        let matches = test(&regex, "é ann@gnome.org, bob@.org");
+        assert_eq!(2, matches.len());
+        assert_eq!(
+            Some(&Group {
+                name: None,
+                start: 2,
+                end: 15,
+                text: "ann@gnome.org".into(),
+            }),
+            matches[0].groups[0].as_ref(),
+            "Offsets count characters"
+        );
+        assert_eq!(
+            Some("name".to_string()),
+            matches[0].groups[1].as_ref().unwrap().name
+        );
+        assert_eq!("gnome", matches[0].groups[2].as_ref().unwrap().text);
+        assert_eq!(None, matches[1].groups[2], "Unmatched optional group");
+    }
+
+    #[test]
+    fn test_limit() {
+        let regex = Regex::new("a*").unwrap();
+        let text = "b".repeat(MATCH_LIMIT * 2);
+        assert_eq!(MATCH_LIMIT, test(&regex, &text).len());
+    }
+}
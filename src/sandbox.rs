@@ -0,0 +1,67 @@
+use crate::glib;
+use once_cell::sync::Lazy;
+use std::path::{Component, Path, PathBuf};
+
+static SANDBOXED: Lazy<bool> = Lazy::new(|| Path::new("/.flatpak-info").is_file());
+
+// Running inside Flatpak, where host files are only reachable through the
+// document portal and host programs cannot be run.
+pub fn is_sandboxed() -> bool {
+    *SANDBOXED
+}
+
+// Files exported by the document portal are mounted here, each under the
+// directory of its document ID.
+fn portal_dir() -> PathBuf {
+    glib::user_runtime_dir().join("doc")
+}
+
+fn document_id_in(portal_dir: &Path, path: &Path) -> Option<String> {
+    let mut components = path.strip_prefix(portal_dir).ok()?.components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(id)), Some(Component::Normal(_))) => {
+            Some(id.to_string_lossy().to_string())
+        }
+        _ => None,
+    }
+}
+
+pub fn document_id(path: &Path) -> Option<String> {
+    document_id_in(&portal_dir(), path)
+}
+
+pub fn is_document(path: &Path) -> bool {
+    document_id(path).is_some()
+}
+
+// Portal paths mean nothing to the user, so only the name is shown.
+pub fn display_path(path: &Path) -> String {
+    match (document_id(path), path.file_name()) {
+        (Some(_), Some(name)) => name.to_string_lossy().to_string(),
+        _ => path.to_string_lossy().to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_document_id() {
+        let portal = Path::new("/run/user/1000/doc");
+        assert_eq!(
+            Some("1a2b3c4d".to_string()),
+            document_id_in(portal, Path::new("/run/user/1000/doc/1a2b3c4d/notes.txt"))
+        );
+        assert_eq!(
+            None,
+            document_id_in(portal, Path::new("/run/user/1000/doc/1a2b3c4d")),
+            "The document directory itself is not a document"
+        );
+        assert_eq!(
+            None,
+            document_id_in(portal, Path::new("/home/user/notes.txt")),
+            "Host paths have no document ID"
+        );
+    }
+}
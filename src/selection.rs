@@ -0,0 +1,175 @@
+use unicode_segmentation::UnicodeSegmentation;
+
+// Selections are (start, end) in characters, as the buffer counts them.
+pub type Range = (usize, usize);
+
+// The word, sentence, paragraph or document around the selection, whichever
+// is the smallest that is larger than it.
+pub fn expand(text: &str, selection: Range) -> Option<Range> {
+    let bytes = to_bytes(text, selection);
+    let levels = [
+        word(text, bytes),
+        sentence(text, bytes),
+        paragraph(text, bytes),
+        Some((0, text.len())),
+    ];
+    levels
+        .iter()
+        .flatten()
+        .find(|&&(start, end)| {
+            start <= bytes.0 && bytes.1 <= end && end - start > bytes.1 - bytes.0
+        })
+        .map(|&range| to_chars(text, range))
+}
+
+// Remembers the selections an expansion grew from, so shrinking retraces
+// them. Selecting anything else starts over.
+#[derive(Debug, Default)]
+pub struct Expansions {
+    previous: Vec<Range>,
+    current: Option<Range>,
+}
+
+impl Expansions {
+    pub fn expand(&mut self, text: &str, selection: Range) -> Option<Range> {
+        if self.current != Some(selection) {
+            self.previous.clear();
+        }
+        let grown = expand(text, selection)?;
+        self.previous.push(selection);
+        self.current = Some(grown);
+        Some(grown)
+    }
+
+    pub fn shrink(&mut self, selection: Range) -> Option<Range> {
+        if self.current != Some(selection) {
+            self.previous.clear();
+            self.current = None;
+            return None;
+        }
+        let previous = self.previous.pop()?;
+        self.current = Some(previous);
+        Some(previous)
+    }
+}
+
+// The words the selection touches. Spaces and punctuation are not words,
+// so a cursor between two words takes the one it is next to.
+fn word(text: &str, (start, end): Range) -> Option<Range> {
+    let words: Vec<Range> = text
+        .split_word_bound_indices()
+        .map(|(i, word)| (i, i + word.len()))
+        .collect();
+    let is_word = |&(s, e): &Range| text[s..e].chars().any(char::is_alphanumeric);
+    if start == end {
+        return words
+            .into_iter()
+            .filter(|&(s, e)| s <= start && start <= e)
+            .find(is_word);
+    }
+    covering(&words, start, end).filter(is_word)
+}
+
+// Sentences come with the spaces after them, which are left out.
+fn sentence(text: &str, (start, end): Range) -> Option<Range> {
+    let sentences: Vec<Range> = text
+        .split_sentence_bound_indices()
+        .map(|(i, sentence)| (i, i + sentence.trim_end().len()))
+        .filter(|(start, end)| start < end)
+        .collect();
+    covering(&sentences, start, end)
+}
+
+// Paragraphs are separated by blank lines, and end before their newline.
+fn paragraph(text: &str, (start, end): Range) -> Option<Range> {
+    let mut paragraphs: Vec<Range> = Vec::new();
+    let mut offset = 0;
+    let mut continues = false;
+    for line in text.split_inclusive('\n') {
+        let content = line.trim_end_matches(['\n', '\r']);
+        if content.trim().is_empty() {
+            continues = false;
+        } else {
+            let line_end = offset + content.len();
+            match paragraphs.last_mut() {
+                Some(paragraph) if continues => paragraph.1 = line_end,
+                _ => paragraphs.push((offset, line_end)),
+            }
+            continues = true;
+        }
+        offset += line.len();
+    }
+    covering(&paragraphs, start, end)
+}
+
+// From the start of the first segment the selection touches to the end of
+// the last. An empty selection is in a segment, or at the end of one.
+fn covering(segments: &[Range], start: usize, end: usize) -> Option<Range> {
+    if start == end {
+        return segments
+            .iter()
+            .find(|&&(s, e)| s <= start && start < e)
+            .or_else(|| segments.iter().rev().find(|&&(_, e)| e == start))
+            .copied();
+    }
+    let touches = |&&(s, e): &&Range| s < end && start < e;
+    let first = segments.iter().find(touches)?;
+    let last = segments.iter().rev().find(touches)?;
+    Some((first.0.min(start), last.1.max(end)))
+}
+
+fn to_bytes(text: &str, (start, end): Range) -> Range {
+    let byte = |chars: usize| {
+        text.char_indices()
+            .nth(chars)
+            .map(|(i, _)| i)
+            .unwrap_or(text.len())
+    };
+    (byte(start), byte(end))
+}
+
+fn to_chars(text: &str, (start, end): Range) -> Range {
+    (text[..start].chars().count(), text[..end].chars().count())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "Café au lait. Très bon!\nStill here.\n\nNext part.";
+
+    #[test]
+    fn test_expand() {
+        let word = expand(TEXT, (1, 1)).unwrap();
+        assert_eq!((0, 4), word, "Café");
+        let sentence = expand(TEXT, word).unwrap();
+        assert_eq!((0, 13), sentence);
+        let paragraph = expand(TEXT, sentence).unwrap();
+        assert_eq!((0, 35), paragraph, "Up to the blank line");
+        assert_eq!(Some((0, 47)), expand(TEXT, paragraph));
+        assert_eq!(None, expand(TEXT, (0, 47)));
+        assert_eq!(
+            Some((14, 18)),
+            expand(TEXT, (18, 18)),
+            "At the end of a word"
+        );
+        assert_eq!(
+            Some((0, 13)),
+            expand(TEXT, (13, 13)),
+            "After a sentence there is no word"
+        );
+    }
+
+    #[test]
+    fn test_expansions() {
+        let mut expansions = Expansions::default();
+        let word = expansions.expand(TEXT, (38, 38)).unwrap();
+        assert_eq!((37, 41), word, "Next");
+        let sentence = expansions.expand(TEXT, word).unwrap();
+        assert_eq!((37, 47), sentence);
+        assert_eq!(Some(word), expansions.shrink(sentence));
+        assert_eq!(Some((38, 38)), expansions.shrink(word));
+        assert_eq!(None, expansions.shrink((38, 38)));
+        assert_eq!(None, expansions.shrink((0, 1)), "Another selection");
+    }
+}
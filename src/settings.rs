@@ -0,0 +1,185 @@
+use super::config::APP_ID;
+use super::upgrade;
+use crate::gio;
+use crate::glib;
+use gio::prelude::*;
+use std::path::Path;
+
+// Keys are named here once, so a rename only needs a matching upgrade.
+pub const VERSION: &str = "settings-version";
+const TAB_WIDTH: &str = "tab-width";
+const INSERT_SPACES: &str = "insert-spaces";
+const UNDO_LIMIT: &str = "undo-limit";
+const TRIM_ON_SAVE: &str = "trim-on-save";
+const FINAL_NEWLINE: &str = "final-newline";
+const CREATE_BACKUP: &str = "create-backup";
+const AUTO_RELOAD: &str = "auto-reload";
+const RESTORE_POSITION: &str = "restore-position";
+const RESTORE_SESSION: &str = "restore-session";
+const AUTOSAVE_NOTES: &str = "autosave-notes";
+const NOTES_DIRECTORY: &str = "notes-directory";
+const FORMAT_ON_SAVE: &str = "format-on-save";
+const NEW_DOCUMENT_EXTENSION: &str = "new-document-extension";
+const NEW_DOCUMENT_TEMPLATE: &str = "new-document-template";
+const DATETIME_FORMAT: &str = "datetime-format";
+const EXCLUDED_DIRECTORIES: &str = "excluded-directories";
+const SEARCH_MAX_FILE_SIZE: &str = "search-max-file-size";
+const SEARCH_CONTEXT_LINES: &str = "search-context-lines";
+const LARGE_FILE_SIZE: &str = "large-file-size";
+
+// The preferences, as the types the editor uses them as. A value it cannot
+// use, such as one set by hand with dconf, reads as the key's default.
+#[derive(Debug, Clone)]
+pub struct Settings(gio::Settings);
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Settings {
+    pub fn new() -> Self {
+        Self(gio::Settings::new(APP_ID))
+    }
+
+    // Brings settings kept by an older version up to date, before anything
+    // reads them.
+    pub fn upgrade(config_dir: &Path) -> Result<(), String> {
+        upgrade::run(APP_ID, config_dir)
+    }
+
+    pub fn tab_width(&self) -> u32 {
+        self.count(TAB_WIDTH, 1) as u32
+    }
+
+    pub fn insert_spaces(&self) -> bool {
+        self.0.boolean(INSERT_SPACES)
+    }
+
+    pub fn undo_limit(&self) -> usize {
+        self.count(UNDO_LIMIT, 0)
+    }
+
+    pub fn trim_on_save(&self) -> bool {
+        self.0.boolean(TRIM_ON_SAVE)
+    }
+
+    pub fn final_newline(&self) -> bool {
+        self.0.boolean(FINAL_NEWLINE)
+    }
+
+    pub fn create_backup(&self) -> bool {
+        self.0.boolean(CREATE_BACKUP)
+    }
+
+    pub fn auto_reload(&self) -> bool {
+        self.0.boolean(AUTO_RELOAD)
+    }
+
+    pub fn restore_position(&self) -> bool {
+        self.0.boolean(RESTORE_POSITION)
+    }
+
+    pub fn restore_session(&self) -> bool {
+        self.0.boolean(RESTORE_SESSION)
+    }
+
+    pub fn autosave_notes(&self) -> bool {
+        self.0.boolean(AUTOSAVE_NOTES)
+    }
+
+    // Blank for the default folder.
+    pub fn notes_directory(&self) -> Option<String> {
+        non_blank(&self.0.string(NOTES_DIRECTORY))
+    }
+
+    pub fn format_on_save(&self) -> Option<String> {
+        non_blank(&self.0.string(FORMAT_ON_SAVE))
+    }
+
+    // Without its dot, as in `md`.
+    pub fn new_document_extension(&self) -> Option<String> {
+        bare_extension(&self.0.string(NEW_DOCUMENT_EXTENSION))
+    }
+
+    pub fn new_document_template(&self) -> String {
+        self.0.string(NEW_DOCUMENT_TEMPLATE).to_string()
+    }
+
+    // A blank format would leave `{datetime}` empty.
+    pub fn datetime_format(&self) -> String {
+        non_blank(&self.0.string(DATETIME_FORMAT))
+            .or_else(|| self.default(DATETIME_FORMAT)?.get::<String>())
+            .unwrap_or_default()
+    }
+
+    pub fn excluded_directories(&self) -> Vec<String> {
+        self.0
+            .strv(EXCLUDED_DIRECTORIES)
+            .iter()
+            .map(|g| g.to_string())
+            .collect()
+    }
+
+    pub fn search_max_kilobytes(&self) -> u64 {
+        self.count(SEARCH_MAX_FILE_SIZE, 0) as u64
+    }
+
+    pub fn search_context_lines(&self) -> usize {
+        self.count(SEARCH_CONTEXT_LINES, 0)
+    }
+
+    // Files above this many bytes ask before being opened. 0 never asks.
+    pub fn large_file_bytes(&self) -> u64 {
+        self.count(LARGE_FILE_SIZE, 0) as u64 * 1024 * 1024
+    }
+
+    // A number of something, falling back to the default when it is less
+    // than `min`.
+    fn count(&self, key: &str, min: i32) -> usize {
+        let default = self.default(key).and_then(|v| v.get::<i32>());
+        at_least(self.0.int(key), default, min)
+    }
+
+    fn default(&self, key: &str) -> Option<glib::Variant> {
+        Some(self.0.settings_schema()?.key(key).default_value())
+    }
+}
+
+fn at_least(value: i32, default: Option<i32>, min: i32) -> usize {
+    Some(value)
+        .filter(|v| *v >= min)
+        .or_else(|| default.filter(|v| *v >= min))
+        .unwrap_or(min) as usize
+}
+
+fn non_blank(value: &str) -> Option<String> {
+    Some(value.trim().to_string()).filter(|v| !v.is_empty())
+}
+
+// Extensions were once entered with their dot.
+fn bare_extension(value: &str) -> Option<String> {
+    non_blank(value.trim().trim_start_matches('.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_at_least() {
+        assert_eq!(4, at_least(4, Some(8), 1));
+        assert_eq!(8, at_least(0, Some(8), 1), "The default");
+        assert_eq!(1, at_least(-3, None, 1));
+        assert_eq!(0, at_least(0, Some(2), 0));
+    }
+
+    #[test]
+    fn test_bare_extension() {
+        assert_eq!(Some("md".to_string()), bare_extension(" .md "));
+        assert_eq!(Some("txt".to_string()), bare_extension("txt"));
+        assert_eq!(None, bare_extension("."));
+        assert_eq!(None, non_blank(" \t"));
+    }
+}
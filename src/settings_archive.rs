@@ -0,0 +1,341 @@
+use crate::gio;
+use crate::glib;
+use gio::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryFrom;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+pub const VERSION: u32 = 1;
+
+// Keys that only make sense on this machine, or that must never be taken
+// from a file someone else may have written.
+const LOCAL_KEYS: [&str; 17] = [
+    "window-width",
+    "window-height",
+    "is-maximized",
+    "split-view",
+    "split-position",
+    "character-map",
+    "auto-reload-always",
+    "auto-reload-never",
+    "trusted-projects",
+    "settings-imported",
+    "settings-version",
+    "last-document",
+    "last-directory",
+    "last-project-directory",
+    "recent-documents",
+    "document-labels",
+    "document-positions",
+];
+
+// Settings as TOML values of the same type, plus the files in the user's
+// configuration directory such as the personal dictionary.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Archive {
+    pub version: u32,
+    #[serde(default)]
+    pub settings: BTreeMap<String, toml::Value>,
+    #[serde(default)]
+    pub files: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Default)]
+pub struct ImportPlan {
+    settings: Vec<(String, glib::Variant)>,
+    files: Vec<(PathBuf, String)>,
+    // Local customizations the import would replace.
+    pub conflicts: Vec<String>,
+    // Entries that were skipped, with the reason.
+    pub invalid: Vec<(String, String)>,
+}
+
+impl ImportPlan {
+    pub fn count(&self) -> usize {
+        self.settings.len() + self.files.len()
+    }
+}
+
+// Written through a TOML value, which puts tables such as string maps after
+// the plain values as TOML requires.
+pub fn to_string(archive: &Archive) -> Result<String, toml::ser::Error> {
+    toml::Value::try_from(archive).map(|value| value.to_string())
+}
+
+// The setting types the schema uses. None for any other type.
+pub fn to_toml(value: &glib::Variant) -> Option<toml::Value> {
+    use toml::Value;
+    match value.type_().to_str() {
+        "b" => value.get::<bool>().map(Value::Boolean),
+        "i" => value.get::<i32>().map(|v| Value::Integer(v.into())),
+        "u" => value.get::<u32>().map(|v| Value::Integer(v.into())),
+        "s" => value.get::<String>().map(Value::String),
+        "as" => value
+            .get::<Vec<String>>()
+            .map(|v| Value::Array(v.into_iter().map(Value::String).collect())),
+        "a{ss}" => value
+            .get::<HashMap<String, String>>()
+            .map(|v| Value::Table(v.into_iter().map(|(k, v)| (k, Value::String(v))).collect())),
+        "a(sii)" => value.get::<Vec<(String, i32, i32)>>().map(|v| {
+            Value::Array(
+                v.into_iter()
+                    .map(|(s, a, b)| {
+                        Value::Array(vec![
+                            Value::String(s),
+                            Value::Integer(a.into()),
+                            Value::Integer(b.into()),
+                        ])
+                    })
+                    .collect(),
+            )
+        }),
+        _ => None,
+    }
+}
+
+fn string(value: &toml::Value) -> Option<String> {
+    value.as_str().map(String::from)
+}
+
+fn int32(value: &toml::Value) -> Option<i32> {
+    value.as_integer().and_then(|v| i32::try_from(v).ok())
+}
+
+fn strings(value: &toml::Value) -> Option<Vec<String>> {
+    value.as_array()?.iter().map(string).collect()
+}
+
+fn string_map(value: &toml::Value) -> Option<HashMap<String, String>> {
+    value
+        .as_table()?
+        .iter()
+        .map(|(k, v)| string(v).map(|v| (k.clone(), v)))
+        .collect()
+}
+
+fn positions(value: &toml::Value) -> Option<Vec<(String, i32, i32)>> {
+    value
+        .as_array()?
+        .iter()
+        .map(|entry| match entry.as_array()?.as_slice() {
+            [s, a, b] => Some((string(s)?, int32(a)?, int32(b)?)),
+            _ => None,
+        })
+        .collect()
+}
+
+// None when the value is not of the setting's type.
+pub fn from_toml(value_type: &str, value: &toml::Value) -> Option<glib::Variant> {
+    match value_type {
+        "b" => value.as_bool().map(|v| v.to_variant()),
+        "i" => int32(value).map(|v| v.to_variant()),
+        "u" => value
+            .as_integer()
+            .and_then(|v| u32::try_from(v).ok())
+            .map(|v| v.to_variant()),
+        "s" => string(value).map(|v| v.to_variant()),
+        "as" => strings(value).map(|v| v.to_variant()),
+        "a{ss}" => string_map(value).map(|v| v.to_variant()),
+        "a(sii)" => positions(value).map(|v| v.to_variant()),
+        _ => None,
+    }
+}
+
+pub fn parse(text: &str) -> Result<Archive, String> {
+    let archive: Archive = toml::from_str(text).map_err(|e| e.to_string())?;
+    if archive.version > VERSION {
+        return Err(format!("Unsupported version {}", archive.version));
+    }
+    Ok(archive)
+}
+
+// Archive file names must stay inside the configuration directory.
+fn is_safe_path(name: &str) -> bool {
+    let path = Path::new(name);
+    !name.is_empty() && path.components().all(|c| matches!(c, Component::Normal(_)))
+}
+
+fn exported_keys(settings: &gio::Settings) -> Vec<String> {
+    settings
+        .settings_schema()
+        .map(|schema| schema.list_keys())
+        .unwrap_or_default()
+        .iter()
+        .map(|k| k.to_string())
+        .filter(|k| !LOCAL_KEYS.contains(&k.as_str()))
+        .collect()
+}
+
+fn collect_files(dir: &Path, root: &Path, files: &mut BTreeMap<String, String>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(&path, root, files)?;
+        } else if let Ok(contents) = fs::read_to_string(&path) {
+            if let Ok(name) = path.strip_prefix(root) {
+                files.insert(name.to_string_lossy().to_string(), contents);
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn export(settings: &gio::Settings, config_dir: &Path) -> Archive {
+    let mut archive = Archive {
+        version: VERSION,
+        ..Archive::default()
+    };
+    for key in exported_keys(settings) {
+        match to_toml(&settings.value(&key)) {
+            Some(value) => {
+                archive.settings.insert(key, value);
+            }
+            None => log::warn!("Failed to export {}, unsupported type", key),
+        }
+    }
+    if config_dir.is_dir() {
+        if let Err(e) = collect_files(config_dir, config_dir, &mut archive.files) {
+            log::warn!("Failed to export {:?}, {}", config_dir, e);
+        }
+    }
+    archive
+}
+
+// Checks every entry against the schema before anything is changed.
+pub fn plan(settings: &gio::Settings, config_dir: &Path, archive: &Archive) -> ImportPlan {
+    let mut plan = ImportPlan::default();
+    let schema = match settings.settings_schema() {
+        Some(schema) => schema,
+        None => return plan,
+    };
+    let keys = exported_keys(settings);
+    for (key, value) in &archive.settings {
+        if !keys.contains(key) {
+            plan.invalid.push((key.clone(), "Unknown setting".into()));
+            continue;
+        }
+        let value_type = schema.key(key).value_type();
+        let value = match from_toml(value_type.to_str(), value) {
+            Some(value) if schema.key(key).range_check(&value) => value,
+            Some(_) => {
+                plan.invalid
+                    .push((key.clone(), "Value out of range".into()));
+                continue;
+            }
+            None => {
+                plan.invalid
+                    .push((key.clone(), format!("Expected {}", value_type.to_str())));
+                continue;
+            }
+        };
+        if matches!(settings.user_value(key), Some(current) if current != value) {
+            plan.conflicts.push(key.clone());
+        }
+        plan.settings.push((key.clone(), value));
+    }
+    for (name, contents) in &archive.files {
+        if !is_safe_path(name) {
+            plan.invalid
+                .push((name.clone(), "Invalid file name".into()));
+            continue;
+        }
+        let path = config_dir.join(name);
+        if matches!(fs::read_to_string(&path), Ok(current) if &current != contents) {
+            plan.conflicts.push(name.clone());
+        }
+        plan.files.push((path, contents.clone()));
+    }
+    plan
+}
+
+pub fn apply(settings: &gio::Settings, plan: &ImportPlan) -> Result<(), String> {
+    for (key, value) in &plan.settings {
+        settings
+            .set_value(key, value)
+            .map_err(|e| format!("{}: {}", key, e))?;
+    }
+    for (path, contents) in &plan.files {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        fs::write(path, contents).map_err(|e| format!("{:?}: {}", path, e))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let mut archive = Archive {
+            version: VERSION,
+            ..Archive::default()
+        };
+        archive
+            .settings
+            .insert("tab-width".into(), toml::Value::Integer(8));
+        archive
+            .settings
+            .insert("font".into(), toml::Value::String("Monospace 11".into()));
+        let mut labels = toml::map::Map::new();
+        labels.insert("/notes.txt".into(), toml::Value::String("Work".into()));
+        archive
+            .settings
+            .insert("document-labels".into(), toml::Value::Table(labels));
+        archive.files.insert("words".into(), "Igiri\n".into());
+        let text = to_string(&archive).unwrap();
+        assert_eq!(archive, parse(&text).unwrap());
+    }
+
+    #[test]
+    fn test_from_toml() {
+        use toml::Value;
+        assert_eq!(Some(8i32.to_variant()), from_toml("i", &Value::Integer(8)));
+        assert_eq!(None, from_toml("i", &Value::Integer(1 << 40)), "Overflow");
+        assert_eq!(None, from_toml("u", &Value::Integer(-1)));
+        assert_eq!(None, from_toml("b", &Value::String("true".into())));
+        let positions = vec![("/a.txt".to_string(), 3, 0)];
+        let value = to_toml(&positions.to_variant()).unwrap();
+        assert_eq!(Some(positions.to_variant()), from_toml("a(sii)", &value));
+        let words = vec!["a".to_string(), "b".to_string()];
+        let value = to_toml(&words.to_variant()).unwrap();
+        assert_eq!(Some(words.to_variant()), from_toml("as", &value));
+        assert_eq!(None, from_toml("x", &Value::Integer(1)));
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(
+            parse("version = 99").is_err(),
+            "Newer versions are rejected"
+        );
+        assert!(parse("settings = 1").is_err(), "Malformed archive");
+        assert!(
+            parse("version = 1\nextra = true").is_err(),
+            "Unknown fields"
+        );
+        assert_eq!(
+            Archive {
+                version: 1,
+                ..Archive::default()
+            },
+            parse("version = 1").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_is_safe_path() {
+        assert!(is_safe_path("words"));
+        assert!(is_safe_path("snippets/rust.toml"));
+        assert!(!is_safe_path("../words"));
+        assert!(!is_safe_path("/etc/passwd"));
+        assert!(!is_safe_path("snippets/../../words"));
+        assert!(!is_safe_path(""));
+    }
+}
@@ -0,0 +1,489 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
+
+const SUGGESTION_ALPHABET: &str = "esianrtolcdugmphbyfvkwzxjq";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FlagMode {
+    Char,
+    Long,
+    Num,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Condition {
+    Any,
+    Chars(Vec<char>, bool),
+}
+
+#[derive(Debug, Clone)]
+struct Affix {
+    flag: String,
+    strip: String,
+    add: String,
+    condition: Vec<Condition>,
+}
+
+// A Hunspell-compatible dictionary reader supporting single prefix and
+// suffix rules, which covers the plain word forms of most languages.
+#[derive(Debug, Clone, Default)]
+pub struct Dictionary {
+    words: HashMap<String, Vec<String>>,
+    prefixes: Vec<Affix>,
+    suffixes: Vec<Affix>,
+    personal: HashSet<String>,
+    alphabet: Vec<char>,
+}
+
+impl Condition {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            Condition::Any => true,
+            Condition::Chars(chars, negate) => chars.contains(&c) != *negate,
+        }
+    }
+}
+
+fn parse_condition(pattern: &str) -> Vec<Condition> {
+    let mut result = Vec::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '.' => result.push(Condition::Any),
+            '[' => {
+                let mut set = Vec::new();
+                let mut negate = false;
+                for c in chars.by_ref() {
+                    match c {
+                        ']' => break,
+                        '^' if set.is_empty() && !negate => negate = true,
+                        c => set.push(c),
+                    }
+                }
+                result.push(Condition::Chars(set, negate));
+            }
+            c => result.push(Condition::Chars(vec![c], false)),
+        }
+    }
+    result
+}
+
+fn conditions_match(conditions: &[Condition], chars: &[char]) -> bool {
+    conditions.len() <= chars.len() && conditions.iter().zip(chars).all(|(c, ch)| c.matches(*ch))
+}
+
+fn parse_flags(flags: &str, mode: FlagMode) -> Vec<String> {
+    match mode {
+        FlagMode::Char => flags.chars().map(String::from).collect(),
+        FlagMode::Long => {
+            let chars: Vec<char> = flags.chars().collect();
+            chars.chunks(2).map(|c| c.iter().collect()).collect()
+        }
+        FlagMode::Num => flags
+            .split(',')
+            .map(|f| f.trim().to_string())
+            .filter(|f| !f.is_empty())
+            .collect(),
+    }
+}
+
+fn strip_flags(value: &str) -> &str {
+    let value = value.split('/').next().unwrap_or("");
+    if value == "0" {
+        ""
+    } else {
+        value
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphabetic() || c == '\'' || c == '’'
+}
+
+impl Dictionary {
+    pub fn parse(aff: &str, dic: &str) -> Self {
+        let mut mode = FlagMode::Char;
+        let mut prefixes = Vec::new();
+        let mut suffixes = Vec::new();
+        let mut alphabet: Vec<char> = SUGGESTION_ALPHABET.chars().collect();
+        for line in aff.lines() {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields.as_slice() {
+                ["FLAG", "long", ..] => mode = FlagMode::Long,
+                ["FLAG", "num", ..] => mode = FlagMode::Num,
+                ["TRY", chars, ..] => alphabet = chars.chars().collect(),
+                [kind @ "PFX", flag, strip, add, condition, ..]
+                | [kind @ "SFX", flag, strip, add, condition, ..] => {
+                    let affix = Affix {
+                        flag: flag.to_string(),
+                        strip: strip_flags(strip).to_string(),
+                        add: strip_flags(add).to_string(),
+                        condition: parse_condition(condition),
+                    };
+                    if *kind == "PFX" {
+                        prefixes.push(affix);
+                    } else {
+                        suffixes.push(affix);
+                    }
+                }
+                _ => {}
+            }
+        }
+        let mut words = HashMap::new();
+        // The first line of a .dic file is the approximate word count.
+        for line in dic.lines().skip(1) {
+            let entry = line.split_whitespace().next().unwrap_or("");
+            let mut parts = entry.splitn(2, '/');
+            let word = parts.next().unwrap_or("");
+            if word.is_empty() {
+                continue;
+            }
+            let flags = parts
+                .next()
+                .map(|f| parse_flags(f, mode))
+                .unwrap_or_default();
+            words
+                .entry(word.to_string())
+                .or_insert_with(Vec::new)
+                .extend(flags);
+        }
+        Self {
+            words,
+            prefixes,
+            suffixes,
+            personal: HashSet::new(),
+            alphabet,
+        }
+    }
+
+    pub fn load(language: &str) -> Option<Self> {
+        dictionary_dirs().into_iter().find_map(|dir| {
+            let aff = fs::read(dir.join(format!("{}.aff", language))).ok()?;
+            let dic = fs::read(dir.join(format!("{}.dic", language))).ok()?;
+            Some(Self::parse(
+                &String::from_utf8_lossy(&aff),
+                &String::from_utf8_lossy(&dic),
+            ))
+        })
+    }
+
+    pub fn add_word(&mut self, word: &str) {
+        self.personal.insert(word.to_string());
+    }
+
+    fn has_flag(&self, stem: &str, flag: &str) -> bool {
+        matches!(self.words.get(stem), Some(flags) if flags.iter().any(|f| f == flag))
+    }
+
+    fn check_exact(&self, word: &str) -> bool {
+        if self.words.contains_key(word) || self.personal.contains(word) {
+            return true;
+        }
+        let chars: Vec<char> = word.chars().collect();
+        let suffixed = self.suffixes.iter().any(|affix| {
+            let add: Vec<char> = affix.add.chars().collect();
+            if !word.ends_with(affix.add.as_str()) || add.len() >= chars.len() {
+                return false;
+            }
+            let mut stem: Vec<char> = chars[..chars.len() - add.len()].to_vec();
+            stem.extend(affix.strip.chars());
+            let tail = stem.len().saturating_sub(affix.condition.len());
+            conditions_match(&affix.condition, &stem[tail..])
+                && self.has_flag(&stem.iter().collect::<String>(), &affix.flag)
+        });
+        suffixed
+            || self.prefixes.iter().any(|affix| {
+                let add: Vec<char> = affix.add.chars().collect();
+                if !word.starts_with(affix.add.as_str()) || add.len() >= chars.len() {
+                    return false;
+                }
+                let mut stem: Vec<char> = affix.strip.chars().collect();
+                stem.extend(&chars[add.len()..]);
+                conditions_match(&affix.condition, &stem)
+                    && self.has_flag(&stem.iter().collect::<String>(), &affix.flag)
+            })
+    }
+
+    // Accepts dictionary words as written, capitalized, or in capitals.
+    pub fn check(&self, word: &str) -> bool {
+        let word = word.trim_matches(|c| c == '\'' || c == '’');
+        if word.is_empty() || self.check_exact(word) {
+            return true;
+        }
+        let lower = word.to_lowercase();
+        if self.check_exact(&lower) {
+            return true;
+        }
+        let mut chars = lower.chars();
+        let capitalized: String = match chars.next() {
+            Some(first) => first.to_uppercase().chain(chars).collect(),
+            None => return true,
+        };
+        word == word.to_uppercase() && self.check_exact(&capitalized)
+    }
+
+    // Dictionary words one edit away, in the order they were generated.
+    pub fn suggest(&self, word: &str, limit: usize) -> Vec<String> {
+        let chars: Vec<char> = word.chars().collect();
+        let mut candidates: Vec<String> = Vec::new();
+        for i in 0..chars.len() {
+            let mut c = chars.clone();
+            c.remove(i);
+            candidates.push(c.into_iter().collect());
+        }
+        for i in 1..chars.len() {
+            let mut c = chars.clone();
+            c.swap(i - 1, i);
+            candidates.push(c.into_iter().collect());
+        }
+        for letter in self.alphabet.iter() {
+            for i in 0..chars.len() {
+                let mut c = chars.clone();
+                c[i] = *letter;
+                candidates.push(c.into_iter().collect());
+            }
+            for i in 0..=chars.len() {
+                let mut c = chars.clone();
+                c.insert(i, *letter);
+                candidates.push(c.into_iter().collect());
+            }
+        }
+        let mut seen = HashSet::new();
+        candidates
+            .into_iter()
+            .filter(|c| c != word && self.check(c) && seen.insert(c.clone()))
+            .take(limit)
+            .collect()
+    }
+}
+
+pub fn dictionary_dirs() -> Vec<PathBuf> {
+    let data_dirs = std::env::var("XDG_DATA_DIRS").unwrap_or_default();
+    let mut result: Vec<PathBuf> = Vec::new();
+    for dir in data_dirs
+        .split(':')
+        .filter(|d| !d.is_empty())
+        .chain(vec!["/app/share", "/usr/share"])
+    {
+        for name in ["hunspell", "myspell"].iter() {
+            let path = PathBuf::from(dir).join(name);
+            if !result.contains(&path) {
+                result.push(path);
+            }
+        }
+    }
+    result
+}
+
+pub fn available_languages() -> Vec<String> {
+    let mut languages: Vec<String> = dictionary_dirs()
+        .into_iter()
+        .filter_map(|dir| fs::read_dir(dir).ok())
+        .flat_map(|entries| entries.filter_map(Result::ok))
+        .filter_map(|entry| {
+            let path = entry.path();
+            match path.extension() {
+                Some(ext) if ext == "dic" => path.file_stem()?.to_str().map(String::from),
+                _ => None,
+            }
+        })
+        .collect();
+    languages.sort();
+    languages.dedup();
+    languages
+}
+
+// Picks the preferred dictionary if installed, otherwise the best match
+// for the user's locales, such as "en_GB" or any "en" variant for "en_GB.UTF-8".
+pub fn resolve_language(
+    preferred: &str,
+    locales: &[String],
+    available: &[String],
+) -> Option<String> {
+    if available.iter().any(|a| a == preferred) {
+        return Some(preferred.to_string());
+    }
+    locales.iter().find_map(|locale| {
+        let name = locale.split(&['.', '@'][..]).next().unwrap_or("");
+        let language = name.split(&['_', '-'][..]).next().unwrap_or("");
+        if language.is_empty() || language == "C" || language == "POSIX" {
+            return None;
+        }
+        available
+            .iter()
+            .find(|a| a.as_str() == name)
+            .or_else(|| {
+                available.iter().find(|a| {
+                    a.as_str() == language
+                        || a.starts_with(&format!("{}_", language))
+                        || a.starts_with(&format!("{}-", language))
+                })
+            })
+            .cloned()
+    })
+}
+
+// Character ranges of every word in the text, skipping words that
+// contain digits or underscores.
+pub fn words(text: &str) -> Vec<(usize, usize)> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut result = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        if !is_word_char(chars[i]) && !chars[i].is_alphanumeric() {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < chars.len()
+            && (is_word_char(chars[i]) || chars[i].is_alphanumeric() || chars[i] == '_')
+        {
+            i += 1;
+        }
+        if chars[start..i].iter().all(|c| is_word_char(*c)) {
+            result.push((start, i));
+        }
+    }
+    result
+}
+
+pub fn misspelled(dictionary: &Dictionary, text: &str) -> Vec<(usize, usize)> {
+    let chars: Vec<char> = text.chars().collect();
+    words(text)
+        .into_iter()
+        .filter(|(start, end)| !dictionary.check(&chars[*start..*end].iter().collect::<String>()))
+        .collect()
+}
+
+pub fn word_at(text: &str, offset: usize) -> Option<(usize, usize)> {
+    words(text)
+        .into_iter()
+        .find(|(start, end)| *start <= offset && offset <= *end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AFF: &str = "SET UTF-8\nTRY esianrtolcdug\n\
+                       SFX S Y 2\nSFX S 0 s [^y]\nSFX S y ies [^aeiou]y\n\
+                       PFX U Y 1\nPFX U 0 un .\n";
+    const DIC: &str = "4\ncat/S\nfly/S\nhappy/U\nParis\n";
+
+    fn dictionary() -> Dictionary {
+        Dictionary::parse(AFF, DIC)
+    }
+
+    #[test]
+    fn test_check_words() {
+        let d = dictionary();
+        assert!(d.check("cat"));
+        assert!(d.check("Cat"), "Capitalized words are accepted");
+        assert!(d.check("CAT"), "Words in capitals are accepted");
+        assert!(d.check("Paris"));
+        assert!(d.check("PARIS"));
+        assert!(!d.check("paris"), "Proper nouns keep their case");
+        assert!(!d.check("kat"));
+    }
+
+    #[test]
+    fn test_check_affixes() {
+        let d = dictionary();
+        assert!(d.check("cats"));
+        assert!(d.check("flies"));
+        assert!(!d.check("flys"), "Condition excludes words ending in y");
+        assert!(d.check("unhappy"));
+        assert!(!d.check("uncat"), "Prefix needs the flag");
+        assert!(!d.check("happys"));
+    }
+
+    #[test]
+    fn test_flag_modes() {
+        let d = Dictionary::parse("FLAG long\nSFX Aa Y 1\nSFX Aa 0 ed .\n", "1\nwalk/AaBb\n");
+        assert!(d.check("walked"));
+        let d = Dictionary::parse("FLAG num\nSFX 12 Y 1\nSFX 12 0 ing .\n", "1\nwalk/7,12\n");
+        assert!(d.check("walking"));
+        assert!(!d.check("walked"));
+    }
+
+    #[test]
+    fn test_personal_words() {
+        let mut d = dictionary();
+        assert!(!d.check("gtk"));
+        d.add_word("gtk");
+        assert!(d.check("gtk"));
+    }
+
+    #[test]
+    fn test_suggest() {
+        let d = dictionary();
+        let suggestions = d.suggest("cst", 5);
+        assert!(suggestions.contains(&"cat".to_string()));
+        assert!(d.suggest("cats", 5).iter().all(|s| s != "cats"));
+        assert!(d.suggest("zzzzzz", 5).is_empty());
+    }
+
+    #[test]
+    fn test_words() {
+        assert_eq!(
+            vec![(0, 2), (4, 9), (25, 28)],
+            words("Hé, don't x86 snake_case ünï."),
+            "Words with digits or underscores are skipped"
+        );
+    }
+
+    #[test]
+    fn test_misspelled() {
+        let d = dictionary();
+        let text = "The cats and a dgo flies";
+        let chars: Vec<char> = text.chars().collect();
+        let wrong: Vec<String> = misspelled(&d, text)
+            .into_iter()
+            .map(|(s, e)| chars[s..e].iter().collect())
+            .collect();
+        assert_eq!(vec!["The", "and", "a", "dgo"], wrong);
+        assert_eq!(Some((4, 8)), word_at(text, 6));
+        assert_eq!(Some((4, 8)), word_at(text, 8), "End of word counts");
+        assert_eq!(None, word_at("a  b", 2));
+    }
+
+    #[test]
+    fn test_parse_condition() {
+        assert_eq!(
+            vec![
+                Condition::Chars(vec!['a', 'e'], true),
+                Condition::Any,
+                Condition::Chars(vec!['y'], false)
+            ],
+            parse_condition("[^ae].y")
+        );
+    }
+
+    #[test]
+    fn test_resolve_language() {
+        let available: Vec<String> = vec!["de_DE".into(), "en_GB".into(), "en_US".into()];
+        let locales =
+            |names: &[&str]| -> Vec<String> { names.iter().map(|n| n.to_string()).collect() };
+        assert_eq!(
+            Some("de_DE".to_string()),
+            resolve_language("de_DE", &locales(&["en_US.UTF-8"]), &available),
+            "The preferred dictionary wins"
+        );
+        assert_eq!(
+            Some("en_US".to_string()),
+            resolve_language(
+                "",
+                &locales(&["en_US.UTF-8", "en_US", "en", "C"]),
+                &available
+            )
+        );
+        assert_eq!(
+            Some("en_GB".to_string()),
+            resolve_language("fr_FR", &locales(&["en_AU.UTF-8"]), &available),
+            "Falls back to any variant of the language"
+        );
+        assert_eq!(None, resolve_language("", &locales(&["C"]), &available));
+        assert_eq!(None, resolve_language("", &locales(&["ja_JP"]), &available));
+    }
+}
@@ -0,0 +1,74 @@
+use log::{debug, info, warn};
+use std::time::{Duration, Instant};
+
+// The first keystroke should be taken this soon after launching.
+pub const READY_TARGET: Duration = Duration::from_millis(150);
+
+// When each stage of starting up was reached, so a slow start can be traced
+// in the log to what made it slow.
+#[derive(Debug)]
+pub struct Timing {
+    launched: Instant,
+    stages: Vec<(&'static str, Duration)>,
+}
+
+impl Timing {
+    pub fn new(launched: Instant) -> Self {
+        Self {
+            launched,
+            stages: Vec::new(),
+        }
+    }
+
+    pub fn mark(&mut self, stage: &'static str) {
+        let elapsed = self.launched.elapsed();
+        debug!("Startup reached {} after {} ms", stage, elapsed.as_millis());
+        self.stages.push((stage, elapsed));
+    }
+
+    // The window is drawn and takes typing. Anything loaded after this is
+    // off the critical path.
+    pub fn ready(mut self) {
+        self.mark("ready");
+        let report = report(&self.stages);
+        if over_target(&self.stages) {
+            warn!(
+                "Startup took longer than {} ms: {}",
+                READY_TARGET.as_millis(),
+                report
+            );
+        } else {
+            info!("Startup: {}", report);
+        }
+    }
+}
+
+fn report(stages: &[(&str, Duration)]) -> String {
+    stages
+        .iter()
+        .map(|(stage, elapsed)| format!("{} {} ms", stage, elapsed.as_millis()))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn over_target(stages: &[(&str, Duration)]) -> bool {
+    matches!(stages.last(), Some((_, elapsed)) if *elapsed > READY_TARGET)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report() {
+        let stages = [
+            ("startup", Duration::from_millis(12)),
+            ("window", Duration::from_micros(80_400)),
+            ("ready", Duration::from_millis(151)),
+        ];
+        assert_eq!("startup 12 ms, window 80 ms, ready 151 ms", report(&stages));
+        assert!(over_target(&stages));
+        assert!(!over_target(&stages[..2]));
+        assert!(!over_target(&[]));
+    }
+}
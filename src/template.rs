@@ -0,0 +1,97 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Fills `{name}` placeholders, such as `{date}`, in a new document's
+// template. Unknown names and unclosed braces are left as they are.
+pub fn expand<F>(template: &str, value: F) -> String
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut expanded = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        expanded.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let replaced = after
+            .find(['{', '}'])
+            .filter(|&end| after[end..].starts_with('}'))
+            .and_then(|end| value(&after[..end]).map(|v| (v, end)));
+        match replaced {
+            Some((text, end)) => {
+                expanded.push_str(&text);
+                rest = &after[end + 1..];
+            }
+            None => {
+                expanded.push('{');
+                rest = after;
+            }
+        }
+    }
+    expanded + rest
+}
+
+// The templates in a folder, named after their files without the
+// extension. Hidden and backup files are skipped.
+pub fn list(dir: &Path) -> Vec<(String, PathBuf)> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    let mut templates: Vec<(String, PathBuf)> = entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .filter_map(|path| {
+            let file_name = path.file_name()?.to_string_lossy().to_string();
+            if file_name.starts_with('.') || file_name.ends_with('~') {
+                return None;
+            }
+            let name = path.file_stem()?.to_string_lossy().to_string();
+            Some((name, path))
+        })
+        .collect();
+    templates.sort_by_key(|(name, _)| name.to_lowercase());
+    templates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(name: &str) -> Option<String> {
+        match name {
+            "date" => Some("2024-02-01".into()),
+            "time" => Some("13:45".into()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_expand() {
+        assert_eq!(
+            "# Notes 2024-02-01\n\n13:45 ",
+            expand("# Notes {date}\n\n{time} ", value)
+        );
+        assert_eq!(
+            "{title} {",
+            expand("{title} {", value),
+            "Unknown and unclosed"
+        );
+        assert_eq!("{2024-02-01}", expand("{{date}}", value));
+        assert_eq!("", expand("", value));
+    }
+
+    #[test]
+    fn test_list() {
+        let dir = std::env::temp_dir().join(format!("textedit2-templates-{}", std::process::id()));
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(dir.join("Folder")).unwrap();
+        for name in ["meeting.md", "Letter.txt", ".hidden", "draft.txt~"].iter() {
+            fs::write(dir.join(name), "").unwrap();
+        }
+        let names: Vec<String> = list(&dir).into_iter().map(|(name, _)| name).collect();
+        assert_eq!(vec!["Letter", "meeting"], names);
+        assert!(list(&dir.join("missing")).is_empty());
+        fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,296 @@
+// Widths count characters, so wide characters such as CJK will not line
+// up in a monospace font.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BoxStyle {
+    Ascii,
+    Lines,
+}
+
+// Corners and junctions, in reading order.
+struct Border {
+    horizontal: char,
+    vertical: char,
+    top: [char; 3],
+    middle: [char; 3],
+    bottom: [char; 3],
+}
+
+const ASCII: Border = Border {
+    horizontal: '-',
+    vertical: '|',
+    top: ['+', '+', '+'],
+    middle: ['+', '+', '+'],
+    bottom: ['+', '+', '+'],
+};
+
+const LINES: Border = Border {
+    horizontal: '─',
+    vertical: '│',
+    top: ['┌', '┬', '┐'],
+    middle: ['├', '┼', '┤'],
+    bottom: ['└', '┴', '┘'],
+};
+
+impl BoxStyle {
+    fn border(self) -> &'static Border {
+        match self {
+            BoxStyle::Ascii => &ASCII,
+            BoxStyle::Lines => &LINES,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Row {
+    pub cells: Vec<String>,
+    // A rule separates this row from the one above.
+    pub rule_above: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Table {
+    pub rows: Vec<Row>,
+    pub style: BoxStyle,
+    pub indent: String,
+}
+
+impl Table {
+    // Blank cells to type into. A header row is ruled off from the rest.
+    pub fn empty(rows: usize, columns: usize, width: usize, header: bool, style: BoxStyle) -> Self {
+        let rows = (0..rows.max(1))
+            .map(|i| Row {
+                cells: vec![" ".repeat(width.max(1)); columns.max(1)],
+                rule_above: header && i == 1,
+            })
+            .collect();
+        Self {
+            rows,
+            style,
+            indent: String::new(),
+        }
+    }
+
+    // A single column box around each line of the text.
+    pub fn frame(text: &str, style: BoxStyle) -> Self {
+        let rows = text
+            .lines()
+            .map(|line| Row {
+                cells: vec![line.trim_end().to_string()],
+                rule_above: false,
+            })
+            .collect();
+        Self {
+            rows,
+            style,
+            indent: String::new(),
+        }
+    }
+
+    // Reads a box or table drawn in either style, whatever state its edges
+    // were left in by editing.
+    pub fn parse(text: &str) -> Option<Self> {
+        let style = if text.contains(LINES.vertical) || text.contains(LINES.horizontal) {
+            BoxStyle::Lines
+        } else {
+            BoxStyle::Ascii
+        };
+        let border = style.border();
+        let first = text.lines().find(|l| !l.trim().is_empty())?;
+        let indent = first[..first.len() - first.trim_start().len()].to_string();
+        let mut rows: Vec<Row> = Vec::new();
+        let mut rule = false;
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() {
+                return None;
+            }
+            if is_border(line, border) {
+                rule = !rows.is_empty();
+                continue;
+            }
+            let inner = line.strip_prefix(border.vertical)?;
+            let inner = inner.strip_suffix(border.vertical).unwrap_or(inner);
+            let cells: Vec<&str> = inner.split(border.vertical).collect();
+            let cells = if cells.len() == 1 {
+                // Boxes keep the indentation of their text.
+                vec![inner
+                    .strip_prefix(' ')
+                    .unwrap_or(inner)
+                    .trim_end()
+                    .to_string()]
+            } else {
+                cells.iter().map(|c| c.trim().to_string()).collect()
+            };
+            rows.push(Row {
+                cells,
+                rule_above: rule,
+            });
+            rule = false;
+        }
+        if rows.is_empty() {
+            return None;
+        }
+        Some(Self {
+            rows,
+            style,
+            indent,
+        })
+    }
+
+    // Every column is as wide as its widest cell, without a final newline.
+    pub fn render(&self) -> String {
+        let border = self.style.border();
+        let columns = self.rows.iter().map(|r| r.cells.len()).max().unwrap_or(1);
+        let widths: Vec<usize> = (0..columns)
+            .map(|c| {
+                self.rows
+                    .iter()
+                    .filter_map(|r| r.cells.get(c))
+                    .map(|cell| cell.chars().count())
+                    .max()
+                    .unwrap_or(0)
+                    .max(1)
+            })
+            .collect();
+        let rule = |[left, junction, right]: [char; 3]| {
+            let segments: Vec<String> = widths
+                .iter()
+                .map(|w| border.horizontal.to_string().repeat(w + 2))
+                .collect();
+            format!(
+                "{}{}{}{}",
+                self.indent,
+                left,
+                segments.join(&junction.to_string()),
+                right
+            )
+        };
+        let mut lines = vec![rule(border.top)];
+        for row in &self.rows {
+            if row.rule_above {
+                lines.push(rule(border.middle));
+            }
+            let cells: Vec<String> = widths
+                .iter()
+                .enumerate()
+                .map(|(c, width)| {
+                    let cell = row.cells.get(c).map_or("", String::as_str);
+                    let padding = width - cell.chars().count();
+                    format!(" {}{} ", cell, " ".repeat(padding))
+                })
+                .collect();
+            let vertical = border.vertical.to_string();
+            lines.push(format!(
+                "{}{}{}{}",
+                self.indent,
+                vertical,
+                cells.join(&vertical),
+                vertical
+            ));
+        }
+        lines.push(rule(border.bottom));
+        lines.join("\n")
+    }
+}
+
+fn is_border(line: &str, border: &Border) -> bool {
+    let corners: Vec<char> = [border.top, border.middle, border.bottom]
+        .iter()
+        .flatten()
+        .copied()
+        .collect();
+    line.chars()
+        .all(|c| c == border.horizontal || corners.contains(&c))
+}
+
+// Whether a line may belong to a box, for finding the one around the
+// cursor.
+pub fn is_box_line(line: &str) -> bool {
+    let line = line.trim_start();
+    [&ASCII, &LINES].iter().any(|border| {
+        line.starts_with(border.vertical)
+            || (!line.is_empty() && is_border(line.trim_end(), border))
+    })
+}
+
+// Redraws a box or table around its edited contents. The final newline,
+// if any, is kept.
+pub fn reflow(text: &str) -> Option<String> {
+    let (body, newline) = match text.strip_suffix('\n') {
+        Some(body) => (body, "\n"),
+        None => (text, ""),
+    };
+    Table::parse(body).map(|table| table.render() + newline)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let table = Table {
+            rows: vec![
+                Row {
+                    cells: vec!["Name".into(), "Qty".into()],
+                    rule_above: false,
+                },
+                Row {
+                    cells: vec!["Apples".into(), "3".into()],
+                    rule_above: true,
+                },
+            ],
+            style: BoxStyle::Ascii,
+            indent: String::new(),
+        };
+        assert_eq!(
+            "+--------+-----+\n\
+             | Name   | Qty |\n\
+             +--------+-----+\n\
+             | Apples | 3   |\n\
+             +--------+-----+",
+            table.render()
+        );
+    }
+
+    #[test]
+    fn test_empty_and_frame() {
+        let table = Table::empty(2, 2, 1, false, BoxStyle::Lines);
+        assert_eq!("┌───┬───┐\n│   │   │\n│   │   │\n└───┴───┘", table.render());
+        assert_eq!(
+            "┌───────┐\n│ Hi    │\n│   you │\n└───────┘",
+            Table::frame("Hi\n  you", BoxStyle::Lines).render(),
+            "Indentation inside the box is kept"
+        );
+    }
+
+    #[test]
+    fn test_reflow() {
+        let edited = "  +------+\n  | Hello there |\n  | x |\n  +------+\n";
+        assert_eq!(
+            Some(
+                "  +-------------+\n  | Hello there |\n  | x           |\n  +-------------+\n"
+                    .into()
+            ),
+            reflow(edited),
+            "Indentation of the box is kept"
+        );
+        let table = "┌──┬──┐\n│ a│ b │\n├──┼──┤\n│ long │ c\n└──┴──┘";
+        assert_eq!(
+            Some("┌──────┬───┐\n│ a    │ b │\n├──────┼───┤\n│ long │ c │\n└──────┴───┘".into()),
+            reflow(table)
+        );
+        assert_eq!(None, reflow("not a box"));
+        let frame = Table::frame("one\ntwo", BoxStyle::Ascii).render();
+        assert_eq!(Some(frame.clone()), reflow(&frame), "Unchanged");
+    }
+
+    #[test]
+    fn test_is_box_line() {
+        assert!(is_box_line("  +---+"));
+        assert!(is_box_line("│ text"));
+        assert!(!is_box_line("text |"));
+        assert!(!is_box_line(""));
+    }
+}
@@ -0,0 +1,613 @@
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortOrder {
+    Alphabetical,
+    // Runs of digits compare by value, so file2 comes before file10.
+    Natural,
+    // By the number the key starts with. Keys without one come first.
+    Numeric,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SortOptions {
+    pub order: SortOrder,
+    pub reverse: bool,
+    pub ignore_case: bool,
+    // Sorts by this field, counting from 1, instead of the whole line.
+    pub column: Option<usize>,
+    // Separates fields, or whitespace when empty.
+    pub delimiter: String,
+}
+
+impl Default for SortOptions {
+    fn default() -> Self {
+        Self {
+            order: SortOrder::Alphabetical,
+            reverse: false,
+            ignore_case: false,
+            column: None,
+            delimiter: String::new(),
+        }
+    }
+}
+
+// Lines that compare equal keep their order. A final newline stays at the
+// end rather than sorting as an empty line.
+pub fn sort_lines(text: &str, options: &SortOptions) -> String {
+    let (body, newline) = match text.strip_suffix('\n') {
+        Some(body) => (body, "\n"),
+        None => (text, ""),
+    };
+    let mut lines: Vec<&str> = body.split('\n').collect();
+    lines.sort_by(|a, b| {
+        let ordering = compare(sort_key(a, options), sort_key(b, options), options);
+        if options.reverse {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    });
+    lines.join("\n") + newline
+}
+
+// Lines keep the ending the text already uses, and a final line ending
+// stays at the end.
+pub fn reverse_lines(text: &str) -> String {
+    let ending = line_ending(text);
+    let (body, newline) = match text.strip_suffix('\n') {
+        Some(body) => (body.strip_suffix('\r').unwrap_or(body), ending),
+        None => (text, ""),
+    };
+    let lines: Vec<&str> = split_lines(body).rev().collect();
+    lines.join(ending) + newline
+}
+
+// Joins lines with single spaces, dropping the whitespace around each
+// break and any blank lines. The first line keeps its indentation.
+pub fn join_lines(text: &str) -> String {
+    let mut lines = text.split('\n').map(str::trim_end);
+    let mut joined = lines.next().unwrap_or_default().to_string();
+    for line in lines.map(str::trim_start).filter(|l| !l.is_empty()) {
+        if joined.trim_start().is_empty() {
+            joined = line.to_string();
+        } else {
+            joined.push(' ');
+            joined.push_str(line);
+        }
+    }
+    joined
+}
+
+// Whole lines are repeated on the next line, after the line ending they
+// already have. The last line of a text has none, so `ending` is used.
+pub fn duplicate_lines(lines: &str, ending: &str) -> String {
+    if lines.ends_with('\n') {
+        lines.repeat(2)
+    } else {
+        format!("{}{}{}", lines, ending, lines)
+    }
+}
+
+// Moves the first line to the end, or the last line to the start, which
+// moves the other lines up or down by one. Lines are joined with the
+// ending the text already uses.
+pub fn rotate_lines(text: &str, up: bool) -> String {
+    let mut lines: Vec<&str> = split_lines(text).collect();
+    if up {
+        lines.rotate_left(1);
+    } else {
+        lines.rotate_right(1);
+    }
+    lines.join(line_ending(text))
+}
+
+// Keeps the first of each repeated line, wherever the repeats are.
+pub fn remove_duplicate_lines(text: &str) -> String {
+    let (body, newline) = match text.strip_suffix('\n') {
+        Some(body) => (body, "\n"),
+        None => (text, ""),
+    };
+    let mut seen = HashSet::new();
+    let lines: Vec<&str> = body
+        .split('\n')
+        .filter(|line| seen.insert(line.strip_suffix('\r').unwrap_or(line)))
+        .collect();
+    lines.join("\n") + newline
+}
+
+// `\r\n` when the first line ends with it, as in a file saved on Windows.
+fn line_ending(text: &str) -> &'static str {
+    match text.find('\n') {
+        Some(i) if text[..i].ends_with('\r') => "\r\n",
+        _ => "\n",
+    }
+}
+
+// The lines without their endings.
+fn split_lines(text: &str) -> impl DoubleEndedIterator<Item = &str> {
+    text.split('\n')
+        .map(|line| line.strip_suffix('\r').unwrap_or(line))
+}
+
+fn sort_key<'a>(line: &'a str, options: &SortOptions) -> &'a str {
+    let line = line.strip_suffix('\r').unwrap_or(line);
+    let column = match options.column {
+        Some(column) if column > 0 => column - 1,
+        _ => return line,
+    };
+    let field = if options.delimiter.is_empty() {
+        line.split_whitespace().nth(column)
+    } else {
+        line.split(options.delimiter.as_str()).nth(column)
+    };
+    field.unwrap_or("")
+}
+
+fn compare(a: &str, b: &str, options: &SortOptions) -> Ordering {
+    match options.order {
+        SortOrder::Alphabetical => compare_text(a, b, options.ignore_case),
+        SortOrder::Natural => compare_natural(a, b, options.ignore_case),
+        SortOrder::Numeric => match (leading_number(a), leading_number(b)) {
+            (Some(x), Some(y)) => x.partial_cmp(&y).unwrap_or(Ordering::Equal),
+            (x, y) => x.is_some().cmp(&y.is_some()),
+        },
+    }
+}
+
+fn compare_text(a: &str, b: &str, ignore_case: bool) -> Ordering {
+    if ignore_case {
+        a.to_lowercase().cmp(&b.to_lowercase())
+    } else {
+        a.cmp(b)
+    }
+}
+
+fn compare_natural(a: &str, b: &str, ignore_case: bool) -> Ordering {
+    let (a_chunks, b_chunks) = (chunks(a), chunks(b));
+    for (x, y) in a_chunks.iter().zip(b_chunks.iter()) {
+        let digits = |s: &str| s.starts_with(|c: char| c.is_ascii_digit());
+        let ordering = if digits(x) && digits(y) {
+            let (x_value, y_value) = (x.trim_start_matches('0'), y.trim_start_matches('0'));
+            // A longer number without leading zeros is larger, and then
+            // fewer leading zeros sort first.
+            x_value
+                .len()
+                .cmp(&y_value.len())
+                .then_with(|| x_value.cmp(y_value))
+                .then_with(|| x.len().cmp(&y.len()))
+        } else {
+            compare_text(x, y, ignore_case)
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    a_chunks.len().cmp(&b_chunks.len())
+}
+
+// Splits text into alternating runs of digits and other characters.
+fn chunks(text: &str) -> Vec<&str> {
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    let mut previous = None;
+    for (i, c) in text.char_indices() {
+        let digit = c.is_ascii_digit();
+        if previous.is_some() && previous != Some(digit) {
+            chunks.push(&text[start..i]);
+            start = i;
+        }
+        previous = Some(digit);
+    }
+    if start < text.len() {
+        chunks.push(&text[start..]);
+    }
+    chunks
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Numbering {
+    pub start: i64,
+    pub step: i64,
+    // Numbers are padded with zeros to this many digits.
+    pub width: usize,
+    pub separator: String,
+}
+
+impl Default for Numbering {
+    fn default() -> Self {
+        Self {
+            start: 1,
+            step: 1,
+            width: 0,
+            separator: ". ".into(),
+        }
+    }
+}
+
+// A final newline does not start another line to number.
+pub fn number_lines(text: &str, numbering: &Numbering) -> String {
+    let (body, newline) = match text.strip_suffix('\n') {
+        Some(body) => (body, "\n"),
+        None => (text, ""),
+    };
+    let mut number = numbering.start;
+    let lines: Vec<String> = body
+        .split('\n')
+        .map(|line| {
+            let numbered = format!(
+                "{:0width$}{}{}",
+                number,
+                numbering.separator,
+                line,
+                width = numbering.width
+            );
+            number += numbering.step;
+            numbered
+        })
+        .collect();
+    lines.join("\n") + newline
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Case {
+    Upper,
+    Lower,
+    // Each word starts with a capital and is otherwise lowercase.
+    Title,
+    // Swaps upper and lowercase letters.
+    Toggle,
+}
+
+// Uses the full Unicode mappings, so the text may change length, as when
+// ß becomes SS.
+pub fn change_case(text: &str, case: Case) -> String {
+    match case {
+        Case::Upper => text.to_uppercase(),
+        Case::Lower => text.to_lowercase(),
+        Case::Title => title_case(text),
+        Case::Toggle => text
+            .chars()
+            .map(|c| {
+                if c.is_lowercase() {
+                    c.to_uppercase().collect()
+                } else if c.is_uppercase() {
+                    c.to_lowercase().collect()
+                } else {
+                    c.to_string()
+                }
+            })
+            .collect(),
+    }
+}
+
+// Apostrophes stay inside a word, so "don't" is one word.
+fn title_case(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut word = String::new();
+    for c in text.chars() {
+        if c.is_alphanumeric() || (!word.is_empty() && ['\'', '’'].contains(&c)) {
+            word.push(c);
+        } else {
+            result.push_str(&capitalize(&word));
+            word.clear();
+            result.push(c);
+        }
+    }
+    result + &capitalize(&word)
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => titlecase(first) + &chars.as_str().to_lowercase(),
+        None => String::new(),
+    }
+}
+
+// The digraphs have a titlecase form that differs from their uppercase.
+fn titlecase(c: char) -> String {
+    match c {
+        'Ǆ' | 'ǅ' | 'ǆ' => "ǅ".into(),
+        'Ǉ' | 'ǈ' | 'ǉ' => "ǈ".into(),
+        'Ǌ' | 'ǋ' | 'ǌ' => "ǋ".into(),
+        'Ǳ' | 'ǲ' | 'ǳ' => "ǲ".into(),
+        _ => c.to_uppercase().collect(),
+    }
+}
+
+pub fn strip_numbering(text: &str) -> String {
+    text.split('\n')
+        .map(strip_number)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// Recognises numbers like `1.`, `2)`, `(3)`, `[4]` or `5:` followed by
+// whitespace, so decimals such as 3.5 are not mistaken for numbering.
+fn strip_number(line: &str) -> &str {
+    let trimmed = line.trim_start();
+    let rest = trimmed.strip_prefix(['(', '[']).unwrap_or(trimmed);
+    let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    if digits == 0 {
+        return line;
+    }
+    let mut chars = rest[digits..].chars();
+    let separated = match chars.next() {
+        Some('\t') => true,
+        Some('.') | Some(')') | Some(']') | Some(':') | Some('-') => {
+            matches!(chars.clone().next(), None | Some(' ') | Some('\t'))
+        }
+        _ => false,
+    };
+    if separated {
+        chars.as_str().trim_start_matches([' ', '\t'])
+    } else {
+        line
+    }
+}
+
+// Nested quotes collapse to `>>` the way mail clients write them, and blank
+// lines get a bare `>` so quoted paragraphs stay together.
+pub fn quote_lines(text: &str) -> String {
+    let (body, newline) = split_trailing_newline(text);
+    let quoted = body
+        .split('\n')
+        .map(|line| match line.chars().next() {
+            None => ">".to_string(),
+            Some('>') => format!(">{}", line),
+            Some(_) => format!("> {}", line),
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    quoted + newline
+}
+
+// Removes one level of quoting and leaves unquoted lines alone.
+pub fn unquote_lines(text: &str) -> String {
+    text.split('\n')
+        .map(|line| match line.strip_prefix('>') {
+            Some(rest) => rest.strip_prefix(' ').unwrap_or(rest),
+            None => line,
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn split_trailing_newline(text: &str) -> (&str, &str) {
+    match text.strip_suffix('\n') {
+        Some(body) => (body, "\n"),
+        None => (text, ""),
+    }
+}
+
+fn leading_number(text: &str) -> Option<f64> {
+    let text = text.trim_start();
+    let end = text
+        .char_indices()
+        .find(|&(i, c)| !(c.is_ascii_digit() || c == '.' || (i == 0 && (c == '-' || c == '+'))))
+        .map_or(text.len(), |(i, _)| i);
+    text[..end].parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(text: &str, options: SortOptions) -> String {
+        sort_lines(text, &options)
+    }
+
+    #[test]
+    fn test_sort_alphabetical() {
+        assert_eq!("a\nb\nc\n", sorted("b\nc\na\n", SortOptions::default()));
+        assert_eq!(
+            "B\na\nc",
+            sorted("c\na\nB", SortOptions::default()),
+            "Uppercase first, without a final newline"
+        );
+        let options = SortOptions {
+            ignore_case: true,
+            ..SortOptions::default()
+        };
+        assert_eq!("a\nB\nc", sorted("c\na\nB", options.clone()));
+        let options = SortOptions {
+            reverse: true,
+            ..options
+        };
+        assert_eq!(
+            "b\nB\na",
+            sorted("b\nB\na", options),
+            "Equal lines keep their order when reversed"
+        );
+    }
+
+    #[test]
+    fn test_sort_natural() {
+        let options = SortOptions {
+            order: SortOrder::Natural,
+            ..SortOptions::default()
+        };
+        assert_eq!(
+            "file1\nfile2\nfile10\nfile10b",
+            sorted("file10\nfile2\nfile10b\nfile1", options.clone())
+        );
+        assert_eq!("a2\na02\na3", sorted("a3\na02\na2", options));
+    }
+
+    #[test]
+    fn test_sort_numeric() {
+        let options = SortOptions {
+            order: SortOrder::Numeric,
+            ..SortOptions::default()
+        };
+        assert_eq!(
+            "none\n-3 below\n2.5\n10 items\n",
+            sorted("10 items\n2.5\nnone\n-3 below\n", options.clone())
+        );
+        assert_eq!(
+            "10 items\n2.5\n-3 below\nnone\n",
+            sorted(
+                "10 items\n2.5\nnone\n-3 below\n",
+                SortOptions {
+                    reverse: true,
+                    ..options
+                }
+            )
+        );
+    }
+
+    #[test]
+    fn test_sort_column() {
+        let options = SortOptions {
+            order: SortOrder::Numeric,
+            column: Some(2),
+            delimiter: ",".into(),
+            ..SortOptions::default()
+        };
+        assert_eq!(
+            "carol,7\nann,12\nbob,30\n",
+            sorted("bob,30\nann,12\ncarol,7\n", options)
+        );
+        let options = SortOptions {
+            column: Some(2),
+            ..SortOptions::default()
+        };
+        assert_eq!(
+            "short\nx  apple\ny banana",
+            sorted("y banana\nx  apple\nshort", options),
+            "Whitespace separated, missing fields first"
+        );
+    }
+
+    #[test]
+    fn test_reverse_lines() {
+        assert_eq!("c\nb\na\n", reverse_lines("a\nb\nc\n"));
+        assert_eq!("b\n\na", reverse_lines("a\n\nb"));
+        assert_eq!("", reverse_lines(""));
+        assert_eq!("b\r\na", reverse_lines("a\r\nb"), "CRLF");
+        assert_eq!("b\r\na\r\n", reverse_lines("a\r\nb\r\n"));
+    }
+
+    #[test]
+    fn test_join_lines() {
+        assert_eq!(
+            "    let x = a + b;",
+            join_lines("    let x = a +  \n        b;")
+        );
+        assert_eq!(
+            "a b c",
+            join_lines("a\r\n\n  \nb\n\tc"),
+            "Blank lines are dropped"
+        );
+        assert_eq!("b", join_lines("  \nb"));
+        assert_eq!("a", join_lines("a"));
+    }
+
+    #[test]
+    fn test_duplicate_lines() {
+        assert_eq!("a\nb\na\nb\n", duplicate_lines("a\nb\n", "\n"));
+        assert_eq!("  x\n  x", duplicate_lines("  x", "\n"));
+        assert_eq!("\n", duplicate_lines("", "\n"), "An empty line");
+        assert_eq!("a\r\na\r\n", duplicate_lines("a\r\n", "\n"), "CRLF");
+        assert_eq!("a\r\na", duplicate_lines("a", "\r\n"));
+    }
+
+    #[test]
+    fn test_rotate_lines() {
+        assert_eq!("b\nc\na", rotate_lines("a\nb\nc", true));
+        assert_eq!("c\na\nb", rotate_lines("a\nb\nc", false));
+        assert_eq!(
+            "a\nb",
+            rotate_lines(&rotate_lines("a\nb", true), false),
+            "Moving back undoes a move"
+        );
+        assert_eq!("", rotate_lines("", true));
+        assert_eq!("b\r\na", rotate_lines("a\r\nb", true), "CRLF");
+        assert_eq!("c\r\na\r\nb", rotate_lines("a\r\nb\r\nc", false));
+    }
+
+    #[test]
+    fn test_remove_duplicate_lines() {
+        assert_eq!(
+            "b\na\nc\n",
+            remove_duplicate_lines("b\na\nb\nc\na\n"),
+            "The first of each is kept"
+        );
+        assert_eq!("a\r\nb", remove_duplicate_lines("a\r\nb\na"));
+        assert_eq!("A\na", remove_duplicate_lines("A\na"), "Case matters");
+    }
+
+    #[test]
+    fn test_change_case() {
+        assert_eq!("STRASSE ÉTÉ", change_case("Straße été", Case::Upper));
+        assert_eq!("οδός", change_case("ΟΔΌΣ", Case::Lower), "Final sigma");
+        assert_eq!("İstanbul", change_case("İSTANBUL", Case::Title));
+        assert_eq!("sTRASSE ÉTÉ", change_case("Straße été", Case::Toggle));
+        assert_eq!("", change_case("", Case::Upper));
+    }
+
+    #[test]
+    fn test_title_case() {
+        assert_eq!(
+            "Don't Stop, Élan-Vital 2nd",
+            change_case("DON'T stop, élan-vital 2ND", Case::Title)
+        );
+        assert_eq!(
+            "ǅungla",
+            change_case("ǆUNGLA", Case::Title),
+            "Titlecase digraph"
+        );
+        assert_eq!("Ǳ Ǉ", change_case("ǳ ǉ", Case::Upper));
+    }
+
+    #[test]
+    fn test_number_lines() {
+        assert_eq!(
+            "1. apples\n2. pears\n",
+            number_lines("apples\npears\n", &Numbering::default())
+        );
+        let numbering = Numbering {
+            start: 8,
+            step: 2,
+            width: 2,
+            separator: "\t".into(),
+        };
+        assert_eq!("08\ta\n10\tb", number_lines("a\nb", &numbering));
+    }
+
+    #[test]
+    fn test_strip_numbering() {
+        assert_eq!(
+            "apples\npears\nplums\nfigs\n",
+            strip_numbering("1. apples\n  2) pears\n(3) plums\n04\tfigs\n")
+        );
+        assert_eq!(
+            "3.5 apples\n2024 was a year\nno number",
+            strip_numbering("3.5 apples\n2024 was a year\nno number"),
+            "Only numbering is removed"
+        );
+        let numbered = number_lines("a\nb\n", &Numbering::default());
+        assert_eq!("a\nb\n", strip_numbering(&numbered));
+    }
+
+    #[test]
+    fn test_quote_lines() {
+        assert_eq!("> Hi,\n>\n> See you\n", quote_lines("Hi,\n\nSee you\n"));
+        assert_eq!(
+            ">> earlier\n> reply",
+            quote_lines("> earlier\nreply"),
+            "Nested quotes"
+        );
+    }
+
+    #[test]
+    fn test_unquote_lines() {
+        assert_eq!("Hi,\n\nSee you\n", unquote_lines("> Hi,\n>\n> See you\n"));
+        assert_eq!("> earlier\nreply", unquote_lines(">> earlier\n> reply"));
+        assert_eq!("> spaced", unquote_lines("> > spaced"));
+        let text = "plain\n> quoted";
+        assert_eq!(text, unquote_lines(&quote_lines(text)), "Round trip");
+    }
+}
@@ -0,0 +1,339 @@
+// Dates are proleptic Gregorian and times are in milliseconds since the
+// Unix epoch, ignoring leap seconds.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    UnixSeconds,
+    UnixMillis,
+    Iso,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Civil {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+    pub millis: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Moment {
+    Unix(i64),
+    // An ISO 8601 date without an offset is in the user's time zone.
+    Local(Civil),
+}
+
+// A timestamp in the text, with character offsets for the buffer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Timestamp {
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+    pub kind: Kind,
+    pub moment: Moment,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unit {
+    Seconds,
+    Minutes,
+    Hours,
+    Days,
+    Months,
+    Years,
+}
+
+fn is_token_char(c: char) -> bool {
+    c.is_ascii_digit() || ['-', ':', '.', '+', 'T', 'Z'].contains(&c)
+}
+
+// The timestamp touching a character offset. Unix timestamps are only
+// recognised with 9 or 10 digits for seconds and 12 or 13 for
+// milliseconds, which covers 1973 to 2286.
+pub fn timestamp_at(text: &str, offset: usize) -> Option<Timestamp> {
+    let chars: Vec<char> = text.chars().collect();
+    let offset = offset.min(chars.len());
+    let mut start = offset;
+    while start > 0 && is_token_char(chars[start - 1]) {
+        start -= 1;
+    }
+    let mut end = offset;
+    while end < chars.len() && is_token_char(chars[end]) {
+        end += 1;
+    }
+    while start < end && !chars[start].is_ascii_digit() {
+        start += 1;
+    }
+    while end > start && !chars[end - 1].is_ascii_digit() && chars[end - 1] != 'Z' {
+        end -= 1;
+    }
+    let word = |c: Option<&char>| matches!(c, Some(c) if c.is_alphanumeric() || *c == '_');
+    if start == end || word(start.checked_sub(1).and_then(|i| chars.get(i))) || word(chars.get(end))
+    {
+        return None;
+    }
+    let token: String = chars[start..end].iter().collect();
+    let (kind, moment) = parse(&token)?;
+    Some(Timestamp {
+        start,
+        end,
+        text: token,
+        kind,
+        moment,
+    })
+}
+
+fn parse(token: &str) -> Option<(Kind, Moment)> {
+    if token.chars().all(|c| c.is_ascii_digit()) {
+        let value: i64 = token.parse().ok()?;
+        return match token.len() {
+            9 | 10 => Some((Kind::UnixSeconds, Moment::Unix(value * 1000))),
+            12 | 13 => Some((Kind::UnixMillis, Moment::Unix(value))),
+            _ => None,
+        };
+    }
+    parse_iso(token).map(|moment| (Kind::Iso, moment))
+}
+
+// `2024-02-01`, optionally with a time such as `T13:45`, `T13:45:09.5` and
+// an offset such as `Z`, `+01:00` or `-0530`.
+fn parse_iso(token: &str) -> Option<Moment> {
+    let number = |s: &str, len: usize| -> Option<u32> {
+        if s.len() == len && s.chars().all(|c| c.is_ascii_digit()) {
+            s.parse().ok()
+        } else {
+            None
+        }
+    };
+    let (date, time) = match token.split_once('T') {
+        Some((date, time)) => (date, Some(time)),
+        None => (token, None),
+    };
+    let mut parts = date.split('-');
+    let year = number(parts.next()?, 4)?;
+    let month = number(parts.next()?, 2)?;
+    let day = number(parts.next()?, 2)?;
+    if parts.next().is_some() || !(1..=12).contains(&month) || day == 0 {
+        return None;
+    }
+    if day > days_in_month(i64::from(year), month) {
+        return None;
+    }
+    let mut civil = Civil {
+        year: i64::from(year),
+        month,
+        day,
+        hour: 0,
+        minute: 0,
+        second: 0,
+        millis: 0,
+    };
+    let time = match time {
+        Some(time) => time,
+        None => return Some(Moment::Local(civil)),
+    };
+    let (clock, offset) = match time.find(['Z', '+', '-']) {
+        Some(i) => (&time[..i], Some(&time[i..])),
+        None => (time, None),
+    };
+    let (clock, fraction) = match clock.split_once('.') {
+        Some((clock, fraction)) => (clock, Some(fraction)),
+        None => (clock, None),
+    };
+    let fields: Vec<&str> = clock.split(':').collect();
+    if !(2..=3).contains(&fields.len()) {
+        return None;
+    }
+    civil.hour = number(fields[0], 2).filter(|h| *h < 24)?;
+    civil.minute = number(fields[1], 2).filter(|m| *m < 60)?;
+    if let Some(second) = fields.get(2) {
+        civil.second = number(second, 2).filter(|s| *s < 60)?;
+    }
+    if let Some(fraction) = fraction {
+        let digits = format!("{:0<3}", fraction);
+        civil.millis = number(&digits[..3], 3)?;
+        if fields.len() < 3 || !fraction.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+    }
+    let offset = match offset {
+        None => return Some(Moment::Local(civil)),
+        Some("Z") => 0,
+        Some(offset) => {
+            let sign = if offset.starts_with('-') { -1 } else { 1 };
+            let digits = offset[1..].replace(':', "");
+            let hours = number(digits.get(..2)?, 2)?;
+            let minutes = number(digits.get(2..)?, 2)?;
+            sign * i64::from(hours * 60 + minutes)
+        }
+    };
+    Some(Moment::Unix(to_unix_millis(civil) - offset * 60_000))
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// Milliseconds since the epoch of a time in UTC.
+pub fn to_unix_millis(civil: Civil) -> i64 {
+    let days = days_from_civil(civil.year, civil.month, civil.day);
+    let seconds = i64::from(civil.hour * 3600 + civil.minute * 60 + civil.second);
+    (days * 86_400 + seconds) * 1000 + i64::from(civil.millis)
+}
+
+pub fn from_unix_millis(millis: i64) -> Civil {
+    let (days, day_millis) = (millis.div_euclid(86_400_000), millis.rem_euclid(86_400_000));
+    let (year, month, day) = civil_from_days(days);
+    let seconds = (day_millis / 1000) as u32;
+    Civil {
+        year,
+        month,
+        day,
+        hour: seconds / 3600,
+        minute: seconds / 60 % 60,
+        second: seconds % 60,
+        millis: (day_millis % 1000) as u32,
+    }
+}
+
+// RFC 3339 in UTC, with milliseconds only when there are some.
+pub fn format_utc(millis: i64) -> String {
+    let civil = from_unix_millis(millis);
+    let fraction = if civil.millis > 0 {
+        format!(".{:03}", civil.millis)
+    } else {
+        String::new()
+    };
+    format!(
+        "{}-{:02}-{:02}T{:02}:{:02}:{:02}{}Z",
+        civil.year, civil.month, civil.day, civil.hour, civil.minute, civil.second, fraction
+    )
+}
+
+// How far from now, negative in the past, in the largest whole unit.
+pub fn relative(seconds: i64) -> (i64, Unit) {
+    let units = [
+        (365 * 86_400, Unit::Years),
+        (30 * 86_400, Unit::Months),
+        (86_400, Unit::Days),
+        (3600, Unit::Hours),
+        (60, Unit::Minutes),
+    ];
+    units
+        .iter()
+        .find(|(size, _)| seconds.abs() >= *size)
+        .map_or((seconds, Unit::Seconds), |&(size, unit)| {
+            (seconds / size, unit)
+        })
+}
+
+// Howard Hinnant's algorithms between days since the epoch and dates.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month = i64::from(month);
+    let day_of_year =
+        (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn moment_at(text: &str, offset: usize) -> Option<(Kind, Moment)> {
+        timestamp_at(text, offset).map(|t| (t.kind, t.moment))
+    }
+
+    #[test]
+    fn test_unix() {
+        let found = timestamp_at("at 1706795109, done", 5).unwrap();
+        assert_eq!((3, 13), (found.start, found.end));
+        assert_eq!(Kind::UnixSeconds, found.kind);
+        assert_eq!(Moment::Unix(1_706_795_109_000), found.moment);
+        assert_eq!(
+            Some((Kind::UnixMillis, Moment::Unix(1_706_795_109_123))),
+            moment_at("1706795109123", 13),
+            "Just past the end"
+        );
+        assert_eq!(None, moment_at("12345", 2), "Too short");
+        assert_eq!(None, moment_at("id1706795109", 5), "Part of a word");
+    }
+
+    #[test]
+    fn test_iso() {
+        let utc = Moment::Unix(1_706_795_109_000);
+        assert_eq!(
+            Some((Kind::Iso, utc)),
+            moment_at("2024-02-01T13:45:09Z.", 3)
+        );
+        assert_eq!(utc, moment_at("(2024-02-01T14:45:09+01:00)", 10).unwrap().1);
+        assert_eq!(utc, moment_at("2024-02-01T08:15:09-0530", 0).unwrap().1);
+        assert_eq!(
+            Some(Moment::Unix(1_706_795_109_500)),
+            moment_at("2024-02-01T13:45:09.5Z", 0).map(|m| m.1)
+        );
+        let local = Civil {
+            year: 2024,
+            month: 2,
+            day: 1,
+            hour: 13,
+            minute: 45,
+            second: 0,
+            millis: 0,
+        };
+        assert_eq!(
+            Some(Moment::Local(local)),
+            moment_at("2024-02-01T13:45", 0).map(|m| m.1)
+        );
+        assert!(matches!(
+            moment_at("on 2024-02-29.", 4),
+            Some((Kind::Iso, Moment::Local(_)))
+        ));
+        assert_eq!(None, moment_at("2023-02-29", 0), "Not a leap year");
+        assert_eq!(None, moment_at("2024-13-01", 0));
+        assert_eq!(None, moment_at("1.2.3", 0));
+    }
+
+    #[test]
+    fn test_conversions() {
+        assert_eq!("1970-01-01T00:00:00Z", format_utc(0));
+        assert_eq!("2024-02-01T13:45:09.123Z", format_utc(1_706_795_109_123));
+        assert_eq!("1969-12-31T23:59:59Z", format_utc(-1000));
+        let millis = 951_827_405_000;
+        assert_eq!(millis, to_unix_millis(from_unix_millis(millis)));
+        assert_eq!("2000-02-29T12:30:05Z", format_utc(millis));
+    }
+
+    #[test]
+    fn test_relative() {
+        assert_eq!((-45, Unit::Seconds), relative(-45));
+        assert_eq!((2, Unit::Hours), relative(2 * 3600 + 59));
+        assert_eq!((-3, Unit::Days), relative(-3 * 86_400 - 10));
+        assert_eq!((1, Unit::Years), relative(400 * 86_400));
+    }
+}
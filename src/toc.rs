@@ -0,0 +1,184 @@
+// A table of contents for Markdown, kept between two comments so it can be
+// found and updated again. Anchors follow GitHub's, which most renderers
+// share.
+
+use super::markdown;
+use std::collections::HashMap;
+
+pub const START: &str = "<!-- toc -->";
+pub const END: &str = "<!-- /toc -->";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Heading {
+    pub level: u8,
+    pub title: String,
+    pub anchor: String,
+}
+
+// Headings outside code blocks, with anchors made unique as renderers do,
+// by numbering repeats.
+pub fn headings(text: &str) -> Vec<Heading> {
+    let mut headings = Vec::new();
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    let mut fence: Option<&str> = None;
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+        if let Some(marker) = fence {
+            if trimmed.starts_with(marker) {
+                fence = None;
+            }
+            continue;
+        }
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            fence = Some(&trimmed[..3]);
+            continue;
+        }
+        // Up to three spaces may come before a heading, more is code.
+        if line.len() - trimmed.len() > 3 {
+            continue;
+        }
+        if let Some((level, title)) = markdown::heading(trimmed) {
+            let title = markdown::plain_text(title);
+            let slug = slug(&title);
+            let count = seen.entry(slug.clone()).or_insert(0);
+            let anchor = match *count {
+                0 => slug,
+                n => format!("{}-{}", slug, n),
+            };
+            *count += 1;
+            headings.push(Heading {
+                level,
+                title,
+                anchor,
+            });
+        }
+    }
+    headings
+}
+
+// Lowercase, with spaces as hyphens and other punctuation removed.
+pub fn slug(title: &str) -> String {
+    title
+        .trim()
+        .to_lowercase()
+        .chars()
+        .filter_map(|c| match c {
+            ' ' => Some('-'),
+            '-' | '_' => Some(c),
+            c if c.is_alphanumeric() => Some(c),
+            _ => None,
+        })
+        .collect()
+}
+
+// A nested list of links. Depth counts levels from the document's top
+// heading, and a heading that skips levels is only nested one deeper.
+pub fn contents(headings: &[Heading], depth: u8, numbered: bool) -> String {
+    let top = match headings.iter().map(|h| h.level).min() {
+        Some(top) => top,
+        None => return String::new(),
+    };
+    let width = if numbered { 3 } else { 2 };
+    let mut counters: Vec<usize> = Vec::new();
+    let mut list = String::new();
+    for heading in headings {
+        let relative = usize::from(heading.level - top);
+        if relative >= usize::from(depth.max(1)) {
+            continue;
+        }
+        let nesting = relative.min(counters.len());
+        counters.truncate(nesting + 1);
+        if counters.len() <= nesting {
+            counters.push(0);
+        }
+        counters[nesting] += 1;
+        let marker = if numbered {
+            format!("{}.", counters[nesting])
+        } else {
+            "-".to_string()
+        };
+        list.push_str(&format!(
+            "{}{} [{}](#{})\n",
+            " ".repeat(nesting * width),
+            marker,
+            heading.title,
+            heading.anchor
+        ));
+    }
+    list
+}
+
+// The contents between the markers, to insert where there are none yet.
+pub fn block(text: &str, depth: u8, numbered: bool) -> String {
+    format!(
+        "{}\n{}{}\n",
+        START,
+        contents(&headings(text), depth, numbered),
+        END
+    )
+}
+
+// The character range of an existing block, from its start marker to the
+// end of its end marker, and what replaces it.
+pub fn update(text: &str, depth: u8, numbered: bool) -> Option<(usize, usize, String)> {
+    let start = text.find(START)?;
+    let end = start + text[start..].find(END)? + END.len();
+    let replacement = block(text, depth, numbered);
+    let replacement = replacement.trim_end_matches('\n').to_string();
+    let chars = |bytes: usize| text[..bytes].chars().count();
+    Some((chars(start), chars(end), replacement))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOCUMENT: &str = "# Guide\n\n## Getting *Started*\n\n```\n# not a heading\n```\n\n#### Deep\n\n## Getting Started\n\n### What's [new](https://example.com)?\n";
+
+    #[test]
+    fn test_headings() {
+        let anchors: Vec<String> = headings(DOCUMENT).into_iter().map(|h| h.anchor).collect();
+        assert_eq!(
+            vec![
+                "guide",
+                "getting-started",
+                "deep",
+                "getting-started-1",
+                "whats-new"
+            ],
+            anchors
+        );
+        assert_eq!("über-c-and-rust", slug("Über: C++ and Rust"));
+    }
+
+    #[test]
+    fn test_contents() {
+        let headings = headings(DOCUMENT);
+        assert_eq!(
+            "- [Guide](#guide)\n  - [Getting Started](#getting-started)\n    - [Deep](#deep)\n  - [Getting Started](#getting-started-1)\n    - [What's new?](#whats-new)\n",
+            contents(&headings, 4, false),
+            "Deep is nested one level below its parent"
+        );
+        assert_eq!(
+            "1. [Guide](#guide)\n   1. [Getting Started](#getting-started)\n   2. [Getting Started](#getting-started-1)\n",
+            contents(&headings, 2, true)
+        );
+        assert_eq!("", contents(&[], 3, false));
+    }
+
+    #[test]
+    fn test_update() {
+        let text = "# A\n\n<!-- toc -->\n- [Old](#old)\n<!-- /toc -->\n\n## B\n";
+        let (start, end, replacement) = update(text, 3, false).unwrap();
+        assert_eq!((5, 45), (start, end));
+        assert_eq!(
+            "<!-- toc -->\n- [A](#a)\n  - [B](#b)\n<!-- /toc -->",
+            replacement
+        );
+        assert_eq!(None, update("# A\n", 3, false), "No markers");
+        assert_eq!(
+            "<!-- toc -->\n<!-- /toc -->\n",
+            block("No headings", 3, false)
+        );
+    }
+}
@@ -0,0 +1,153 @@
+use std::fs::File;
+use std::io::{self, Read};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Hex,
+    Base64,
+    // Base64 with `-` and `_`, without padding, for URLs and file names.
+    Base64Url,
+}
+
+impl Encoding {
+    pub fn from_id(id: &str) -> Option<Self> {
+        match id {
+            "hex" => Some(Encoding::Hex),
+            "base64" => Some(Encoding::Base64),
+            "base64url" => Some(Encoding::Base64Url),
+            _ => None,
+        }
+    }
+}
+
+// Tokens may end up as secrets, so they come from the system's random
+// source rather than a seeded generator.
+pub fn random_bytes(count: usize) -> io::Result<Vec<u8>> {
+    let mut bytes = vec![0; count];
+    File::open("/dev/urandom")?.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+pub fn uuid() -> io::Result<String> {
+    let bytes = random_bytes(16)?;
+    let mut array = [0; 16];
+    array.copy_from_slice(&bytes);
+    Ok(uuid_v4(array))
+}
+
+pub fn ulid() -> io::Result<String> {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64);
+    let bytes = random_bytes(10)?;
+    let mut array = [0; 10];
+    array.copy_from_slice(&bytes);
+    Ok(encode_ulid(millis, array))
+}
+
+pub fn token(count: usize, encoding: Encoding) -> io::Result<String> {
+    random_bytes(count).map(|bytes| encode(&bytes, encoding))
+}
+
+pub fn uuid_v4(mut bytes: [u8; 16]) -> String {
+    bytes[6] = bytes[6] & 0x0f | 0x40;
+    bytes[8] = bytes[8] & 0x3f | 0x80;
+    let hex = encode(&bytes, Encoding::Hex);
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+// A 48 bit timestamp in milliseconds then 80 random bits, in Crockford's
+// base 32, so ULIDs sort by the time they were made.
+fn encode_ulid(millis: u64, random: [u8; 10]) -> String {
+    const ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+    let mut value = u128::from(millis & 0xffff_ffff_ffff) << 80;
+    for (i, byte) in random.iter().enumerate() {
+        value |= u128::from(*byte) << (72 - 8 * i);
+    }
+    (0..26)
+        .rev()
+        .map(|i| ALPHABET[(value >> (5 * i) & 0x1f) as usize] as char)
+        .collect()
+}
+
+fn encode(bytes: &[u8], encoding: Encoding) -> String {
+    let alphabet: &[u8; 64] = match encoding {
+        Encoding::Hex => {
+            return bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        }
+        Encoding::Base64 => b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/",
+        Encoding::Base64Url => b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_",
+    };
+    let mut text = String::new();
+    for chunk in bytes.chunks(3) {
+        let value = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |v, (i, b)| v | u32::from(*b) << (16 - 8 * i));
+        for i in 0..=chunk.len() {
+            text.push(alphabet[(value >> (18 - 6 * i) & 0x3f) as usize] as char);
+        }
+        if encoding == Encoding::Base64 {
+            text.push_str(&"=".repeat(3 - chunk.len()));
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        assert_eq!("00ff10", encode(&[0, 255, 16], Encoding::Hex));
+        assert_eq!("Zm9vYmFy", encode(b"foobar", Encoding::Base64));
+        assert_eq!("Zm9vYg==", encode(b"foob", Encoding::Base64));
+        assert_eq!("Zm8=", encode(b"fo", Encoding::Base64));
+        assert_eq!(
+            "-_8",
+            encode(&[0xfb, 0xff], Encoding::Base64Url),
+            "No padding"
+        );
+        assert_eq!("", encode(&[], Encoding::Base64));
+    }
+
+    #[test]
+    fn test_uuid_v4() {
+        assert_eq!(
+            "ffffffff-ffff-4fff-bfff-ffffffffffff",
+            uuid_v4([0xff; 16]),
+            "Version and variant bits"
+        );
+        assert_eq!("00000000-0000-4000-8000-000000000000", uuid_v4([0; 16]));
+    }
+
+    #[test]
+    fn test_ulid() {
+        assert_eq!("00000000000000000000000000", encode_ulid(0, [0; 10]));
+        assert_eq!(
+            "01ARZ3NDEKZZZZZZZZZZZZZZZZ",
+            encode_ulid(1_469_922_850_259, [0xff; 10])
+        );
+        assert!(
+            encode_ulid(2, [0; 10]) > encode_ulid(1, [0xff; 10]),
+            "Sorted by time"
+        );
+    }
+
+    #[test]
+    fn test_random() {
+        assert_eq!(64, token(32, Encoding::Hex).unwrap().len());
+        assert_eq!(43, token(32, Encoding::Base64Url).unwrap().len());
+        assert_ne!(uuid().unwrap(), uuid().unwrap());
+        assert_eq!(26, ulid().unwrap().len());
+    }
+}
@@ -0,0 +1,246 @@
+use super::text_ops::{self, Case, SortOptions};
+use gettextrs::gettext;
+use std::fmt;
+
+// What a transform is given.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    // The selected text, which stays selected. Nothing happens without one.
+    Selection,
+    // The whole lines the selection touches, or every line without one.
+    Lines,
+}
+
+// Whether the text was selected, or is the whole document.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Selection {
+    Selected,
+    Document,
+}
+
+// Where a transform is listed in the menu.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Menu {
+    ChangeCase,
+    Tools,
+}
+
+// An edit command that rewrites text. Each runs as the window action named
+// by its id, so ids stay the same once shortcuts and menus use them.
+pub trait TextTransform {
+    fn id(&self) -> &'static str;
+    fn name(&self) -> String;
+    // A default shortcut, as GTK writes accelerators.
+    fn accel(&self) -> Option<&'static str> {
+        None
+    }
+    fn scope(&self) -> Scope {
+        Scope::Lines
+    }
+    fn menu(&self) -> Menu {
+        Menu::Tools
+    }
+    fn apply(&self, text: &str, selection: Selection) -> String;
+}
+
+type Rewrite = fn(&str) -> String;
+
+// Most transforms work on whole lines and need nothing else.
+struct Lines {
+    id: &'static str,
+    name: fn() -> String,
+    apply: Rewrite,
+}
+
+impl TextTransform for Lines {
+    fn id(&self) -> &'static str {
+        self.id
+    }
+
+    fn name(&self) -> String {
+        (self.name)()
+    }
+
+    fn apply(&self, text: &str, _: Selection) -> String {
+        (self.apply)(text)
+    }
+}
+
+struct ChangeCase {
+    id: &'static str,
+    name: fn() -> String,
+    case: Case,
+}
+
+impl TextTransform for ChangeCase {
+    fn id(&self) -> &'static str {
+        self.id
+    }
+
+    fn name(&self) -> String {
+        (self.name)()
+    }
+
+    fn scope(&self) -> Scope {
+        Scope::Selection
+    }
+
+    fn menu(&self) -> Menu {
+        Menu::ChangeCase
+    }
+
+    fn apply(&self, text: &str, _: Selection) -> String {
+        text_ops::change_case(text, self.case)
+    }
+}
+
+// A built-in transform's id, name and what it does.
+type Builtin<T> = (&'static str, fn() -> String, T);
+
+// The transforms, in the order the menu lists them.
+pub struct Registry(Vec<Box<dyn TextTransform>>);
+
+impl Registry {
+    pub fn builtin() -> Self {
+        let mut registry = Self(Vec::new());
+        let cases: [Builtin<Case>; 4] = [
+            ("uppercase", || gettext("UPPERCASE"), Case::Upper),
+            ("lowercase", || gettext("lowercase"), Case::Lower),
+            ("title-case", || gettext("Title Case"), Case::Title),
+            ("toggle-case", || gettext("Toggle Case"), Case::Toggle),
+        ];
+        for &(id, name, case) in cases.iter() {
+            registry.push(ChangeCase { id, name, case });
+        }
+        let lines: [Builtin<Rewrite>; 7] = [
+            (
+                "sort-lines-ascending",
+                || gettext("Sort Lines"),
+                |text| text_ops::sort_lines(text, &SortOptions::default()),
+            ),
+            (
+                "sort-lines-ignore-case",
+                || gettext("Sort Lines (Case-Insensitive)"),
+                |text| {
+                    let options = SortOptions {
+                        ignore_case: true,
+                        ..SortOptions::default()
+                    };
+                    text_ops::sort_lines(text, &options)
+                },
+            ),
+            (
+                "reverse-lines",
+                || gettext("Reverse Lines"),
+                text_ops::reverse_lines,
+            ),
+            (
+                "remove-duplicate-lines",
+                || gettext("Remove Duplicate Lines"),
+                text_ops::remove_duplicate_lines,
+            ),
+            (
+                "strip-numbering",
+                || gettext("Remove Line Numbers"),
+                text_ops::strip_numbering,
+            ),
+            (
+                "quote-lines",
+                || gettext("Quote Lines"),
+                text_ops::quote_lines,
+            ),
+            (
+                "unquote-lines",
+                || gettext("Unquote Lines"),
+                text_ops::unquote_lines,
+            ),
+        ];
+        for &(id, name, apply) in lines.iter() {
+            registry.push(Lines { id, name, apply });
+        }
+        registry
+    }
+
+    fn push(&mut self, transform: impl TextTransform + 'static) {
+        self.register(Box::new(transform))
+            .expect("Built-in transforms have their own ids");
+    }
+
+    // Adds a transform after the others. Ids are unique, as they name
+    // actions.
+    pub fn register(&mut self, transform: Box<dyn TextTransform>) -> Result<(), String> {
+        if self.find(transform.id()).is_some() {
+            return Err(format!("A transform is already named {}", transform.id()));
+        }
+        self.0.push(transform);
+        Ok(())
+    }
+
+    pub fn find(&self, id: &str) -> Option<&dyn TextTransform> {
+        self.iter().find(|transform| transform.id() == id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &dyn TextTransform> {
+        self.0.iter().map(|transform| transform.as_ref())
+    }
+
+    pub fn in_menu(&self, menu: Menu) -> impl Iterator<Item = &dyn TextTransform> {
+        self.iter()
+            .filter(move |transform| transform.menu() == menu)
+    }
+}
+
+impl fmt::Debug for Registry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.iter().map(|transform| transform.id()))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Shout;
+
+    impl TextTransform for Shout {
+        fn id(&self) -> &'static str {
+            "shout"
+        }
+
+        fn name(&self) -> String {
+            "Shout".into()
+        }
+
+        fn apply(&self, text: &str, selection: Selection) -> String {
+            match selection {
+                Selection::Selected => text.to_uppercase() + "!",
+                Selection::Document => text.to_uppercase(),
+            }
+        }
+    }
+
+    #[test]
+    fn test_registry() {
+        let mut registry = Registry::builtin();
+        let reverse = registry.find("reverse-lines").unwrap();
+        assert_eq!("b\na\n", reverse.apply("a\nb\n", Selection::Document));
+        assert_eq!(Scope::Lines, reverse.scope());
+        let upper = registry.find("uppercase").unwrap();
+        assert_eq!("ABC", upper.apply("abc", Selection::Selected));
+        assert_eq!(Scope::Selection, upper.scope());
+        assert_eq!(4, registry.in_menu(Menu::ChangeCase).count());
+
+        registry.register(Box::new(Shout)).unwrap();
+        assert_eq!(Some("shout"), registry.iter().last().map(|t| t.id()));
+        assert_eq!(
+            "HI!",
+            registry
+                .find("shout")
+                .unwrap()
+                .apply("hi", Selection::Selected)
+        );
+        assert!(registry.register(Box::new(Shout)).is_err(), "Same id");
+    }
+}
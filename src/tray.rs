@@ -0,0 +1,421 @@
+use crate::gio;
+use crate::glib;
+use gettextrs::gettext;
+use glib::{Continue, StaticVariantType, ToVariant, Variant, VariantTy};
+use log::{info, warn};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Mutex;
+
+// The StatusNotifierItem and its menu, as desktops with a tray look for
+// them. Desktops without one have no watcher, and the icon is left out.
+const ITEM_PATH: &str = "/StatusNotifierItem";
+const MENU_PATH: &str = "/MenuBar";
+const ITEM_INTERFACE: &str = "org.kde.StatusNotifierItem";
+const MENU_INTERFACE: &str = "com.canonical.dbusmenu";
+const WATCHER_NAME: &str = "org.kde.StatusNotifierWatcher";
+const WATCHER_PATH: &str = "/StatusNotifierWatcher";
+// Recent documents get ids from here, after the fixed entries.
+const RECENT_ID: i32 = 100;
+
+const INTERFACES: &str = r#"<node>
+  <interface name="org.kde.StatusNotifierItem">
+    <property name="Category" type="s" access="read"/>
+    <property name="Id" type="s" access="read"/>
+    <property name="Title" type="s" access="read"/>
+    <property name="Status" type="s" access="read"/>
+    <property name="IconName" type="s" access="read"/>
+    <property name="ItemIsMenu" type="b" access="read"/>
+    <property name="Menu" type="o" access="read"/>
+    <method name="Activate">
+      <arg name="x" type="i" direction="in"/>
+      <arg name="y" type="i" direction="in"/>
+    </method>
+    <method name="SecondaryActivate">
+      <arg name="x" type="i" direction="in"/>
+      <arg name="y" type="i" direction="in"/>
+    </method>
+    <method name="ContextMenu">
+      <arg name="x" type="i" direction="in"/>
+      <arg name="y" type="i" direction="in"/>
+    </method>
+    <method name="Scroll">
+      <arg name="delta" type="i" direction="in"/>
+      <arg name="orientation" type="s" direction="in"/>
+    </method>
+  </interface>
+  <interface name="com.canonical.dbusmenu">
+    <property name="Version" type="u" access="read"/>
+    <property name="TextDirection" type="s" access="read"/>
+    <property name="Status" type="s" access="read"/>
+    <property name="IconThemePath" type="as" access="read"/>
+    <method name="GetLayout">
+      <arg name="parentId" type="i" direction="in"/>
+      <arg name="recursionDepth" type="i" direction="in"/>
+      <arg name="propertyNames" type="as" direction="in"/>
+      <arg name="revision" type="u" direction="out"/>
+      <arg name="layout" type="(ia{sv}av)" direction="out"/>
+    </method>
+    <method name="GetGroupProperties">
+      <arg name="ids" type="ai" direction="in"/>
+      <arg name="propertyNames" type="as" direction="in"/>
+      <arg name="properties" type="a(ia{sv})" direction="out"/>
+    </method>
+    <method name="Event">
+      <arg name="id" type="i" direction="in"/>
+      <arg name="eventId" type="s" direction="in"/>
+      <arg name="data" type="v" direction="in"/>
+      <arg name="timestamp" type="u" direction="in"/>
+    </method>
+    <method name="AboutToShow">
+      <arg name="id" type="i" direction="in"/>
+      <arg name="needUpdate" type="b" direction="out"/>
+    </method>
+    <signal name="LayoutUpdated">
+      <arg name="revision" type="u"/>
+      <arg name="parent" type="i"/>
+    </signal>
+  </interface>
+</node>"#;
+
+// What a tray menu entry does.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    ShowWindow,
+    NewNote,
+    OpenRecent(PathBuf),
+    Quit,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub id: i32,
+    // Empty for a separator.
+    pub label: String,
+    pub command: Option<Command>,
+    pub children: Vec<Entry>,
+}
+
+impl Entry {
+    fn new(id: i32, label: String, command: Option<Command>) -> Self {
+        Self {
+            id,
+            label,
+            command,
+            children: Vec::new(),
+        }
+    }
+
+    pub fn find(&self, id: i32) -> Option<&Entry> {
+        if self.id == id {
+            return Some(self);
+        }
+        self.children.iter().find_map(|child| child.find(id))
+    }
+
+    fn properties(&self) -> HashMap<String, Variant> {
+        let mut properties = HashMap::new();
+        if self.id == 0 {
+            properties.insert("children-display".into(), "submenu".to_variant());
+        } else if self.label.is_empty() {
+            properties.insert("type".into(), "separator".to_variant());
+        } else {
+            properties.insert("label".into(), self.label.to_variant());
+            let enabled = self.command.is_some() || !self.children.is_empty();
+            properties.insert("enabled".into(), enabled.to_variant());
+            if !self.children.is_empty() {
+                properties.insert("children-display".into(), "submenu".to_variant());
+            }
+        }
+        properties
+    }
+
+    // As GetLayout returns it, `(ia{sv}av)`, to `depth` levels below the
+    // entry. A negative depth has no limit.
+    pub fn layout(&self, depth: i32) -> Variant {
+        let children: Vec<Variant> = if depth == 0 {
+            Vec::new()
+        } else {
+            self.children
+                .iter()
+                .map(|child| child.layout(depth - 1))
+                .collect()
+        };
+        Variant::from_tuple(&[
+            self.id.to_variant(),
+            self.properties().to_variant(),
+            children.to_variant(),
+        ])
+    }
+}
+
+// The menu as it is now. Open Recent lists the documents by name, and is
+// disabled without any.
+pub fn menu(recent: &[PathBuf]) -> Entry {
+    let mut open_recent = Entry::new(3, gettext("Open Recent"), None);
+    open_recent.children = recent
+        .iter()
+        .zip(RECENT_ID..)
+        .map(|(path, id)| {
+            let name = path
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_else(|| path.to_string_lossy().to_string());
+            Entry::new(id, name, Some(Command::OpenRecent(path.clone())))
+        })
+        .collect();
+    let mut root = Entry::new(0, String::new(), None);
+    root.children = vec![
+        Entry::new(1, gettext("Show Window"), Some(Command::ShowWindow)),
+        Entry::new(2, gettext("New Note"), Some(Command::NewNote)),
+        open_recent,
+        Entry::new(4, String::new(), None),
+        Entry::new(5, gettext("Quit"), Some(Command::Quit)),
+    ];
+    root
+}
+
+// The tray icon while it is shown. Dropping it takes the icon away.
+#[derive(Debug)]
+pub struct Tray {
+    connection: gio::DBusConnection,
+    registrations: Vec<gio::RegistrationId>,
+}
+
+impl Tray {
+    // `recent` lists the documents for Open Recent each time the menu
+    // opens. `failed` is called instead of showing the icon when there is
+    // no tray to show it in.
+    // gio wants D-Bus handlers that may be called from any thread, so they
+    // keep their state behind locks and send commands back to the main
+    // context, where `command` and `failed` are called.
+    pub fn start<R, C, F>(
+        connection: &gio::DBusConnection,
+        icon_name: &str,
+        recent: R,
+        command: C,
+        failed: F,
+    ) -> Result<Self, glib::Error>
+    where
+        R: Fn() -> Vec<PathBuf> + Send + Sync + 'static,
+        C: Fn(Command) + 'static,
+        F: FnOnce() + 'static,
+    {
+        let node = gio::DBusNodeInfo::for_xml(INTERFACES)?;
+        let interface = |name: &str| {
+            node.lookup_interface(name).ok_or_else(|| {
+                glib::Error::new(
+                    gio::IOErrorEnum::NotFound,
+                    &format!("No {} interface", name),
+                )
+            })
+        };
+        let (sender, receiver) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+        receiver.attach(None, move |chosen| {
+            command(chosen);
+            Continue(true)
+        });
+        let mut tray = Self {
+            connection: connection.clone(),
+            registrations: Vec::new(),
+        };
+
+        let icon_name = icon_name.to_string();
+        let activate = Mutex::new(sender.clone());
+        tray.registrations.push(connection.register_object(
+            ITEM_PATH,
+            &interface(ITEM_INTERFACE)?,
+            move |_, _, _, _, method, _, invocation| {
+                if method == "Activate" {
+                    activate.lock().unwrap().send(Command::ShowWindow).ok();
+                }
+                invocation.return_value(None);
+            },
+            move |_, _, _, _, property| match property {
+                "Category" => "ApplicationStatus".to_variant(),
+                "Id" | "IconName" => icon_name.to_variant(),
+                "Title" => gettext("TextEdit 2").to_variant(),
+                "Status" => "Active".to_variant(),
+                "ItemIsMenu" => false.to_variant(),
+                "Menu" => menu_path(),
+                _ => "".to_variant(),
+            },
+            |_, _, _, _, _, _| false,
+        )?);
+
+        // The menu is built again each time it is asked for, so the recent
+        // documents are current, and events act on what was shown.
+        let shown = Mutex::new(menu(&recent()));
+        let revision = AtomicU32::new(1);
+        let chosen = Mutex::new(sender);
+        tray.registrations.push(connection.register_object(
+            MENU_PATH,
+            &interface(MENU_INTERFACE)?,
+            move |_, _, _, _, method, parameters, invocation| match method {
+                "GetLayout" => {
+                    let (parent, depth, _) = parameters
+                        .get::<(i32, i32, Vec<String>)>()
+                        .unwrap_or((0, -1, Vec::new()));
+                    let mut shown = shown.lock().unwrap();
+                    if parent == 0 {
+                        *shown = menu(&recent());
+                        revision.fetch_add(1, Ordering::Relaxed);
+                    }
+                    let layout = match shown.find(parent) {
+                        Some(entry) => entry.layout(depth),
+                        None => shown.layout(depth),
+                    };
+                    invocation.return_value(Some(&Variant::from_tuple(&[
+                        revision.load(Ordering::Relaxed).to_variant(),
+                        layout,
+                    ])));
+                }
+                "GetGroupProperties" => {
+                    let ids = parameters
+                        .get::<(Vec<i32>, Vec<String>)>()
+                        .map(|(ids, _)| ids)
+                        .unwrap_or_default();
+                    let shown = shown.lock().unwrap();
+                    let properties: Vec<Variant> = ids
+                        .iter()
+                        .filter_map(|id| shown.find(*id))
+                        .map(|entry| {
+                            Variant::from_tuple(&[
+                                entry.id.to_variant(),
+                                entry.properties().to_variant(),
+                            ])
+                        })
+                        .collect();
+                    let properties =
+                        Variant::from_array::<(i32, HashMap<String, Variant>)>(&properties);
+                    invocation.return_value(Some(&Variant::from_tuple(&[properties])));
+                }
+                "Event" => {
+                    let event = parameters.get::<(i32, String, Variant, u32)>();
+                    let clicked = match event {
+                        Some((id, event, _, _)) if event == "clicked" => shown
+                            .lock()
+                            .unwrap()
+                            .find(id)
+                            .and_then(|entry| entry.command.clone()),
+                        _ => None,
+                    };
+                    invocation.return_value(None);
+                    if let Some(clicked) = clicked {
+                        chosen.lock().unwrap().send(clicked).ok();
+                    }
+                }
+                "AboutToShow" => {
+                    invocation.return_value(Some(&(true,).to_variant()));
+                }
+                _ => invocation.return_value(None),
+            },
+            |_, _, _, _, property| match property {
+                "Version" => 3u32.to_variant(),
+                "TextDirection" => "ltr".to_variant(),
+                "Status" => "normal".to_variant(),
+                "IconThemePath" => Vec::<String>::new().to_variant(),
+                _ => "".to_variant(),
+            },
+            |_, _, _, _, _, _| false,
+        )?);
+
+        let name = connection
+            .unique_name()
+            .map(|name| name.to_string())
+            .unwrap_or_default();
+        let registered = connection.call_future(
+            Some(WATCHER_NAME),
+            WATCHER_PATH,
+            WATCHER_NAME,
+            "RegisterStatusNotifierItem",
+            Some(&(name,).to_variant()),
+            None,
+            gio::DBusCallFlags::NONE,
+            -1,
+        );
+        glib::MainContext::ref_thread_default().spawn_local(async move {
+            if let Err(e) = registered.await {
+                info!("No system tray to show the icon in, {}", e);
+                failed();
+            }
+        });
+        Ok(tray)
+    }
+}
+
+impl Drop for Tray {
+    fn drop(&mut self) {
+        for registration in self.registrations.drain(..) {
+            if let Err(e) = self.connection.unregister_object(registration) {
+                warn!("Failed to remove the tray icon, {}", e);
+            }
+        }
+    }
+}
+
+// glib has no Rust type for object paths, so the menu's is built from its
+// serialized form, the path and a nul.
+struct ObjectPath;
+
+impl StaticVariantType for ObjectPath {
+    fn static_variant_type() -> Cow<'static, VariantTy> {
+        Cow::Borrowed(VariantTy::new("o").expect("Object paths have a type"))
+    }
+}
+
+fn menu_path() -> Variant {
+    let path = format!("{}\0", MENU_PATH);
+    Variant::from_bytes::<ObjectPath>(&glib::Bytes::from_owned(path.into_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_menu() {
+        let recent = vec![PathBuf::from("/notes/a.md"), PathBuf::from("/b.txt")];
+        let menu = menu(&recent);
+        assert_eq!(
+            Some(&Command::OpenRecent(PathBuf::from("/b.txt"))),
+            menu.find(RECENT_ID + 1)
+                .and_then(|entry| entry.command.as_ref())
+        );
+        assert_eq!("a.md", menu.find(RECENT_ID).unwrap().label);
+        assert_eq!(None, menu.find(RECENT_ID + 2));
+        assert_eq!(
+            Some(&Command::ShowWindow),
+            menu.find(1).unwrap().command.as_ref()
+        );
+        assert_eq!(
+            Some(&false.to_variant()),
+            super::menu(&[])
+                .find(3)
+                .unwrap()
+                .properties()
+                .get("enabled"),
+            "Open Recent without any"
+        );
+    }
+
+    #[test]
+    fn test_layout() {
+        let menu = menu(&[PathBuf::from("/a.txt")]);
+        let layout = menu.layout(-1);
+        assert_eq!("(ia{sv}av)", layout.type_().to_str());
+        assert_eq!(5, layout.child_value(2).n_children());
+        assert_eq!(0, menu.layout(0).child_value(2).n_children(), "No depth");
+        let separator = menu.find(4).unwrap().properties();
+        assert_eq!(Some(&"separator".to_variant()), separator.get("type"));
+    }
+
+    #[test]
+    fn test_menu_path() {
+        let path = menu_path();
+        assert_eq!("o", path.type_().to_str());
+        assert_eq!(Some(MENU_PATH), path.str());
+    }
+}
@@ -0,0 +1,232 @@
+use super::settings::VERSION as SETTINGS_VERSION;
+use super::settings_archive::{self, Archive};
+use crate::gio;
+use crate::glib;
+use gio::prelude::*;
+use glib::ToVariant;
+use log::{info, warn};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+// Settings and the files in the configuration directory are upgraded one
+// version at a time, from the one they were last used with.
+pub const VERSION: u32 = 1;
+const BACKUPS_DIR: &str = "backups";
+
+// A change to how settings or state files are kept. No upgrade renames a
+// key or moves a file yet, but later versions will.
+#[derive(Debug, Clone, Copy)]
+#[allow(dead_code)]
+pub enum Change {
+    // The value moves to a key of the same type.
+    Rename(&'static str, &'static str),
+    // The value is rewritten, or reset when there is none.
+    Convert(&'static str, fn(&glib::Variant) -> Option<glib::Variant>),
+    // A file in the configuration directory moves.
+    MoveFile(&'static str, &'static str),
+}
+
+// The changes from each version to the next, starting from 0.
+const UPGRADES: [&[Change]; 1] = [&[Change::Convert("new-document-extension", bare_extension)]];
+
+// Extensions were once entered with their dot, as in `.md`.
+fn bare_extension(value: &glib::Variant) -> Option<glib::Variant> {
+    let extension = value.str()?.trim().trim_start_matches('.');
+    if extension.is_empty() {
+        None
+    } else {
+        Some(extension.to_variant())
+    }
+}
+
+// The changes since `version`, in the order they are made.
+pub fn pending(version: u32) -> Vec<Change> {
+    UPGRADES
+        .iter()
+        .skip(version as usize)
+        .flat_map(|changes| changes.iter().copied())
+        .collect()
+}
+
+// Changes the values the user set, by key. Keys left without a value go
+// back to their defaults.
+pub fn upgrade_values(values: &mut BTreeMap<String, glib::Variant>, changes: &[Change]) {
+    for change in changes {
+        match *change {
+            Change::Rename(from, to) => {
+                if let Some(value) = values.remove(from) {
+                    values.insert(to.to_string(), value);
+                }
+            }
+            Change::Convert(key, convert) => {
+                if let Some(value) = values.remove(key) {
+                    if let Some(converted) = convert(&value) {
+                        values.insert(key.to_string(), converted);
+                    }
+                }
+            }
+            Change::MoveFile(..) => (),
+        }
+    }
+}
+
+// Upgrades the settings from the version they were last used with. They
+// are backed up first, to an archive that Import Settings can read, and
+// written together, so a crash leaves them as they were.
+pub fn run(schema_id: &str, config_dir: &Path) -> Result<(), String> {
+    // Delayed settings stay delayed, so this takes its own.
+    let settings = gio::Settings::new(schema_id);
+    let version = settings.uint(SETTINGS_VERSION);
+    // Settings from a newer version are left for it.
+    if version >= VERSION {
+        return Ok(());
+    }
+    let changes = pending(version);
+    let keys: Vec<String> = settings
+        .settings_schema()
+        .ok_or("The settings schema is missing")?
+        .list_keys()
+        .iter()
+        .map(|key| key.to_string())
+        .collect();
+    let before: BTreeMap<String, glib::Variant> = keys
+        .iter()
+        .filter_map(|key| Some((key.clone(), settings.user_value(key)?)))
+        .collect();
+    let mut after = before.clone();
+    upgrade_values(&mut after, &changes);
+    if after != before {
+        backup(&before, config_dir, version)?;
+    }
+
+    settings.delay();
+    let changed = keys.iter().try_for_each(|key| {
+        match (before.get(key), after.get(key)) {
+            (Some(old), Some(new)) if old == new => Ok(()),
+            (_, Some(new)) => settings.set_value(key, new),
+            (Some(_), None) => {
+                settings.reset(key);
+                Ok(())
+            }
+            (None, None) => Ok(()),
+        }
+        .map_err(|e| format!("{}: {}", key, e))
+    });
+    let changed = changed.and_then(|_| {
+        settings
+            .set_uint(SETTINGS_VERSION, VERSION)
+            .map_err(|e| e.to_string())
+    });
+    if let Err(e) = changed {
+        settings.revert();
+        return Err(e);
+    }
+    settings.apply();
+    gio::Settings::sync();
+
+    for change in &changes {
+        if let Change::MoveFile(from, to) = *change {
+            move_file(&config_dir.join(from), &config_dir.join(to));
+        }
+    }
+    info!(
+        "Upgraded the settings from version {} to {}",
+        version, VERSION
+    );
+    Ok(())
+}
+
+// Written beside its final name and then renamed, so a backup is never
+// left half written.
+fn backup(
+    values: &BTreeMap<String, glib::Variant>,
+    config_dir: &Path,
+    version: u32,
+) -> Result<(), String> {
+    let archive = Archive {
+        version: settings_archive::VERSION,
+        settings: values
+            .iter()
+            .filter_map(|(key, value)| {
+                settings_archive::to_toml(value).map(|value| (key.clone(), value))
+            })
+            .collect(),
+        files: BTreeMap::new(),
+    };
+    let text = settings_archive::to_string(&archive).map_err(|e| e.to_string())?;
+    let dir = config_dir.join(BACKUPS_DIR);
+    let path = dir.join(format!("settings-v{}.toml", version));
+    let partial = path.with_extension("toml.partial");
+    fs::create_dir_all(&dir)
+        .and_then(|_| fs::write(&partial, text))
+        .and_then(|_| fs::rename(&partial, &path))
+        .map_err(|e| format!("{:?}: {}", path, e))
+}
+
+// Files that were already moved, or that the user has since replaced, are
+// left where they are.
+fn move_file(from: &Path, to: &Path) {
+    if !from.exists() || to.exists() {
+        return;
+    }
+    let moved = match to.parent() {
+        Some(dir) => fs::create_dir_all(dir),
+        None => Ok(()),
+    }
+    .and_then(|_| fs::rename(from, to));
+    if let Err(e) = moved {
+        warn!("Failed to move {:?} to {:?}, {}", from, to, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lowercase(value: &glib::Variant) -> Option<glib::Variant> {
+        Some(value.str()?.to_lowercase().to_variant())
+    }
+
+    #[test]
+    fn test_upgrade_values() {
+        let mut values = BTreeMap::new();
+        values.insert("old-font".to_string(), "Monospace 11".to_variant());
+        values.insert("scheme".to_string(), "Dark".to_variant());
+        values.insert("new-document-extension".to_string(), " .md".to_variant());
+        upgrade_values(
+            &mut values,
+            &[
+                Change::Rename("old-font", "font"),
+                Change::Rename("missing", "tab-width"),
+                Change::Convert("scheme", lowercase),
+                Change::Convert("new-document-extension", bare_extension),
+            ],
+        );
+        assert_eq!(Some(&"Monospace 11".to_variant()), values.get("font"));
+        assert!(!values.contains_key("old-font"));
+        assert!(!values.contains_key("tab-width"), "Only set values move");
+        assert_eq!(Some(&"dark".to_variant()), values.get("scheme"));
+        assert_eq!(
+            Some(&"md".to_variant()),
+            values.get("new-document-extension")
+        );
+    }
+
+    #[test]
+    fn test_bare_extension() {
+        assert_eq!(
+            Some("txt".to_variant()),
+            bare_extension(&"txt".to_variant())
+        );
+        assert_eq!(None, bare_extension(&".".to_variant()), "Reset");
+        assert_eq!(None, bare_extension(&8.to_variant()), "Not a string");
+    }
+
+    #[test]
+    fn test_pending() {
+        assert_eq!(UPGRADES[0].len(), pending(0).len());
+        assert!(pending(VERSION).is_empty());
+        assert!(pending(VERSION + 1).is_empty());
+    }
+}
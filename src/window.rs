@@ -1,19 +1,95 @@
 use gettextrs::*;
+use glib::{clone, Continue};
 use gtk::prelude::*;
 use gtk::subclass::prelude::*;
-use gtk::{gio, glib};
-use log::debug;
+use gtk::{gdk, gio, glib, pango};
+use log::{debug, warn};
+use regex::Regex;
+use ropey::Rope;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use super::actions::Action;
-use super::actions::Action::DocumentChanged;
+use super::actions::Action::{
+    CancelFindInFiles, CancelOpen, CompareFile, KeepFile, MergeFile, OpenFile, OpenFileAs,
+    OpenLargeFile, ReloadFile, ReplaceInFile, SaveFile, TextDeleted, TextInserted, UserActionEnded,
+    UserActionStarted, ViewMoved,
+};
 use crate::glib::Sender;
 
-use super::application_model::{ApplicationModel, Changes, StatusMessage};
+use super::analysis::{self, Duplicate, TermCount};
+use super::application_model::{
+    config_dir, ApplicationModel, Changes, Notification, NotificationAction, Severity,
+    StatusMessage, Toast, LABEL_COLORS,
+};
+use super::banner;
+use super::charmap;
+use super::colors::{self, ColorLiteral, Notation, Rgba};
+use super::comment;
+use super::diff::{self, DiffLine};
+use super::document::{Document, OpenMode};
+use super::error::{Error, IoKind};
+use super::file_system::{FileSystem, CHUNK_SIZE};
+use super::indent;
+use super::invisible::{self, Invisible};
+use super::language;
+use super::locale::{self, Conversion, LocaleFormat, LOCALES};
+use super::macros::{self, Macro, MoveUnit, Step};
+use super::markdown;
+use super::normalize::{self, Confusable, Form, FORMS};
+use super::paste;
+use super::patch::{self, Hunk};
+use super::placeholder;
+use super::position::Position;
+use super::reading;
+use super::recent;
+use super::regex_tester::{self, MATCH_LIMIT};
+use super::sandbox;
+use super::selection;
+use super::spell::{self, Dictionary};
+use super::text_box::{self, BoxStyle, Table};
+use super::text_ops::{self, Numbering, SortOptions, SortOrder};
+use super::timestamp::{self, Moment, Timestamp, Unit};
+use super::toc;
+use super::token;
+use super::transforms::{self, Registry, Scope, TextTransform};
+use super::workspace::{FileMatches, Query};
 use crate::application::Application;
 use crate::config::{APP_ID, PROFILE};
 
+const TOAST_SECONDS: u32 = 3;
+// Longer folders in the title are shortened, in characters.
+const TITLE_DIR_CHARS: usize = 40;
+const PINNED_CHARACTERS: &str = "pinned-characters";
+const MERGE_RESPONSE: gtk::ResponseType = gtk::ResponseType::Other(1);
+const PATCH_FILE_RESPONSE: gtk::ResponseType = gtk::ResponseType::Other(2);
+const SPELL_CHECK_DELAY: Duration = Duration::from_millis(500);
+const MAX_SUGGESTIONS: usize = 8;
+const COLOR_PREVIEW_DELAY: Duration = Duration::from_millis(300);
+// Larger documents are not scanned for colors, in characters.
+const COLOR_PREVIEW_LIMIT: i32 = 1 << 20;
+// How close to the top edge the pointer reveals the header when fullscreen.
+const HEADER_REVEAL_DISTANCE: f64 = 8.0;
+const PREVIEW_DELAY: Duration = Duration::from_millis(300);
+// Characters in a line of reading mode's column, and pixels between lines.
+const READING_COLUMN: usize = 70;
+const LANGUAGE_ASSOCIATIONS: &str = "language-associations";
+const READING_LINE_SPACING: i32 = 6;
+const READING_MIN_MARGIN: i32 = 12;
+// Offered in the format dialog, any other format can be typed.
+const DATETIME_FORMATS: [&str; 5] = ["%Y-%m-%d %H:%M", "%Y-%m-%d", "%x", "%c", "%A, %e %B %Y"];
+// Capture groups cycle through these in the regex tester.
+const GROUP_COLORS: [&str; 3] = ["#99c1f1", "#8ff0a4", "#dc8add"];
+
 mod imp {
     use super::*;
+    use once_cell::sync::OnceCell;
+    use std::cell::{Cell, RefCell};
 
     use gtk::CompositeTemplate;
 
@@ -25,9 +101,39 @@ mod imp {
         #[template_child]
         pub modified: TemplateChild<gtk::Label>,
         #[template_child]
+        pub subtitle: TemplateChild<gtk::Label>,
+        #[template_child]
+        pub label_dot: TemplateChild<gtk::Label>,
+        #[template_child]
+        pub mode_badge: TemplateChild<gtk::Label>,
+        #[template_child]
         pub headerbar: TemplateChild<gtk::HeaderBar>,
         #[template_child]
+        pub header_revealer: TemplateChild<gtk::Revealer>,
+        #[template_child]
+        pub preview_scroll: TemplateChild<gtk::ScrolledWindow>,
+        #[template_child]
+        pub preview: TemplateChild<gtk::TextView>,
+        #[template_child]
+        pub switcher: TemplateChild<gtk::Frame>,
+        #[template_child]
+        pub switcher_list: TemplateChild<gtk::ListBox>,
+        #[template_child]
+        pub appmenu_button: TemplateChild<gtk::MenuButton>,
+        #[template_child]
+        pub templates_menu: TemplateChild<gio::Menu>,
+        #[template_child]
+        pub case_menu: TemplateChild<gio::Menu>,
+        #[template_child]
+        pub lines_menu: TemplateChild<gio::Menu>,
+        #[template_child]
         pub bodytext: TemplateChild<gtk::TextView>,
+        #[template_child]
+        pub split_paned: TemplateChild<gtk::Paned>,
+        #[template_child]
+        pub split_scroll: TemplateChild<gtk::ScrolledWindow>,
+        #[template_child]
+        pub split_view: TemplateChild<gtk::TextView>,
         pub settings: gio::Settings,
         #[template_child]
         pub save_button: TemplateChild<gtk::Button>,
@@ -35,6 +141,107 @@ mod imp {
         pub open_button: TemplateChild<gtk::Button>,
         #[template_child]
         pub status_bar: TemplateChild<gtk::Label>,
+        #[template_child]
+        pub reading_progress: TemplateChild<gtk::Label>,
+        #[template_child]
+        pub language_button: TemplateChild<gtk::MenuButton>,
+        #[template_child]
+        pub external_change_bar: TemplateChild<gtk::InfoBar>,
+        #[template_child]
+        pub external_change_label: TemplateChild<gtk::Label>,
+        #[template_child]
+        pub large_file_bar: TemplateChild<gtk::InfoBar>,
+        #[template_child]
+        pub large_file_label: TemplateChild<gtk::Label>,
+        #[template_child]
+        pub notification_bar: TemplateChild<gtk::InfoBar>,
+        #[template_child]
+        pub notification_label: TemplateChild<gtk::Label>,
+        // The notification shown, and the buttons added for its actions.
+        pub notification: RefCell<Option<Notification>>,
+        pub notification_buttons: RefCell<Vec<gtk::Button>>,
+        #[template_child]
+        pub open_progress: TemplateChild<gtk::ProgressBar>,
+        #[template_child]
+        pub cancel_open_button: TemplateChild<gtk::Button>,
+        #[template_child]
+        pub error_details_button: TemplateChild<gtk::Button>,
+        #[template_child]
+        pub companions: TemplateChild<gtk::Box>,
+        #[template_child]
+        pub stats_label: TemplateChild<gtk::Label>,
+        #[template_child]
+        pub search_revealer: TemplateChild<gtk::Revealer>,
+        #[template_child]
+        pub charmap_revealer: TemplateChild<gtk::Revealer>,
+        #[template_child]
+        pub charmap: TemplateChild<gtk::Box>,
+        #[template_child]
+        pub search_summary: TemplateChild<gtk::Label>,
+        #[template_child]
+        pub replace_entry: TemplateChild<gtk::Entry>,
+        #[template_child]
+        pub search_results: TemplateChild<gtk::ListBox>,
+        #[template_child]
+        pub toast_revealer: TemplateChild<gtk::Revealer>,
+        #[template_child]
+        pub toast_label: TemplateChild<gtk::Label>,
+        #[template_child]
+        pub toast_button: TemplateChild<gtk::Button>,
+        pub toast_serial: Cell<u32>,
+        pub tx: OnceCell<Sender<Action>>,
+        pub spell_check_action: gio::SimpleAction,
+        pub dictionary: RefCell<Option<Dictionary>>,
+        pub dictionary_loaded: Cell<bool>,
+        pub spell_serial: Cell<u32>,
+        pub spell_word: Cell<Option<(i32, i32)>>,
+        pub spell_document: RefCell<Option<PathBuf>>,
+        pub buffer_handlers: RefCell<Vec<glib::SignalHandlerId>>,
+        pub font_provider: gtk::CssProvider,
+        pub text_serial: Cell<u32>,
+        // The line to scroll to the top once the text is in place.
+        pub top_line: Cell<Option<usize>>,
+        pub expansions: RefCell<selection::Expansions>,
+        pub document_path: RefCell<Option<PathBuf>>,
+        // The model's search results shown so far.
+        pub search_serial: Cell<usize>,
+        pub search_files: Cell<usize>,
+        // Every match shown in order, for stepping through them.
+        pub search_hits: RefCell<Vec<(PathBuf, usize)>>,
+        pub search_hit: Cell<Option<usize>>,
+        // A match to go to once its file has been opened.
+        pub pending_match: RefCell<Option<(PathBuf, usize)>>,
+        pub color_tags: RefCell<Vec<gtk::TextTag>>,
+        pub color_serial: Cell<u32>,
+        pub preview_serial: Cell<u32>,
+        // The color under the context menu.
+        pub color_literal: RefCell<Option<ColorLiteral>>,
+        // The timestamp under the context menu.
+        pub timestamp: RefCell<Option<Timestamp>>,
+        // The status bar text and the failure behind it.
+        pub error_details: RefCell<Option<(String, Error)>>,
+        // Indentation as set for the current document.
+        pub tab_width: Cell<u32>,
+        pub insert_spaces: Cell<bool>,
+        pub pointer_y: Cell<f64>,
+        pub reading: Cell<bool>,
+        // The language found from the file name or, failing that, the text.
+        pub detected_language: Cell<Option<&'static str>>,
+        // Whether the split view, rather than the main one, had focus last.
+        pub split_focused: Cell<bool>,
+        // Whether the document itself can be edited, outside reading mode.
+        pub text_editable: Cell<bool>,
+        pub recent: RefCell<Vec<PathBuf>>,
+        // The documents listed while the switcher is open. The current
+        // one comes first, and has no path when it is untitled.
+        pub switcher_entries: RefCell<Vec<Option<PathBuf>>>,
+        // While a paste is under way, where its text went and what it was.
+        pub pasting: Cell<bool>,
+        pub pasted: RefCell<Option<(usize, String)>>,
+        pub paste_popover: RefCell<Option<gtk::Popover>>,
+        pub transforms: Registry,
+        // Edits are kept here while a macro is recorded.
+        pub recording: RefCell<Option<Macro>>,
     }
 
     impl Default for ApplicationWindow {
@@ -42,11 +249,93 @@ mod imp {
             Self {
                 title: TemplateChild::default(),
                 modified: TemplateChild::default(),
+                subtitle: TemplateChild::default(),
+                label_dot: TemplateChild::default(),
+                mode_badge: TemplateChild::default(),
                 headerbar: TemplateChild::default(),
+                header_revealer: TemplateChild::default(),
+                preview_scroll: TemplateChild::default(),
+                preview: TemplateChild::default(),
+                switcher: TemplateChild::default(),
+                switcher_list: TemplateChild::default(),
+                appmenu_button: TemplateChild::default(),
+                templates_menu: TemplateChild::default(),
+                case_menu: TemplateChild::default(),
+                lines_menu: TemplateChild::default(),
                 bodytext: TemplateChild::default(),
+                split_paned: TemplateChild::default(),
+                split_scroll: TemplateChild::default(),
+                split_view: TemplateChild::default(),
                 save_button: TemplateChild::default(),
                 open_button: TemplateChild::default(),
                 status_bar: TemplateChild::default(),
+                reading_progress: TemplateChild::default(),
+                language_button: TemplateChild::default(),
+                external_change_bar: TemplateChild::default(),
+                external_change_label: TemplateChild::default(),
+                large_file_bar: TemplateChild::default(),
+                large_file_label: TemplateChild::default(),
+                notification_bar: TemplateChild::default(),
+                notification_label: TemplateChild::default(),
+                notification: RefCell::new(None),
+                notification_buttons: RefCell::new(Vec::new()),
+                open_progress: TemplateChild::default(),
+                cancel_open_button: TemplateChild::default(),
+                error_details_button: TemplateChild::default(),
+                companions: TemplateChild::default(),
+                stats_label: TemplateChild::default(),
+                search_revealer: TemplateChild::default(),
+                charmap_revealer: TemplateChild::default(),
+                charmap: TemplateChild::default(),
+                search_summary: TemplateChild::default(),
+                replace_entry: TemplateChild::default(),
+                search_results: TemplateChild::default(),
+                toast_revealer: TemplateChild::default(),
+                toast_label: TemplateChild::default(),
+                toast_button: TemplateChild::default(),
+                toast_serial: Cell::new(0),
+                tx: OnceCell::new(),
+                spell_check_action: gio::SimpleAction::new_stateful(
+                    "spell-check",
+                    None,
+                    &false.to_variant(),
+                ),
+                dictionary: RefCell::new(None),
+                dictionary_loaded: Cell::new(false),
+                spell_serial: Cell::new(0),
+                spell_word: Cell::new(None),
+                spell_document: RefCell::new(None),
+                buffer_handlers: RefCell::new(Vec::new()),
+                font_provider: gtk::CssProvider::new(),
+                text_serial: Cell::new(0),
+                top_line: Cell::new(None),
+                expansions: RefCell::default(),
+                document_path: RefCell::new(None),
+                search_serial: Cell::new(0),
+                search_files: Cell::new(0),
+                search_hits: RefCell::new(Vec::new()),
+                search_hit: Cell::new(None),
+                pending_match: RefCell::new(None),
+                color_tags: RefCell::new(Vec::new()),
+                color_serial: Cell::new(0),
+                preview_serial: Cell::new(0),
+                color_literal: RefCell::new(None),
+                timestamp: RefCell::new(None),
+                error_details: RefCell::new(None),
+                tab_width: Cell::new(4),
+                insert_spaces: Cell::new(false),
+                pointer_y: Cell::new(0.0),
+                reading: Cell::new(false),
+                detected_language: Cell::new(None),
+                split_focused: Cell::new(false),
+                text_editable: Cell::new(true),
+                recent: RefCell::new(Vec::new()),
+                switcher_entries: RefCell::new(Vec::new()),
+                pasting: Cell::new(false),
+                pasted: RefCell::new(None),
+                paste_popover: RefCell::new(None),
+                transforms: Registry::builtin(),
+                recording: RefCell::new(None),
                 settings: gio::Settings::new(APP_ID),
             }
         }
@@ -79,6 +368,76 @@ mod imp {
 
             // Load latest window state
             obj.load_window_size();
+
+            self.external_change_bar
+                .add_button(&gettext("Reload"), gtk::ResponseType::Accept);
+            self.external_change_bar
+                .add_button(&gettext("Keep"), gtk::ResponseType::Reject);
+            self.external_change_bar
+                .add_button(&gettext("Compare"), gtk::ResponseType::Apply);
+            self.external_change_bar
+                .add_button(&gettext("Merge"), MERGE_RESPONSE);
+
+            self.large_file_bar
+                .add_button(&gettext("Open"), gtk::ResponseType::Accept);
+            self.large_file_bar
+                .add_button(&gettext("Open Read-Only Part"), gtk::ResponseType::Apply);
+            self.large_file_bar
+                .add_button(&gettext("Cancel"), gtk::ResponseType::Cancel);
+
+            self.bodytext.style_context().add_provider(
+                &self.font_provider,
+                gtk::STYLE_PROVIDER_PRIORITY_APPLICATION,
+            );
+
+            // Undo history is kept by the model.
+            self.bodytext.buffer().set_enable_undo(false);
+            let tags = self.bodytext.buffer().tag_table();
+            tags.add(
+                &gtk::TextTag::builder()
+                    .name("highlight")
+                    .background("#f6d32d")
+                    .build(),
+            );
+            tags.add(
+                &gtk::TextTag::builder()
+                    .name("link")
+                    .underline(pango::Underline::Single)
+                    .build(),
+            );
+
+            obj.setup_spell_check();
+            obj.setup_search();
+            obj.setup_color_preview();
+            obj.setup_tools();
+            obj.setup_edit_actions();
+            obj.setup_macros();
+            obj.setup_paste();
+            obj.setup_drop();
+            obj.setup_indentation();
+            obj.setup_fullscreen();
+            obj.setup_switcher();
+            obj.setup_preview();
+            obj.setup_reading();
+            obj.setup_split();
+            obj.setup_character_map();
+            obj.setup_language();
+            obj.setup_notifications();
+
+            let action = gio::SimpleAction::new("error-details", None);
+            action.connect_activate(clone!(@weak obj => move |_, _| {
+                obj.show_error_details();
+            }));
+            obj.add_action(&action);
+        }
+    }
+
+    impl ApplicationWindow {
+        pub fn spell_enabled(&self) -> bool {
+            self.spell_check_action
+                .state()
+                .and_then(|s| s.get::<bool>())
+                .unwrap_or(false)
         }
     }
 
@@ -86,6 +445,12 @@ mod imp {
     impl WindowImpl for ApplicationWindow {
         // Save window state on delete event
         fn close_request(&self, window: &Self::Type) -> gtk::Inhibit {
+            let app = window
+                .application()
+                .and_then(|a| a.downcast::<Application>().ok());
+            if matches!(app, Some(app) if !app.may_close(window)) {
+                return gtk::Inhibit(true);
+            }
             if let Err(err) = window.save_window_size() {
                 log::warn!("Failed to save window state, {}", &err);
             }
@@ -121,6 +486,12 @@ impl ApplicationWindow {
             .settings
             .set_boolean("is-maximized", self.is_maximized())?;
 
+        if window.split_scroll.is_visible() {
+            window
+                .settings
+                .set_int("split-position", window.split_paned.position())?;
+        }
+
         Ok(())
     }
 
@@ -143,107 +514,4762 @@ impl ApplicationWindow {
         let window = imp::ApplicationWindow::from_instance(self);
         let document = model.document();
         let modified = document.modified();
-        window.modified.set_visible(modified);
+        if changes.progress {
+            let progress = model.progress();
+            window.open_progress.set_fraction(progress.unwrap_or(0.0));
+            window.open_progress.set_visible(progress.is_some());
+            window.cancel_open_button.set_visible(progress.is_some());
+            window
+                .cancel_open_button
+                .set_action_name(Some(if model.saving() {
+                    "app.cancel-save"
+                } else {
+                    "app.cancel-open"
+                }));
+        }
+        if changes.large_file {
+            if let Some((file, size)) = model.large_file() {
+                let name = Self::file_name(file);
+                window.large_file_label.set_text(&format!(
+                    "{} \"{}\" ({} MB). {}",
+                    gettext("This file is large"),
+                    name,
+                    size / (1024 * 1024),
+                    gettext("Opening all of it may take a while.")
+                ));
+            }
+            window
+                .large_file_bar
+                .set_revealed(model.large_file().is_some());
+        }
         if changes.text {
-            window.bodytext.buffer().set_text(document.text().as_str());
+            let mut cursor = if changes.cursor { model.cursor() } else { None };
+            window
+                .top_line
+                .set(model.top_line().filter(|_| changes.cursor));
+            if changes.filename {
+                if let Some((path, line)) = window.pending_match.take() {
+                    if document.filepath().as_ref() == Some(&path) {
+                        let text = document.text();
+                        let line = line.saturating_sub(1).min(text.len_lines());
+                        cursor = Some(text.line_to_char(line));
+                    }
+                }
+            }
+            self.set_text(document.text(), cursor, !document.read_only());
             debug!("GtkApplicationWindow<Application>::update m {}", modified);
         }
         if changes.filename {
-            match document.filename() {
-                Some(title) => window.title.set_text(title.as_str()),
-                None => window.title.set_text(""),
+            // Spell checking is toggled per document, so a different
+            // document starts from the default again.
+            let path = document.filepath();
+            window.document_path.replace(path.clone());
+            window.recent.replace(model.recent_documents());
+            self.show_companions(&model.companions());
+            self.set_language("auto");
+            self.update_edit_actions();
+            if *window.spell_document.borrow() != path {
+                window.spell_document.replace(path);
+                self.set_spell_check(window.settings.boolean("spell-check"));
             }
         }
+        if changes.text || changes.filename || changes.modified {
+            self.update_title(document);
+        }
+        if changes.text || changes.filename {
+            self.set_mode_badge(document);
+            self.detect_language(document);
+        }
+        if changes.filename || changes.label || changes.preferences {
+            self.set_document_label(&model.document_label().unwrap_or_default());
+        }
         if changes.status_message {
             let text = match model.status_message() {
                 StatusMessage::None => String::new(),
                 StatusMessage::SavingFile => gettext("Saving file..."),
                 StatusMessage::OpeningFile => gettext("Opening file..."),
+                StatusMessage::AnalyzingText => gettext("Analyzing text..."),
+                StatusMessage::Searching => gettext("Searching..."),
+                StatusMessage::Exporting => gettext("Exporting..."),
+                StatusMessage::ExportFinished(Ok(path)) => {
+                    format!("{}: \"{}\"", gettext("Exported to"), path.display())
+                }
+                StatusMessage::ExportFinished(Err(e)) => format!(
+                    "{}! {}",
+                    gettext("Could not export the document"),
+                    Self::error_message(e)
+                ),
+                StatusMessage::CopySaved(Ok(file)) => {
+                    format!("{}: \"{}\"", gettext("Copy saved to"), file.parse_name())
+                }
+                StatusMessage::CopySaved(Err(e)) => format!(
+                    "{}! {}",
+                    gettext("Could not save a copy"),
+                    Self::error_message(e)
+                ),
                 StatusMessage::FileSaveFinished(Ok(())) => format!(
                     "{}: \"{}\"",
                     gettext("File saved to"),
                     Self::filepath_string(model)
                 ),
+                StatusMessage::FileOpenFinished(Ok(())) if document.partial() => {
+                    gettext("Only the beginning of the file was opened, read-only")
+                }
                 StatusMessage::FileOpenFinished(Ok(())) => String::new(),
-                StatusMessage::FileSaveFinished(Err(_)) => format!(
-                    "{}: \"{}\"!",
+                StatusMessage::FileOpenFinished(Err(Error::Cancelled)) => {
+                    gettext("Opening cancelled")
+                }
+                StatusMessage::FileSaveFinished(Err(Error::Cancelled)) => {
+                    gettext("Saving cancelled, the file was left as it was")
+                }
+                StatusMessage::FileSaveFinished(Err(e)) => format!(
+                    "{}: \"{}\"! {}",
                     gettext("Could not save file"),
-                    Self::filepath_string(model)
+                    Self::filepath_string(model),
+                    Self::error_message(e)
                 ),
-                StatusMessage::FileOpenFinished(Err(_)) => format!(
-                    "{}: \"{}\"!",
+                StatusMessage::FileOpenFinished(Err(e)) => format!(
+                    "{}: \"{}\"! {}",
                     gettext("Could not open file"),
-                    Self::filepath_string(model)
+                    Self::filepath_string(model),
+                    Self::error_message(e)
+                ),
+                StatusMessage::Failed(e) => Self::error_message(e),
+            };
+            let error = match model.status_message() {
+                StatusMessage::FileSaveFinished(Err(e))
+                | StatusMessage::FileOpenFinished(Err(e))
+                | StatusMessage::ExportFinished(Err(e))
+                | StatusMessage::CopySaved(Err(e))
+                | StatusMessage::Failed(e) => Some(e),
+                _ => None,
+            };
+            self.set_status(&text, error);
+        }
+        if changes.external_change {
+            let label = if modified {
+                gettext("The file has changed on disk and you have unsaved changes.")
+            } else {
+                gettext("The file has changed on disk.")
+            };
+            window.external_change_label.set_text(&label);
+            window
+                .external_change_bar
+                .set_response_sensitive(MERGE_RESPONSE, modified);
+            window
+                .external_change_bar
+                .set_revealed(model.disk_contents().is_some());
+        }
+        if changes.preferences {
+            self.set_font(&window.settings.string("font"));
+            self.update_wrap_mode();
+            self.set_tab_width(model.tab_width());
+            window.insert_spaces.set(model.insert_spaces());
+        }
+        if changes.conflict {
+            self.show_conflict_dialog();
+        }
+        if changes.merge {
+            self.show_diff(
+                &gettext("Merge Result"),
+                &document.original().to_string(),
+                &document.text().to_string(),
+            );
+        }
+        if changes.compare {
+            if let Some(disk_contents) = model.disk_contents() {
+                self.show_diff(
+                    &gettext("Compare with Disk"),
+                    disk_contents,
+                    &document.text().to_string(),
+                );
+            }
+        }
+        if let (true, Some(stats)) = (changes.stats, model.stats()) {
+            window.stats_label.set_text(&format!(
+                "{}: {}  {}: {}",
+                gettext("Words"),
+                stats.words,
+                gettext("Characters"),
+                stats.characters
+            ));
+        }
+        if changes.links {
+            self.show_links(model.links());
+        }
+        if changes.notification {
+            self.show_notification(model.notification());
+        }
+        if let (true, Some(toast)) = (changes.toast, model.toast()) {
+            let text = match toast {
+                Toast::FileReloaded => gettext("File reloaded from disk"),
+                Toast::ReadOnly if document.partial() => {
+                    gettext("Partially opened files cannot be saved over")
+                }
+                Toast::ReadOnly => gettext("Files opened read-only cannot be saved over"),
+                Toast::Replaced(count) => format!("{}: {}", gettext("Replaced"), count),
+                Toast::ReplaceFailed => gettext("Could not replace in the file"),
+                Toast::InvalidPattern => gettext("Invalid regular expression"),
+                Toast::NoChanges => gettext("There are no unsaved changes"),
+                Toast::Merged(0) => gettext("Changes on disk merged"),
+                Toast::Merged(conflicts) => format!(
+                    "{}: {}",
+                    gettext("Changes on disk merged with conflicts"),
+                    conflicts
                 ),
             };
-            window.status_bar.set_text(text.as_str());
+            self.show_toast(&text);
+        }
+        if changes.term_counts {
+            self.show_term_counts(model.term_counts());
+        }
+        if changes.duplicates {
+            self.show_duplicates(model.duplicates());
+        }
+        if changes.file_matches {
+            self.show_file_matches(model);
         }
     }
 
-    fn filepath_string(model: &ApplicationModel) -> String {
-        match model.document().filepath() {
-            Some(path) => match path.into_os_string().into_string() {
-                Ok(s) => s,
-                Err(_) => model.document().filename().unwrap_or_else(String::new),
-            },
-            None => String::new(),
+    // The model already has this text, so it is not sent back. Large texts
+    // are added a chunk at a time so the window keeps responding.
+    fn set_text(&self, text: &Rope, cursor: Option<usize>, editable: bool) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let serial = window.text_serial.get().wrapping_add(1);
+        window.text_serial.set(serial);
+        if text.len_bytes() <= CHUNK_SIZE {
+            self.insert_text(&text.to_string(), true);
+            self.finish_text(cursor, editable);
+            return;
         }
+        self.insert_text("", true);
+        window.bodytext.set_editable(false);
+        window.open_progress.set_fraction(0.0);
+        window.open_progress.set_visible(true);
+        let text = text.clone();
+        let len = text.len_chars();
+        let mut start = 0;
+        glib::idle_add_local(
+            clone!(@weak self as win => @default-return Continue(false), move || {
+                let window = imp::ApplicationWindow::from_instance(&win);
+                if window.text_serial.get() != serial {
+                    return Continue(false);
+                }
+                let end = (start + CHUNK_SIZE).min(len);
+                win.insert_text(&text.slice(start..end).to_string(), false);
+                start = end;
+                window.open_progress.set_fraction(start as f64 / len as f64);
+                if start < len {
+                    Continue(true)
+                } else {
+                    window.open_progress.set_visible(false);
+                    win.finish_text(cursor, editable);
+                    Continue(false)
+                }
+            }),
+        );
     }
 
-    fn get_buffer_value(buffer: gtk::TextBuffer) -> String {
-        let start = buffer.start_iter();
-        let end = buffer.end_iter();
-        buffer.text(&start, &end, true).to_string()
+    fn insert_text(&self, text: &str, replace: bool) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let buffer = window.bodytext.buffer();
+        for handler in window.buffer_handlers.borrow().iter() {
+            buffer.block_signal(handler);
+        }
+        if replace {
+            buffer.set_text(text);
+        } else {
+            buffer.insert(&mut buffer.end_iter(), text);
+        }
+        for handler in window.buffer_handlers.borrow().iter() {
+            buffer.unblock_signal(handler);
+        }
     }
 
-    pub fn transmit(&self, tx: Sender<Action>) {
+    fn finish_text(&self, cursor: Option<usize>, editable: bool) {
         let window = imp::ApplicationWindow::from_instance(self);
         let buffer = window.bodytext.buffer();
-        let tx_local = tx.clone();
-        buffer
-            .connect("insert-text", true, move |args| {
-                let buffer: gtk::TextBuffer = args[0].get().unwrap();
-                let value = Self::get_buffer_value(buffer);
-                debug!(
-                    "GtkApplicationWindow<Application>::transmit insert-text {}",
-                    value
+        window.text_editable.set(editable);
+        window
+            .bodytext
+            .set_editable(editable && !window.reading.get());
+        if let Some(cursor) = cursor {
+            let mut iter = buffer.iter_at_offset(cursor as i32);
+            buffer.place_cursor(&iter);
+            match window.top_line.take() {
+                Some(line) => {
+                    let mut top = buffer.iter_at_line(line as i32).unwrap_or(iter);
+                    window
+                        .bodytext
+                        .scroll_to_iter(&mut top, 0.0, true, 0.0, 0.0);
+                }
+                None => {
+                    window
+                        .bodytext
+                        .scroll_to_iter(&mut iter, 0.1, false, 0.0, 0.0);
+                }
+            }
+        }
+    }
+
+    // Tells the model where the view is, so it can be returned to.
+    fn report_view(&self) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        if !self.is_active() {
+            return;
+        }
+        let view = &window.bodytext;
+        let (top, _) = view.line_at_y(view.visible_rect().y);
+        self.send(ViewMoved(Position {
+            cursor: view.buffer().cursor_position().max(0) as usize,
+            top_line: top.line().max(0) as usize,
+        }));
+    }
+
+    // Fonts are applied through CSS, as GTK 4 has no API for it.
+    fn set_font(&self, font: &str) {
+        use glib::translate::IntoGlib;
+        let window = imp::ApplicationWindow::from_instance(self);
+        let css = if font.trim().is_empty() {
+            String::new()
+        } else {
+            let description = pango::FontDescription::from_string(font);
+            let unit = if description.is_size_absolute() {
+                "px"
+            } else {
+                "pt"
+            };
+            let mut css = String::from("textview {");
+            if let Some(family) = description.family() {
+                css.push_str(&format!(" font-family: \"{}\";", family));
+            }
+            if description.size() > 0 {
+                css.push_str(&format!(
+                    " font-size: {}{};",
+                    description.size() / pango::SCALE,
+                    unit
+                ));
+            }
+            if description.style() == pango::Style::Italic {
+                css.push_str(" font-style: italic;");
+            }
+            css.push_str(&format!(
+                " font-weight: {}; }}",
+                description.weight().into_glib()
+            ));
+            css
+        };
+        window.font_provider.load_from_data(css.as_bytes());
+    }
+
+    // Reading mode always wraps, at word boundaries.
+    fn update_wrap_mode(&self) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let wrap = if window.reading.get() {
+            gtk::WrapMode::Word
+        } else if window.settings.boolean("wrap-text") {
+            gtk::WrapMode::WordChar
+        } else {
+            gtk::WrapMode::None
+        };
+        window.bodytext.set_wrap_mode(wrap);
+    }
+
+    fn set_tab_width(&self, width: u32) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        window.tab_width.set(width);
+        let view = &window.bodytext;
+        let layout = view.create_pango_layout(Some(&" ".repeat(width as usize)));
+        let (pixels, _) = layout.pixel_size();
+        let mut tabs = pango::TabArray::new(1, true);
+        tabs.set_tab(0, pango::TabAlign::Left, pixels);
+        view.set_tabs(&tabs);
+    }
+
+    fn setup_spell_check(&self) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let buffer = window.bodytext.buffer();
+        buffer.tag_table().add(
+            &gtk::TextTag::builder()
+                .name("misspelled")
+                .underline(pango::Underline::Error)
+                .build(),
+        );
+        buffer.connect_changed(clone!(@weak self as win => move |_| {
+            win.schedule_spell_check();
+        }));
+
+        let action = &window.spell_check_action;
+        action.connect_activate(clone!(@weak self as win => move |action, _| {
+            let enabled = action.state().and_then(|s| s.get::<bool>()).unwrap_or(false);
+            win.set_spell_check(!enabled);
+        }));
+        self.add_action(action);
+
+        let action = gio::SimpleAction::new("spell-replace", Some(&*String::static_variant_type()));
+        action.connect_activate(clone!(@weak self as win => move |_, parameter| {
+            if let Some(word) = parameter.and_then(|p| p.get::<String>()) {
+                win.replace_misspelled_word(&word);
+            }
+        }));
+        self.add_action(&action);
+
+        let action = gio::SimpleAction::new("spell-add", None);
+        action.connect_activate(clone!(@weak self as win => move |_, _| {
+            win.add_misspelled_word();
+        }));
+        self.add_action(&action);
+
+        // The suggestions are added to the text view's own context menu
+        // just before it opens.
+        let gesture = gtk::GestureClick::new();
+        gesture.set_button(3);
+        gesture.set_propagation_phase(gtk::PropagationPhase::Capture);
+        gesture.connect_pressed(clone!(@weak self as win => move |_, _, x, y| {
+            win.update_context_menu(x, y);
+        }));
+        window.bodytext.add_controller(&gesture);
+
+        window.settings.connect_changed(
+            Some("spell-language"),
+            clone!(@weak self as win => move |_, _| {
+                let window = imp::ApplicationWindow::from_instance(&win);
+                window.dictionary_loaded.set(false);
+                win.set_spell_check(window.spell_enabled());
+            }),
+        );
+    }
+
+    fn set_spell_check(&self, enabled: bool) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        window.spell_check_action.set_state(&enabled.to_variant());
+        // Dictionaries are large, so they are read once the window is drawn
+        // rather than before it can take typing.
+        if enabled && !window.dictionary_loaded.replace(true) {
+            glib::idle_add_local(
+                clone!(@weak self as win => @default-return Continue(false), move || {
+                    let window = imp::ApplicationWindow::from_instance(&win);
+                    window
+                        .dictionary
+                        .replace(Self::load_dictionary(&window.settings));
+                    win.check_spelling();
+                    Continue(false)
+                }),
+            );
+        }
+        self.check_spelling();
+    }
+
+    fn load_dictionary(settings: &gio::Settings) -> Option<Dictionary> {
+        let preferred = settings.string("spell-language").to_string();
+        let locales: Vec<String> = glib::language_names()
+            .iter()
+            .map(|l| l.to_string())
+            .collect();
+        let language = spell::resolve_language(&preferred, &locales, &spell::available_languages());
+        let mut dictionary = match language.as_ref().and_then(|l| Dictionary::load(l)) {
+            Some(dictionary) => dictionary,
+            None => {
+                warn!("No spelling dictionary found for {:?}", language);
+                return None;
+            }
+        };
+        if let Ok(words) = fs::read_to_string(Self::personal_dictionary_path()) {
+            for word in words.lines() {
+                dictionary.add_word(word);
+            }
+        }
+        Some(dictionary)
+    }
+
+    fn personal_dictionary_path() -> PathBuf {
+        config_dir().join("words")
+    }
+
+    // Rechecks once typing pauses rather than on every keystroke.
+    fn schedule_spell_check(&self) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        if !window.spell_enabled() {
+            return;
+        }
+        let serial = window.spell_serial.get().wrapping_add(1);
+        window.spell_serial.set(serial);
+        glib::timeout_add_local(
+            SPELL_CHECK_DELAY,
+            clone!(@weak self as win => @default-return Continue(false), move || {
+                let window = imp::ApplicationWindow::from_instance(&win);
+                if window.spell_serial.get() == serial {
+                    win.check_spelling();
+                }
+                Continue(false)
+            }),
+        );
+    }
+
+    fn check_spelling(&self) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let buffer = window.bodytext.buffer();
+        buffer.remove_tag_by_name("misspelled", &buffer.start_iter(), &buffer.end_iter());
+        if !window.spell_enabled() {
+            return;
+        }
+        if let Some(dictionary) = window.dictionary.borrow().as_ref() {
+            let text = Self::get_buffer_value(buffer.clone());
+            for (start, end) in spell::misspelled(dictionary, &text) {
+                buffer.apply_tag_by_name(
+                    "misspelled",
+                    &buffer.iter_at_offset(start as i32),
+                    &buffer.iter_at_offset(end as i32),
                 );
-                tx_local.send(DocumentChanged(value)).ok();
-                None
-            })
-            .ok();
-        let tx_local = tx;
-        buffer
-            .connect("delete-range", true, move |args| {
-                let buffer: gtk::TextBuffer = args[0].get().unwrap();
-                let value = Self::get_buffer_value(buffer);
-                debug!(
-                    "GtkApplicationWindow<Application>::transmit delete-range {}",
-                    value
+            }
+        }
+    }
+
+    fn update_context_menu(&self, x: f64, y: f64) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let view = &window.bodytext;
+        let menu = gio::Menu::new();
+        let literal = self.color_literal_at(x, y);
+        if literal.is_some() {
+            menu.append(Some(&gettext("Change Color…")), Some("win.pick-color"));
+        }
+        window.color_literal.replace(literal);
+        let found = self.timestamp_at(x, y);
+        if found.is_some() {
+            menu.append(
+                Some(&gettext("Convert Timestamp…")),
+                Some("win.convert-timestamp"),
+            );
+        }
+        window.timestamp.replace(found);
+        window.spell_word.set(None);
+        let (x, y) = view.window_to_buffer_coords(gtk::TextWindowType::Widget, x as i32, y as i32);
+        let dictionary = window.dictionary.borrow();
+        if let (true, Some(dictionary), Some(iter)) = (
+            window.spell_enabled(),
+            dictionary.as_ref(),
+            view.iter_at_location(x, y),
+        ) {
+            let text = Self::get_buffer_value(view.buffer());
+            if let Some((start, end)) = spell::word_at(&text, iter.offset() as usize) {
+                let word: String = text.chars().skip(start).take(end - start).collect();
+                if !dictionary.check(&word) {
+                    let section = gio::Menu::new();
+                    for suggestion in dictionary.suggest(&word, MAX_SUGGESTIONS) {
+                        let item = gio::MenuItem::new(Some(&suggestion), None);
+                        item.set_action_and_target_value(
+                            Some("win.spell-replace"),
+                            Some(&suggestion.to_variant()),
+                        );
+                        section.append_item(&item);
+                    }
+                    section.append(Some(&gettext("Add to Dictionary")), Some("win.spell-add"));
+                    menu.append_section(None, &section);
+                    window.spell_word.set(Some((start as i32, end as i32)));
+                }
+            }
+        }
+        if view.is_editable() && view.buffer().has_selection() {
+            let cases = gio::Menu::new();
+            for transform in window.transforms.in_menu(transforms::Menu::ChangeCase) {
+                cases.append(
+                    Some(&transform.name()),
+                    Some(&format!("win.{}", transform.id())),
                 );
-                tx_local.send(DocumentChanged(value)).ok();
-                None
-            })
-            .ok();
+            }
+            menu.append_submenu(Some(&gettext("Change Case")), &cases);
+        }
+        if view.is_editable() {
+            let section = gio::Menu::new();
+            section.append(Some(&gettext("Insert UUID")), Some("win.insert-uuid"));
+            section.append(Some(&gettext("Insert ULID")), Some("win.insert-ulid"));
+            section.append(
+                Some(&gettext("Insert Random Token…")),
+                Some("win.insert-token"),
+            );
+            menu.append_section(None, &section);
+        }
+        view.set_extra_menu(Some(&menu));
+    }
+
+    // A dot before the title in the label's color, and optionally a tint
+    // over the whole header bar.
+    fn set_document_label(&self, label: &str) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        for color in LABEL_COLORS.iter() {
+            window
+                .label_dot
+                .remove_css_class(&format!("label-{}", color));
+            window
+                .headerbar
+                .remove_css_class(&format!("tint-{}", color));
+        }
+        window.label_dot.set_visible(!label.is_empty());
+        if label.is_empty() {
+            return;
+        }
+        window.label_dot.add_css_class(&format!("label-{}", label));
+        if window.settings.boolean("label-header-tint") {
+            window.headerbar.add_css_class(&format!("tint-{}", label));
+        }
+    }
+
+    // A badge after the title for files that are not opened the usual way.
+    // The window title is what task switchers show, so it has the folder and
+    // the modified mark too.
+    fn update_title(&self, document: &Document) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let name = document.filename();
+        window.title.set_text(name.as_deref().unwrap_or(""));
+        // Files in the document portal are shown without its folder.
+        let dir = document
+            .filepath()
+            .filter(|path| !sandbox::is_document(path))
+            .and_then(|path| {
+                let home = glib::home_dir();
+                let dir = path.parent()?;
+                Some(FileSystem::abbreviate_dir(
+                    dir,
+                    Some(&home),
+                    TITLE_DIR_CHARS,
+                ))
+            });
+        window.subtitle.set_text(dir.as_deref().unwrap_or(""));
+        window.subtitle.set_visible(dir.is_some());
+        let modified = document.modified();
+        window.modified.set_visible(modified);
+        let name = name.unwrap_or_else(|| gettext("Untitled"));
+        let mut title = if modified {
+            format!("• {}", name)
+        } else {
+            name
+        };
+        if let Some(dir) = dir {
+            title = format!("{} — {}", title, dir);
+        }
+        self.set_title(Some(&title));
+    }
+
+    fn set_mode_badge(&self, document: &Document) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let badge = &window.mode_badge;
+        let text = match document.mode() {
+            OpenMode::Elevated => Some(gettext("Administrator")),
+            _ if document.read_only() => Some(gettext("Read-Only")),
+            _ => None,
+        };
+        badge.set_visible(text.is_some());
+        badge.set_text(&text.unwrap_or_default());
+        if document.mode() == OpenMode::Elevated {
+            badge.add_css_class("elevated");
+        } else {
+            badge.remove_css_class("elevated");
+        }
+    }
+
+    fn setup_preview(&self) {
+        use glib::translate::IntoGlib;
+        let window = imp::ApplicationWindow::from_instance(self);
+        let tags = window.preview.buffer().tag_table();
+        let bold = pango::Weight::Bold.into_glib();
+        let scales = [2.0, 1.7, 1.4, 1.2, 1.1, 1.0];
+        for (level, scale) in scales.iter().enumerate() {
+            tags.add(
+                &gtk::TextTag::builder()
+                    .name(&format!("h{}", level + 1))
+                    .weight(bold)
+                    .scale(*scale)
+                    .build(),
+            );
+        }
+        tags.add(&gtk::TextTag::builder().name("strong").weight(bold).build());
+        tags.add(
+            &gtk::TextTag::builder()
+                .name("emphasis")
+                .style(pango::Style::Italic)
+                .build(),
+        );
+        tags.add(
+            &gtk::TextTag::builder()
+                .name("code")
+                .family("monospace")
+                .build(),
+        );
+        tags.add(
+            &gtk::TextTag::builder()
+                .name("code-block")
+                .family("monospace")
+                .left_margin(24)
+                .build(),
+        );
+        tags.add(
+            &gtk::TextTag::builder()
+                .name("quote")
+                .style(pango::Style::Italic)
+                .left_margin(24)
+                .build(),
+        );
+        tags.add(
+            &gtk::TextTag::builder()
+                .name("link")
+                .underline(pango::Underline::Single)
+                .foreground("#3584e4")
+                .build(),
+        );
+        window
+            .bodytext
+            .buffer()
+            .connect_changed(clone!(@weak self as win => move |_| {
+                win.schedule_preview();
+            }));
+
+        let action = gio::SimpleAction::new_stateful("markdown-preview", None, &false.to_variant());
+        action.set_enabled(false);
+        action.connect_activate(clone!(@weak self as win => move |action, _| {
+            let shown = action.state().and_then(|s| s.get::<bool>()).unwrap_or(false);
+            win.set_preview(!shown);
+        }));
+        self.add_action(&action);
+    }
+
+    // Only Markdown documents can be previewed.
+    fn update_preview_action(&self) {
+        let markdown = self.is_markdown();
+        if let Some(action) = self.preview_action() {
+            action.set_enabled(markdown);
+        }
+        if !markdown {
+            self.set_preview(false);
+        }
+    }
+
+    fn preview_action(&self) -> Option<gio::SimpleAction> {
+        self.lookup_action("markdown-preview")
+            .and_then(|a| a.downcast::<gio::SimpleAction>().ok())
+    }
+
+    fn set_preview(&self, shown: bool) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        if let Some(action) = self.preview_action() {
+            action.set_state(&shown.to_variant());
+        }
+        window.preview_scroll.set_visible(shown);
+        if shown {
+            self.render_preview();
+        } else {
+            window.preview.buffer().set_text("");
+        }
+    }
+
+    // Renders again once typing pauses.
+    fn schedule_preview(&self) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        if !window.preview_scroll.is_visible() {
+            return;
+        }
+        let serial = window.preview_serial.get().wrapping_add(1);
+        window.preview_serial.set(serial);
+        glib::timeout_add_local(
+            PREVIEW_DELAY,
+            clone!(@weak self as win => @default-return Continue(false), move || {
+                let window = imp::ApplicationWindow::from_instance(&win);
+                if window.preview_serial.get() == serial && window.preview_scroll.is_visible() {
+                    win.render_preview();
+                }
+                Continue(false)
+            }),
+        );
+    }
+
+    fn render_preview(&self) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let text = Self::get_buffer_value(window.bodytext.buffer());
+        let buffer = window.preview.buffer();
+        buffer.set_text("");
+        let mut end = buffer.end_iter();
+        for span in markdown::render(&text) {
+            let start = end.offset();
+            buffer.insert(&mut end, &span.text);
+            for name in span.style.names() {
+                buffer.apply_tag_by_name(&name, &buffer.iter_at_offset(start), &end);
+            }
+        }
+    }
+
+    fn setup_color_preview(&self) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        window
+            .bodytext
+            .buffer()
+            .connect_changed(clone!(@weak self as win => move |_| {
+                win.schedule_color_preview();
+            }));
+
+        let action = gio::SimpleAction::new("pick-color", None);
+        action.connect_activate(clone!(@weak self as win => move |_, _| {
+            let window = imp::ApplicationWindow::from_instance(&win);
+            if let Some(literal) = window.color_literal.take() {
+                win.pick_color(literal);
+            }
+        }));
+        self.add_action(&action);
+
+        // Ctrl+click on a color opens the chooser without the menu.
+        let gesture = gtk::GestureClick::new();
+        gesture.set_button(1);
+        gesture.connect_released(clone!(@weak self as win => move |gesture, _, x, y| {
+            if gesture
+                .current_event_state()
+                .contains(gdk::ModifierType::CONTROL_MASK)
+            {
+                if let Some(literal) = win.color_literal_at(x, y) {
+                    win.pick_color(literal);
+                }
+            }
+        }));
+        window.bodytext.add_controller(&gesture);
+    }
+
+    fn schedule_color_preview(&self) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let serial = window.color_serial.get().wrapping_add(1);
+        window.color_serial.set(serial);
+        glib::timeout_add_local(
+            COLOR_PREVIEW_DELAY,
+            clone!(@weak self as win => @default-return Continue(false), move || {
+                let window = imp::ApplicationWindow::from_instance(&win);
+                if window.color_serial.get() == serial {
+                    win.preview_colors();
+                }
+                Continue(false)
+            }),
+        );
+    }
+
+    // Color literals are underlined in the color they describe.
+    fn preview_colors(&self) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let buffer = window.bodytext.buffer();
+        let table = buffer.tag_table();
+        for tag in window.color_tags.take() {
+            table.remove(&tag);
+        }
+        if buffer.char_count() > COLOR_PREVIEW_LIMIT {
+            return;
+        }
+        let text = Self::get_buffer_value(buffer.clone());
+        // Literals of the same color share a tag.
+        let mut tags = HashMap::new();
+        for literal in colors::find_colors(&text) {
+            let tag = tags
+                .entry(colors::format(literal.color, Notation::Hex))
+                .or_insert_with(|| {
+                    let tag = gtk::TextTag::builder()
+                        .underline(pango::Underline::Double)
+                        .underline_rgba(&Self::to_gdk_rgba(literal.color))
+                        .build();
+                    table.add(&tag);
+                    tag
+                });
+            buffer.apply_tag(
+                tag,
+                &buffer.iter_at_offset(literal.start as i32),
+                &buffer.iter_at_offset(literal.end as i32),
+            );
+        }
+        window.color_tags.replace(tags.into_values().collect());
+    }
+
+    fn color_literal_at(&self, x: f64, y: f64) -> Option<ColorLiteral> {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let view = &window.bodytext;
+        let (x, y) = view.window_to_buffer_coords(gtk::TextWindowType::Widget, x as i32, y as i32);
+        let iter = view.iter_at_location(x, y)?;
+        let text = Self::get_buffer_value(view.buffer());
+        colors::color_at(&text, iter.offset() as usize)
+    }
+
+    fn pick_color(&self, literal: ColorLiteral) {
+        let dialog = gtk::ColorChooserDialog::new(Some(&gettext("Pick Color")), Some(self));
+        dialog.set_modal(true);
+        dialog.set_use_alpha(true);
+        dialog.set_rgba(&Self::to_gdk_rgba(literal.color));
+        dialog.connect_response(clone!(@weak self as win => move |d, response| {
+            if response == gtk::ResponseType::Ok {
+                let rgba = d.rgba();
+                let color = Rgba {
+                    red: f64::from(rgba.red),
+                    green: f64::from(rgba.green),
+                    blue: f64::from(rgba.blue),
+                    alpha: f64::from(rgba.alpha),
+                };
+                win.replace_color(&literal, &colors::format(color, literal.notation));
+            }
+            d.close();
+        }));
+        dialog.show();
+    }
+
+    // Keeps the notation the literal was written in.
+    fn replace_color(&self, literal: &ColorLiteral, replacement: &str) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let buffer = window.bodytext.buffer();
+        let mut start = buffer.iter_at_offset(literal.start as i32);
+        let mut end = buffer.iter_at_offset(literal.end as i32);
+        // The text may have changed while the chooser was open.
+        if buffer.text(&start, &end, false).as_str() != literal.text {
+            return;
+        }
+        buffer.begin_user_action();
+        buffer.delete(&mut start, &mut end);
+        buffer.insert(&mut start, replacement);
+        buffer.end_user_action();
     }
 
-    pub fn undo(&self) {
+    fn to_gdk_rgba(color: Rgba) -> gdk::RGBA {
+        gdk::RGBA {
+            red: color.red as f32,
+            green: color.green as f32,
+            blue: color.blue as f32,
+            alpha: color.alpha as f32,
+        }
+    }
+
+    fn timestamp_at(&self, x: f64, y: f64) -> Option<Timestamp> {
         let window = imp::ApplicationWindow::from_instance(self);
-        window.bodytext.buffer().undo();
+        let view = &window.bodytext;
+        let (x, y) = view.window_to_buffer_coords(gtk::TextWindowType::Widget, x as i32, y as i32);
+        let iter = view.iter_at_location(x, y)?;
+        let text = Self::get_buffer_value(view.buffer());
+        timestamp::timestamp_at(&text, iter.offset() as usize)
     }
 
-    pub fn redo(&self) {
+    // The timestamp under the context menu, or else at the cursor.
+    fn convert_timestamp(&self) {
         let window = imp::ApplicationWindow::from_instance(self);
-        window.bodytext.buffer().redo();
+        let buffer = window.bodytext.buffer();
+        let found = window.timestamp.take().or_else(|| {
+            let text = Self::get_buffer_value(buffer.clone());
+            timestamp::timestamp_at(&text, buffer.cursor_position() as usize)
+        });
+        match found {
+            Some(found) => self.show_timestamp_popover(found),
+            None => self.show_toast(&gettext("No timestamp at the cursor")),
+        }
+    }
+
+    fn resolve_moment(moment: Moment) -> Option<i64> {
+        match moment {
+            Moment::Unix(millis) => Some(millis),
+            Moment::Local(civil) => {
+                let local = glib::DateTime::new(
+                    &glib::TimeZone::new_local(),
+                    civil.year as i32,
+                    civil.month as i32,
+                    civil.day as i32,
+                    civil.hour as i32,
+                    civil.minute as i32,
+                    f64::from(civil.second),
+                )
+                .ok()?;
+                Some(local.to_unix() * 1000 + i64::from(civil.millis))
+            }
+        }
     }
 
-    pub fn can_undo(&self) -> bool {
+    fn show_timestamp_popover(&self, found: Timestamp) {
         let window = imp::ApplicationWindow::from_instance(self);
-        window.bodytext.buffer().can_undo()
+        let view = &window.bodytext;
+        let millis = match Self::resolve_moment(found.moment) {
+            Some(millis) => millis,
+            None => return self.show_toast(&gettext("The time does not exist here")),
+        };
+        let local = glib::DateTime::from_unix_local(millis.div_euclid(1000)).ok();
+        let local_format = |format: &str| {
+            local
+                .as_ref()
+                .and_then(|t| t.format(format).ok())
+                .map(|t| t.to_string())
+        };
+        let mut choices = vec![
+            (gettext("Unix time"), millis.div_euclid(1000).to_string()),
+            (gettext("Unix time in milliseconds"), millis.to_string()),
+            (gettext("UTC"), timestamp::format_utc(millis)),
+        ];
+        if let Some(text) = local_format("%Y-%m-%dT%H:%M:%S%:z") {
+            choices.push((gettext("Local time"), text));
+        }
+
+        let content = gtk::Box::new(gtk::Orientation::Vertical, 6);
+        if let Some(text) = local_format("%c") {
+            let label = gtk::Label::new(Some(&text));
+            label.set_xalign(0.0);
+            label.set_selectable(true);
+            content.append(&label);
+        }
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis() as i64);
+        let relative = gtk::Label::new(Some(&Self::format_relative((millis - now) / 1000)));
+        relative.set_xalign(0.0);
+        relative.add_css_class("dim-label");
+        content.append(&relative);
+
+        let popover = gtk::Popover::new();
+        for (name, text) in choices {
+            if text == found.text {
+                continue;
+            }
+            let button = gtk::Button::with_label(&format!("{}: {}", name, text));
+            button.set_has_frame(false);
+            button.set_tooltip_text(Some(&gettext("Replace the timestamp")));
+            if let Some(label) = button.child().and_then(|c| c.downcast::<gtk::Label>().ok()) {
+                label.set_xalign(0.0);
+            }
+            button.connect_clicked(
+                clone!(@weak self as win, @weak popover, @strong found => move |_| {
+                    win.replace_timestamp(&found, &text);
+                    popover.popdown();
+                }),
+            );
+            content.append(&button);
+        }
+        popover.set_child(Some(&content));
+        popover.set_parent(&**view);
+        let buffer = view.buffer();
+        let rect = view.iter_location(&buffer.iter_at_offset(found.start as i32));
+        let (x, y) = view.buffer_to_window_coords(gtk::TextWindowType::Widget, rect.x, rect.y);
+        popover.set_pointing_to(&gdk::Rectangle {
+            x,
+            y,
+            width: rect.width.max(1),
+            height: rect.height,
+        });
+        popover.connect_closed(|popover| popover.unparent());
+        popover.popup();
     }
 
-    pub fn can_redo(&self) -> bool {
+    fn format_relative(seconds: i64) -> String {
+        let (amount, unit) = timestamp::relative(seconds);
+        let count = amount.unsigned_abs() as u32;
+        let text = match (unit, amount < 0) {
+            (Unit::Seconds, true) => ngettext("{} second ago", "{} seconds ago", count),
+            (Unit::Seconds, false) => ngettext("in {} second", "in {} seconds", count),
+            (Unit::Minutes, true) => ngettext("{} minute ago", "{} minutes ago", count),
+            (Unit::Minutes, false) => ngettext("in {} minute", "in {} minutes", count),
+            (Unit::Hours, true) => ngettext("{} hour ago", "{} hours ago", count),
+            (Unit::Hours, false) => ngettext("in {} hour", "in {} hours", count),
+            (Unit::Days, true) => ngettext("{} day ago", "{} days ago", count),
+            (Unit::Days, false) => ngettext("in {} day", "in {} days", count),
+            (Unit::Months, true) => ngettext("{} month ago", "{} months ago", count),
+            (Unit::Months, false) => ngettext("in {} month", "in {} months", count),
+            (Unit::Years, true) => ngettext("{} year ago", "{} years ago", count),
+            (Unit::Years, false) => ngettext("in {} year", "in {} years", count),
+        };
+        text.replace("{}", &count.to_string())
+    }
+
+    fn replace_timestamp(&self, found: &Timestamp, replacement: &str) {
         let window = imp::ApplicationWindow::from_instance(self);
-        window.bodytext.buffer().can_redo()
+        let buffer = window.bodytext.buffer();
+        let mut start = buffer.iter_at_offset(found.start as i32);
+        let mut end = buffer.iter_at_offset(found.end as i32);
+        if buffer.text(&start, &end, false).as_str() != found.text {
+            return;
+        }
+        buffer.begin_user_action();
+        buffer.delete(&mut start, &mut end);
+        buffer.insert(&mut start, replacement);
+        buffer.end_user_action();
+    }
+
+    fn replace_misspelled_word(&self, word: &str) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        if let Some((start, end)) = window.spell_word.take() {
+            let buffer = window.bodytext.buffer();
+            let mut start = buffer.iter_at_offset(start);
+            let mut end = buffer.iter_at_offset(end);
+            buffer.begin_user_action();
+            buffer.delete(&mut start, &mut end);
+            buffer.insert(&mut start, word);
+            buffer.end_user_action();
+        }
+    }
+
+    fn add_misspelled_word(&self) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        if let Some((start, end)) = window.spell_word.take() {
+            let buffer = window.bodytext.buffer();
+            let word = buffer
+                .text(
+                    &buffer.iter_at_offset(start),
+                    &buffer.iter_at_offset(end),
+                    false,
+                )
+                .to_string();
+            if let Some(dictionary) = window.dictionary.borrow_mut().as_mut() {
+                dictionary.add_word(&word);
+            }
+            if let Err(err) = Self::save_personal_word(&word) {
+                warn!("Failed to save word to personal dictionary, {}", &err);
+            }
+            self.check_spelling();
+        }
+    }
+
+    fn save_personal_word(word: &str) -> std::io::Result<()> {
+        let path = Self::personal_dictionary_path();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        writeln!(file, "{}", word)
+    }
+
+    fn show_term_counts(&self, counts: &[TermCount]) {
+        let store = gtk::ListStore::new(&[String::static_type(), u32::static_type()]);
+        for c in counts {
+            store.insert_with_values(None, &[(0, &c.term), (1, &(c.count as u32))]);
+        }
+        let view = gtk::TreeView::with_model(&store);
+        let add_column = |id: i32, title: &str| {
+            let cell = gtk::CellRendererText::new();
+            let column = gtk::TreeViewColumn::new();
+            column.set_title(title);
+            column.pack_start(&cell, true);
+            column.add_attribute(&cell, "text", id);
+            column.set_sort_column_id(id);
+            column.set_resizable(true);
+            column.set_expand(id == 0);
+            view.append_column(&column);
+        };
+        add_column(0, &gettext("Term"));
+        add_column(1, &gettext("Count"));
+        view.selection()
+            .connect_changed(clone!(@weak self as window => move |selection| {
+                if let Some((model, iter)) = selection.selected() {
+                    if let Ok(term) = model.get(&iter, 0).get::<String>() {
+                        window.highlight_occurrences(&term);
+                    }
+                }
+            }));
+
+        let dialog = self.show_tool_window(&gettext("Word Frequency"), &view, 320, 480);
+        dialog.connect_close_request(
+            clone!(@weak self as window => @default-return gtk::Inhibit(false), move |_| {
+                window.highlight_occurrences("");
+                gtk::Inhibit(false)
+            }),
+        );
+    }
+
+    fn show_duplicates(&self, duplicates: &[Duplicate]) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let buffer = window.bodytext.buffer();
+        let list = gtk::ListBox::new();
+        list.set_selection_mode(gtk::SelectionMode::None);
+        list.set_placeholder(Some(&gtk::Label::new(Some(&gettext(
+            "No duplicates found",
+        )))));
+        for d in duplicates {
+            let row = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+            row.set_margin_start(6);
+            row.set_margin_end(6);
+            let similarity = gtk::Label::new(Some(&format!("{:.0}%", d.similarity * 100.0)));
+            let excerpt = gtk::Label::new(Some(&d.first.text));
+            excerpt.set_ellipsize(pango::EllipsizeMode::End);
+            excerpt.set_hexpand(true);
+            excerpt.set_xalign(0.0);
+            excerpt.set_tooltip_text(Some(&d.second.text));
+            row.append(&similarity);
+            row.append(&excerpt);
+            for passage in [&d.first, &d.second].iter() {
+                let line = buffer.iter_at_offset(passage.start as i32).line() + 1;
+                let button = gtk::Button::with_label(&format!("{} {}", gettext("Line"), line));
+                button.set_has_frame(false);
+                let (start, end) = (passage.start, passage.end);
+                button.connect_clicked(clone!(@weak self as window => move |_| {
+                    window.select_range(start, end);
+                }));
+                row.append(&button);
+            }
+            list.append(&row);
+        }
+        self.show_tool_window(&gettext("Duplicates"), &list, 560, 400);
+    }
+
+    // Results arrive a file at a time, and are added to those shown
+    // unless the model started over.
+    fn show_file_matches(&self, model: &ApplicationModel) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let list = &window.search_results;
+        if window.search_serial.get() != model.search_serial() {
+            window.search_serial.set(model.search_serial());
+            window.search_files.set(0);
+            window.search_hits.borrow_mut().clear();
+            window.search_hit.set(None);
+            while let Some(child) = list.first_child() {
+                list.remove(&child);
+            }
+            window.search_revealer.set_reveal_child(true);
+        }
+        let root = model.search_root().unwrap_or_default();
+        let matches = model.file_matches();
+        for group in matches.iter().skip(window.search_files.get()) {
+            list.append(&self.file_matches_row(&root, group));
+        }
+        window.search_files.set(matches.len());
+
+        let count: usize = matches.iter().map(|m| m.matches.len()).sum();
+        let summary = if count == 0 && !model.is_searching() {
+            gettext("No matches found")
+        } else {
+            format!(
+                "\"{}\" {}: {}, {}: {}",
+                model.search_query().pattern,
+                gettext("Matches"),
+                count,
+                gettext("Files"),
+                matches.len()
+            )
+        };
+        window.search_summary.set_text(&summary);
+    }
+
+    // A collapsible group of a file's matches, each shown among its
+    // context lines the way grep does.
+    fn file_matches_row(&self, root: &Path, group: &FileMatches) -> gtk::ListBoxRow {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let relative = group.path.strip_prefix(root).unwrap_or(&group.path);
+        let expander = gtk::Expander::new(Some(&format!(
+            "{} ({})",
+            relative.display(),
+            group.matches.len()
+        )));
+        expander.set_expanded(true);
+        let content = gtk::Box::new(gtk::Orientation::Vertical, 3);
+        let list = gtk::ListBox::new();
+        list.set_selection_mode(gtk::SelectionMode::None);
+        let mut hits = window.search_hits.borrow_mut();
+        let first = hits.len();
+        for m in &group.matches {
+            hits.push((group.path.clone(), m.line));
+            let lines = gtk::Box::new(gtk::Orientation::Vertical, 0);
+            let before = m.line - m.before.len();
+            for (i, text) in m.before.iter().enumerate() {
+                lines.append(&Self::match_line(before + i, '-', text, true));
+            }
+            lines.append(&Self::match_line(m.line, ':', &m.text, false));
+            for (i, text) in m.after.iter().enumerate() {
+                lines.append(&Self::match_line(m.line + 1 + i, '-', text, true));
+            }
+            list.append(&lines);
+        }
+        list.connect_row_activated(clone!(@weak self as win => move |_, row| {
+            win.go_to_match(first + row.index() as usize);
+        }));
+        content.append(&list);
+
+        let button = gtk::Button::with_label(&gettext("Replace in This File"));
+        button.set_halign(gtk::Align::End);
+        let path = group.path.clone();
+        button.connect_clicked(clone!(@weak self as win => move |_| {
+            let window = imp::ApplicationWindow::from_instance(&win);
+            let replacement = window.replace_entry.text().to_string();
+            win.send(ReplaceInFile(path.clone(), replacement));
+        }));
+        content.append(&button);
+        expander.set_child(Some(&content));
+
+        let row = gtk::ListBoxRow::new();
+        row.set_activatable(false);
+        row.set_child(Some(&expander));
+        row
+    }
+
+    fn match_line(line: usize, separator: char, text: &str, context: bool) -> gtk::Label {
+        let label = gtk::Label::new(Some(&format!("{:>5}{} {}", line, separator, text)));
+        label.set_xalign(0.0);
+        label.set_ellipsize(pango::EllipsizeMode::End);
+        label.add_css_class("monospace");
+        if context {
+            label.add_css_class("dim-label");
+        }
+        label
+    }
+
+    fn setup_search(&self) {
+        let action = gio::SimpleAction::new("next-match", None);
+        action.connect_activate(clone!(@weak self as win => move |_, _| {
+            win.step_match(true);
+        }));
+        self.add_action(&action);
+
+        let action = gio::SimpleAction::new("previous-match", None);
+        action.connect_activate(clone!(@weak self as win => move |_, _| {
+            win.step_match(false);
+        }));
+        self.add_action(&action);
+
+        let action = gio::SimpleAction::new("close-search", None);
+        action.connect_activate(clone!(@weak self as win => move |_, _| {
+            let window = imp::ApplicationWindow::from_instance(&win);
+            window.search_revealer.set_reveal_child(false);
+            window.bodytext.grab_focus();
+            win.send(CancelFindInFiles);
+        }));
+        self.add_action(&action);
+
+        let action = gio::SimpleAction::new("regex-tester", None);
+        action.connect_activate(clone!(@weak self as win => move |_, _| {
+            win.show_regex_tester();
+        }));
+        self.add_action(&action);
+    }
+
+    // Runs before the text view handles Enter and Tab itself.
+    fn setup_indentation(&self) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let controller = gtk::EventControllerKey::new();
+        controller.set_propagation_phase(gtk::PropagationPhase::Capture);
+        controller.connect_key_pressed(
+            clone!(@weak self as win => @default-return gtk::Inhibit(false), move |_, key, _, state| {
+                use gdk::keys::constants;
+                let window = imp::ApplicationWindow::from_instance(&win);
+                // The view would take Ctrl+/ to select all.
+                if key == constants::slash && state.contains(gdk::ModifierType::CONTROL_MASK) {
+                    let editable = window.bodytext.is_editable();
+                    if editable {
+                        win.toggle_comment();
+                    }
+                    return gtk::Inhibit(editable);
+                }
+                let shortcut = gdk::ModifierType::CONTROL_MASK | gdk::ModifierType::ALT_MASK;
+                if !window.bodytext.is_editable() || state.intersects(shortcut) {
+                    return gtk::Inhibit(false);
+                }
+                let handled = if key == constants::Return || key == constants::KP_Enter {
+                    window.settings.boolean("auto-indent") && win.insert_indented_newline()
+                } else if key == constants::ISO_Left_Tab
+                    || (key == constants::Tab && state.contains(gdk::ModifierType::SHIFT_MASK))
+                {
+                    win.shift_lines(true);
+                    true
+                } else if key == constants::Tab {
+                    win.insert_tab()
+                } else {
+                    false
+                };
+                gtk::Inhibit(handled)
+            }),
+        );
+        window.bodytext.add_controller(&controller);
+    }
+
+    // A selection across lines is indented as a block. Otherwise spaces
+    // are only inserted in place of the view's own tab when asked for.
+    fn insert_tab(&self) -> bool {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let buffer = window.bodytext.buffer();
+        if let Some((start, end)) = buffer.selection_bounds() {
+            if start.line() != end.line() {
+                self.shift_lines(false);
+                return true;
+            }
+        }
+        if !window.insert_spaces.get() {
+            return false;
+        }
+        buffer.begin_user_action();
+        buffer.delete_selection(true, true);
+        let mut cursor = buffer.iter_at_offset(buffer.cursor_position());
+        let mut start = cursor.clone();
+        start.set_line_offset(0);
+        let before = buffer.text(&start, &cursor, false);
+        let spaces = indent::spaces_to_tab_stop(&before, window.tab_width.get() as usize);
+        buffer.insert(&mut cursor, &spaces);
+        buffer.end_user_action();
+        true
+    }
+
+    // Indents or dedents the selected lines, or the cursor's line, in one
+    // undoable edit that keeps the lines selected.
+    fn shift_lines(&self, dedent: bool) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let view = &window.bodytext;
+        let buffer = view.buffer();
+        let selected = buffer.selection_bounds();
+        let cursor = buffer.iter_at_offset(buffer.cursor_position());
+        let (mut start, mut end) = selected
+            .clone()
+            .unwrap_or_else(|| (cursor.clone(), cursor.clone()));
+        start.set_line_offset(0);
+        // A selection ending at the start of a line does not include it.
+        if selected.is_some() && end.starts_line() && end.line() > start.line() {
+            end.backward_char();
+        }
+        if !end.ends_line() {
+            end.forward_to_line_end();
+        }
+        let from_end = end.offset() - cursor.offset();
+        let tab_width = window.tab_width.get() as usize;
+        let text = buffer.text(&start, &end, false).to_string();
+        let shifted = if dedent {
+            indent::dedent_lines(&text, tab_width)
+        } else {
+            let unit = indent::indent_unit(window.insert_spaces.get(), tab_width);
+            indent::indent_lines(&text, &unit)
+        };
+        if shifted == text {
+            return;
+        }
+        let first = start.offset();
+        buffer.begin_user_action();
+        buffer.delete(&mut start, &mut end);
+        buffer.insert(&mut start, &shifted);
+        buffer.end_user_action();
+        let last = start.offset();
+        if selected.is_some() {
+            buffer.select_range(&buffer.iter_at_offset(first), &buffer.iter_at_offset(last));
+        } else {
+            let line_start = buffer.iter_at_offset(first);
+            let mut cursor = buffer.iter_at_offset(last - from_end);
+            if cursor.offset() < line_start.offset() {
+                cursor = line_start;
+            }
+            buffer.place_cursor(&cursor);
+        }
+    }
+
+    // Whether the newline was inserted, leaving it to the text view when
+    // there is no indentation to copy.
+    fn insert_indented_newline(&self) -> bool {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let view = &window.bodytext;
+        let buffer = view.buffer();
+        buffer.begin_user_action();
+        buffer.delete_selection(true, true);
+        let mut cursor = buffer.iter_at_offset(buffer.cursor_position());
+        let mut start = cursor.clone();
+        start.set_line_offset(0);
+        let newline = indent::newline(&buffer.text(&start, &cursor, false));
+        if newline.indent.is_empty() && newline.trailing == 0 {
+            buffer.end_user_action();
+            return false;
+        }
+        let mut trailing = cursor.clone();
+        trailing.backward_chars(newline.trailing as i32);
+        buffer.delete(&mut trailing, &mut cursor);
+        buffer.insert(&mut cursor, &format!("\n{}", newline.indent));
+        buffer.end_user_action();
+        buffer.place_cursor(&cursor);
+        view.scroll_mark_onscreen(&buffer.get_insert());
+        true
+    }
+
+    // When fullscreen the header bar moves into a revealer over the text,
+    // shown while the pointer is at the top edge or the menu is open.
+    fn setup_fullscreen(&self) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let action = gio::SimpleAction::new_stateful("fullscreen", None, &false.to_variant());
+        action.connect_activate(clone!(@weak self as win => move |_, _| {
+            if win.is_fullscreen() {
+                win.unfullscreen();
+            } else {
+                win.fullscreen();
+            }
+        }));
+        self.add_action(&action);
+        self.connect_notify_local(Some("fullscreened"), |win, _| {
+            win.update_fullscreen();
+        });
+
+        let action = gio::SimpleAction::new("show-menu", None);
+        action.connect_activate(clone!(@weak self as win => move |_, _| {
+            let window = imp::ApplicationWindow::from_instance(&win);
+            if win.is_fullscreen() {
+                window.header_revealer.set_reveal_child(true);
+            }
+            window.appmenu_button.popup();
+        }));
+        self.add_action(&action);
+
+        let motion = gtk::EventControllerMotion::new();
+        motion.connect_motion(clone!(@weak self as win => move |_, _, y| {
+            win.track_pointer(y);
+        }));
+        self.add_controller(&motion);
+        if let Some(popover) = window.appmenu_button.popover() {
+            popover.connect_closed(clone!(@weak self as win => move |_| {
+                let window = imp::ApplicationWindow::from_instance(&win);
+                if window.pointer_y.get() > HEADER_REVEAL_DISTANCE {
+                    window.header_revealer.set_reveal_child(false);
+                }
+            }));
+        }
+    }
+
+    fn update_fullscreen(&self) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let fullscreen = self.is_fullscreen();
+        if let Some(action) = self
+            .lookup_action("fullscreen")
+            .and_then(|a| a.downcast::<gio::SimpleAction>().ok())
+        {
+            action.set_state(&fullscreen.to_variant());
+        }
+        let headerbar = &*window.headerbar;
+        let revealer = &window.header_revealer;
+        revealer.set_reveal_child(false);
+        if fullscreen {
+            self.set_titlebar(None::<&gtk::Widget>);
+            revealer.set_child(Some(headerbar));
+        } else {
+            revealer.set_child(None::<&gtk::Widget>);
+            self.set_titlebar(Some(headerbar));
+        }
+    }
+
+    fn track_pointer(&self, y: f64) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        window.pointer_y.set(y);
+        let revealer = &window.header_revealer;
+        if !self.is_fullscreen() {
+            return;
+        }
+        let menu_open = matches!(window.appmenu_button.popover(), Some(p) if p.is_visible());
+        if y <= HEADER_REVEAL_DISTANCE {
+            revealer.set_reveal_child(true);
+        } else if !menu_open && y > f64::from(revealer.height()) + HEADER_REVEAL_DISTANCE {
+            revealer.set_reveal_child(false);
+        }
+    }
+
+    // Reading mode shows the document read-only in a justified column.
+    // Page Up, Page Down and space turn whole pages.
+    fn setup_reading(&self) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let action = gio::SimpleAction::new_stateful("reading-mode", None, &false.to_variant());
+        action.connect_activate(clone!(@weak self as win => move |action, _| {
+            let reading = !action.state().and_then(|s| s.get::<bool>()).unwrap_or(false);
+            action.set_state(&reading.to_variant());
+            win.set_reading(reading);
+        }));
+        self.add_action(&action);
+
+        let controller = gtk::EventControllerKey::new();
+        controller.set_propagation_phase(gtk::PropagationPhase::Capture);
+        controller.connect_key_pressed(
+            clone!(@weak self as win => @default-return gtk::Inhibit(false), move |_, key, _, state| {
+                use gdk::keys::constants;
+                let window = imp::ApplicationWindow::from_instance(&win);
+                let shortcut = gdk::ModifierType::CONTROL_MASK | gdk::ModifierType::ALT_MASK;
+                if !window.reading.get() || state.intersects(shortcut) {
+                    return gtk::Inhibit(false);
+                }
+                let shift = state.contains(gdk::ModifierType::SHIFT_MASK);
+                let forward = if key == constants::Page_Down || (key == constants::space && !shift) {
+                    true
+                } else if key == constants::Page_Up || key == constants::space {
+                    false
+                } else {
+                    return gtk::Inhibit(false);
+                };
+                win.turn_page(forward);
+                gtk::Inhibit(true)
+            }),
+        );
+        window.bodytext.add_controller(&controller);
+
+        if let Some(adjustment) = window.bodytext.vadjustment() {
+            adjustment.connect_value_changed(clone!(@weak self as win => move |_| {
+                win.update_reading_progress();
+                win.report_view();
+            }));
+            adjustment.connect_changed(clone!(@weak self as win => move |_| {
+                win.update_reading_progress();
+            }));
+        }
+        // The view only has its new width once the window is laid out.
+        for property in ["default-width", "maximized", "fullscreened"].iter() {
+            self.connect_notify_local(Some(property), |win, _| {
+                glib::idle_add_local(
+                    clone!(@weak win => @default-return Continue(false), move || {
+                        win.update_reading_column();
+                        Continue(false)
+                    }),
+                );
+            });
+        }
+    }
+
+    // The status bar's language menu, which overrides the detected
+    // language until another document is opened.
+    fn setup_language(&self) {
+        let action = gio::SimpleAction::new_stateful(
+            "language",
+            Some(&String::static_variant_type()),
+            &"auto".to_variant(),
+        );
+        action.connect_activate(clone!(@weak self as win => move |_, parameter| {
+            if let Some(language) = parameter.and_then(|p| p.get::<String>()) {
+                win.set_language(&language);
+            }
+        }));
+        self.add_action(&action);
+
+        let action = gio::SimpleAction::new("associate-language", None);
+        action.connect_activate(clone!(@weak self as win => move |_, _| {
+            win.associate_language();
+        }));
+        self.add_action(&action);
+
+        // The language list is long and only needed once the button is
+        // used, so it is built after the window is drawn.
+        glib::idle_add_local(
+            clone!(@weak self as win => @default-return Continue(false), move || {
+                win.fill_language_menu();
+                Continue(false)
+            }),
+        );
+        self.update_language_button();
+    }
+
+    fn fill_language_menu(&self) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let menu = gio::Menu::new();
+        let choices = gio::Menu::new();
+        choices.append(Some(&gettext("Automatic")), Some("win.language::auto"));
+        choices.append(Some(&gettext("Plain Text")), Some("win.language::text"));
+        menu.append_section(None, &choices);
+        let languages = gio::Menu::new();
+        for (id, name) in language::LANGUAGES.iter() {
+            languages.append(Some(name), Some(&format!("win.language::{}", id)));
+        }
+        menu.append_section(None, &languages);
+        let remember = gio::Menu::new();
+        remember.append(
+            Some(&gettext("Use for This Extension")),
+            Some("win.associate-language"),
+        );
+        menu.append_section(None, &remember);
+        window.language_button.set_menu_model(Some(&menu));
+    }
+
+    // "auto" follows the detected language and "text" is plain text.
+    fn set_language(&self, language: &str) {
+        if let Some(action) = self.lookup_action("language") {
+            action.change_state(&language.to_variant());
+        }
+        self.update_language_button();
+        self.update_preview_action();
+        self.update_edit_actions();
+    }
+
+    // Files whose extension has a language chosen for it use that choice
+    // from then on, and choosing Automatic forgets it.
+    fn associate_language(&self) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let extension = window
+            .document_path
+            .borrow()
+            .as_deref()
+            .map(language::extensions)
+            .and_then(|e| e.into_iter().next());
+        let extension = match extension {
+            Some(extension) => extension,
+            None => return,
+        };
+        let choice = self
+            .lookup_action("language")
+            .and_then(|a| a.state())
+            .and_then(|s| s.get::<String>())
+            .unwrap_or_default();
+        let mut associations = self.language_associations();
+        match choice.as_str() {
+            "auto" | "" => associations.remove(&extension),
+            id => associations.insert(extension, id.to_string()),
+        };
+        if let Err(e) = window
+            .settings
+            .set_value(LANGUAGE_ASSOCIATIONS, &associations.to_variant())
+        {
+            warn!("Failed to update {}, {}", LANGUAGE_ASSOCIATIONS, e);
+        }
+    }
+
+    fn language_associations(&self) -> HashMap<String, String> {
+        let window = imp::ApplicationWindow::from_instance(self);
+        window
+            .settings
+            .value(LANGUAGE_ASSOCIATIONS)
+            .get::<HashMap<String, String>>()
+            .unwrap_or_default()
+    }
+
+    fn detect_language(&self, document: &Document) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let path = document.filepath();
+        let associated = path
+            .as_deref()
+            .and_then(|p| language::from_associations(p, &self.language_associations()));
+        let detected = match (associated, path.as_deref().and_then(language::from_path)) {
+            (Some(id), _) => id,
+            (None, Some(id)) => Some(id),
+            // Names such as Makefile are known by their comments.
+            (None, None) if matches!(path.as_deref(), Some(path) if comment::token_for(path).is_some()) => {
+                None
+            }
+            (None, None) => {
+                let text = document.text();
+                let lines = text.len_lines().min(language::DETECT_LINES);
+                language::detect(&text.slice(..text.line_to_char(lines)).to_string())
+            }
+        };
+        if window.detected_language.replace(detected) != detected {
+            self.update_language_button();
+            self.update_preview_action();
+            self.update_edit_actions();
+        }
+    }
+
+    fn language(&self) -> Option<String> {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let choice = self
+            .lookup_action("language")
+            .and_then(|a| a.state())
+            .and_then(|s| s.get::<String>())
+            .unwrap_or_default();
+        match choice.as_str() {
+            "text" => None,
+            "auto" | "" => window.detected_language.get().map(str::to_string),
+            id => Some(id.to_string()),
+        }
+    }
+
+    fn is_markdown(&self) -> bool {
+        self.language().as_deref() == Some("md")
+    }
+
+    fn update_language_button(&self) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let name = match self.language().as_deref().and_then(language::name) {
+            Some(name) => name.to_string(),
+            None => gettext("Plain Text"),
+        };
+        window.language_button.set_label(&name);
+    }
+
+    // A second view of the same buffer, which follows the main view's
+    // layout. Views on one buffer share its cursor, so each keeps its own
+    // in a mark and takes it back when it is focused.
+    fn setup_split(&self) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let buffer = window.bodytext.buffer();
+        window.split_view.set_buffer(Some(&buffer));
+        window.split_view.style_context().add_provider(
+            &window.font_provider,
+            gtk::STYLE_PROVIDER_PRIORITY_APPLICATION,
+        );
+        for property in [
+            "editable",
+            "wrap-mode",
+            "tabs",
+            "justification",
+            "pixels-inside-wrap",
+            "pixels-below-lines",
+            "left-margin",
+            "right-margin",
+        ]
+        .iter()
+        {
+            window
+                .bodytext
+                .bind_property(*property, &*window.split_view, *property)
+                .flags(glib::BindingFlags::SYNC_CREATE)
+                .build();
+        }
+        let start = buffer.start_iter();
+        for (view, split) in [(&*window.bodytext, false), (&*window.split_view, true)].iter() {
+            let split = *split;
+            let mark = buffer.create_mark(None, &start, false);
+            let controller = gtk::EventControllerFocus::new();
+            controller.connect_enter(clone!(@weak self as win, @weak mark => move |_| {
+                let window = imp::ApplicationWindow::from_instance(&win);
+                // Focus coming back from elsewhere, such as the search bar,
+                // keeps the cursor where it was moved to.
+                if window.split_focused.replace(split) != split {
+                    let buffer = window.bodytext.buffer();
+                    buffer.place_cursor(&buffer.iter_at_mark(&mark));
+                }
+            }));
+            controller.connect_leave(clone!(@weak self as win, @weak mark => move |_| {
+                let buffer = imp::ApplicationWindow::from_instance(&win).bodytext.buffer();
+                buffer.move_mark(&mark, &buffer.iter_at_mark(&buffer.get_insert()));
+            }));
+            view.add_controller(&controller);
+        }
+
+        let split = window.settings.string("split-view");
+        let action = gio::SimpleAction::new_stateful(
+            "split-view",
+            Some(&String::static_variant_type()),
+            &split.to_variant(),
+        );
+        action.connect_activate(clone!(@weak self as win => move |action, parameter| {
+            if let Some(split) = parameter.and_then(|p| p.get::<String>()) {
+                action.set_state(&split.to_variant());
+                win.set_split(&split, -1);
+            }
+        }));
+        self.add_action(&action);
+        self.set_split(&split, window.settings.int("split-position"));
+    }
+
+    // "horizontal" puts the views side by side, "vertical" one above the
+    // other and "none" hides the second. A negative position splits evenly.
+    fn set_split(&self, split: &str, position: i32) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let orientation = match split {
+            "horizontal" => gtk::Orientation::Horizontal,
+            "vertical" => gtk::Orientation::Vertical,
+            _ => {
+                if window.split_scroll.is_visible() {
+                    window.split_scroll.set_visible(false);
+                    window.bodytext.grab_focus();
+                }
+                if let Err(e) = window.settings.set_string("split-view", "none") {
+                    warn!("Failed to save the split view, {}", e);
+                }
+                return;
+            }
+        };
+        window.split_paned.set_orientation(orientation);
+        window.split_scroll.set_visible(true);
+        if position >= 0 {
+            window.split_paned.set_position(position);
+        } else {
+            // The paned is only sized once it is laid out.
+            glib::idle_add_local(
+                clone!(@weak self as win => @default-return Continue(false), move || {
+                    let window = imp::ApplicationWindow::from_instance(&win);
+                    let paned = &window.split_paned;
+                    let size = match paned.orientation() {
+                        gtk::Orientation::Horizontal => paned.width(),
+                        _ => paned.height(),
+                    };
+                    paned.set_position(size / 2);
+                    Continue(false)
+                }),
+            );
+        }
+        if let Err(e) = window.settings.set_string("split-view", split) {
+            warn!("Failed to save the split view, {}", e);
+        }
+    }
+
+    // A side panel of characters to insert. Pinned ones stay at the top, and
+    // the rest are found by name.
+    fn setup_character_map(&self) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let search = gtk::Entry::new();
+        search.set_placeholder_text(Some(&gettext("Search by name or U+code")));
+        let pinned = gtk::FlowBox::new();
+        let results = gtk::FlowBox::new();
+        for flow in [&pinned, &results].iter() {
+            flow.set_selection_mode(gtk::SelectionMode::None);
+            flow.set_valign(gtk::Align::Start);
+        }
+        let scroll = gtk::ScrolledWindow::new();
+        scroll.set_vexpand(true);
+        scroll.set_hscrollbar_policy(gtk::PolicyType::Never);
+        scroll.set_child(Some(&results));
+        let heading = gtk::Label::new(Some(&gettext("Pinned")));
+        heading.set_xalign(0.0);
+        heading.add_css_class("dim-label");
+        let hint = gtk::Label::new(Some(&gettext("Right-click a character to pin or unpin it")));
+        hint.set_wrap(true);
+        hint.set_xalign(0.0);
+        hint.add_css_class("dim-label");
+        window.charmap.append(&search);
+        window.charmap.append(&heading);
+        window.charmap.append(&pinned);
+        window.charmap.append(&scroll);
+        window.charmap.append(&hint);
+
+        search.connect_changed(clone!(@weak self as win, @weak results => move |entry| {
+            win.fill_characters(&results, &charmap::search(&entry.text()));
+        }));
+        // Every window shows the same pins.
+        window.settings.connect_changed(
+            Some(PINNED_CHARACTERS),
+            clone!(@weak self as win, @weak pinned => move |_, _| {
+                win.fill_characters(&pinned, &win.pinned_characters());
+            }),
+        );
+        self.fill_characters(&pinned, &self.pinned_characters());
+        self.fill_characters(&results, &charmap::search(""));
+
+        let shown = window.settings.boolean("character-map");
+        let action = gio::SimpleAction::new_stateful("character-map", None, &shown.to_variant());
+        action.connect_activate(clone!(@weak self as win => move |action, _| {
+            let window = imp::ApplicationWindow::from_instance(&win);
+            let shown = !action.state().and_then(|s| s.get::<bool>()).unwrap_or(false);
+            action.set_state(&shown.to_variant());
+            window.charmap_revealer.set_reveal_child(shown);
+            if let Err(e) = window.settings.set_boolean("character-map", shown) {
+                warn!("Failed to save the character map, {}", e);
+            }
+        }));
+        self.add_action(&action);
+        window.charmap_revealer.set_reveal_child(shown);
+    }
+
+    fn pinned_characters(&self) -> Vec<char> {
+        let window = imp::ApplicationWindow::from_instance(self);
+        window
+            .settings
+            .strv(PINNED_CHARACTERS)
+            .iter()
+            .filter_map(|c| c.as_str().chars().next())
+            .collect()
+    }
+
+    fn fill_characters(&self, flow: &gtk::FlowBox, characters: &[char]) {
+        while let Some(child) = flow.first_child() {
+            flow.remove(&child);
+        }
+        for &c in characters {
+            let button = gtk::Button::with_label(&c.to_string());
+            button.add_css_class("flat");
+            let code = format!("U+{:04X}", c as u32);
+            let tooltip = match charmap::name(c) {
+                Some(name) => format!("{}\n{}", name, code),
+                None => code,
+            };
+            button.set_tooltip_text(Some(&tooltip));
+            button.connect_clicked(clone!(@weak self as win => move |_| {
+                win.insert_at_cursor(&c.to_string());
+            }));
+            let gesture = gtk::GestureClick::new();
+            gesture.set_button(3);
+            gesture.connect_pressed(clone!(@weak self as win => move |_, _, _, _| {
+                win.toggle_pin(c);
+            }));
+            button.add_controller(&gesture);
+            flow.insert(&button, -1);
+        }
+    }
+
+    fn toggle_pin(&self, c: char) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let pinned: Vec<String> = window
+            .settings
+            .strv(PINNED_CHARACTERS)
+            .iter()
+            .map(|p| p.to_string())
+            .collect();
+        let pinned = charmap::toggle_pin(&pinned, c);
+        let pinned: Vec<&str> = pinned.iter().map(String::as_str).collect();
+        if let Err(e) = window.settings.set_strv(PINNED_CHARACTERS, &pinned) {
+            warn!("Failed to save the pinned characters, {}", e);
+        }
+    }
+
+    fn set_reading(&self, reading: bool) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        window.reading.set(reading);
+        let view = &window.bodytext;
+        view.set_editable(window.text_editable.get() && !reading);
+        view.set_cursor_visible(!reading);
+        let (justification, spacing) = if reading {
+            (gtk::Justification::Fill, READING_LINE_SPACING)
+        } else {
+            (gtk::Justification::Left, 0)
+        };
+        view.set_justification(justification);
+        view.set_pixels_inside_wrap(spacing);
+        view.set_pixels_below_lines(spacing);
+        window.reading_progress.set_visible(reading);
+        self.update_wrap_mode();
+        self.update_reading_column();
+        self.update_reading_progress();
+    }
+
+    fn update_reading_column(&self) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let view = &window.bodytext;
+        let margin = if window.reading.get() {
+            let layout = view.create_pango_layout(Some(&"x".repeat(READING_COLUMN)));
+            let (column, _) = layout.pixel_size();
+            let width = match view.width() {
+                0 => self.default_width(),
+                width => width,
+            };
+            reading::column_margin(width, column, READING_MIN_MARGIN)
+        } else {
+            0
+        };
+        view.set_left_margin(margin);
+        view.set_right_margin(margin);
+    }
+
+    fn turn_page(&self, forward: bool) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let view = &window.bodytext;
+        let adjustment = match view.vadjustment() {
+            Some(adjustment) => adjustment,
+            None => return,
+        };
+        let (_, line) = view.create_pango_layout(Some("x")).pixel_size();
+        adjustment.set_value(reading::turn_page(
+            adjustment.value(),
+            adjustment.upper(),
+            adjustment.page_size(),
+            f64::from(line + READING_LINE_SPACING),
+            forward,
+        ));
+    }
+
+    fn update_reading_progress(&self) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        if !window.reading.get() {
+            return;
+        }
+        if let Some(adjustment) = window.bodytext.vadjustment() {
+            let percent = reading::progress(
+                adjustment.value(),
+                adjustment.upper(),
+                adjustment.page_size(),
+            );
+            window.reading_progress.set_text(&format!("{}%", percent));
+        }
+    }
+
+    // Ctrl+Tab lists the recently used documents, most recent first, and
+    // opens the chosen one when Ctrl is let go.
+    fn setup_switcher(&self) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let controller = gtk::EventControllerKey::new();
+        controller.set_propagation_phase(gtk::PropagationPhase::Capture);
+        controller.connect_key_pressed(
+            clone!(@weak self as win => @default-return gtk::Inhibit(false), move |_, key, _, state| {
+                use gdk::keys::constants;
+                let window = imp::ApplicationWindow::from_instance(&win);
+                let open = window.switcher.is_visible();
+                let tab = key == constants::Tab || key == constants::ISO_Left_Tab;
+                if tab && state.contains(gdk::ModifierType::CONTROL_MASK) {
+                    let backward = key == constants::ISO_Left_Tab
+                        || state.contains(gdk::ModifierType::SHIFT_MASK);
+                    return gtk::Inhibit(win.cycle_switcher(backward));
+                }
+                if open && key == constants::Escape {
+                    window.switcher.hide();
+                    return gtk::Inhibit(true);
+                }
+                gtk::Inhibit(false)
+            }),
+        );
+        controller.connect_key_released(clone!(@weak self as win => move |_, key, _, _| {
+            use gdk::keys::constants;
+            let window = imp::ApplicationWindow::from_instance(&win);
+            let control = key == constants::Control_L || key == constants::Control_R;
+            if control && window.switcher.is_visible() {
+                win.finish_switcher();
+            }
+        }));
+        self.add_controller(&controller);
+        window
+            .switcher_list
+            .connect_row_activated(clone!(@weak self as win => move |list, row| {
+                list.select_row(Some(row));
+                win.finish_switcher();
+            }));
+    }
+
+    // Opens the switcher on the previous document, or moves to the next.
+    fn cycle_switcher(&self, backward: bool) -> bool {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let list = &window.switcher_list;
+        if !window.switcher.is_visible() {
+            let current = window.document_path.borrow().clone();
+            let mut entries = vec![current.clone()];
+            entries.extend(
+                window
+                    .recent
+                    .borrow()
+                    .iter()
+                    .filter(|p| Some(*p) != current.as_ref())
+                    .cloned()
+                    .map(Some),
+            );
+            if entries.len() < 2 {
+                return false;
+            }
+            while let Some(child) = list.first_child() {
+                list.remove(&child);
+            }
+            for (i, entry) in entries.iter().enumerate() {
+                list.append(&self.switcher_row(entry.as_deref(), i == 0));
+            }
+            window.switcher_entries.replace(entries);
+            list.select_row(list.row_at_index(0).as_ref());
+            window.switcher.show();
+        }
+        let len = window.switcher_entries.borrow().len();
+        let index = list
+            .selected_row()
+            .map_or(0, |row| row.index().max(0) as usize);
+        let next = recent::step(index, len, backward) as i32;
+        list.select_row(list.row_at_index(next).as_ref());
+        true
+    }
+
+    fn switcher_row(&self, path: Option<&Path>, current: bool) -> gtk::ListBoxRow {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let name = path
+            .and_then(Path::file_name)
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_else(|| gettext("Untitled"));
+        let row_box = gtk::Box::new(gtk::Orientation::Horizontal, 12);
+        row_box.set_margin_start(12);
+        row_box.set_margin_end(12);
+        row_box.set_margin_top(6);
+        row_box.set_margin_bottom(6);
+        let label = gtk::Label::new(Some(&name));
+        label.set_xalign(0.0);
+        label.set_hexpand(true);
+        row_box.append(&label);
+        // Only the open document can have unsaved changes.
+        if current && window.modified.is_visible() {
+            let badge = gtk::Label::new(Some(&gettext("Modified")));
+            badge.add_css_class("dim-label");
+            row_box.append(&badge);
+        }
+        let row = gtk::ListBoxRow::new();
+        row.set_child(Some(&row_box));
+        if let Some(dir) = path.and_then(Path::parent) {
+            row.set_tooltip_text(Some(&dir.to_string_lossy()));
+        }
+        row
+    }
+
+    fn finish_switcher(&self) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        window.switcher.hide();
+        let index = match window.switcher_list.selected_row() {
+            Some(row) if row.index() > 0 => row.index() as usize,
+            _ => return,
+        };
+        let path = window
+            .switcher_entries
+            .borrow()
+            .get(index)
+            .cloned()
+            .flatten();
+        if let Some(path) = path {
+            self.send(OpenFile(Some(gio::File::for_path(path))));
+        }
+    }
+
+    fn setup_edit_actions(&self) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let view = &window.bodytext;
+        view.buffer().connect_notify_local(
+            Some("has-selection"),
+            clone!(@weak self as win => move |_, _| {
+                win.update_edit_actions();
+            }),
+        );
+        view.connect_notify_local(
+            Some("editable"),
+            clone!(@weak self as win => move |_, _| {
+                win.update_edit_actions();
+            }),
+        );
+        view.clipboard()
+            .connect_changed(clone!(@weak self as win => move |_| {
+                win.update_edit_actions();
+            }));
+        // The application is only set after the window is constructed, and
+        // its actions follow whichever window is focused.
+        for property in ["application", "is-active"].iter() {
+            self.connect_notify_local(Some(property), |win, _| {
+                win.update_edit_actions();
+            });
+        }
+    }
+
+    fn update_edit_actions(&self) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let app = match self.application() {
+            Some(app) => app,
+            None => return,
+        };
+        let view = &window.bodytext;
+        let selected = view.buffer().has_selection();
+        let editable = view.is_editable();
+        let recording = window.recording.borrow().is_some();
+        let markdown = self.is_markdown();
+        let has_extension = matches!(
+            window.document_path.borrow().as_deref(),
+            Some(path) if !language::extensions(path).is_empty()
+        );
+        let can_paste = view
+            .clipboard()
+            .formats()
+            .is_some_and(|f| f.contains_type(glib::Type::STRING));
+        let states = [
+            ("cut", selected && editable),
+            ("copy", selected),
+            ("paste", can_paste && editable),
+            ("delete", selected && editable),
+        ];
+        // Application actions belong to the focused window.
+        let states: &[(&str, bool)] = if self.is_active() { &states } else { &[] };
+        for (name, enabled) in states.iter() {
+            let action = app
+                .lookup_action(name)
+                .and_then(|a| a.downcast::<gio::SimpleAction>().ok());
+            if let Some(action) = action {
+                action.set_enabled(*enabled);
+            }
+        }
+        let transform_states = window.transforms.iter().map(|transform| {
+            let enabled = match transform.scope() {
+                Scope::Selection => selected && editable,
+                Scope::Lines => editable,
+            };
+            (transform.id(), enabled)
+        });
+        let window_states = [
+            ("join-lines", editable),
+            ("duplicate", editable),
+            ("move-lines-up", editable),
+            ("toggle-comment", editable),
+            ("play-macro", editable && !recording),
+            ("apply-patch", editable),
+            ("move-lines-down", editable),
+            ("insert-toc", editable && markdown),
+            ("associate-language", has_extension),
+        ];
+        for (name, enabled) in window_states.iter().copied().chain(transform_states) {
+            let action = self
+                .lookup_action(name)
+                .and_then(|a| a.downcast::<gio::SimpleAction>().ok());
+            if let Some(action) = action {
+                action.set_enabled(enabled);
+            }
+        }
+    }
+
+    // Edits made while recording are kept as a macro, which replays from
+    // wherever the cursor is. The last one recorded is kept on disk.
+    fn setup_macros(&self) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let action = gio::SimpleAction::new_stateful("record-macro", None, &false.to_variant());
+        action.connect_activate(clone!(@weak self as win => move |_, _| {
+            win.toggle_macro_recording();
+        }));
+        self.add_action(&action);
+
+        let action = gio::SimpleAction::new("play-macro", None);
+        action.connect_activate(clone!(@weak self as win => move |_, _| {
+            win.play_macro();
+        }));
+        self.add_action(&action);
+
+        let view = &window.bodytext;
+        let buffer = view.buffer();
+        buffer.connect_insert_text(clone!(@weak self as win => move |buffer, location, text| {
+            if location.offset() == buffer.cursor_position() {
+                win.record_step(Step::Insert {
+                    text: text.to_string(),
+                });
+            }
+        }));
+        buffer.connect_delete_range(clone!(@weak self as win => move |buffer, start, end| {
+            let cursor = buffer.cursor_position() as usize;
+            let (start, end) = (start.offset() as usize, end.offset() as usize);
+            if let Some(step) = macros::delete_step(cursor, start, end) {
+                win.record_step(step);
+            }
+        }));
+        view.connect_move_cursor(clone!(@weak self as win => move |_, step, count, extend| {
+            if let Some(unit) = Self::macro_unit(step) {
+                win.record_step(Step::Move {
+                    unit,
+                    count,
+                    extend,
+                });
+            }
+        }));
+    }
+
+    fn record_step(&self, step: Step) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        if let Some(recording) = window.recording.borrow_mut().as_mut() {
+            recording.push(step);
+        }
+    }
+
+    fn toggle_macro_recording(&self) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let recorded = window.recording.borrow_mut().take();
+        match recorded {
+            None => {
+                window.recording.replace(Some(Macro::default()));
+                self.show_toast(&gettext("Recording a macro"));
+            }
+            Some(recorded) if recorded.is_empty() => {
+                self.show_toast(&gettext("Nothing was recorded"));
+            }
+            Some(recorded) => match macros::save(&Self::macro_path(), &recorded) {
+                Ok(()) => self.show_toast(&gettext("Macro recorded")),
+                Err(e) => {
+                    warn!("Failed to save the macro, {}", e);
+                    self.show_toast(&gettext("The macro could not be saved"));
+                }
+            },
+        }
+        let recording = window.recording.borrow().is_some();
+        let action = self
+            .lookup_action("record-macro")
+            .and_then(|a| a.downcast::<gio::SimpleAction>().ok());
+        if let Some(action) = action {
+            action.set_state(&recording.to_variant());
+        }
+        self.update_edit_actions();
+    }
+
+    // The whole macro is one undo step.
+    fn play_macro(&self) {
+        let recorded = match macros::load(&Self::macro_path()) {
+            Ok(Some(recorded)) => recorded,
+            Ok(None) => return self.show_toast(&gettext("No macro has been recorded")),
+            Err(e) => {
+                warn!("Failed to read the macro, {}", e);
+                return self.show_toast(&gettext("The macro could not be read"));
+            }
+        };
+        let window = imp::ApplicationWindow::from_instance(self);
+        let view = &window.bodytext;
+        let buffer = view.buffer();
+        buffer.begin_user_action();
+        for step in recorded.steps {
+            match step {
+                Step::Insert { text } => buffer.insert_at_cursor(&text),
+                Step::Delete { before, after } => {
+                    let cursor = buffer.cursor_position();
+                    let mut start = buffer.iter_at_offset((cursor - before as i32).max(0));
+                    let mut end = buffer.iter_at_offset(cursor + after as i32);
+                    buffer.delete(&mut start, &mut end);
+                }
+                Step::Move {
+                    unit,
+                    count,
+                    extend,
+                } => view.emit_move_cursor(Self::movement_step(unit), count, extend),
+            }
+        }
+        buffer.end_user_action();
+    }
+
+    fn macro_path() -> PathBuf {
+        config_dir().join("macro.toml")
+    }
+
+    // Moves across pages sideways only happen in wrapped views that scroll,
+    // and are left out.
+    fn macro_unit(step: gtk::MovementStep) -> Option<MoveUnit> {
+        match step {
+            gtk::MovementStep::LogicalPositions | gtk::MovementStep::VisualPositions => {
+                Some(MoveUnit::Characters)
+            }
+            gtk::MovementStep::Words => Some(MoveUnit::Words),
+            gtk::MovementStep::DisplayLines => Some(MoveUnit::Lines),
+            gtk::MovementStep::DisplayLineEnds => Some(MoveUnit::LineEnds),
+            gtk::MovementStep::Paragraphs => Some(MoveUnit::Paragraphs),
+            gtk::MovementStep::ParagraphEnds => Some(MoveUnit::ParagraphEnds),
+            gtk::MovementStep::Pages => Some(MoveUnit::Pages),
+            gtk::MovementStep::BufferEnds => Some(MoveUnit::DocumentEnds),
+            _ => None,
+        }
+    }
+
+    fn movement_step(unit: MoveUnit) -> gtk::MovementStep {
+        match unit {
+            MoveUnit::Characters => gtk::MovementStep::VisualPositions,
+            MoveUnit::Words => gtk::MovementStep::Words,
+            MoveUnit::Lines => gtk::MovementStep::DisplayLines,
+            MoveUnit::LineEnds => gtk::MovementStep::DisplayLineEnds,
+            MoveUnit::Paragraphs => gtk::MovementStep::Paragraphs,
+            MoveUnit::ParagraphEnds => gtk::MovementStep::ParagraphEnds,
+            MoveUnit::Pages => gtk::MovementStep::Pages,
+            MoveUnit::DocumentEnds => gtk::MovementStep::BufferEnds,
+        }
+    }
+
+    pub fn cut(&self) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        window.bodytext.emit_cut_clipboard();
+    }
+
+    pub fn copy(&self) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        window.bodytext.emit_copy_clipboard();
+    }
+
+    pub fn paste(&self) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        window.bodytext.emit_paste_clipboard();
+    }
+
+    // Pasted text is left as it is. Text from a terminal gets an offer to
+    // strip its prompts, which goes away once the editing carries on.
+    fn setup_paste(&self) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let view = &window.bodytext;
+        let buffer = view.buffer();
+        view.connect_paste_clipboard(clone!(@weak self as win => move |_| {
+            let window = imp::ApplicationWindow::from_instance(&win);
+            window.pasting.set(true);
+            window.pasted.replace(None);
+        }));
+        buffer.connect_insert_text(clone!(@weak self as win => move |_, location, text| {
+            let window = imp::ApplicationWindow::from_instance(&win);
+            if window.pasting.get() && window.pasted.borrow().is_none() {
+                window
+                    .pasted
+                    .replace(Some((location.offset() as usize, text.to_string())));
+            }
+        }));
+        buffer.connect_paste_done(clone!(@weak self as win => move |_, _| {
+            let window = imp::ApplicationWindow::from_instance(&win);
+            window.pasting.set(false);
+            if let Some((offset, text)) = window.pasted.take() {
+                win.suggest_stripped_paste(offset, &text);
+            }
+        }));
+        buffer.connect_changed(clone!(@weak self as win => move |_| {
+            win.dismiss_paste_suggestion();
+        }));
+        buffer.connect_notify_local(
+            Some("cursor-position"),
+            clone!(@weak self as win => move |_, _| {
+                win.dismiss_paste_suggestion();
+                win.report_view();
+            }),
+        );
+    }
+
+    // Files dropped on the text open each in a window of their own, as
+    // when several are chosen to open.
+    fn setup_drop(&self) {
+        const URI_LIST: &str = "text/uri-list";
+        let window = imp::ApplicationWindow::from_instance(self);
+        let formats = gdk::ContentFormats::new(&[URI_LIST]);
+        let target = gtk::DropTargetAsync::new(Some(&formats), gdk::DragAction::COPY);
+        target.connect_drop(
+            clone!(@weak self as win => @default-return false, move |_, drop, _, _| {
+                let dropped = drop.clone();
+                drop.read_async(
+                    &[URI_LIST],
+                    glib::PRIORITY_DEFAULT,
+                    None::<&gio::Cancellable>,
+                    clone!(@weak win => move |result| {
+                        let stream = match result {
+                            Ok((stream, _)) => stream,
+                            Err(e) => {
+                                warn!("Failed to read the dropped files, {}", e);
+                                return dropped.finish(gdk::DragAction::empty());
+                            }
+                        };
+                        let cancellable = gio::Cancellable::new();
+                        FileSystem::read_stream(stream, None, &cancellable, move |text| {
+                            match text {
+                                Ok(text) => {
+                                    dropped.finish(gdk::DragAction::COPY);
+                                    win.open_dropped(&text);
+                                }
+                                Err(e) => {
+                                    warn!("Failed to read the dropped files, {}", e);
+                                    dropped.finish(gdk::DragAction::empty());
+                                }
+                            }
+                        });
+                    }),
+                );
+                true
+            }),
+        );
+        window.bodytext.add_controller(&target);
+    }
+
+    fn open_dropped(&self, uri_list: &str) {
+        let files = FileSystem::uri_list(uri_list)
+            .into_iter()
+            .map(gio::File::for_uri)
+            .collect();
+        let app = self
+            .application()
+            .and_then(|a| a.downcast::<Application>().ok());
+        if let Some(app) = app {
+            app.open_batch(files, OpenMode::Normal);
+        }
+    }
+
+    fn suggest_stripped_paste(&self, offset: usize, pasted: &str) {
+        let stripped = match paste::strip_prompts(pasted) {
+            Some(stripped) => stripped,
+            None => return,
+        };
+        let window = imp::ApplicationWindow::from_instance(self);
+        let view = &window.bodytext;
+        let pasted = pasted.to_string();
+        let popover = gtk::Popover::new();
+        // Typing carries on while the offer is shown.
+        popover.set_autohide(false);
+        let button = gtk::Button::with_label(&gettext("Remove Terminal Prompts"));
+        button.set_has_frame(false);
+        button.set_tooltip_text(Some(&gettext(
+            "Remove the shell prompts and join continued lines",
+        )));
+        button.connect_clicked(clone!(@weak self as win => move |_| {
+            win.dismiss_paste_suggestion();
+            win.replace_pasted(offset, &pasted, &stripped);
+        }));
+        popover.set_child(Some(&button));
+        popover.set_parent(&**view);
+        let buffer = view.buffer();
+        let rect = view.iter_location(&buffer.iter_at_offset(offset as i32));
+        let (x, y) = view.buffer_to_window_coords(gtk::TextWindowType::Widget, rect.x, rect.y);
+        popover.set_pointing_to(&gdk::Rectangle {
+            x,
+            y,
+            width: rect.width.max(1),
+            height: rect.height,
+        });
+        popover.connect_closed(|popover| popover.unparent());
+        self.dismiss_paste_suggestion();
+        window.paste_popover.replace(Some(popover.clone()));
+        popover.popup();
+    }
+
+    fn dismiss_paste_suggestion(&self) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let popover = window.paste_popover.take();
+        if let Some(popover) = popover {
+            popover.popdown();
+        }
+    }
+
+    // Only while the pasted text is still there as it was.
+    fn replace_pasted(&self, offset: usize, pasted: &str, replacement: &str) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let buffer = window.bodytext.buffer();
+        let mut start = buffer.iter_at_offset(offset as i32);
+        let mut end = buffer.iter_at_offset((offset + pasted.chars().count()) as i32);
+        if buffer.text(&start, &end, false).as_str() != pasted {
+            return;
+        }
+        buffer.begin_user_action();
+        buffer.delete(&mut start, &mut end);
+        buffer.insert(&mut start, replacement);
+        buffer.end_user_action();
+    }
+
+    pub fn delete_selection(&self) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let view = &window.bodytext;
+        view.buffer().delete_selection(true, view.is_editable());
+    }
+
+    pub fn select_all(&self) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        window.bodytext.emit_select_all(true);
+    }
+
+    pub fn insert_datetime(&self) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let format = window.settings.string("datetime-format");
+        match Self::format_now(&format) {
+            Some(text) => self.insert_at_cursor(&text),
+            None => self.show_toast(&gettext("The date and time format is not valid")),
+        }
+    }
+
+    // Replaces the selection, if any.
+    fn insert_at_cursor(&self, text: &str) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let view = &window.bodytext;
+        let buffer = view.buffer();
+        buffer.begin_user_action();
+        buffer.delete_selection(true, view.is_editable());
+        buffer.insert_interactive_at_cursor(text, view.is_editable());
+        buffer.end_user_action();
+    }
+
+    fn insert_generated(&self, generated: io::Result<String>) {
+        match generated {
+            Ok(text) => self.insert_at_cursor(&text),
+            Err(e) => {
+                warn!("Failed to read random bytes, {}", e);
+                self.show_toast(&gettext("Random data is not available"));
+            }
+        }
+    }
+
+    fn show_token_dialog(&self) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let dialog = gtk::MessageDialog::new(
+            Some(self),
+            gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+            gtk::MessageType::Question,
+            gtk::ButtonsType::None,
+            &gettext("Insert Random Token"),
+        );
+        let encoding = gtk::ComboBoxText::new();
+        encoding.append(Some("hex"), &gettext("Hexadecimal"));
+        encoding.append(Some("base64"), &gettext("Base64"));
+        encoding.append(Some("base64url"), &gettext("Base64 for URLs"));
+        window
+            .settings
+            .bind("token-encoding", &encoding, "active-id")
+            .build();
+        let length = gtk::SpinButton::with_range(1.0, 1024.0, 1.0);
+        Self::activate_default_on_enter(&length);
+        window
+            .settings
+            .bind("token-length", &length, "value")
+            .build();
+        let grid = gtk::Grid::new();
+        grid.set_row_spacing(6);
+        grid.set_column_spacing(6);
+        let fields: [(String, &gtk::Widget); 2] = [
+            (gettext("Encoding"), encoding.upcast_ref()),
+            (gettext("Random bytes"), length.upcast_ref()),
+        ];
+        for (row, (text, widget)) in fields.iter().enumerate() {
+            let label = gtk::Label::new(Some(text.as_str()));
+            label.set_xalign(0.0);
+            grid.attach(&label, 0, row as i32, 1, 1);
+            grid.attach(*widget, 1, row as i32, 1, 1);
+        }
+        if let Ok(area) = dialog.message_area().downcast::<gtk::Box>() {
+            area.append(&grid);
+        }
+        dialog.add_button(&gettext("Cancel"), gtk::ResponseType::Cancel);
+        dialog.add_button(&gettext("Insert"), gtk::ResponseType::Accept);
+        dialog.set_default_response(gtk::ResponseType::Accept);
+        dialog.connect_response(clone!(@weak self as win => move |d, response| {
+            let encoding = encoding.active_id().and_then(|id| token::Encoding::from_id(&id));
+            if let (gtk::ResponseType::Accept, Some(encoding)) = (response, encoding) {
+                length.update();
+                let count = length.value_as_int() as usize;
+                win.insert_generated(token::token(count, encoding));
+            }
+            d.close();
+        }));
+        dialog.show();
+    }
+
+    // Enter in a spin button answers its dialog, as it does in an entry.
+    // The spin button itself has no such setting, but its text does.
+    fn activate_default_on_enter(spin: &gtk::SpinButton) {
+        let text = spin.delegate().and_then(|d| d.downcast::<gtk::Text>().ok());
+        if let Some(text) = text {
+            text.set_activates_default(true);
+        }
+    }
+
+    fn show_patch_dialog(&self) {
+        let dialog = gtk::MessageDialog::new(
+            Some(self),
+            gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+            gtk::MessageType::Question,
+            gtk::ButtonsType::None,
+            &gettext("Apply Patch"),
+        );
+        dialog.set_secondary_text(Some(&gettext(
+            "Apply a unified diff to this document, one change at a time.",
+        )));
+        dialog.add_button(&gettext("Cancel"), gtk::ResponseType::Cancel);
+        dialog.add_button(&gettext("From File…"), PATCH_FILE_RESPONSE);
+        dialog.add_button(&gettext("From Clipboard"), gtk::ResponseType::Accept);
+        dialog.set_default_response(gtk::ResponseType::Accept);
+        dialog.connect_response(clone!(@weak self as win => move |d, response| {
+            d.close();
+            match response {
+                gtk::ResponseType::Accept => {
+                    win.clipboard().read_text_async(
+                        None::<&gio::Cancellable>,
+                        clone!(@weak win => move |text| match text {
+                            Ok(Some(text)) => win.apply_patch(&text),
+                            _ => win.show_toast(&gettext("The clipboard has no text")),
+                        }),
+                    );
+                }
+                r if r == PATCH_FILE_RESPONSE => {
+                    let app = win.application().and_then(|a| a.downcast::<Application>().ok());
+                    if let Some(app) = app {
+                        app.open_patch(&win);
+                    }
+                }
+                _ => (),
+            }
+        }));
+        dialog.show();
+    }
+
+    pub fn apply_patch(&self, patch: &str) {
+        match patch::parse(patch) {
+            Ok(hunks) => self.confirm_hunk(Rc::new(hunks), 0, 0, 0, Vec::new()),
+            Err(e) => self.show_toast(&e),
+        }
+    }
+
+    // Hunks are offered in order, each found again after the ones before it
+    // were applied or skipped. `shift` is how far the lines have moved.
+    fn confirm_hunk(
+        &self,
+        hunks: Rc<Vec<Hunk>>,
+        index: usize,
+        shift: isize,
+        applied: usize,
+        mut rejected: Vec<String>,
+    ) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let hunk = match hunks.get(index) {
+            Some(hunk) => hunk,
+            None => return self.report_patch(hunks.len(), applied, &rejected),
+        };
+        let text = Self::get_buffer_value(window.bodytext.buffer());
+        let edit = match patch::locate(&text, hunk, shift) {
+            Some(edit) => edit,
+            None => {
+                rejected.push(hunk.header.clone());
+                return self.confirm_hunk(hunks.clone(), index + 1, shift, applied, rejected);
+            }
+        };
+        self.select_range(edit.start, edit.end);
+        let dialog = gtk::MessageDialog::new(
+            Some(self),
+            gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+            gtk::MessageType::Question,
+            gtk::ButtonsType::None,
+            &format!(
+                "{} {} / {}",
+                gettext("Apply change"),
+                index + 1,
+                hunks.len()
+            ),
+        );
+        dialog.set_secondary_text(Some(&format!(
+            "{} {} {}",
+            hunk.header,
+            gettext("at line"),
+            edit.line + 1
+        )));
+        let (old, new) = (hunk.old.join("\n"), hunk.new.join("\n"));
+        let preview: String = diff::diff_lines(&old, &new)
+            .iter()
+            .map(|line| format!("{} {}\n", line.prefix(), line.text()))
+            .collect();
+        let label = gtk::Label::new(Some(preview.trim_end()));
+        label.add_css_class("monospace");
+        label.set_xalign(0.0);
+        label.set_selectable(true);
+        if let Ok(area) = dialog.message_area().downcast::<gtk::Box>() {
+            area.append(&label);
+        }
+        dialog.add_button(&gettext("Stop"), gtk::ResponseType::Cancel);
+        dialog.add_button(&gettext("Skip"), gtk::ResponseType::Reject);
+        dialog.add_button(&gettext("Apply"), gtk::ResponseType::Accept);
+        dialog.set_default_response(gtk::ResponseType::Accept);
+        dialog.connect_response(clone!(@weak self as win => move |d, response| {
+            d.close();
+            let window = imp::ApplicationWindow::from_instance(&win);
+            let rejected = rejected.clone();
+            match response {
+                gtk::ResponseType::Accept => {
+                    let buffer = window.bodytext.buffer();
+                    let mut start = buffer.iter_at_offset(edit.start as i32);
+                    let mut end = buffer.iter_at_offset(edit.end as i32);
+                    buffer.begin_user_action();
+                    buffer.delete(&mut start, &mut end);
+                    buffer.insert(&mut start, &edit.replacement);
+                    buffer.end_user_action();
+                    let shift = edit.offset + hunks[index].growth();
+                    win.confirm_hunk(hunks.clone(), index + 1, shift, applied + 1, rejected);
+                }
+                gtk::ResponseType::Reject => {
+                    win.confirm_hunk(hunks.clone(), index + 1, edit.offset, applied, rejected);
+                }
+                _ => win.report_patch(hunks.len(), applied, &rejected),
+            }
+        }));
+        dialog.show();
+    }
+
+    fn report_patch(&self, total: usize, applied: usize, rejected: &[String]) {
+        let summary = format!("{} {} / {}", gettext("Changes applied:"), applied, total);
+        if rejected.is_empty() {
+            return self.show_toast(&summary);
+        }
+        let dialog = gtk::MessageDialog::new(
+            Some(self),
+            gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+            gtk::MessageType::Warning,
+            gtk::ButtonsType::Close,
+            &summary,
+        );
+        dialog.set_secondary_text(Some(&format!(
+            "{}\n{}",
+            gettext("These changes did not match the document:"),
+            rejected.join("\n")
+        )));
+        dialog.connect_response(|d, _| d.close());
+        dialog.show();
+    }
+
+    fn format_now(format: &str) -> Option<String> {
+        let now = glib::DateTime::new_now_local().ok()?;
+        now.format(format).ok().map(|text| text.to_string())
+    }
+
+    pub fn show_datetime_format_dialog(&self) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let dialog = gtk::MessageDialog::new(
+            Some(self),
+            gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+            gtk::MessageType::Question,
+            gtk::ButtonsType::None,
+            &gettext("Date and Time Format"),
+        );
+        dialog.set_secondary_text(Some(&gettext(
+            "Uses codes such as %Y for the year, %m for the month, %d for the day, %H:%M for the time and %x for the locale's date.",
+        )));
+        let combo = gtk::ComboBoxText::with_entry();
+        for format in DATETIME_FORMATS.iter() {
+            combo.append_text(format);
+        }
+        let preview = gtk::Label::new(None);
+        preview.set_xalign(0.0);
+        preview.set_selectable(true);
+        if let Some(entry) = combo.child().and_then(|c| c.downcast::<gtk::Entry>().ok()) {
+            entry.set_activates_default(true);
+            entry.connect_changed(clone!(@weak preview => move |entry| {
+                let text = Self::format_now(&entry.text())
+                    .unwrap_or_else(|| gettext("Not a valid format"));
+                preview.set_text(&text);
+            }));
+            entry.set_text(&window.settings.string("datetime-format"));
+        }
+        if let Ok(area) = dialog.message_area().downcast::<gtk::Box>() {
+            area.append(&combo);
+            area.append(&preview);
+        }
+        dialog.add_button(&gettext("Cancel"), gtk::ResponseType::Cancel);
+        dialog.add_button(&gettext("Use Format"), gtk::ResponseType::Accept);
+        dialog.set_default_response(gtk::ResponseType::Accept);
+        dialog.connect_response(clone!(@weak self as win => move |d, response| {
+            let format = combo.active_text().map(|t| t.to_string()).unwrap_or_default();
+            if response == gtk::ResponseType::Accept && Self::format_now(&format).is_some() {
+                let window = imp::ApplicationWindow::from_instance(&win);
+                if let Err(e) = window.settings.set_string("datetime-format", &format) {
+                    warn!("Failed to update datetime-format, {}", e);
+                }
+            }
+            d.close();
+        }));
+        dialog.show();
+    }
+
+    fn setup_tools(&self) {
+        self.setup_transforms();
+
+        let action = gio::SimpleAction::new("convert-locale", None);
+        action.connect_activate(clone!(@weak self as win => move |_, _| {
+            win.show_locale_converter();
+        }));
+        self.add_action(&action);
+
+        let action = gio::SimpleAction::new("find-invisible", None);
+        action.connect_activate(clone!(@weak self as win => move |_, _| {
+            win.show_invisible_checker();
+        }));
+        self.add_action(&action);
+
+        let action = gio::SimpleAction::new("normalize-unicode", None);
+        action.connect_activate(clone!(@weak self as win => move |_, _| {
+            win.show_normalizer();
+        }));
+        self.add_action(&action);
+
+        let action = gio::SimpleAction::new("sort-lines", None);
+        action.connect_activate(clone!(@weak self as win => move |_, _| {
+            win.show_sort_dialog();
+        }));
+        self.add_action(&action);
+
+        let action = gio::SimpleAction::new("join-lines", None);
+        action.connect_activate(clone!(@weak self as win => move |_, _| {
+            win.join_lines();
+        }));
+        self.add_action(&action);
+
+        let action = gio::SimpleAction::new("duplicate", None);
+        action.connect_activate(clone!(@weak self as win => move |_, _| {
+            win.duplicate();
+        }));
+        self.add_action(&action);
+
+        let action = gio::SimpleAction::new("move-lines-up", None);
+        action.connect_activate(clone!(@weak self as win => move |_, _| {
+            win.move_lines(true);
+        }));
+        self.add_action(&action);
+
+        let action = gio::SimpleAction::new("move-lines-down", None);
+        action.connect_activate(clone!(@weak self as win => move |_, _| {
+            win.move_lines(false);
+        }));
+        self.add_action(&action);
+
+        let action = gio::SimpleAction::new("expand-selection", None);
+        action.connect_activate(clone!(@weak self as win => move |_, _| {
+            win.change_selection(true);
+        }));
+        self.add_action(&action);
+
+        let action = gio::SimpleAction::new("shrink-selection", None);
+        action.connect_activate(clone!(@weak self as win => move |_, _| {
+            win.change_selection(false);
+        }));
+        self.add_action(&action);
+
+        let action = gio::SimpleAction::new("toggle-comment", None);
+        action.connect_activate(clone!(@weak self as win => move |_, _| {
+            win.toggle_comment();
+        }));
+        self.add_action(&action);
+
+        let action = gio::SimpleAction::new("number-lines", None);
+        action.connect_activate(clone!(@weak self as win => move |_, _| {
+            win.show_numbering_dialog();
+        }));
+        self.add_action(&action);
+
+        let action = gio::SimpleAction::new("apply-patch", None);
+        action.connect_activate(clone!(@weak self as win => move |_, _| {
+            win.show_patch_dialog();
+        }));
+        self.add_action(&action);
+
+        let action = gio::SimpleAction::new("insert-table", None);
+        action.connect_activate(clone!(@weak self as win => move |_, _| {
+            win.show_table_dialog();
+        }));
+        self.add_action(&action);
+
+        let action = gio::SimpleAction::new("insert-toc", None);
+        action.connect_activate(clone!(@weak self as win => move |_, _| {
+            win.insert_toc();
+        }));
+        self.add_action(&action);
+
+        let action = gio::SimpleAction::new("frame-lines", None);
+        action.connect_activate(clone!(@weak self as win => move |_, _| {
+            let style = win.box_style();
+            win.transform_lines(|text| {
+                let newline = if text.ends_with('\n') { "\n" } else { "" };
+                Table::frame(text, style).render() + newline
+            });
+        }));
+        self.add_action(&action);
+
+        let action = gio::SimpleAction::new("reflow-box", None);
+        action.connect_activate(clone!(@weak self as win => move |_, _| {
+            win.reflow_box();
+        }));
+        self.add_action(&action);
+
+        let action = gio::SimpleAction::new("insert-banner", None);
+        action.connect_activate(clone!(@weak self as win => move |_, _| {
+            win.show_banner_dialog();
+        }));
+        self.add_action(&action);
+
+        let action = gio::SimpleAction::new("insert-placeholder", None);
+        action.connect_activate(clone!(@weak self as win => move |_, _| {
+            win.show_placeholder_dialog();
+        }));
+        self.add_action(&action);
+
+        let action = gio::SimpleAction::new("convert-timestamp", None);
+        action.connect_activate(clone!(@weak self as win => move |_, _| {
+            win.convert_timestamp();
+        }));
+        self.add_action(&action);
+
+        let action = gio::SimpleAction::new("insert-uuid", None);
+        action.connect_activate(clone!(@weak self as win => move |_, _| {
+            win.insert_generated(token::uuid());
+        }));
+        self.add_action(&action);
+
+        let action = gio::SimpleAction::new("insert-ulid", None);
+        action.connect_activate(clone!(@weak self as win => move |_, _| {
+            win.insert_generated(token::ulid());
+        }));
+        self.add_action(&action);
+
+        let action = gio::SimpleAction::new("insert-token", None);
+        action.connect_activate(clone!(@weak self as win => move |_, _| {
+            win.show_token_dialog();
+        }));
+        self.add_action(&action);
+    }
+
+    fn box_style(&self) -> BoxStyle {
+        let window = imp::ApplicationWindow::from_instance(self);
+        match window.settings.string("box-style").as_str() {
+            "ascii" => BoxStyle::Ascii,
+            _ => BoxStyle::Lines,
+        }
+    }
+
+    fn show_table_dialog(&self) {
+        let dialog = gtk::MessageDialog::new(
+            Some(self),
+            gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+            gtk::MessageType::Question,
+            gtk::ButtonsType::None,
+            &gettext("Insert Table"),
+        );
+        let rows = gtk::SpinButton::with_range(1.0, 100.0, 1.0);
+        rows.set_value(3.0);
+        let columns = gtk::SpinButton::with_range(1.0, 20.0, 1.0);
+        columns.set_value(3.0);
+        let width = gtk::SpinButton::with_range(1.0, 80.0, 1.0);
+        width.set_value(8.0);
+        let style = gtk::ComboBoxText::new();
+        style.append(Some("lines"), &gettext("Box-drawing lines"));
+        style.append(Some("ascii"), &gettext("ASCII"));
+        let window = imp::ApplicationWindow::from_instance(self);
+        window
+            .settings
+            .bind("box-style", &style, "active-id")
+            .build();
+        let header = gtk::CheckButton::with_label(&gettext("Header row"));
+        header.set_active(true);
+        let grid = gtk::Grid::new();
+        grid.set_row_spacing(6);
+        grid.set_column_spacing(6);
+        let fields: [(String, &gtk::Widget); 4] = [
+            (gettext("Rows"), rows.upcast_ref()),
+            (gettext("Columns"), columns.upcast_ref()),
+            (gettext("Cell width"), width.upcast_ref()),
+            (gettext("Style"), style.upcast_ref()),
+        ];
+        for (row, (text, widget)) in fields.iter().enumerate() {
+            let label = gtk::Label::new(Some(text.as_str()));
+            label.set_xalign(0.0);
+            grid.attach(&label, 0, row as i32, 1, 1);
+            grid.attach(*widget, 1, row as i32, 1, 1);
+        }
+        if let Ok(area) = dialog.message_area().downcast::<gtk::Box>() {
+            area.append(&grid);
+            area.append(&header);
+        }
+        dialog.add_button(&gettext("Cancel"), gtk::ResponseType::Cancel);
+        dialog.add_button(&gettext("Insert"), gtk::ResponseType::Accept);
+        dialog.set_default_response(gtk::ResponseType::Accept);
+        dialog.connect_response(clone!(@weak self as win => move |d, response| {
+            if response == gtk::ResponseType::Accept {
+                let table = Table::empty(
+                    rows.value_as_int() as usize,
+                    columns.value_as_int() as usize,
+                    width.value_as_int() as usize,
+                    header.is_active(),
+                    win.box_style(),
+                );
+                win.insert_block(&table.render());
+            }
+            d.close();
+        }));
+        dialog.show();
+    }
+
+    // Starts from a selection on one line. A prefix such as `# ` makes the
+    // banner a comment in config files.
+    fn show_banner_dialog(&self) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let buffer = window.bodytext.buffer();
+        let dialog = gtk::MessageDialog::new(
+            Some(self),
+            gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+            gtk::MessageType::Question,
+            gtk::ButtonsType::None,
+            &gettext("Insert Banner"),
+        );
+        let text = gtk::Entry::new();
+        text.set_placeholder_text(Some(&gettext("Text")));
+        text.set_activates_default(true);
+        if let Some((start, end)) = buffer.selection_bounds() {
+            let selected = buffer.text(&start, &end, false);
+            if !selected.contains('\n') {
+                text.set_text(selected.trim());
+            }
+        }
+        let prefix = gtk::Entry::new();
+        prefix.set_placeholder_text(Some(&gettext("Line prefix, such as #")));
+        prefix.set_activates_default(true);
+        let preview = gtk::Label::new(None);
+        preview.set_xalign(0.0);
+        preview.set_selectable(true);
+        preview.add_css_class("monospace");
+        let banner = clone!(@weak text, @weak prefix => @default-return String::new(), move || {
+            banner::BLOCK
+                .render(&text.text())
+                .lines()
+                .map(|line| format!("{}{}", prefix.text(), line).trim_end().to_string())
+                .collect::<Vec<_>>()
+                .join("\n")
+        });
+        let banner = Rc::new(banner);
+        let update = clone!(@weak preview, @strong banner => move |_: &gtk::Entry| {
+            preview.set_text(&banner());
+        });
+        text.connect_changed(update.clone());
+        prefix.connect_changed(update);
+        preview.set_text(&banner());
+        let scroller = gtk::ScrolledWindow::new();
+        scroller.set_min_content_height(120);
+        scroller.set_min_content_width(400);
+        scroller.set_child(Some(&preview));
+        if let Ok(area) = dialog.message_area().downcast::<gtk::Box>() {
+            area.append(&text);
+            area.append(&prefix);
+            area.append(&scroller);
+        }
+        dialog.add_button(&gettext("Cancel"), gtk::ResponseType::Cancel);
+        dialog.add_button(&gettext("Insert"), gtk::ResponseType::Accept);
+        dialog.set_default_response(gtk::ResponseType::Accept);
+        dialog.connect_response(clone!(@weak self as win => move |d, response| {
+            let text = banner();
+            if response == gtk::ResponseType::Accept && !text.is_empty() {
+                win.insert_block(&text);
+            }
+            d.close();
+        }));
+        dialog.show();
+    }
+
+    fn show_placeholder_dialog(&self) {
+        let dialog = gtk::MessageDialog::new(
+            Some(self),
+            gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+            gtk::MessageType::Question,
+            gtk::ButtonsType::None,
+            &gettext("Insert Placeholder Text"),
+        );
+        let kind = gtk::ComboBoxText::new();
+        kind.append(Some("paragraphs"), &gettext("Lorem ipsum paragraphs"));
+        kind.append(Some("names"), &gettext("Names"));
+        kind.append(Some("emails"), &gettext("Email addresses"));
+        kind.append(Some("uuids"), &gettext("UUIDs"));
+        kind.append(Some("timestamps"), &gettext("Timestamps"));
+        kind.set_active_id(Some("paragraphs"));
+        let count = gtk::SpinButton::with_range(1.0, 1000.0, 1.0);
+        count.set_value(3.0);
+        Self::activate_default_on_enter(&count);
+        let grid = gtk::Grid::new();
+        grid.set_row_spacing(6);
+        grid.set_column_spacing(6);
+        let fields: [(String, &gtk::Widget); 2] = [
+            (gettext("Generate"), kind.upcast_ref()),
+            (gettext("Count"), count.upcast_ref()),
+        ];
+        for (row, (text, widget)) in fields.iter().enumerate() {
+            let label = gtk::Label::new(Some(text.as_str()));
+            label.set_xalign(0.0);
+            grid.attach(&label, 0, row as i32, 1, 1);
+            grid.attach(*widget, 1, row as i32, 1, 1);
+        }
+        if let Ok(area) = dialog.message_area().downcast::<gtk::Box>() {
+            area.append(&grid);
+        }
+        dialog.add_button(&gettext("Cancel"), gtk::ResponseType::Cancel);
+        dialog.add_button(&gettext("Insert"), gtk::ResponseType::Accept);
+        dialog.set_default_response(gtk::ResponseType::Accept);
+        dialog.connect_response(clone!(@weak self as win => move |d, response| {
+            let kind = kind.active_id().and_then(|id| placeholder::Kind::from_id(&id));
+            if let (gtk::ResponseType::Accept, Some(kind)) = (response, kind) {
+                let mut rng = placeholder::Rng::from_time();
+                let count = count.value_as_int() as usize;
+                win.insert_block(&placeholder::generate(kind, count, &mut rng));
+            }
+            d.close();
+        }));
+        dialog.show();
+    }
+
+    // Inserts whole lines at the cursor.
+    fn insert_block(&self, block: &str) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let buffer = window.bodytext.buffer();
+        let mut iter = buffer.iter_at_offset(buffer.cursor_position());
+        let mut text = String::new();
+        if !iter.starts_line() {
+            text.push('\n');
+        }
+        text.push_str(block);
+        text.push('\n');
+        buffer.begin_user_action();
+        buffer.insert(&mut iter, &text);
+        buffer.end_user_action();
+    }
+
+    // Without a selection, reflows the box around the cursor.
+    fn reflow_box(&self) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let buffer = window.bodytext.buffer();
+        if !buffer.has_selection() {
+            let cursor = buffer.iter_at_offset(buffer.cursor_position());
+            let is_box_line = |line: i32| {
+                let start = match buffer.iter_at_line(line) {
+                    Some(start) => start,
+                    None => return false,
+                };
+                let mut end = start.clone();
+                if !end.ends_line() {
+                    end.forward_to_line_end();
+                }
+                text_box::is_box_line(&buffer.text(&start, &end, false))
+            };
+            let mut first = cursor.line();
+            let mut last = cursor.line();
+            if !is_box_line(first) {
+                self.show_toast(&gettext("There is no box at the cursor"));
+                return;
+            }
+            while first > 0 && is_box_line(first - 1) {
+                first -= 1;
+            }
+            while last + 1 < buffer.line_count() && is_box_line(last + 1) {
+                last += 1;
+            }
+            if let (Some(start), Some(mut end)) =
+                (buffer.iter_at_line(first), buffer.iter_at_line(last))
+            {
+                if !end.ends_line() {
+                    end.forward_to_line_end();
+                }
+                buffer.select_range(&start, &end);
+            }
+        }
+        let mut found = true;
+        self.transform_lines(|text| {
+            text_box::reflow(text).unwrap_or_else(|| {
+                found = false;
+                text.to_string()
+            })
+        });
+        if !found {
+            self.show_toast(&gettext("The selection is not a box or table"));
+        }
+    }
+
+    fn show_sort_dialog(&self) {
+        let dialog = gtk::MessageDialog::new(
+            Some(self),
+            gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+            gtk::MessageType::Question,
+            gtk::ButtonsType::None,
+            &gettext("Sort Lines"),
+        );
+        let order = gtk::ComboBoxText::new();
+        order.append(Some("alphabetical"), &gettext("Alphabetical"));
+        order.append(Some("natural"), &gettext("Natural (file2 before file10)"));
+        order.append(Some("numeric"), &gettext("Numeric"));
+        order.set_active_id(Some("alphabetical"));
+        let reverse = gtk::CheckButton::with_label(&gettext("Reverse order"));
+        let ignore_case = gtk::CheckButton::with_label(&gettext("Ignore case"));
+        let column = gtk::SpinButton::with_range(0.0, 99.0, 1.0);
+        column.set_tooltip_text(Some(&gettext("0 sorts by the whole line")));
+        let delimiter = gtk::Entry::new();
+        delimiter.set_placeholder_text(Some(&gettext("Whitespace")));
+        delimiter.set_width_chars(8);
+        let grid = gtk::Grid::new();
+        grid.set_row_spacing(6);
+        grid.set_column_spacing(6);
+        let field_label = gtk::Label::new(Some(&gettext("Sort by field")));
+        field_label.set_xalign(0.0);
+        let delimiter_label = gtk::Label::new(Some(&gettext("Delimiter")));
+        delimiter_label.set_xalign(0.0);
+        grid.attach(&field_label, 0, 0, 1, 1);
+        grid.attach(&column, 1, 0, 1, 1);
+        grid.attach(&delimiter_label, 0, 1, 1, 1);
+        grid.attach(&delimiter, 1, 1, 1, 1);
+        if let Ok(area) = dialog.message_area().downcast::<gtk::Box>() {
+            area.append(&order);
+            area.append(&reverse);
+            area.append(&ignore_case);
+            area.append(&grid);
+        }
+        dialog.add_button(&gettext("Cancel"), gtk::ResponseType::Cancel);
+        dialog.add_button(&gettext("Sort"), gtk::ResponseType::Accept);
+        dialog.set_default_response(gtk::ResponseType::Accept);
+        dialog.connect_response(clone!(@weak self as win => move |d, response| {
+            if response == gtk::ResponseType::Accept {
+                let sort_order = match order.active_id().as_deref() {
+                    Some("natural") => SortOrder::Natural,
+                    Some("numeric") => SortOrder::Numeric,
+                    _ => SortOrder::Alphabetical,
+                };
+                let field = column.value_as_int() as usize;
+                let options = SortOptions {
+                    order: sort_order,
+                    reverse: reverse.is_active(),
+                    ignore_case: ignore_case.is_active(),
+                    column: Some(field).filter(|&f| f > 0),
+                    delimiter: delimiter.text().to_string(),
+                };
+                win.transform_lines(|text| text_ops::sort_lines(text, &options));
+            }
+            d.close();
+        }));
+        dialog.show();
+    }
+
+    // Replaces the whole lines the selection touches, or every line when
+    // nothing is selected, as one undo step.
+    fn transform_lines<F>(&self, transform: F)
+    where
+        F: FnOnce(&str) -> String,
+    {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let buffer = window.bodytext.buffer();
+        let (mut start, mut end) = buffer.selection_bounds().unwrap_or_else(|| buffer.bounds());
+        start.set_line_offset(0);
+        if !end.starts_line() {
+            end.forward_to_line_end();
+        }
+        let text = buffer.text(&start, &end, false).to_string();
+        let transformed = transform(&text);
+        if transformed != text {
+            buffer.begin_user_action();
+            buffer.delete(&mut start, &mut end);
+            buffer.insert(&mut start, &transformed);
+            buffer.end_user_action();
+        }
+    }
+
+    // Updates the table of contents between its markers, or inserts one at
+    // the cursor when there is none yet.
+    fn insert_toc(&self) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let buffer = window.bodytext.buffer();
+        let text = Self::get_buffer_value(buffer.clone());
+        let depth = window.settings.int("toc-depth").max(1) as u8;
+        let numbered = window.settings.boolean("toc-numbered");
+        buffer.begin_user_action();
+        match toc::update(&text, depth, numbered) {
+            Some((start, end, replacement)) => {
+                let mut start = buffer.iter_at_offset(start as i32);
+                let mut end = buffer.iter_at_offset(end as i32);
+                buffer.delete(&mut start, &mut end);
+                buffer.insert(&mut start, &replacement);
+            }
+            None => {
+                buffer.delete_selection(true, true);
+                buffer.insert_at_cursor(&toc::block(&text, depth, numbered));
+            }
+        }
+        buffer.end_user_action();
+    }
+
+    // Each transform is an action named by its id, listed in its menu.
+    fn setup_transforms(&self) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        for transform in window.transforms.iter() {
+            let id = transform.id();
+            let action = gio::SimpleAction::new(id, None);
+            action.connect_activate(clone!(@weak self as win => move |_, _| {
+                let window = imp::ApplicationWindow::from_instance(&win);
+                if let Some(transform) = window.transforms.find(id) {
+                    win.apply_transform(transform);
+                }
+            }));
+            self.add_action(&action);
+            let menu = match transform.menu() {
+                transforms::Menu::ChangeCase => &window.case_menu,
+                transforms::Menu::Tools => &window.lines_menu,
+            };
+            menu.append(Some(&transform.name()), Some(&format!("win.{}", id)));
+        }
+    }
+
+    fn apply_transform(&self, transform: &dyn TextTransform) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let selection = if window.bodytext.buffer().has_selection() {
+            transforms::Selection::Selected
+        } else {
+            transforms::Selection::Document
+        };
+        match transform.scope() {
+            Scope::Lines => self.transform_lines(|text| transform.apply(text, selection)),
+            Scope::Selection => self.transform_selection(|text| transform.apply(text, selection)),
+        }
+    }
+
+    // Replaces the selection, keeping it selected, as one undo step.
+    fn transform_selection<F>(&self, transform: F)
+    where
+        F: FnOnce(&str) -> String,
+    {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let buffer = window.bodytext.buffer();
+        let (mut start, mut end) = match buffer.selection_bounds() {
+            Some(bounds) => bounds,
+            None => return,
+        };
+        let text = buffer.text(&start, &end, false).to_string();
+        let changed = transform(&text);
+        if changed == text {
+            return;
+        }
+        let first = start.offset();
+        buffer.begin_user_action();
+        buffer.delete(&mut start, &mut end);
+        buffer.insert(&mut start, &changed);
+        buffer.end_user_action();
+        buffer.select_range(&buffer.iter_at_offset(first), &start);
+    }
+
+    // Joins the selected lines, or the cursor's line with the next one.
+    fn join_lines(&self) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let buffer = window.bodytext.buffer();
+        let selected = buffer.selection_bounds();
+        let cursor = buffer.iter_at_offset(buffer.cursor_position());
+        let (mut start, mut end) = selected
+            .clone()
+            .unwrap_or_else(|| (cursor.clone(), cursor.clone()));
+        start.set_line_offset(0);
+        if selected.is_some() && end.starts_line() && end.line() > start.line() {
+            end.backward_char();
+        }
+        if end.line() == start.line() {
+            end.forward_line();
+        }
+        if !end.ends_line() {
+            end.forward_to_line_end();
+        }
+        let text = buffer.text(&start, &end, false).to_string();
+        let joined = text_ops::join_lines(&text);
+        if joined == text {
+            return;
+        }
+        let first = start.offset();
+        // The cursor goes where the first two lines were joined.
+        let join = first
+            + text
+                .lines()
+                .next()
+                .unwrap_or_default()
+                .trim_end()
+                .chars()
+                .count() as i32;
+        buffer.begin_user_action();
+        buffer.delete(&mut start, &mut end);
+        buffer.insert(&mut start, &joined);
+        buffer.end_user_action();
+        if selected.is_some() {
+            buffer.select_range(&buffer.iter_at_offset(first), &start);
+        } else {
+            buffer.place_cursor(&buffer.iter_at_offset(join));
+        }
+    }
+
+    // Repeats the selection after itself, or the cursor's line below it.
+    fn duplicate(&self) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let buffer = window.bodytext.buffer();
+        buffer.begin_user_action();
+        if let Some((start, mut end)) = buffer.selection_bounds() {
+            let text = buffer.text(&start, &end, false).to_string();
+            let copied = end.offset();
+            buffer.insert(&mut end, &text);
+            buffer.select_range(&buffer.iter_at_offset(copied), &end);
+        } else {
+            let cursor = buffer.iter_at_offset(buffer.cursor_position());
+            let column = cursor.line_offset();
+            let mut start = cursor.clone();
+            start.set_line_offset(0);
+            // The line is taken with its own ending, which may be `\r\n`.
+            let mut end = start.clone();
+            end.forward_line();
+            let line = buffer.text(&start, &end, false).to_string();
+            // The last line has none, so takes the one before it.
+            let mut before = start.clone();
+            let ending = if before.backward_line() {
+                before.forward_to_line_end();
+                buffer.text(&before, &start, false).to_string()
+            } else {
+                "\n".to_string()
+            };
+            let duplicated = text_ops::duplicate_lines(&line, &ending);
+            let first = start.offset();
+            buffer.delete(&mut start, &mut end);
+            buffer.insert(&mut start, &duplicated);
+            let copy = duplicated.chars().count() - line.chars().count();
+            let below = first + copy as i32 + column;
+            buffer.place_cursor(&buffer.iter_at_offset(below));
+        }
+        buffer.end_user_action();
+    }
+
+    // Grows the selection to the word, sentence, paragraph and document
+    // around it, or shrinks it back through the same steps.
+    fn change_selection(&self, expand: bool) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let buffer = window.bodytext.buffer();
+        let range = match buffer.selection_bounds() {
+            Some((start, end)) => (start.offset() as usize, end.offset() as usize),
+            None => {
+                let cursor = buffer.cursor_position() as usize;
+                (cursor, cursor)
+            }
+        };
+        let selected = {
+            let mut expansions = window.expansions.borrow_mut();
+            if expand {
+                expansions.expand(&Self::get_buffer_value(buffer), range)
+            } else {
+                expansions.shrink(range)
+            }
+        };
+        if let Some((start, end)) = selected {
+            self.select_range(start, end);
+        }
+    }
+
+    // Swaps the cursor's line, or the selected lines, with the line above
+    // or below, keeping the selection on the moved text.
+    fn move_lines(&self, up: bool) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let buffer = window.bodytext.buffer();
+        let selected = buffer.selection_bounds();
+        let cursor = buffer.iter_at_offset(buffer.cursor_position());
+        let (mut start, mut end) = selected
+            .clone()
+            .unwrap_or_else(|| (cursor.clone(), cursor.clone()));
+        start.set_line_offset(0);
+        if selected.is_some() && end.starts_line() && end.line() > start.line() {
+            end.backward_char();
+        }
+        if !end.ends_line() {
+            end.forward_to_line_end();
+        }
+        // The moved text shifts by the neighbouring line and its line
+        // ending, which may be `\r\n`.
+        let shift = if up {
+            if start.line() == 0 {
+                return;
+            }
+            let moved_start = start.offset();
+            start.backward_line();
+            -(moved_start - start.offset())
+        } else {
+            if end.line() + 1 >= buffer.line_count() {
+                return;
+            }
+            let moved_end = end.offset();
+            end.forward_line();
+            if !end.ends_line() {
+                end.forward_to_line_end();
+            }
+            end.offset() - moved_end
+        };
+        let (anchor, position) = match &selected {
+            Some((a, b)) => (a.offset() + shift, b.offset() + shift),
+            None => (cursor.offset() + shift, cursor.offset() + shift),
+        };
+        let text = buffer.text(&start, &end, false).to_string();
+        let rotated = text_ops::rotate_lines(&text, up);
+        buffer.begin_user_action();
+        buffer.delete(&mut start, &mut end);
+        buffer.insert(&mut start, &rotated);
+        buffer.end_user_action();
+        buffer.select_range(
+            &buffer.iter_at_offset(position),
+            &buffer.iter_at_offset(anchor),
+        );
+        window.bodytext.scroll_mark_onscreen(&buffer.get_insert());
+    }
+
+    // Files in a language without line comments, and untitled documents,
+    // use the token from the preferences.
+    fn toggle_comment(&self) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let buffer = window.bodytext.buffer();
+        let token = match self.language() {
+            Some(language) => comment::token_for_language(&language),
+            None => window
+                .document_path
+                .borrow()
+                .as_deref()
+                .and_then(comment::token_for),
+        };
+        let token = match token {
+            Some(token) => token.to_string(),
+            None => window.settings.string("comment-token").to_string(),
+        };
+        if token.trim().is_empty() {
+            return;
+        }
+        let selected = buffer.selection_bounds();
+        let cursor = buffer.iter_at_offset(buffer.cursor_position());
+        let (mut start, mut end) = selected
+            .clone()
+            .unwrap_or_else(|| (cursor.clone(), cursor.clone()));
+        start.set_line_offset(0);
+        if selected.is_some() && end.starts_line() && end.line() > start.line() {
+            end.backward_char();
+        }
+        if !end.ends_line() {
+            end.forward_to_line_end();
+        }
+        let from_end = end.offset() - cursor.offset();
+        let text = buffer.text(&start, &end, false).to_string();
+        let toggled = comment::toggle(&text, token.trim());
+        if toggled == text {
+            return;
+        }
+        let first = start.offset();
+        buffer.begin_user_action();
+        buffer.delete(&mut start, &mut end);
+        buffer.insert(&mut start, &toggled);
+        buffer.end_user_action();
+        let last = start.offset();
+        if selected.is_some() {
+            buffer.select_range(&buffer.iter_at_offset(first), &buffer.iter_at_offset(last));
+        } else {
+            let cursor = (last - from_end).max(first);
+            buffer.place_cursor(&buffer.iter_at_offset(cursor));
+        }
+    }
+
+    fn show_numbering_dialog(&self) {
+        let dialog = gtk::MessageDialog::new(
+            Some(self),
+            gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+            gtk::MessageType::Question,
+            gtk::ButtonsType::None,
+            &gettext("Number Lines"),
+        );
+        let defaults = Numbering::default();
+        let start = gtk::SpinButton::with_range(-99999.0, 99999.0, 1.0);
+        start.set_value(defaults.start as f64);
+        let step = gtk::SpinButton::with_range(-1000.0, 1000.0, 1.0);
+        step.set_value(defaults.step as f64);
+        let width = gtk::SpinButton::with_range(0.0, 10.0, 1.0);
+        width.set_tooltip_text(Some(&gettext(
+            "Pads numbers with zeros to this many digits",
+        )));
+        let separator = gtk::Entry::new();
+        separator.set_text(&defaults.separator);
+        separator.set_width_chars(8);
+        let grid = gtk::Grid::new();
+        grid.set_row_spacing(6);
+        grid.set_column_spacing(6);
+        let rows: [(String, &gtk::Widget); 4] = [
+            (gettext("Start at"), start.upcast_ref()),
+            (gettext("Step"), step.upcast_ref()),
+            (gettext("Digits"), width.upcast_ref()),
+            (gettext("Separator"), separator.upcast_ref()),
+        ];
+        for (row, (text, widget)) in rows.iter().enumerate() {
+            let label = gtk::Label::new(Some(text.as_str()));
+            label.set_xalign(0.0);
+            grid.attach(&label, 0, row as i32, 1, 1);
+            grid.attach(*widget, 1, row as i32, 1, 1);
+        }
+        if let Ok(area) = dialog.message_area().downcast::<gtk::Box>() {
+            area.append(&grid);
+        }
+        dialog.add_button(&gettext("Cancel"), gtk::ResponseType::Cancel);
+        dialog.add_button(&gettext("Number"), gtk::ResponseType::Accept);
+        dialog.set_default_response(gtk::ResponseType::Accept);
+        dialog.connect_response(clone!(@weak self as win => move |d, response| {
+            if response == gtk::ResponseType::Accept {
+                let numbering = Numbering {
+                    start: i64::from(start.value_as_int()),
+                    step: i64::from(step.value_as_int()),
+                    width: width.value_as_int() as usize,
+                    separator: separator.text().to_string(),
+                };
+                win.transform_lines(|text| text_ops::number_lines(text, &numbering));
+            }
+            d.close();
+        }));
+        dialog.show();
+    }
+
+    // Works on the selection, or the whole document when nothing is
+    // selected. Each change can be unticked before applying.
+    fn show_locale_converter(&self) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let buffer = window.bodytext.buffer();
+        let (start, end) = buffer.selection_bounds().unwrap_or_else(|| buffer.bounds());
+        let offset = start.offset() as usize;
+        let text = buffer.text(&start, &end, false).to_string();
+
+        let content = gtk::Box::new(gtk::Orientation::Vertical, 6);
+        content.set_margin_start(6);
+        content.set_margin_end(6);
+        content.set_margin_top(6);
+        content.set_margin_bottom(6);
+        let from = gtk::ComboBoxText::new();
+        let to = gtk::ComboBoxText::new();
+        for locale in LOCALES.iter() {
+            from.append(Some(locale.id), locale.name);
+            to.append(Some(locale.id), locale.name);
+        }
+        // Most likely the text is written the user's way.
+        from.set_active_id(Some(LocaleFormat::current().map_or("en_US", |l| l.id)));
+        to.set_active_id(Some("iso"));
+        let grid = gtk::Grid::new();
+        grid.set_row_spacing(6);
+        grid.set_column_spacing(6);
+        for (row, (label, combo)) in [(gettext("From"), &from), (gettext("To"), &to)]
+            .iter()
+            .enumerate()
+        {
+            let label = gtk::Label::new(Some(label.as_str()));
+            label.set_xalign(0.0);
+            combo.set_hexpand(true);
+            grid.attach(&label, 0, row as i32, 1, 1);
+            grid.attach(*combo, 1, row as i32, 1, 1);
+        }
+
+        let list = gtk::ListBox::new();
+        list.set_selection_mode(gtk::SelectionMode::None);
+        list.set_vexpand(true);
+        list.set_placeholder(Some(&gtk::Label::new(Some(&gettext(
+            "No dates or numbers to convert",
+        )))));
+        let apply = gtk::Button::with_label(&gettext("Apply"));
+        apply.set_halign(gtk::Align::End);
+        apply.add_css_class("suggested-action");
+
+        let conversions = Rc::new(RefCell::new(Vec::new()));
+        let preview = Rc::new(
+            clone!(@weak from, @weak to, @weak list, @weak apply, @strong conversions => move || {
+                Self::preview_conversions(&text, &from, &to, &list, &apply, &conversions);
+            }),
+        );
+        preview();
+        from.connect_changed(clone!(@strong preview => move |_| preview()));
+        to.connect_changed(clone!(@strong preview => move |_| preview()));
+
+        content.append(&grid);
+        content.append(&list);
+        content.append(&apply);
+        let dialog =
+            self.show_tool_window(&gettext("Convert Dates and Numbers"), &content, 480, 420);
+        apply.connect_clicked(clone!(@weak self as win, @weak dialog => move |_| {
+            win.apply_conversions(offset, &conversions.borrow());
+            dialog.close();
+        }));
+    }
+
+    fn preview_conversions(
+        text: &str,
+        from: &gtk::ComboBoxText,
+        to: &gtk::ComboBoxText,
+        list: &gtk::ListBox,
+        apply: &gtk::Button,
+        conversions: &RefCell<Vec<(Conversion, gtk::CheckButton)>>,
+    ) {
+        while let Some(child) = list.first_child() {
+            list.remove(&child);
+        }
+        let locale =
+            |combo: &gtk::ComboBoxText| combo.active_id().and_then(|id| LocaleFormat::find(&id));
+        let found = match (locale(from), locale(to)) {
+            (Some(from), Some(to)) => locale::convert(text, from, to),
+            _ => Vec::new(),
+        };
+        let rows = found
+            .into_iter()
+            .map(|conversion| {
+                let check = gtk::CheckButton::with_label(&format!(
+                    "{} → {}",
+                    conversion.original, conversion.converted
+                ));
+                check.set_active(true);
+                list.append(&check);
+                (conversion, check)
+            })
+            .collect::<Vec<_>>();
+        apply.set_sensitive(!rows.is_empty());
+        conversions.replace(rows);
+    }
+
+    // Replaces from the end so earlier offsets stay valid, as one undo step.
+    fn apply_conversions(&self, offset: usize, conversions: &[(Conversion, gtk::CheckButton)]) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let buffer = window.bodytext.buffer();
+        buffer.begin_user_action();
+        for (conversion, _) in conversions
+            .iter()
+            .rev()
+            .filter(|(_, check)| check.is_active())
+        {
+            let mut start = buffer.iter_at_offset((offset + conversion.start) as i32);
+            let mut end = buffer.iter_at_offset((offset + conversion.end) as i32);
+            if buffer.text(&start, &end, false).as_str() == conversion.original {
+                buffer.delete(&mut start, &mut end);
+                buffer.insert(&mut start, &conversion.converted);
+            }
+        }
+        buffer.end_user_action();
+    }
+
+    // Previews how many characters each form would change, and lists
+    // look-alike letters that normalization leaves as they are.
+    fn show_normalizer(&self) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let buffer = window.bodytext.buffer();
+        let text = Self::get_buffer_value(buffer);
+
+        let content = gtk::Box::new(gtk::Orientation::Vertical, 6);
+        content.set_margin_start(6);
+        content.set_margin_end(6);
+        content.set_margin_top(6);
+        content.set_margin_bottom(6);
+        let combo = gtk::ComboBoxText::new();
+        for form in FORMS.iter() {
+            combo.append(Some(form.name()), form.name());
+        }
+        combo.set_active_id(Some(Form::Nfc.name()));
+        let summary = gtk::Label::new(None);
+        summary.set_xalign(0.0);
+        let apply = gtk::Button::with_label(&gettext("Apply"));
+        apply.set_halign(gtk::Align::End);
+        apply.add_css_class("suggested-action");
+
+        let selected = |combo: &gtk::ComboBoxText| {
+            let id = combo.active_id();
+            FORMS
+                .iter()
+                .copied()
+                .find(|f| Some(f.name()) == id.as_deref())
+                .unwrap_or(Form::Nfc)
+        };
+        let editable = window.bodytext.is_editable();
+        let preview = clone!(@weak summary, @weak apply, @strong text => move |form: Form| {
+            let (_, changed) = normalize::normalize(&text, form);
+            summary.set_text(&format!(
+                "{}: {}",
+                gettext("Characters that will change"),
+                changed
+            ));
+            apply.set_sensitive(editable && changed > 0);
+        });
+        preview(selected(&combo));
+        combo.connect_changed(move |combo| preview(selected(combo)));
+
+        let heading = gtk::Label::new(Some(&gettext("Look-alike characters")));
+        heading.set_xalign(0.0);
+        heading.add_css_class("heading");
+        let list = gtk::ListBox::new();
+        list.set_vexpand(true);
+        list.set_placeholder(Some(&gtk::Label::new(Some(&gettext(
+            "No look-alike characters found",
+        )))));
+        let found = normalize::confusables(&text);
+        for confusable in found.iter() {
+            list.append(&Self::confusable_row(confusable));
+        }
+        list.connect_row_activated(clone!(@weak self as win => move |_, row| {
+            if let Some(confusable) = found.get(row.index() as usize) {
+                win.select_range(confusable.offset, confusable.offset + 1);
+            }
+        }));
+
+        let row = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+        row.append(&gtk::Label::new(Some(&gettext("Form"))));
+        combo.set_hexpand(true);
+        row.append(&combo);
+        content.append(&row);
+        content.append(&summary);
+        content.append(&apply);
+        content.append(&heading);
+        content.append(&list);
+        let dialog = self.show_tool_window(&gettext("Normalize Unicode"), &content, 480, 420);
+        apply.connect_clicked(
+            clone!(@weak self as win, @weak dialog, @weak combo => move |_| {
+                win.apply_normalization(selected(&combo));
+                dialog.close();
+            }),
+        );
+    }
+
+    fn confusable_row(confusable: &Confusable) -> gtk::ListBoxRow {
+        let label = gtk::Label::new(Some(&format!(
+            "{} {}: {} — {} “{}” {} “{}”",
+            gettext("Line"),
+            confusable.line,
+            confusable.word,
+            confusable.script,
+            confusable.character,
+            gettext("looks like"),
+            confusable.looks_like
+        )));
+        label.set_xalign(0.0);
+        let row = gtk::ListBoxRow::new();
+        row.set_child(Some(&label));
+        row
+    }
+
+    // The whole document is replaced as one undo step.
+    fn apply_normalization(&self, form: Form) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let buffer = window.bodytext.buffer();
+        let text = Self::get_buffer_value(buffer.clone());
+        let (normalized, changed) = normalize::normalize(&text, form);
+        if changed > 0 {
+            let offset = buffer.cursor_position();
+            let (mut start, mut end) = buffer.bounds();
+            buffer.begin_user_action();
+            buffer.delete(&mut start, &mut end);
+            buffer.insert(&mut start, &normalized);
+            buffer.end_user_action();
+            buffer.place_cursor(&buffer.iter_at_offset(offset));
+        }
+        self.show_toast(&format!(
+            "{} {}: {}",
+            gettext("Normalized to"),
+            form.name(),
+            changed
+        ));
+    }
+
+    // Lists characters that cannot be seen, such as zero-width spaces and
+    // bidi controls, and removes them all at once.
+    fn show_invisible_checker(&self) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let buffer = window.bodytext.buffer();
+        let found = invisible::find(&Self::get_buffer_value(buffer));
+
+        let content = gtk::Box::new(gtk::Orientation::Vertical, 6);
+        content.set_margin_start(6);
+        content.set_margin_end(6);
+        content.set_margin_top(6);
+        content.set_margin_bottom(6);
+        let summary = gtk::Label::new(Some(&format!(
+            "{}: {}",
+            gettext("Invisible characters"),
+            found.len()
+        )));
+        summary.set_xalign(0.0);
+        let list = gtk::ListBox::new();
+        list.set_vexpand(true);
+        list.set_placeholder(Some(&gtk::Label::new(Some(&gettext(
+            "No invisible characters found",
+        )))));
+        for character in found.iter() {
+            list.append(&Self::invisible_row(character));
+        }
+        let remove = gtk::Button::with_label(&gettext("Remove All"));
+        remove.set_halign(gtk::Align::End);
+        remove.add_css_class("destructive-action");
+        remove.set_sensitive(window.bodytext.is_editable() && !found.is_empty());
+        content.append(&summary);
+        content.append(&list);
+        content.append(&remove);
+        let dialog = self.show_tool_window(&gettext("Invisible Characters"), &content, 420, 360);
+        let found = Rc::new(found);
+        list.connect_row_activated(clone!(@weak self as win, @strong found => move |_, row| {
+            if let Some(character) = found.get(row.index() as usize) {
+                win.select_range(character.offset, character.offset + 1);
+            }
+        }));
+        remove.connect_clicked(clone!(@weak self as win, @weak dialog => move |_| {
+            win.remove_invisible(&found);
+            dialog.close();
+        }));
+    }
+
+    fn invisible_row(character: &Invisible) -> gtk::ListBoxRow {
+        let label = gtk::Label::new(Some(&format!(
+            "{} {}: {} {}",
+            gettext("Line"),
+            character.line,
+            character.code(),
+            character.name
+        )));
+        label.set_xalign(0.0);
+        let row = gtk::ListBoxRow::new();
+        row.set_child(Some(&label));
+        row
+    }
+
+    // Deleted from the end so the earlier offsets still hold, as one undo
+    // step.
+    fn remove_invisible(&self, found: &[Invisible]) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let buffer = window.bodytext.buffer();
+        buffer.begin_user_action();
+        for character in found.iter().rev() {
+            let mut start = buffer.iter_at_offset(character.offset as i32);
+            let mut end = buffer.iter_at_offset(character.offset as i32 + 1);
+            if buffer
+                .text(&start, &end, false)
+                .as_str()
+                .starts_with(character.character)
+            {
+                buffer.delete(&mut start, &mut end);
+            }
+        }
+        buffer.end_user_action();
+        self.show_toast(&format!(
+            "{}: {}",
+            gettext("Invisible characters removed"),
+            found.len()
+        ));
+    }
+
+    fn show_regex_tester(&self) {
+        let content = gtk::Box::new(gtk::Orientation::Vertical, 6);
+        content.set_margin_start(6);
+        content.set_margin_end(6);
+        content.set_margin_top(6);
+        content.set_margin_bottom(6);
+        let entry = gtk::Entry::new();
+        entry.set_placeholder_text(Some(&gettext("Regular expression")));
+        entry.add_css_class("monospace");
+        let error = gtk::Label::new(None);
+        error.set_xalign(0.0);
+        error.set_wrap(true);
+        error.add_css_class("error");
+        error.set_visible(false);
+
+        let buffer = gtk::TextBuffer::new(None);
+        let tags = buffer.tag_table();
+        tags.add(
+            &gtk::TextTag::builder()
+                .name("match")
+                .background("#f6d32d")
+                .build(),
+        );
+        for (i, color) in GROUP_COLORS.iter().enumerate() {
+            tags.add(
+                &gtk::TextTag::builder()
+                    .name(&format!("group{}", i))
+                    .background(color)
+                    .build(),
+            );
+        }
+        let view = gtk::TextView::with_buffer(&buffer);
+        view.set_monospace(true);
+        view.set_wrap_mode(gtk::WrapMode::WordChar);
+        view.set_height_request(160);
+        let details = gtk::Label::new(None);
+        details.set_xalign(0.0);
+        details.set_wrap(true);
+        details.set_selectable(true);
+        details.add_css_class("monospace");
+
+        let button = gtk::Button::with_label(&gettext("Use as Search Pattern"));
+        button.set_halign(gtk::Align::End);
+        button.set_sensitive(false);
+        button.connect_clicked(clone!(@weak self as win, @weak entry => move |_| {
+            let app = win.application().and_then(|a| a.downcast::<Application>().ok());
+            if let Some(app) = app {
+                app.find_in_files(&Query {
+                    pattern: entry.text().to_string(),
+                    regex: true,
+                    ..Query::default()
+                });
+            }
+        }));
+
+        entry.connect_changed(
+            clone!(@weak buffer, @weak error, @weak details, @weak button => move |entry| {
+                Self::test_regex(entry, &buffer, &error, &details, &button);
+            }),
+        );
+        buffer.connect_changed(
+            clone!(@weak entry, @weak error, @weak details, @weak button => move |buffer| {
+                Self::test_regex(&entry, buffer, &error, &details, &button);
+            }),
+        );
+
+        content.append(&entry);
+        content.append(&error);
+        content.append(&view);
+        content.append(&details);
+        content.append(&button);
+        self.show_tool_window(&gettext("Regex Tester"), &content, 560, 480);
+        entry.grab_focus();
+    }
+
+    // Highlights each match and capture group in the test text, and lists
+    // what they captured.
+    fn test_regex(
+        entry: &gtk::Entry,
+        buffer: &gtk::TextBuffer,
+        error: &gtk::Label,
+        details: &gtk::Label,
+        button: &gtk::Button,
+    ) {
+        let (start, end) = buffer.bounds();
+        buffer.remove_all_tags(&start, &end);
+        let text = buffer.text(&start, &end, true).to_string();
+        let pattern = entry.text();
+        let regex = match Regex::new(&pattern) {
+            Ok(_) if pattern.is_empty() => None,
+            Ok(regex) => Some(regex),
+            Err(e) => {
+                error.set_text(&e.to_string());
+                None
+            }
+        };
+        error.set_visible(regex.is_none() && !pattern.is_empty());
+        button.set_sensitive(regex.is_some());
+        let regex = match regex {
+            Some(regex) => regex,
+            None => {
+                details.set_text("");
+                return;
+            }
+        };
+
+        let matches = regex_tester::test(&regex, &text);
+        let mut lines = Vec::new();
+        for (n, m) in matches.iter().enumerate() {
+            for (i, group) in m.groups.iter().enumerate() {
+                let group = match group {
+                    Some(group) => group,
+                    None => continue,
+                };
+                let tag = match i {
+                    0 => "match".to_string(),
+                    _ => format!("group{}", (i - 1) % GROUP_COLORS.len()),
+                };
+                buffer.apply_tag_by_name(
+                    &tag,
+                    &buffer.iter_at_offset(group.start as i32),
+                    &buffer.iter_at_offset(group.end as i32),
+                );
+                let label = match (i, &group.name) {
+                    (0, _) => format!("{} {}", gettext("Match"), n + 1),
+                    (_, Some(name)) => format!("  {} ({})", i, name),
+                    _ => format!("  {}", i),
+                };
+                lines.push(format!("{}: {:?}", label, group.text));
+            }
+        }
+        if matches.is_empty() {
+            lines.push(gettext("No matches"));
+        } else if matches.len() == MATCH_LIMIT {
+            lines.push(format!("{} {}", gettext("Showing the first"), MATCH_LIMIT));
+        }
+        details.set_text(&lines.join("\n"));
+    }
+
+    // Goes to the next or previous match, wrapping around at either end.
+    fn step_match(&self, forward: bool) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let count = window.search_hits.borrow().len();
+        if count == 0 {
+            return;
+        }
+        let index = match (window.search_hit.get(), forward) {
+            (Some(i), true) => (i + 1) % count,
+            (Some(i), false) => (i + count - 1) % count,
+            (None, true) => 0,
+            (None, false) => count - 1,
+        };
+        window.search_revealer.set_reveal_child(true);
+        self.go_to_match(index);
+    }
+
+    fn go_to_match(&self, index: usize) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let (path, line) = match window.search_hits.borrow().get(index) {
+            Some(hit) => hit.clone(),
+            None => return,
+        };
+        window.search_hit.set(Some(index));
+        if window.document_path.borrow().as_ref() == Some(&path) {
+            let buffer = window.bodytext.buffer();
+            if let Some(mut iter) = buffer.iter_at_line(line as i32 - 1) {
+                buffer.place_cursor(&iter);
+                window
+                    .bodytext
+                    .scroll_to_iter(&mut iter, 0.1, false, 0.0, 0.0);
+                window.bodytext.grab_focus();
+            }
+        } else {
+            window.pending_match.replace(Some((path.clone(), line)));
+            self.send(OpenFile(Some(gio::File::for_path(path))));
+        }
+    }
+
+    fn select_range(&self, start: usize, end: usize) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let buffer = window.bodytext.buffer();
+        let mut start = buffer.iter_at_offset(start as i32);
+        let end = buffer.iter_at_offset(end as i32);
+        buffer.select_range(&start, &end);
+        window
+            .bodytext
+            .scroll_to_iter(&mut start, 0.1, false, 0.0, 0.0);
+        window.bodytext.grab_focus();
+    }
+
+    fn highlight_occurrences(&self, term: &str) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let buffer = window.bodytext.buffer();
+        buffer.remove_tag_by_name("highlight", &buffer.start_iter(), &buffer.end_iter());
+        let text = Self::get_buffer_value(buffer.clone());
+        let occurrences = analysis::find_occurrences(&text, term);
+        for (start, end) in occurrences.iter() {
+            buffer.apply_tag_by_name(
+                "highlight",
+                &buffer.iter_at_offset(*start as i32),
+                &buffer.iter_at_offset(*end as i32),
+            );
+        }
+        if let Some((start, _)) = occurrences.first() {
+            let mut iter = buffer.iter_at_offset(*start as i32);
+            window
+                .bodytext
+                .scroll_to_iter(&mut iter, 0.1, false, 0.0, 0.0);
+        }
+    }
+
+    fn show_conflict_dialog(&self) {
+        let dialog = gtk::MessageDialog::new(
+            Some(self),
+            gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+            gtk::MessageType::Warning,
+            gtk::ButtonsType::None,
+            &gettext("The file has changed on disk"),
+        );
+        dialog.set_secondary_text(Some(&gettext(
+            "Both the document and the file on disk have changes. Saving now would overwrite the changes on disk.",
+        )));
+        dialog.add_button(&gettext("Cancel"), gtk::ResponseType::Cancel);
+        dialog.add_button(&gettext("Take Disk"), gtk::ResponseType::Accept);
+        dialog.add_button(&gettext("Merge"), MERGE_RESPONSE);
+        dialog.add_button(&gettext("Keep Mine"), gtk::ResponseType::Reject);
+        dialog.connect_response(clone!(@weak self as window => move |d, response| {
+            let action = match response {
+                gtk::ResponseType::Accept => Some(ReloadFile),
+                gtk::ResponseType::Reject => Some(KeepFile),
+                r if r == MERGE_RESPONSE => Some(MergeFile),
+                _ => None,
+            };
+            if let Some(action) = action {
+                window.send(action);
+            }
+            d.close();
+        }));
+        dialog.show();
+    }
+
+    fn send(&self, action: Action) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let sent = match window.tx.get() {
+            Some(tx) => tx.send(action).map_err(|_| Error::Disconnected),
+            None => Err(Error::Disconnected),
+        };
+        if let Err(e) = sent {
+            warn!("{}", e);
+            self.show_error(&e);
+        }
+    }
+
+    // Fills the New from Template menu, which otherwise only offers to
+    // open the templates folder.
+    pub fn set_templates(&self, templates: &[(String, PathBuf)]) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        window.templates_menu.remove_all();
+        for (name, path) in templates {
+            let item = gio::MenuItem::new(Some(name), None);
+            item.set_action_and_target_value(
+                Some("app.new-from-template"),
+                Some(&path.to_string_lossy().to_string().to_variant()),
+            );
+            window.templates_menu.append_item(&item);
+        }
+    }
+
+    // For failures outside of the model, which reports its own.
+    pub fn show_error(&self, error: &Error) {
+        self.set_status(&Self::error_message(error), Some(error));
+    }
+
+    // The model found them in the text as it is, so the offsets still hold.
+    fn show_links(&self, links: &[(usize, usize)]) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let buffer = window.bodytext.buffer();
+        buffer.remove_tag_by_name("link", &buffer.start_iter(), &buffer.end_iter());
+        for (start, end) in links {
+            buffer.apply_tag_by_name(
+                "link",
+                &buffer.iter_at_offset(*start as i32),
+                &buffer.iter_at_offset(*end as i32),
+            );
+        }
+    }
+
+    // Each companion file is a button beside the status, which opens it.
+    fn show_companions(&self, companions: &[PathBuf]) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        while let Some(child) = window.companions.first_child() {
+            window.companions.remove(&child);
+        }
+        for path in companions {
+            let name = path
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default();
+            let button = gtk::Button::with_label(&name);
+            button.set_tooltip_text(Some(&path.to_string_lossy()));
+            button.add_css_class("flat");
+            let path = path.clone();
+            button.connect_clicked(clone!(@weak self as win => move |_| {
+                win.send(OpenFile(Some(gio::File::for_path(&path))));
+            }));
+            window.companions.append(&button);
+        }
+        window.companions.set_visible(!companions.is_empty());
+    }
+
+    // Failures from the file system offer the system's own message.
+    fn set_status(&self, text: &str, error: Option<&Error>) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        window.status_bar.set_text(text);
+        let details = match error {
+            Some(e @ Error::Io { .. }) => Some((text.to_string(), e.clone())),
+            _ => None,
+        };
+        window.error_details_button.set_visible(details.is_some());
+        window.error_details.replace(details);
+    }
+
+    fn show_error_details(&self) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let (text, error) = match &*window.error_details.borrow() {
+            Some(details) => details.clone(),
+            None => return,
+        };
+        let dialog = gtk::MessageDialog::new(
+            Some(self),
+            gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+            gtk::MessageType::Error,
+            gtk::ButtonsType::Close,
+            &text,
+        );
+        dialog.set_secondary_text(Some(&error.to_string()));
+        dialog.connect_response(|d, _| d.close());
+        dialog.show();
+    }
+
+    fn error_message(error: &Error) -> String {
+        match error {
+            Error::Io { kind, .. } => match kind {
+                IoKind::NotFound => gettext("The file does not exist"),
+                IoKind::PermissionDenied => gettext("Permission denied"),
+                IoKind::DiskFull => gettext("The disk is full"),
+                IoKind::Other => gettext("Reading or writing the file failed"),
+            },
+            Error::Cancelled => gettext("Cancelled"),
+            Error::NotLocal(uri) => {
+                format!("{}: {}", gettext("Only local files can be opened"), uri)
+            }
+            Error::NoFileName(path) => format!("{}: \"{}\"", gettext("Not a file"), path.display()),
+            Error::Folder(_) => gettext("Folders cannot be opened"),
+            Error::Disconnected => gettext("The editor stopped responding, try restarting it"),
+        }
+    }
+
+    // Action buttons answer with their index in the notification's actions.
+    fn setup_notifications(&self) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        window
+            .notification_bar
+            .connect_response(clone!(@weak self as win => move |_, response| {
+                if let gtk::ResponseType::Other(index) = response {
+                    win.run_notification_action(usize::from(index));
+                }
+                win.show_notification(None);
+            }));
+    }
+
+    fn show_notification(&self, notification: Option<&Notification>) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let bar = &window.notification_bar;
+        for button in window.notification_buttons.borrow_mut().drain(..) {
+            bar.remove_action_widget(&button);
+        }
+        window.notification.replace(notification.cloned());
+        let notification = match notification {
+            Some(notification) => notification,
+            None => {
+                bar.set_revealed(false);
+                return;
+            }
+        };
+        bar.set_message_type(match notification.severity() {
+            Severity::Info => gtk::MessageType::Info,
+            Severity::Warning => gtk::MessageType::Warning,
+            Severity::Error => gtk::MessageType::Error,
+        });
+        window
+            .notification_label
+            .set_text(&Self::notification_message(notification));
+        let mut buttons = window.notification_buttons.borrow_mut();
+        for (index, action) in notification.actions().iter().enumerate() {
+            let label = match action {
+                NotificationAction::Retry => gettext("Retry"),
+                NotificationAction::OpenFolder => gettext("Open Containing Folder"),
+                NotificationAction::Reload => gettext("Reload"),
+            };
+            buttons.push(bar.add_button(&label, gtk::ResponseType::Other(index as u16)));
+        }
+        bar.set_revealed(true);
+    }
+
+    fn notification_message(notification: &Notification) -> String {
+        let name = notification
+            .file()
+            .map(|file| Self::file_name(&file))
+            .unwrap_or_default();
+        match notification {
+            Notification::OpenFailed(_, _, e) => format!(
+                "{} \"{}\". {}",
+                gettext("Could not open"),
+                name,
+                Self::error_message(e)
+            ),
+            Notification::OpenFailures(failures) => {
+                let failed: Vec<String> = failures
+                    .iter()
+                    .map(|(file, e)| {
+                        format!("\"{}\": {}", Self::file_name(file), Self::error_message(e))
+                    })
+                    .collect();
+                format!(
+                    "{} {}. {}",
+                    gettext("Could not open"),
+                    ngettext("{} file", "{} files", failures.len() as u32)
+                        .replace("{}", &failures.len().to_string()),
+                    failed.join(". ")
+                )
+            }
+            Notification::SaveFailed(_, e) => format!(
+                "{} \"{}\". {}",
+                gettext("Could not save"),
+                name,
+                Self::error_message(e)
+            ),
+            Notification::Exported(_) => format!("{} \"{}\"", gettext("Exported to"), name),
+            Notification::ChangedOnDisk(_) => gettext(
+                "The file has changed on disk. Only its beginning is open, so it was not reloaded.",
+            ),
+        }
+    }
+
+    fn run_notification_action(&self, index: usize) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let notification = match window.notification.borrow().clone() {
+            Some(notification) => notification,
+            None => return,
+        };
+        match notification.actions().get(index) {
+            Some(NotificationAction::Retry) => match notification {
+                Notification::OpenFailed(file, mode, _) => self.send(OpenFileAs(file, mode)),
+                Notification::SaveFailed(file, _) => self.send(SaveFile(file)),
+                _ => (),
+            },
+            Some(NotificationAction::OpenFolder) => {
+                if let Some(dir) = notification.file().and_then(|file| file.parent()) {
+                    gtk::show_uri(Some(self), &dir.uri(), gdk::CURRENT_TIME);
+                }
+            }
+            Some(NotificationAction::Reload) => self.send(ReloadFile),
+            None => (),
+        }
+    }
+
+    fn show_toast(&self, text: &str) {
+        self.show_toast_with(text, None);
+    }
+
+    // An untitled document closed without saving can still be brought back.
+    pub fn offer_undo_close(&self) {
+        self.show_toast_with(
+            &gettext("Unsaved document closed"),
+            Some((&gettext("Undo Close"), "app.undo-close")),
+        );
+    }
+
+    // The button, with its label and action, only shows with this toast.
+    fn show_toast_with(&self, text: &str, button: Option<(&str, &str)>) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        window.toast_button.set_visible(button.is_some());
+        if let Some((label, action)) = button {
+            window.toast_button.set_label(label);
+            window.toast_button.set_action_name(Some(action));
+        }
+        let serial = window.toast_serial.get().wrapping_add(1);
+        window.toast_serial.set(serial);
+        window.toast_label.set_text(text);
+        window.toast_revealer.set_reveal_child(true);
+        glib::timeout_add_seconds_local(
+            TOAST_SECONDS,
+            clone!(@weak self as win => @default-return Continue(false), move || {
+                let window = imp::ApplicationWindow::from_instance(&win);
+                // A newer toast restarts the timeout.
+                if window.toast_serial.get() == serial {
+                    window.toast_revealer.set_reveal_child(false);
+                }
+                Continue(false)
+            }),
+        );
+    }
+
+    // Whitespace-only changes can be shown like any other, highlighted
+    // apart from them, or left out.
+    fn show_diff(&self, title: &str, old: &str, new: &str) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        let buffer = gtk::TextBuffer::new(None);
+        let tags = buffer.tag_table();
+        tags.add(
+            &gtk::TextTag::builder()
+                .name("removed")
+                .foreground("#c01c28")
+                .build(),
+        );
+        tags.add(
+            &gtk::TextTag::builder()
+                .name("added")
+                .foreground("#26a269")
+                .build(),
+        );
+        tags.add(
+            &gtk::TextTag::builder()
+                .name("whitespace")
+                .background("#f6d32d55")
+                .build(),
+        );
+
+        let whitespace = gtk::ComboBoxText::new();
+        whitespace.append(Some("show"), &gettext("Show"));
+        whitespace.append(Some("highlight"), &gettext("Highlight"));
+        whitespace.append(Some("ignore"), &gettext("Ignore"));
+        window
+            .settings
+            .bind("diff-whitespace", &whitespace, "active-id")
+            .build();
+        let label = gtk::Label::new(Some(&gettext("Whitespace changes")));
+        let options = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+        options.set_margin_start(6);
+        options.set_margin_end(6);
+        options.set_margin_top(6);
+        options.set_margin_bottom(6);
+        options.append(&label);
+        options.append(&whitespace);
+
+        let view = gtk::TextView::with_buffer(&buffer);
+        view.set_editable(false);
+        view.set_monospace(true);
+        view.set_vexpand(true);
+        let content = gtk::Box::new(gtk::Orientation::Vertical, 0);
+        content.append(&options);
+        content.append(&view);
+
+        let (old, new) = (old.to_string(), new.to_string());
+        let fill = move |buffer: &gtk::TextBuffer, mode: &str| {
+            Self::fill_diff(buffer, &old, &new, mode);
+        };
+        fill(
+            &buffer,
+            whitespace.active_id().as_deref().unwrap_or_default(),
+        );
+        whitespace.connect_changed(clone!(@weak buffer => move |combo| {
+            fill(&buffer, combo.active_id().as_deref().unwrap_or_default());
+        }));
+        self.show_tool_window(title, &content, 600, 400);
+    }
+
+    fn fill_diff(buffer: &gtk::TextBuffer, old: &str, new: &str, mode: &str) {
+        let mut lines = diff::diff_lines(old, new);
+        if mode == "ignore" {
+            lines = diff::ignore_whitespace(&lines);
+        }
+        let whitespace = diff::whitespace_changes(&lines);
+        let mut text = String::new();
+        let mut tagged = Vec::new();
+        let mut offset = 0;
+        for (line, whitespace) in lines.iter().zip(whitespace) {
+            let row = format!("{} {}\n", line.prefix(), line.text());
+            let len = row.chars().count() as i32;
+            match line {
+                DiffLine::Removed(_) => tagged.push(("removed", offset, offset + len)),
+                DiffLine::Added(_) => tagged.push(("added", offset, offset + len)),
+                DiffLine::Same(_) => {}
+            }
+            if whitespace && mode == "highlight" {
+                tagged.push(("whitespace", offset, offset + len));
+            }
+            offset += len;
+            text.push_str(&row);
+        }
+        buffer.set_text(&text);
+        for (tag, start, end) in tagged {
+            buffer.apply_tag_by_name(
+                tag,
+                &buffer.iter_at_offset(start),
+                &buffer.iter_at_offset(end),
+            );
+        }
+    }
+
+    fn show_tool_window(
+        &self,
+        title: &str,
+        child: &impl IsA<gtk::Widget>,
+        width: i32,
+        height: i32,
+    ) -> gtk::Window {
+        let scrolled = gtk::ScrolledWindow::new();
+        scrolled.set_child(Some(child));
+        let dialog = gtk::Window::new();
+        dialog.set_title(Some(title));
+        dialog.set_transient_for(Some(self));
+        dialog.set_default_size(width, height);
+        dialog.set_child(Some(&scrolled));
+        dialog.show();
+        dialog
+    }
+
+    // Remote files are shown by their URI.
+    fn filepath_string(model: &ApplicationModel) -> String {
+        match model.document().filepath() {
+            Some(path) if sandbox::is_document(&path) => sandbox::display_path(&path),
+            Some(path) => match path.into_os_string().into_string() {
+                Ok(s) => s,
+                Err(_) => model.document().filename().unwrap_or_default(),
+            },
+            None => model
+                .document()
+                .file()
+                .map(|f| f.uri().to_string())
+                .unwrap_or_default(),
+        }
+    }
+
+    fn file_name(file: &gio::File) -> String {
+        file.basename()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default()
+    }
+
+    fn get_buffer_value(buffer: gtk::TextBuffer) -> String {
+        let start = buffer.start_iter();
+        let end = buffer.end_iter();
+        buffer.text(&start, &end, true).to_string()
+    }
+
+    pub fn transmit(&self, tx: Sender<Action>) {
+        let window = imp::ApplicationWindow::from_instance(self);
+        window.tx.set(tx.clone()).ok();
+        let buffer = window.bodytext.buffer();
+        let mut handlers = window.buffer_handlers.borrow_mut();
+        // Only the change is sent, so edits cost the same in any size of
+        // document. These run before the buffer changes, while the
+        // iterators still point at the edited range.
+        let tx_local = tx.clone();
+        handlers.extend(
+            buffer
+                .connect("insert-text", false, move |args| {
+                    let location: gtk::TextIter = args[1].get().unwrap();
+                    let text: String = args[2].get().unwrap();
+                    let offset = location.offset() as usize;
+                    debug!(
+                        "GtkApplicationWindow<Application>::transmit insert-text {} {} characters",
+                        offset,
+                        text.chars().count()
+                    );
+                    tx_local.send(TextInserted { offset, text }).ok();
+                    None
+                })
+                .ok(),
+        );
+        let tx_local = tx.clone();
+        handlers.extend(
+            buffer
+                .connect("delete-range", false, move |args| {
+                    let start: gtk::TextIter = args[1].get().unwrap();
+                    let end: gtk::TextIter = args[2].get().unwrap();
+                    let (start, end) = (start.offset() as usize, end.offset() as usize);
+                    debug!(
+                        "GtkApplicationWindow<Application>::transmit delete-range {} {}",
+                        start, end
+                    );
+                    tx_local
+                        .send(TextDeleted {
+                            start: start.min(end),
+                            end: start.max(end),
+                        })
+                        .ok();
+                    None
+                })
+                .ok(),
+        );
+        // GTK's own undo is off, so its grouping of edits is passed on.
+        let tx_local = tx.clone();
+        handlers.push(buffer.connect_begin_user_action(move |_| {
+            tx_local.send(UserActionStarted).ok();
+        }));
+        let tx_local = tx.clone();
+        handlers.push(buffer.connect_end_user_action(move |_| {
+            tx_local.send(UserActionEnded).ok();
+        }));
+        let tx_local = tx.clone();
+        window
+            .external_change_bar
+            .connect_response(move |_, response| {
+                let action = match response {
+                    gtk::ResponseType::Accept => ReloadFile,
+                    gtk::ResponseType::Apply => CompareFile,
+                    r if r == MERGE_RESPONSE => MergeFile,
+                    _ => KeepFile,
+                };
+                tx_local.send(action).ok();
+            });
+        let tx_local = tx;
+        window.large_file_bar.connect_response(move |_, response| {
+            let action = match response {
+                gtk::ResponseType::Accept => OpenLargeFile(false),
+                gtk::ResponseType::Apply => OpenLargeFile(true),
+                _ => CancelOpen,
+            };
+            tx_local.send(action).ok();
+        });
     }
 }
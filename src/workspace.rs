@@ -0,0 +1,486 @@
+use log::debug;
+use regex::{NoExpand, Regex};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+
+// Files with a NUL byte this early are taken to be binary, as git does.
+const BINARY_CHECK_SIZE: usize = 8000;
+// Threads that read and scan files during a search.
+const SEARCH_WORKERS: usize = 4;
+
+// Which files of a workspace are indexed and searched.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Filter {
+    // Globs matched against each name, or against the relative path of
+    // each directory when they contain a separator.
+    excluded: Vec<String>,
+    // Largest file in bytes, or 0 for any size.
+    max_size: u64,
+    // Globs a file's name must match, when there are any.
+    included: Vec<String>,
+}
+
+impl Filter {
+    pub fn new(excluded: Vec<String>, max_size: u64) -> Self {
+        let excluded = excluded
+            .into_iter()
+            .map(|g| g.trim().trim_end_matches('/').to_string())
+            .filter(|g| !g.is_empty())
+            .collect();
+        Self {
+            excluded,
+            max_size,
+            included: Vec::new(),
+        }
+    }
+
+    pub fn with_included(mut self, included: Vec<String>) -> Self {
+        self.included = included;
+        self
+    }
+
+    pub fn is_included(&self, name: &str) -> bool {
+        self.included.is_empty() || self.included.iter().any(|glob| glob_match(glob, name))
+    }
+
+    pub fn is_excluded(&self, relative: &Path) -> bool {
+        let names: Vec<String> = relative
+            .components()
+            .filter_map(|c| match c {
+                Component::Normal(name) => Some(name.to_string_lossy().to_string()),
+                _ => None,
+            })
+            .collect();
+        self.excluded.iter().any(|glob| {
+            if glob.contains('/') {
+                // Excluding a directory excludes everything in it.
+                (1..=names.len()).any(|i| glob_match(glob, &names[..i].join("/")))
+            } else {
+                names.iter().any(|name| glob_match(glob, name))
+            }
+        })
+    }
+
+    pub fn is_too_large(&self, size: u64) -> bool {
+        self.max_size > 0 && size > self.max_size
+    }
+}
+
+// Supports `*` and `?` within a name, and `**` across separators.
+pub fn glob_match(glob: &str, text: &str) -> bool {
+    let glob: Vec<char> = glob.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    match_from(&glob, &text)
+}
+
+fn match_from(glob: &[char], text: &[char]) -> bool {
+    match glob.first() {
+        None => text.is_empty(),
+        Some('*') if glob.get(1) == Some(&'*') => {
+            let rest = glob[2..].strip_prefix(&['/']).unwrap_or(&glob[2..]);
+            (0..=text.len()).any(|i| match_from(rest, &text[i..]))
+        }
+        Some('*') => {
+            let run = text.iter().take_while(|&&c| c != '/').count();
+            (0..=run).any(|i| match_from(&glob[1..], &text[i..]))
+        }
+        Some('?') => {
+            matches!(text.first(), Some(&c) if c != '/') && match_from(&glob[1..], &text[1..])
+        }
+        Some(&c) => text.first() == Some(&c) && match_from(&glob[1..], &text[1..]),
+    }
+}
+
+pub fn is_binary(bytes: &[u8]) -> bool {
+    bytes.iter().take(BINARY_CHECK_SIZE).any(|&b| b == 0)
+}
+
+fn is_binary_file(path: &Path) -> io::Result<bool> {
+    let mut start = Vec::with_capacity(BINARY_CHECK_SIZE);
+    File::open(path)?
+        .take(BINARY_CHECK_SIZE as u64)
+        .read_to_end(&mut start)?;
+    Ok(is_binary(&start))
+}
+
+// The text files under the root that pass the filter, in path order.
+// Symbolic links to directories are not followed, so cycles cannot occur.
+pub fn files(root: &Path, filter: &Filter) -> Vec<PathBuf> {
+    let mut found = Vec::new();
+    collect(root, root, filter, &mut found);
+    found.sort();
+    found
+}
+
+fn collect(root: &Path, dir: &Path, filter: &Filter, found: &mut Vec<PathBuf>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            debug!("Skipping {:?}, {}", dir, e);
+            return;
+        }
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let relative = path.strip_prefix(root).unwrap_or(&path);
+        if filter.is_excluded(relative) {
+            continue;
+        }
+        let file_type = match entry.file_type() {
+            Ok(file_type) => file_type,
+            Err(_) => continue,
+        };
+        if file_type.is_dir() {
+            collect(root, &path, filter, found);
+            continue;
+        }
+        if !filter.is_included(&entry.file_name().to_string_lossy()) {
+            continue;
+        }
+        let size = match fs::metadata(&path) {
+            Ok(metadata) if metadata.is_file() => metadata.len(),
+            _ => continue,
+        };
+        if !filter.is_too_large(size) && matches!(is_binary_file(&path), Ok(false)) {
+            found.push(path);
+        }
+    }
+}
+
+// What to search for, as typed into the find in files dialog.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Query {
+    pub pattern: String,
+    pub regex: bool,
+    // The folder searched, rather than the workspace.
+    pub directory: Option<PathBuf>,
+    // File name globs such as `*.rs, *.toml`, or empty for every file.
+    pub include: String,
+}
+
+impl Query {
+    // Plain text is escaped, so both kinds are searched the same way.
+    pub fn compile(&self) -> Result<Regex, regex::Error> {
+        if self.regex {
+            Regex::new(&self.pattern)
+        } else {
+            Regex::new(&regex::escape(&self.pattern))
+        }
+    }
+
+    pub fn included(&self) -> Vec<String> {
+        self.include
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|g| !g.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineMatch {
+    // Starting from 1.
+    pub line: usize,
+    pub text: String,
+    // Lines around the match, nearest last for `before`.
+    pub before: Vec<String>,
+    pub after: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileMatches {
+    pub path: PathBuf,
+    pub matches: Vec<LineMatch>,
+}
+
+// Lines matching the pattern, with up to `context` lines on each side.
+pub fn search_text(text: &str, pattern: &Regex, context: usize) -> Vec<LineMatch> {
+    if pattern.as_str().is_empty() {
+        return Vec::new();
+    }
+    let lines: Vec<&str> = text.lines().collect();
+    let owned = |range: &[&str]| range.iter().map(|l| l.to_string()).collect();
+    lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| pattern.is_match(line))
+        .map(|(i, line)| LineMatch {
+            line: i + 1,
+            text: line.to_string(),
+            before: owned(&lines[i.saturating_sub(context)..i]),
+            after: owned(&lines[i + 1..(i + 1 + context).min(lines.len())]),
+        })
+        .collect()
+}
+
+// Searches the workspace's files in path order, passing each file with
+// matches to `found` as soon as it is scanned. `found` stops the search by
+// returning false. Files that are not valid UTF-8 are skipped.
+pub fn search<F>(root: &Path, filter: &Filter, pattern: &Regex, context: usize, mut found: F)
+where
+    F: FnMut(FileMatches) -> bool,
+{
+    if pattern.as_str().is_empty() {
+        return;
+    }
+    let files = Arc::new(files(root, filter));
+    let next = Arc::new(AtomicUsize::new(0));
+    let stop = Arc::new(AtomicBool::new(false));
+    let (tx, rx) = mpsc::channel();
+    for _ in 0..SEARCH_WORKERS.min(files.len()) {
+        let (files, next, stop, tx) = (files.clone(), next.clone(), stop.clone(), tx.clone());
+        let pattern = pattern.clone();
+        thread::spawn(move || loop {
+            let i = next.fetch_add(1, Ordering::Relaxed);
+            if i >= files.len() || stop.load(Ordering::Relaxed) {
+                break;
+            }
+            let matches = match fs::read_to_string(&files[i]) {
+                Ok(text) => search_text(&text, &pattern, context),
+                Err(_) => Vec::new(),
+            };
+            if tx.send((i, matches)).is_err() {
+                break;
+            }
+        });
+    }
+    drop(tx);
+    // Workers finish out of order, so results wait for those before them.
+    let mut pending = HashMap::new();
+    let mut reported = 0;
+    for (i, matches) in rx {
+        pending.insert(i, matches);
+        while let Some(matches) = pending.remove(&reported) {
+            let path = files[reported].clone();
+            reported += 1;
+            if !matches.is_empty() && !found(FileMatches { path, matches }) {
+                stop.store(true, Ordering::Relaxed);
+                return;
+            }
+        }
+    }
+}
+
+// Replaces every match, returning the new text and how many were
+// replaced. Expanding lets the replacement refer to groups, as in `$1`.
+pub fn replace_text(
+    text: &str,
+    pattern: &Regex,
+    replacement: &str,
+    expand: bool,
+) -> (String, usize) {
+    if pattern.as_str().is_empty() {
+        return (text.to_string(), 0);
+    }
+    let count = pattern.find_iter(text).count();
+    let replaced = if expand {
+        pattern.replace_all(text, replacement)
+    } else {
+        pattern.replace_all(text, NoExpand(replacement))
+    };
+    (replaced.to_string(), count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("textedit2-{}-{}", name, std::process::id()));
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("target", "target"));
+        assert!(glob_match("*.min.js", "app.min.js"));
+        assert!(!glob_match("*.min.js", "app.js"));
+        assert!(glob_match("build-?", "build-1"));
+        assert!(!glob_match("*", "src/main.rs"), "* stays within a name");
+        assert!(glob_match("src/**/*.rs", "src/ui/window.rs"));
+        assert!(
+            glob_match("src/**/*.rs", "src/main.rs"),
+            "** matches no directory"
+        );
+        assert!(glob_match("**/fixtures", "tests/data/fixtures"));
+        assert!(!glob_match("docs/*", "src/docs/index.md"));
+    }
+
+    #[test]
+    fn test_is_excluded() {
+        let filter = Filter::new(
+            vec![
+                "node_modules".into(),
+                ".git/".into(),
+                "*.lock".into(),
+                "docs/generated".into(),
+                " ".into(),
+            ],
+            0,
+        );
+        assert!(filter.is_excluded(Path::new("web/node_modules/left-pad/index.js")));
+        assert!(
+            filter.is_excluded(Path::new(".git")),
+            "Trailing separators are ignored"
+        );
+        assert!(filter.is_excluded(Path::new("Cargo.lock")));
+        assert!(filter.is_excluded(Path::new("docs/generated/api.html")));
+        assert!(!filter.is_excluded(Path::new("src/docs/generated.rs")));
+        assert!(!filter.is_excluded(Path::new("src/main.rs")));
+    }
+
+    #[test]
+    fn test_is_binary() {
+        assert!(!is_binary(b"Mary had a little lamb"));
+        assert!(is_binary(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"));
+        assert!(!is_binary("naïve café".as_bytes()));
+    }
+
+    #[test]
+    fn test_files() {
+        let dir = test_dir("workspace");
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::create_dir_all(dir.join("target/debug")).unwrap();
+        fs::write(dir.join("src/main.rs"), "fn main() {}").unwrap();
+        fs::write(dir.join("README.md"), "# TextEdit 2").unwrap();
+        fs::write(dir.join("target/debug/build.log"), "Compiling").unwrap();
+        fs::write(dir.join("icon.png"), b"\x89PNG\0\0").unwrap();
+        fs::write(dir.join("large.txt"), "a".repeat(2048)).unwrap();
+
+        let filter = Filter::new(vec!["target".into()], 1024);
+        assert_eq!(
+            vec![dir.join("README.md"), dir.join("src/main.rs")],
+            files(&dir, &filter),
+            "Excluded, binary and large files are skipped"
+        );
+        let filter = Filter::new(Vec::new(), 0);
+        assert_eq!(4, files(&dir, &filter).len(), "No exclusions or size cap");
+        let filter = Filter::new(Vec::new(), 0).with_included(vec!["*.rs".into()]);
+        assert_eq!(vec![dir.join("src/main.rs")], files(&dir, &filter));
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    fn text_query(pattern: &str) -> Regex {
+        Query {
+            pattern: pattern.into(),
+            ..Query::default()
+        }
+        .compile()
+        .unwrap()
+    }
+
+    fn regex_query(pattern: &str) -> Regex {
+        Query {
+            pattern: pattern.into(),
+            regex: true,
+            ..Query::default()
+        }
+        .compile()
+        .unwrap()
+    }
+
+    #[test]
+    fn test_query() {
+        assert!(text_query("a.b").is_match("a.b"));
+        assert!(!text_query("a.b").is_match("axb"), "Plain text is escaped");
+        assert!(regex_query("a.b").is_match("axb"));
+        let invalid = Query {
+            pattern: "(".into(),
+            regex: true,
+            ..Query::default()
+        };
+        assert!(invalid.compile().is_err());
+        let query = Query {
+            include: "*.rs, *.toml  Makefile".into(),
+            ..Query::default()
+        };
+        assert_eq!(vec!["*.rs", "*.toml", "Makefile"], query.included());
+    }
+
+    #[test]
+    fn test_search_text() {
+        let text = "Mary had\na little lamb\nwhose fleece\nwas white\nlittle lamb";
+        let matches = search_text(text, &text_query("little"), 1);
+        assert_eq!(
+            LineMatch {
+                line: 2,
+                text: "a little lamb".into(),
+                before: vec!["Mary had".into()],
+                after: vec!["whose fleece".into()],
+            },
+            matches[0]
+        );
+        assert_eq!(5, matches[1].line);
+        assert!(matches[1].after.is_empty(), "No context past the end");
+        assert_eq!(
+            vec!["Mary had".to_string(), "a little lamb".to_string()],
+            search_text(text, &text_query("whose"), 2)[0].before
+        );
+        assert!(search_text(text, &text_query(""), 2).is_empty());
+        let matches = search_text(text, &regex_query(r"^\w+ lamb$"), 0);
+        assert_eq!(vec![5], matches.iter().map(|m| m.line).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_replace_text() {
+        assert_eq!(
+            ("a big lamb, big lamb".to_string(), 2),
+            replace_text(
+                "a little lamb, little lamb",
+                &text_query("little"),
+                "big",
+                false
+            )
+        );
+        assert_eq!(
+            ("lamb".to_string(), 0),
+            replace_text("lamb", &text_query(""), "x", false)
+        );
+        assert_eq!(
+            ("a little sheep".to_string(), 1),
+            replace_text(
+                "a little lamb",
+                &regex_query(r"(\w+) lamb"),
+                "$1 sheep",
+                true
+            )
+        );
+        assert_eq!(
+            ("cost: $1".to_string(), 1),
+            replace_text("cost: 5", &text_query("5"), "$1", false),
+            "Plain replacements are not expanded"
+        );
+    }
+
+    #[test]
+    fn test_search() {
+        let dir = test_dir("workspace-search");
+        fs::create_dir_all(dir.join("target")).unwrap();
+        fs::write(dir.join("a.txt"), "little lamb").unwrap();
+        fs::write(dir.join("b.txt"), "no match").unwrap();
+        fs::write(dir.join("c.txt"), "a little\nlittle lamb").unwrap();
+        fs::write(dir.join("target/lamb.txt"), "little lamb").unwrap();
+        let filter = Filter::new(vec!["target".into()], 0);
+        let mut found = Vec::new();
+        search(&dir, &filter, &text_query("little"), 0, |m| {
+            found.push((m.path, m.matches.len()));
+            true
+        });
+        assert_eq!(vec![(dir.join("a.txt"), 1), (dir.join("c.txt"), 2)], found);
+
+        let mut count = 0;
+        search(&dir, &filter, &text_query("little"), 0, |_| {
+            count += 1;
+            false
+        });
+        assert_eq!(1, count, "The search stops when asked");
+        fs::remove_dir_all(&dir).ok();
+    }
+}